@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "backups" (
+    node_id TEXT PRIMARY KEY NOT NULL,
+    backup BYTEA NOT NULL,
+    timestamp timestamp WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "positions" (
+    channel_id TEXT PRIMARY KEY NOT NULL,
+    contract_id TEXT UNIQUE,
+    trader_pubkey TEXT NOT NULL,
+    contract_symbol TEXT NOT NULL,
+    leverage DOUBLE PRECISION NOT NULL,
+    quantity DOUBLE PRECISION NOT NULL,
+    direction "Direction_Type" NOT NULL,
+    average_entry_price DOUBLE PRECISION NOT NULL,
+    creation_timestamp timestamp WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX positions_trader_pubkey ON positions(trader_pubkey);
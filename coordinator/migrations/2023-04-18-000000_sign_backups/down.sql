@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "backups"
+    DROP COLUMN sequence,
+    DROP COLUMN signature;
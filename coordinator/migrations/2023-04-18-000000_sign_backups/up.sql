@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Backups stored so far were not signed over a sequence number, so they are kept without sequence
+-- number and signature. They can still be restored, as only the seed of the node can decrypt
+-- them, and are replaced by the next signed backup of the node.
+ALTER TABLE "backups"
+    ADD COLUMN sequence BIGINT,
+    ADD COLUMN signature TEXT;
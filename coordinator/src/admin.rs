@@ -0,0 +1,124 @@
+//! The routes to manage the on-chain wallet of the coordinator.
+//!
+//! The routes are not authenticated, so they are only served on the admin API bound to localhost.
+
+use crate::AppError;
+use anyhow::Result;
+use axum::extract::State;
+use axum::routing::get;
+use axum::routing::post;
+use axum::Json;
+use axum::Router;
+use bitcoin::Address;
+use bitcoin::OutPoint;
+use bitcoin::Txid;
+use ln_dlc_node::node::Fee;
+use ln_dlc_node::node::Node;
+use ln_dlc_node::node::SendAmount;
+use ln_dlc_node::node::Utxo;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The wallet routes, nested under `/api/admin/wallet` of the admin API.
+pub fn wallet_router(node: Arc<Node>) -> Router {
+    Router::new()
+        .route("/utxos", get(list_utxos))
+        .route("/send", post(post_send_on_chain))
+        .route("/bump-fee", post(post_bump_fee))
+        .route("/cpfp", post(post_cpfp))
+        .with_state(node)
+}
+
+pub async fn list_utxos(State(node): State<Arc<Node>>) -> Result<Json<Vec<Utxo>>, AppError> {
+    let utxos = spawn_blocking(move || node.list_utxos())
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to list UTXOs: {e:#}")))?;
+
+    Ok(Json(utxos))
+}
+
+#[derive(Deserialize)]
+pub struct SendOnChainParams {
+    address: String,
+    amount: SendAmount,
+    fee: Fee,
+    /// If not empty, only these UTXOs (formatted as `txid:vout`) are spent.
+    #[serde(default)]
+    utxos: Vec<String>,
+}
+
+pub async fn post_send_on_chain(
+    State(node): State<Arc<Node>>,
+    params: Json<SendOnChainParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let address = Address::from_str(&params.address)
+        .map_err(|e| AppError::BadRequest(format!("Invalid address provided: {e:#}")))?;
+    let utxos = params
+        .utxos
+        .iter()
+        .map(|utxo| OutPoint::from_str(utxo))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::BadRequest(format!("Invalid UTXO provided: {e:#}")))?;
+
+    let txid =
+        spawn_blocking(move || node.send_to_address(&address, params.amount, params.fee, &utxos))
+            .await
+            .map_err(|e| {
+                AppError::InternalServerError(format!("Failed to send on-chain: {e:#}"))
+            })?;
+
+    Ok(Json(txid.to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct BumpFeeParams {
+    txid: String,
+    fee: Fee,
+}
+
+pub async fn post_bump_fee(
+    State(node): State<Arc<Node>>,
+    params: Json<BumpFeeParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let txid = Txid::from_str(&params.txid)
+        .map_err(|e| AppError::BadRequest(format!("Invalid txid provided: {e:#}")))?;
+
+    let txid = spawn_blocking(move || node.bump_fee(txid, params.fee))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to bump fee: {e:#}")))?;
+
+    Ok(Json(txid.to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct CpfpParams {
+    /// The output of the stuck transaction which pays to us, formatted as `txid:vout`.
+    outpoint: String,
+    fee: Fee,
+}
+
+pub async fn post_cpfp(
+    State(node): State<Arc<Node>>,
+    params: Json<CpfpParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let outpoint = OutPoint::from_str(&params.outpoint)
+        .map_err(|e| AppError::BadRequest(format!("Invalid outpoint provided: {e:#}")))?;
+
+    let txid = spawn_blocking(move || node.cpfp(outpoint, params.fee))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to bump fee: {e:#}")))?;
+
+    Ok(Json(txid.to_string()))
+}
+
+/// Runs the blocking wallet operation `f` without blocking the executor.
+async fn spawn_blocking<T>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T>
+where
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
@@ -0,0 +1,160 @@
+use crate::schema::backups;
+use bitcoin::secp256k1::PublicKey;
+use coordinator_commons::Backup;
+use diesel::prelude::*;
+use diesel::result::QueryResult;
+use diesel::PgConnection;
+use time::OffsetDateTime;
+
+/// The largest backup we store for a node.
+pub const MAX_BACKUP_SIZE: usize = 1024 * 1024;
+
+#[derive(Insertable, AsChangeset, Debug)]
+#[diesel(table_name = backups)]
+struct NewBackup {
+    pub node_id: String,
+    pub backup: Vec<u8>,
+    pub timestamp: OffsetDateTime,
+    pub sequence: i64,
+    pub signature: String,
+}
+
+#[derive(Queryable, Debug)]
+struct BackupRow {
+    pub backup: Vec<u8>,
+    /// Missing for backups stored before backups were signed
+    pub sequence: Option<i64>,
+    pub signature: Option<String>,
+}
+
+/// Stores the encrypted `backup` of `node_id` with its `sequence` number and `signature`,
+/// replacing its previous backup.
+///
+/// Returns false without storing anything if the previous backup has the same or a higher
+/// sequence number.
+pub fn upsert(
+    conn: &mut PgConnection,
+    node_id: PublicKey,
+    backup: Vec<u8>,
+    sequence: u64,
+    signature: String,
+) -> QueryResult<bool> {
+    let backup = NewBackup {
+        node_id: node_id.to_string(),
+        backup,
+        timestamp: OffsetDateTime::now_utc(),
+        sequence: sequence as i64,
+        signature,
+    };
+
+    conn.transaction(|conn| {
+        let stored_sequence = backups::table
+            .filter(backups::node_id.eq(&backup.node_id))
+            .select(backups::sequence)
+            .for_update()
+            .first::<Option<i64>>(conn)
+            .optional()?
+            .flatten();
+
+        if matches!(stored_sequence, Some(stored_sequence) if stored_sequence >= backup.sequence) {
+            return Ok(false);
+        }
+
+        diesel::insert_into(backups::table)
+            .values(&backup)
+            .on_conflict(backups::node_id)
+            .do_update()
+            .set(&backup)
+            .execute(conn)?;
+
+        Ok(true)
+    })
+}
+
+/// Returns the latest encrypted backup of `node_id` as signed by the node, if any.
+///
+/// Backups stored before backups were signed are returned without signature.
+pub fn get(conn: &mut PgConnection, node_id: PublicKey) -> QueryResult<Option<Backup>> {
+    let backup = backups::table
+        .filter(backups::node_id.eq(node_id.to_string()))
+        .select((backups::backup, backups::sequence, backups::signature))
+        .first::<BackupRow>(conn)
+        .optional()?;
+
+    Ok(backup.map(|backup| Backup {
+        sequence: backup.sequence.unwrap_or_default() as u64,
+        backup: hex::encode(backup.backup),
+        signature: backup.signature,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup;
+    use crate::tests::setup_db;
+    use crate::tests::start_postgres;
+    use bitcoin::secp256k1::PublicKey;
+    use diesel::sql_query;
+    use diesel::RunQueryDsl;
+    use std::str::FromStr;
+    use testcontainers::clients::Cli;
+
+    #[tokio::test]
+    async fn backup_with_non_increasing_sequence_is_rejected() {
+        let docker = Cli::default();
+        let (_container, conn_spec) = start_postgres(&docker).unwrap();
+
+        let mut conn = setup_db(conn_spec);
+
+        let node_id = PublicKey::from_str(
+            "027f31ebc5462c1fdce1b737ecff52d37d75dea43ce11c74d25aa297165faa2007",
+        )
+        .unwrap();
+
+        assert!(backup::upsert(&mut conn, node_id, vec![1], 1, "signature-1".to_string()).unwrap());
+        assert!(backup::upsert(&mut conn, node_id, vec![2], 2, "signature-2".to_string()).unwrap());
+
+        assert!(
+            !backup::upsert(&mut conn, node_id, vec![3], 2, "signature-3".to_string()).unwrap()
+        );
+        assert!(
+            !backup::upsert(&mut conn, node_id, vec![1], 1, "signature-1".to_string()).unwrap()
+        );
+
+        let stored = backup::get(&mut conn, node_id).unwrap().unwrap();
+        assert_eq!(stored.sequence, 2);
+        assert_eq!(stored.backup, hex::encode([2]));
+        assert_eq!(stored.signature, Some("signature-2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn unsigned_backup_is_readable_and_replaced_by_signed_backup() {
+        let docker = Cli::default();
+        let (_container, conn_spec) = start_postgres(&docker).unwrap();
+
+        let mut conn = setup_db(conn_spec);
+
+        let node_id = PublicKey::from_str(
+            "027f31ebc5462c1fdce1b737ecff52d37d75dea43ce11c74d25aa297165faa2007",
+        )
+        .unwrap();
+
+        // Stored before backups were signed
+        sql_query(format!(
+            "INSERT INTO backups (node_id, backup) VALUES ('{node_id}', '\\x01')"
+        ))
+        .execute(&mut conn)
+        .unwrap();
+
+        let stored = backup::get(&mut conn, node_id).unwrap().unwrap();
+        assert_eq!(stored.sequence, 0);
+        assert_eq!(stored.backup, hex::encode([1]));
+        assert_eq!(stored.signature, None);
+
+        assert!(backup::upsert(&mut conn, node_id, vec![2], 0, "signature-0".to_string()).unwrap());
+
+        let stored = backup::get(&mut conn, node_id).unwrap().unwrap();
+        assert_eq!(stored.backup, hex::encode([2]));
+        assert_eq!(stored.signature, Some("signature-0".to_string()));
+    }
+}
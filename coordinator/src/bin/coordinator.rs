@@ -2,8 +2,8 @@ use anyhow::Context;
 use anyhow::Result;
 use coordinator::cli::Opts;
 use coordinator::logger;
-use coordinator::node;
 use coordinator::node::Node;
+use coordinator::routes::admin_router;
 use coordinator::routes::router;
 use coordinator::run_migration;
 use diesel::r2d2;
@@ -13,17 +13,13 @@ use ln_dlc_node::seed::Bip39Seed;
 use rand::thread_rng;
 use rand::RngCore;
 use std::backtrace::Backtrace;
-use std::collections::HashMap;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Duration;
 use tracing::metadata::LevelFilter;
 
-const PROCESS_INCOMING_MESSAGES_INTERVAL: Duration = Duration::from_secs(5);
-
 #[tokio::main]
 async fn main() -> Result<()> {
     std::panic::set_hook(
@@ -41,7 +37,11 @@ async fn main() -> Result<()> {
     let opts = Opts::read();
     let data_dir = opts.data_dir()?;
     let address = opts.p2p_address;
+    let liquidity_offer = opts.liquidity_offer();
+    let jit_fee_policy = opts.jit_fee_policy();
+    let dlc_fee_rate_policy = opts.dlc_fee_rate_policy()?;
     let http_address = opts.http_address;
+    let admin_address = opts.admin_address;
     let network = opts.network();
 
     logger::init_tracing(LevelFilter::DEBUG, false)?;
@@ -68,14 +68,20 @@ async fn main() -> Result<()> {
             data_dir.as_path(),
             address,
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), address.port()),
-            opts.p2p_announcement_addresses(),
-            opts.electrum,
+            opts.p2p_announcement_addresses()?,
+            opts.chain_source()?,
             seed,
             ephemeral_randomness,
+            opts.socks5_proxy,
         )
         .await?,
     );
 
+    // Reconnect to the public nodes we have channels with. The apps connect to us themselves
+    if let Err(e) = node.keep_connected_to_channel_peers() {
+        tracing::error!("Failed to reconnect to channel peers: {e:#}");
+    }
+
     tokio::spawn({
         let node = node.clone();
         async move {
@@ -88,29 +94,13 @@ async fn main() -> Result<()> {
         }
     });
 
-    {
-        let dlc_manager = node.dlc_manager.clone();
-        let sub_channel_manager = node.sub_channel_manager.clone();
-        tokio::spawn({
-            let dlc_message_handler = node.dlc_message_handler.clone();
-            let peer_manager = node.peer_manager.clone();
-
-            async move {
-                loop {
-                    if let Err(e) = node::process_incoming_messages_internal(
-                        &dlc_message_handler,
-                        &dlc_manager,
-                        &sub_channel_manager,
-                        &peer_manager,
-                    ) {
-                        tracing::error!("Unable to process internal message: {e:#}");
-                    }
-
-                    tokio::time::sleep(PROCESS_INCOMING_MESSAGES_INTERVAL).await;
-                }
-            }
-        })
-    };
+    tokio::spawn({
+        let node = node.clone();
+        async move {
+            node.process_incoming_messages_continuously(|_, _, _| {})
+                .await
+        }
+    });
 
     // set up database connection pool
     let manager = ConnectionManager::<PgConnection>::new(opts.database);
@@ -121,13 +111,35 @@ async fn main() -> Result<()> {
     let mut conn = pool.get().unwrap();
     run_migration(&mut conn);
 
-    let app = router(
-        Node {
-            inner: node,
-            positions: Mutex::new(HashMap::new()),
-        },
-        pool,
-    );
+    let node = Node {
+        inner: node,
+        pool: pool.clone(),
+        reconciliation_report: Arc::new(Mutex::new(None)),
+        dlc_fee_rate_policy,
+    };
+
+    tokio::spawn({
+        let node = node.clone();
+        async move { node.handle_dlc_channel_events().await }
+    });
+
+    tokio::spawn({
+        let node = node.clone();
+        async move { node.reconcile_dlc_channels_periodically().await }
+    });
+
+    let admin_app = admin_router(node.clone());
+    tokio::spawn(async move {
+        tracing::debug!("Admin API listening on http://{}", admin_address);
+        if let Err(e) = axum::Server::bind(&admin_address)
+            .serve(admin_app.into_make_service())
+            .await
+        {
+            tracing::error!("Admin API stopped: {e:#}");
+        }
+    });
+
+    let app = router(node, pool, liquidity_offer, jit_fee_policy);
 
     tracing::debug!("listening on http://{}", http_address);
     axum::Server::bind(&http_address)
@@ -1,6 +1,15 @@
+use anyhow::ensure;
+use anyhow::Context;
 use anyhow::Result;
+use bdk::blockchain::rpc::Auth;
 use clap::Parser;
+use coordinator_commons::LiquidityOffer;
+use coordinator_commons::MAX_DLC_FEE_RATE_SATS_PER_VBYTE;
 use lightning::ln::msgs::NetAddress;
+use ln_dlc_node::node::ChainSource;
+use ln_dlc_node::node::DlcFeeRatePolicy;
+use ln_dlc_node::node::JitFeePolicy;
+use ln_dlc_node::node::PeerAddress;
 use local_ip_address::local_ip;
 use std::env::current_dir;
 use std::net::IpAddr;
@@ -17,10 +26,24 @@ pub struct Opts {
     #[clap(long, default_value = "0.0.0.0:8000")]
     pub http_address: SocketAddr,
 
+    /// The IP address to listen on for the admin HTTP API, which is not authenticated and must
+    /// only be reachable from trusted hosts.
+    #[clap(long, default_value = "127.0.0.1:8001")]
+    pub admin_address: SocketAddr,
+
     /// Where to permanently store data, defaults to the current working directory.
     #[clap(long)]
     data_dir: Option<PathBuf>,
 
+    /// A Tor onion service (`<address>.onion:<port>`) forwarding to the p2p address, which is
+    /// announced in addition to the p2p address.
+    #[clap(long)]
+    p2p_onion_address: Option<PeerAddress>,
+
+    /// The SOCKS5 proxy to connect to peers through, e.g. Tor at `127.0.0.1:9050`.
+    #[clap(long)]
+    pub socks5_proxy: Option<SocketAddr>,
+
     /// Will skip announcing the node on the local ip address. Set this flag for production.
     #[clap(long)]
     skip_local_network_announcement: bool,
@@ -37,7 +60,58 @@ pub struct Opts {
 
     /// The address to connect electrum to
     #[clap(long, default_value = "tcp://localhost:50000")]
-    pub electrum: String,
+    electrum: String,
+
+    /// The URL of an Esplora API to use instead of electrum.
+    #[clap(long, conflicts_with = "bitcoind_rpc_url")]
+    esplora: Option<String>,
+
+    /// The RPC address of a bitcoind (with `txindex=1`) to use instead of electrum.
+    #[clap(long)]
+    bitcoind_rpc_url: Option<String>,
+
+    /// The RPC username for bitcoind. Unless set, the cookie file is used.
+    #[clap(long, requires = "bitcoind_rpc_password")]
+    bitcoind_rpc_user: Option<String>,
+
+    /// The RPC password for bitcoind.
+    #[clap(long, requires = "bitcoind_rpc_user")]
+    bitcoind_rpc_password: Option<String>,
+
+    /// The RPC cookie file of bitcoind.
+    #[clap(long, conflicts_with = "bitcoind_rpc_user")]
+    bitcoind_rpc_cookie: Option<PathBuf>,
+
+    /// The price in millisats at which we sell one sat of inbound liquidity to the app.
+    #[clap(long, default_value = "10")]
+    pub liquidity_price_msat_per_sat: u64,
+
+    /// For how many days we keep inbound liquidity sold to the app in its channel.
+    #[clap(long, default_value = "30")]
+    pub liquidity_lease_duration_days: u64,
+
+    /// The base fee in millisats we charge for payments forwarded through a just-in-time channel.
+    #[clap(long, default_value = "1000")]
+    pub jit_fee_base_msat: u32,
+
+    /// The fee in millionths of the amount we charge for payments forwarded through a
+    /// just-in-time channel.
+    #[clap(long, default_value = "20000")]
+    pub jit_fee_proportional_millionths: u32,
+
+    /// Within how many blocks the transactions of the DLC channels we propose should be confirmed.
+    #[clap(long, default_value = "6")]
+    pub dlc_fee_confirmation_target_blocks: usize,
+
+    /// The lowest fee rate in sats/vbyte we use for the transactions of DLC channels.
+    #[clap(long, default_value = "1")]
+    pub dlc_min_fee_rate: u64,
+
+    /// The highest fee rate in sats/vbyte we use for the transactions of DLC channels.
+    ///
+    /// Cannot exceed the highest fee rate the app accepts.
+    #[clap(long, default_value_t = MAX_DLC_FEE_RATE_SATS_PER_VBYTE)]
+    pub dlc_max_fee_rate: u64,
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -69,6 +143,36 @@ impl Opts {
         self.network.into()
     }
 
+    pub fn chain_source(&self) -> Result<ChainSource> {
+        if let Some(url) = &self.bitcoind_rpc_url {
+            let auth = match (&self.bitcoind_rpc_user, &self.bitcoind_rpc_password) {
+                (Some(username), Some(password)) => Auth::UserPass {
+                    username: username.clone(),
+                    password: password.clone(),
+                },
+                _ => Auth::Cookie {
+                    file: self
+                        .bitcoind_rpc_cookie
+                        .clone()
+                        .context("Either bitcoind RPC credentials or a cookie file are required")?,
+                },
+            };
+
+            return Ok(ChainSource::Bitcoind {
+                url: url.clone(),
+                auth,
+            });
+        }
+
+        if let Some(url) = &self.esplora {
+            return Ok(ChainSource::Esplora { url: url.clone() });
+        }
+
+        Ok(ChainSource::Electrum {
+            url: self.electrum.clone(),
+        })
+    }
+
     pub fn data_dir(&self) -> Result<PathBuf> {
         let data_dir = match self.data_dir.clone() {
             None => current_dir()?.join("data"),
@@ -79,9 +183,45 @@ impl Opts {
         Ok(data_dir)
     }
 
+    pub fn liquidity_offer(&self) -> LiquidityOffer {
+        LiquidityOffer {
+            price_msat_per_sat: self.liquidity_price_msat_per_sat,
+            lease_duration_secs: self.liquidity_lease_duration_days * 24 * 60 * 60,
+            min_inbound_liquidity_sat: 10_000,
+            max_inbound_liquidity_sat: 1_000_000,
+        }
+    }
+
+    pub fn jit_fee_policy(&self) -> JitFeePolicy {
+        JitFeePolicy {
+            base_msat: self.jit_fee_base_msat,
+            proportional_millionths: self.jit_fee_proportional_millionths,
+        }
+    }
+
+    pub fn dlc_fee_rate_policy(&self) -> Result<DlcFeeRatePolicy> {
+        ensure!(
+            self.dlc_min_fee_rate <= self.dlc_max_fee_rate,
+            "Minimum DLC fee rate of {} sats/vbyte exceeds the maximum of {} sats/vbyte",
+            self.dlc_min_fee_rate,
+            self.dlc_max_fee_rate
+        );
+        ensure!(
+            self.dlc_max_fee_rate <= MAX_DLC_FEE_RATE_SATS_PER_VBYTE,
+            "Maximum DLC fee rate of {} sats/vbyte exceeds the maximum of {MAX_DLC_FEE_RATE_SATS_PER_VBYTE} sats/vbyte accepted by the app",
+            self.dlc_max_fee_rate
+        );
+
+        Ok(DlcFeeRatePolicy {
+            confirmation_target_blocks: self.dlc_fee_confirmation_target_blocks,
+            min_sats_per_vbyte: self.dlc_min_fee_rate,
+            max_sats_per_vbyte: self.dlc_max_fee_rate,
+        })
+    }
+
     /// Returns a list of addresses under which the node can be reached. Note this is used for the
     /// node announcements.
-    pub fn p2p_announcement_addresses(&self) -> Vec<NetAddress> {
+    pub fn p2p_announcement_addresses(&self) -> Result<Vec<NetAddress>> {
         let mut addresses: Vec<NetAddress> = vec![];
         if !self.p2p_address.ip().is_unspecified() {
             addresses.push(build_net_address(
@@ -99,7 +239,16 @@ impl Opts {
             addresses.push(build_net_address(local_ip, self.p2p_address.port()));
         }
 
-        addresses
+        if let Some(onion_address) = &self.p2p_onion_address {
+            ensure!(
+                matches!(onion_address, PeerAddress::OnionV3 { .. }),
+                "Not an onion address: {onion_address}"
+            );
+
+            addresses.extend(onion_address.to_net_address());
+        }
+
+        Ok(addresses)
     }
 }
 
@@ -1,10 +1,17 @@
+pub mod admin;
+pub mod backup;
 pub mod cli;
 pub mod logger;
 pub mod node;
 pub mod orderbook;
+pub mod position;
+pub mod reconciliation;
 pub mod routes;
 pub mod schema;
 
+#[cfg(test)]
+mod tests;
+
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
@@ -26,6 +33,8 @@ pub enum AppError {
     InternalServerError(String),
     BadRequest(String),
     NoMatchFound(String),
+    NotFound(String),
+    Unauthorized(String),
 }
 
 impl IntoResponse for AppError {
@@ -34,6 +43,8 @@ impl IntoResponse for AppError {
             AppError::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
             AppError::NoMatchFound(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
         };
 
         let body = Json(json!({
@@ -1,55 +1,38 @@
-use anyhow::anyhow;
+use crate::position;
+use crate::position::Position;
+use crate::reconciliation::ReconciliationReport;
+use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use bitcoin::secp256k1::PublicKey;
 use coordinator_commons::TradeParams;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
+use diesel::Connection;
+use diesel::PgConnection;
 use dlc_manager::contract::contract_input::ContractInput;
 use dlc_manager::contract::contract_input::ContractInputInfo;
 use dlc_manager::contract::contract_input::OracleInput;
-use dlc_manager::contract::numerical_descriptor::NumericalDescriptor;
-use dlc_manager::contract::ContractDescriptor;
-use dlc_manager::payout_curve::PayoutFunction;
-use dlc_manager::payout_curve::PayoutFunctionPiece;
-use dlc_manager::payout_curve::PayoutPoint;
-use dlc_manager::payout_curve::PolynomialPayoutCurvePiece;
-use dlc_manager::payout_curve::RoundingInterval;
-use dlc_manager::payout_curve::RoundingIntervals;
 use dlc_manager::ChannelId;
-use dlc_messages::message_handler::MessageHandler as DlcMessageHandler;
-use dlc_messages::Message;
 use lightning::ln::channelmanager::ChannelDetails;
-use ln_dlc_node::node::sub_channel_message_as_str;
-use ln_dlc_node::node::DlcManager;
-use ln_dlc_node::node::SubChannelManager;
-use ln_dlc_node::PeerManager;
+use ln_dlc_node::node::DlcChannelEvent;
+use ln_dlc_node::node::DlcChannelEventKind;
+use ln_dlc_node::node::DlcFeeRatePolicy;
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
-use trade::cfd;
-use trade::cfd::calculate_long_liquidation_price;
-use trade::cfd::calculate_margin;
-use trade::cfd::calculate_short_liquidation_price;
-use trade::cfd::BTCUSD_MAX_PRICE;
-use trade::ContractSymbol;
-use trade::Direction;
-
-/// The leverage used by the coordinator for all trades.
-const COORDINATOR_LEVERAGE: f64 = 1.0;
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
 
+#[derive(Clone)]
 pub struct Node {
     pub inner: Arc<ln_dlc_node::node::Node>,
-    pub positions: Mutex<HashMap<String, Position>>,
-}
-
-pub struct Position {
-    pub contract_symbol: ContractSymbol,
-    pub leverage: f64,
-    pub quantity: f64,
-    pub direction: Direction,
-    pub trader: PublicKey,
-    pub average_entry_price: f64,
+    /// The database holding the positions with the traders.
+    pub pool: Pool<ConnectionManager<PgConnection>>,
+    pub reconciliation_report: Arc<Mutex<Option<ReconciliationReport>>>,
+    pub dlc_fee_rate_policy: DlcFeeRatePolicy,
 }
 
 impl Node {
@@ -65,8 +48,10 @@ impl Node {
     async fn open_position(&self, trade_params: &TradeParams) -> Result<()> {
         tracing::info!("Opening position");
 
-        // todo: Revisit position model and store to database.
+        let channel_details = self.get_counterparty_channel(trade_params.pubkey)?;
+
         let position = Position {
+            channel_id: channel_details.channel_id,
             contract_symbol: trade_params.contract_symbol,
             leverage: trade_params.leverage,
             quantity: trade_params.quantity,
@@ -76,40 +61,22 @@ impl Node {
                 .average_execution_price()
                 .to_f64()
                 .expect("to fit into f64"),
+            contract_id: None,
         };
-        self.positions
-            .lock()
-            .expect("to get lock on positions")
-            .insert(trade_params.pubkey.to_string(), position);
-
-        let margin_trader = margin_trader(trade_params);
-        let margin_coordinator = margin_coordinator(trade_params);
 
-        let leverage_long = leverage_long(trade_params);
-        let leverage_short = leverage_short(trade_params);
-
-        let total_collateral = margin_coordinator + margin_trader;
-
-        let contract_descriptor = build_contract_descriptor(
-            total_collateral,
-            trade_params.average_execution_price(),
-            leverage_long,
-            leverage_short,
-        )
-        .context("Could not build contract descriptor")?;
-
-        let contract_symbol = trade_params.contract_symbol.label();
-        let maturity_time = trade_params.filled_with.expiry_timestamp;
-        let maturity_time = maturity_time.unix_timestamp();
+        let contract_descriptor = trade_params
+            .contract_descriptor()
+            .context("Could not build contract descriptor")?;
 
         // The contract input to be used for setting up the trade between the trader and the
         // coordinator
-        let event_id = format!("{contract_symbol}{maturity_time}");
-        tracing::debug!(event_id, "Proposing dlc channel");
+        let event_id = trade_params.event_id();
+        let fee_rate = self.inner.dlc_fee_rate(self.dlc_fee_rate_policy).await?;
+        tracing::debug!(event_id, fee_rate, "Proposing dlc channel");
         let contract_input = ContractInput {
-            offer_collateral: margin_coordinator,
-            accept_collateral: margin_trader,
-            fee_rate: 2,
+            offer_collateral: trade_params.margin_coordinator(),
+            accept_collateral: trade_params.margin_trader(),
+            fee_rate,
             contract_infos: vec![ContractInputInfo {
                 contract_descriptor,
                 oracles: OracleInput {
@@ -120,11 +87,20 @@ impl Node {
             }],
         };
 
-        let channel_details = self.get_counterparty_channel(trade_params.pubkey)?;
-        self.inner
-            .propose_dlc_channel(&channel_details, &contract_input)
-            .await
-            .context("Could not propose dlc channel")?;
+        // The position is recorded before the DLC channel is proposed, so that we cannot miss the
+        // DLC of the position. If proposing fails, the position is rolled back.
+        self.pool.get()?.transaction(|conn| {
+            position::insert(conn, &position)?;
+
+            tokio::task::block_in_place(|| {
+                Handle::current().block_on(
+                    self.inner
+                        .propose_dlc_channel(&channel_details, &contract_input),
+                )
+            })
+            .context("Could not propose dlc channel")
+        })?;
+
         Ok(())
     }
 
@@ -141,35 +117,18 @@ impl Node {
             "Closing position"
         );
 
-        let leverage_long = leverage_long(trade_params);
-        let leverage_short = leverage_short(trade_params);
-
-        let closing_price = trade_params.average_execution_price();
-
-        let opening_price = match self
-            .positions
-            .lock()
-            .expect("to get lock on positions")
-            .remove(&trade_params.pubkey.to_string())
-        {
+        let opening_price = match position::get(&mut self.pool.get()?, channel_id)? {
             Some(position) => Decimal::try_from(position.average_entry_price)?,
             None => {
-                tracing::warn!("Did not find position in memory, thus we do not have the opening price to calculate a correct accept settlement amount. Using the closing price.");
-                // FIXME: This is wrong as we cannot use the closing price to calculated the
-                // `accept_settlement_amount`. We must save the initial price when creating the
-                // position and use it here again for closing.
-                closing_price
+                tracing::warn!(
+                    channel_id = %hex::encode(channel_id),
+                    "Did not find position, thus we do not have the opening price to calculate a correct accept settlement amount. Using the closing price."
+                );
+                trade_params.average_execution_price()
             }
         };
 
-        let accept_settlement_amount = calculate_accept_settlement_amount(
-            opening_price,
-            closing_price,
-            trade_params.quantity,
-            leverage_long,
-            leverage_short,
-            trade_params.direction,
-        )?;
+        let accept_settlement_amount = trade_params.accept_settlement_amount(opening_price)?;
 
         tracing::debug!(
             "Settling position of {accept_settlement_amount} with {}",
@@ -182,6 +141,64 @@ impl Node {
         Ok(())
     }
 
+    /// Keeps track of the DLC of each position and forgets about the positions whose DLC channel
+    /// did not come about or was closed.
+    pub async fn handle_dlc_channel_events(&self) {
+        let mut events = self.inner.subscribe_to_dlc_channel_events();
+
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "Missed DLC channel events");
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            if let Err(e) = self.handle_dlc_channel_event(&event) {
+                tracing::error!(?event, "Failed to handle DLC channel event: {e:#}");
+            }
+        }
+    }
+
+    fn handle_dlc_channel_event(&self, event: &DlcChannelEvent) -> Result<()> {
+        let mut conn = self.pool.get()?;
+
+        match event.kind {
+            DlcChannelEventKind::Signed => {
+                let dlc = event.dlc.context("Signed DLC channel without DLC")?;
+                position::set_contract_id(&mut conn, event.channel_id, dlc.id)?;
+            }
+            // Only a rejected DLC channel offer means that the position did not come about. If
+            // closing the position is rejected, the position stays open.
+            DlcChannelEventKind::Rejected
+            | DlcChannelEventKind::Closed
+            | DlcChannelEventKind::ForceClosed => {
+                if position::delete(&mut conn, event.channel_id)? > 0 {
+                    tracing::info!(
+                        trader_pk = %event.counterparty,
+                        channel_id = %hex::encode(event.channel_id),
+                        kind = ?event.kind,
+                        "Removed position after DLC channel event"
+                    );
+                }
+            }
+            DlcChannelEventKind::CloseRejected => {
+                tracing::warn!(
+                    trader_pk = %event.counterparty,
+                    channel_id = %hex::encode(event.channel_id),
+                    "Closing position was rejected"
+                );
+            }
+            DlcChannelEventKind::OfferReceived
+            | DlcChannelEventKind::Accepted
+            | DlcChannelEventKind::CloseOffered => {}
+        }
+
+        Ok(())
+    }
+
     /// Decides what trade action should be performed according to the
     /// coordinator's current trading status with the trader.
     ///
@@ -191,38 +208,71 @@ impl Node {
     /// 1. If a position of equal quantity and opposite direction is
     /// found, we direct the caller to close the position.
     ///
-    /// 2. If no position is found, we direct the caller to open a
-    /// position.
+    /// 2. If a DLC channel without position is found, e.g. one that was
+    /// established before the coordinator recorded positions, we
+    /// direct the caller to close it.
     ///
-    /// 3. If a position of differing quantity is found, we direct the
-    /// caller to extend or reduce the position. _This is currently
-    /// not supported_.
+    /// 3. Otherwise, we direct the caller to open another position,
+    /// i.e. a trader can have several positions at once. _Extending or
+    /// reducing a position is currently not supported_.
     fn decide_trade_action(&self, trade_params: &TradeParams) -> Result<TradeAction> {
-        let action = match self.inner.get_dlc_channel_signed(&trade_params.pubkey)? {
-            Some(subchannel) => {
-                // FIXME: Should query the database for more
-                // information
+        let trader_positions = position::by_trader(&mut self.pool.get()?, trade_params.pubkey)?;
+
+        let position_to_close = trader_positions.iter().find(|position| {
+            position.contract_id.is_some()
+                && position.direction == trade_params.direction.opposite()
+                && position.quantity == trade_params.quantity
+        });
 
-                // TODO: Detect if the position should be
-                // extended/reduced. Return corresponding error as
-                // this is currently not supported.
+        if let Some(position) = position_to_close {
+            return Ok(TradeAction::Close(position.channel_id));
+        }
 
-                TradeAction::Close(subchannel.channel_id)
+        // A DLC channel without position was established before the coordinator recorded its
+        // positions, so we can only assume that the trader wants to close it.
+        if let Some(subchannel) = self.inner.get_dlc_channel_signed(&trade_params.pubkey)? {
+            let has_position = trader_positions
+                .iter()
+                .any(|position| position.channel_id == subchannel.channel_id);
+            if !has_position {
+                tracing::warn!(
+                    channel_id = %hex::encode(subchannel.channel_id),
+                    "Found DLC channel without position"
+                );
+                return Ok(TradeAction::Close(subchannel.channel_id));
             }
-            None => TradeAction::Open,
-        };
+        }
 
-        Ok(action)
+        // TODO: Detect if the position should be extended/reduced. Return corresponding error as
+        // this is currently not supported.
+
+        Ok(TradeAction::Open)
     }
 
+    /// Finds an LN channel with the trader which can hold the DLC of a new position.
+    ///
+    /// Each LN channel holds at most one DLC, so a trader who has a position in each of their
+    /// channels cannot open another position.
     fn get_counterparty_channel(&self, trader_pubkey: PublicKey) -> Result<ChannelDetails> {
-        let channel_details = self.inner.list_usable_channels();
-        let channel_details = channel_details
+        let channel_details = self
+            .inner
+            .list_usable_channels_without_dlc(&trader_pubkey)?
             .into_iter()
-            .find(|c| c.counterparty.node_id == trader_pubkey)
-            .context("Channel details not found")
-            .map_err(|e| anyhow!("{e:#}"))?;
-        Ok(channel_details)
+            .next();
+
+        match channel_details {
+            Some(channel_details) => Ok(channel_details),
+            None => {
+                let has_usable_channel = self
+                    .inner
+                    .list_usable_channels()
+                    .iter()
+                    .any(|channel| channel.counterparty.node_id == trader_pubkey);
+                ensure!(has_usable_channel, "No usable channel with trader found");
+
+                bail!("Trader already has a position in each of their channels, another position requires another channel")
+            }
+        }
     }
 }
 
@@ -232,321 +282,3 @@ enum TradeAction {
     // Extend,
     // Reduce,
 }
-
-/// Calculates the accept settlement amount based on the pnl.
-fn calculate_accept_settlement_amount(
-    opening_price: Decimal,
-    closing_price: Decimal,
-    quantity: f64,
-    long_leverage: f64,
-    short_leverage: f64,
-    direction: Direction,
-) -> Result<u64> {
-    let pnl = cfd::calculate_pnl(
-        opening_price,
-        closing_price,
-        quantity,
-        long_leverage,
-        short_leverage,
-        direction,
-    )?;
-
-    let leverage = match direction {
-        Direction::Long => long_leverage,
-        Direction::Short => short_leverage,
-    };
-
-    let margin_trader = calculate_margin(opening_price, quantity, leverage);
-
-    let accept_settlement_amount = Decimal::from(margin_trader) + Decimal::from(pnl);
-    // the amount can only be positive, adding a safeguard here with the max comparison to
-    // ensure the i64 fits into u64
-    let accept_settlement_amount = accept_settlement_amount
-        .max(Decimal::ZERO)
-        .to_u64()
-        .expect("to fit into u64");
-    Ok(accept_settlement_amount)
-}
-
-fn margin_trader(trade_params: &TradeParams) -> u64 {
-    calculate_margin(
-        trade_params.average_execution_price(),
-        trade_params.quantity,
-        trade_params.leverage,
-    )
-}
-
-fn margin_coordinator(trade_params: &TradeParams) -> u64 {
-    calculate_margin(
-        trade_params.average_execution_price(),
-        trade_params.quantity,
-        COORDINATOR_LEVERAGE,
-    )
-}
-
-fn leverage_long(trade_params: &TradeParams) -> f64 {
-    match trade_params.direction {
-        Direction::Long => trade_params.leverage,
-        Direction::Short => COORDINATOR_LEVERAGE,
-    }
-}
-
-fn leverage_short(trade_params: &TradeParams) -> f64 {
-    match trade_params.direction {
-        Direction::Long => COORDINATOR_LEVERAGE,
-        Direction::Short => trade_params.leverage,
-    }
-}
-
-fn get_rounding_intervals() -> RoundingIntervals {
-    RoundingIntervals {
-        intervals: vec![RoundingInterval {
-            begin_interval: 0,
-            rounding_mod: 500,
-        }],
-    }
-}
-
-/// Builds the contract descriptor from the point of view of the trader.
-fn build_contract_descriptor(
-    total_collateral: u64,
-    initial_price: Decimal,
-    leverage_long: f64,
-    leverage_short: f64,
-) -> Result<ContractDescriptor> {
-    Ok(ContractDescriptor::Numerical(NumericalDescriptor {
-        payout_function: build_payout_function(
-            total_collateral,
-            initial_price,
-            leverage_long,
-            leverage_short,
-        )?,
-        rounding_intervals: get_rounding_intervals(),
-        difference_params: None,
-        oracle_numeric_infos: dlc_trie::OracleNumericInfo {
-            base: 2,
-            nb_digits: vec![20],
-        },
-    }))
-}
-
-/// Builds a [`PayoutFunction`].
-///
-/// TODO: We are currently building a linear payout function for
-/// simplicity. This is *wrong*. We should build an inverse payout
-/// function like we used to do in ItchySats.
-fn build_payout_function(
-    total_collateral: u64,
-    initial_price: Decimal,
-    leverage_long: f64,
-    leverage_short: f64,
-) -> Result<PayoutFunction> {
-    let leverage_short = Decimal::try_from(leverage_short)?;
-    let liquidation_price_short = calculate_short_liquidation_price(leverage_short, initial_price);
-
-    let leverage_long = Decimal::try_from(leverage_long)?;
-    let liquidation_price_long = calculate_long_liquidation_price(leverage_long, initial_price);
-
-    let lower_limit = liquidation_price_long
-        .floor()
-        .to_u64()
-        .expect("Failed to fit floored liquidation price to u64");
-    let upper_limit = liquidation_price_short
-        .floor()
-        .to_u64()
-        .expect("Failed to fit floored liquidation price to u64");
-
-    let lower_range = PolynomialPayoutCurvePiece::new(vec![
-        PayoutPoint {
-            event_outcome: 0,
-            outcome_payout: 0,
-            extra_precision: 0,
-        },
-        PayoutPoint {
-            event_outcome: lower_limit,
-            outcome_payout: 0,
-            extra_precision: 0,
-        },
-    ])
-    .map_err(|e| anyhow!("{e:#}"))?;
-
-    let middle_range = PolynomialPayoutCurvePiece::new(vec![
-        PayoutPoint {
-            event_outcome: lower_limit,
-            outcome_payout: 0,
-            extra_precision: 0,
-        },
-        PayoutPoint {
-            event_outcome: upper_limit,
-            outcome_payout: total_collateral,
-            extra_precision: 0,
-        },
-    ])
-    .map_err(|e| anyhow!("{e:#}"))?;
-
-    let mut pieces = vec![
-        PayoutFunctionPiece::PolynomialPayoutCurvePiece(lower_range),
-        PayoutFunctionPiece::PolynomialPayoutCurvePiece(middle_range),
-    ];
-
-    // When the upper limit is greater than or equal to the
-    // `BTCUSD_MAX_PRICE`, we don't have to add another curve piece.
-    if upper_limit < BTCUSD_MAX_PRICE {
-        let upper_range = PolynomialPayoutCurvePiece::new(vec![
-            PayoutPoint {
-                event_outcome: upper_limit,
-                outcome_payout: total_collateral,
-                extra_precision: 0,
-            },
-            PayoutPoint {
-                event_outcome: BTCUSD_MAX_PRICE,
-                outcome_payout: total_collateral,
-                extra_precision: 0,
-            },
-        ])
-        .map_err(|e| anyhow!("{e:#}"))?;
-
-        pieces.push(PayoutFunctionPiece::PolynomialPayoutCurvePiece(upper_range));
-    }
-
-    PayoutFunction::new(pieces).map_err(|e| anyhow!("{e:#}"))
-}
-
-pub fn process_incoming_messages_internal(
-    dlc_message_handler: &DlcMessageHandler,
-    dlc_manager: &DlcManager,
-    sub_channel_manager: &SubChannelManager,
-    peer_manager: &PeerManager,
-) -> Result<()> {
-    let messages = dlc_message_handler.get_and_clear_received_messages();
-
-    for (node_id, msg) in messages {
-        match msg {
-            Message::OnChain(_) | Message::Channel(_) => {
-                tracing::debug!(from = %node_id, "Processing DLC-manager message");
-                let resp = dlc_manager
-                    .on_dlc_message(&msg, node_id)
-                    .map_err(|e| anyhow!(e.to_string()))?;
-
-                if let Some(msg) = resp {
-                    tracing::debug!(to = %node_id, "Sending DLC-manager message");
-                    dlc_message_handler.send_message(node_id, msg);
-                }
-            }
-            Message::SubChannel(msg) => {
-                tracing::debug!(
-                    from = %node_id,
-                    msg = %sub_channel_message_as_str(&msg),
-                    "Processing DLC channel message"
-                );
-                let resp = sub_channel_manager
-                    .on_sub_channel_message(&msg, &node_id)
-                    .map_err(|e| anyhow!(e.to_string()))?;
-
-                if let Some(msg) = resp {
-                    tracing::debug!(
-                        to = %node_id,
-                        msg = %sub_channel_message_as_str(&msg),
-                        "Sending DLC channel message"
-                    );
-                    dlc_message_handler.send_message(node_id, Message::SubChannel(msg));
-                }
-            }
-        }
-    }
-
-    // NOTE: According to the docs of `process_events` we shouldn't have to call this since we
-    // use `lightning-net-tokio`. But we copied this from `p2pderivatives/ldk-sample`
-    if dlc_message_handler.has_pending_messages() {
-        peer_manager.process_events();
-    }
-
-    Ok(())
-}
-
-#[cfg(test)]
-pub mod tests {
-    use crate::node::calculate_accept_settlement_amount;
-    use rust_decimal::Decimal;
-    use trade::cfd::calculate_margin;
-    use trade::Direction;
-
-    // some basic sanity tests, that in case the position goes the right or wrong way the settlement
-    // amount is moving correspondingly up or down.
-
-    #[test]
-    fn given_a_long_position_and_a_larger_closing_price() {
-        let opening_price = Decimal::from(22000);
-        let closing_price = Decimal::from(23000);
-        let quantity: f64 = 1.0;
-        let accept_settlement_amount = calculate_accept_settlement_amount(
-            opening_price,
-            closing_price,
-            quantity,
-            1.0,
-            1.0,
-            Direction::Long,
-        )
-        .unwrap();
-
-        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
-        assert!(accept_settlement_amount > margin_trader);
-    }
-
-    #[test]
-    fn given_a_short_position_and_a_larger_closing_price() {
-        let opening_price = Decimal::from(22000);
-        let closing_price = Decimal::from(23000);
-        let quantity: f64 = 1.0;
-        let accept_settlement_amount = calculate_accept_settlement_amount(
-            opening_price,
-            closing_price,
-            quantity,
-            1.0,
-            1.0,
-            Direction::Short,
-        )
-        .unwrap();
-
-        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
-        assert!(accept_settlement_amount < margin_trader);
-    }
-
-    #[test]
-    fn given_a_long_position_and_a_smaller_closing_price() {
-        let opening_price = Decimal::from(23000);
-        let closing_price = Decimal::from(22000);
-        let quantity: f64 = 1.0;
-        let accept_settlement_amount = calculate_accept_settlement_amount(
-            opening_price,
-            closing_price,
-            quantity,
-            1.0,
-            1.0,
-            Direction::Long,
-        )
-        .unwrap();
-
-        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
-        assert!(accept_settlement_amount < margin_trader);
-    }
-
-    #[test]
-    fn given_a_short_position_and_a_smaller_closing_price() {
-        let opening_price = Decimal::from(23000);
-        let closing_price = Decimal::from(22000);
-        let quantity: f64 = 1.0;
-        let accept_settlement_amount = calculate_accept_settlement_amount(
-            opening_price,
-            closing_price,
-            quantity,
-            1.0,
-            1.0,
-            Direction::Short,
-        )
-        .unwrap();
-
-        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
-        assert!(accept_settlement_amount > margin_trader);
-    }
-}
@@ -2,5 +2,7 @@ mod db;
 pub mod routes;
 pub mod trading;
 
+pub(crate) use db::custom_types::Direction;
+
 #[cfg(test)]
 mod tests;
@@ -1,6 +1,6 @@
 use crate::orderbook::db::orders;
-use crate::orderbook::tests::setup_db;
-use crate::orderbook::tests::start_postgres;
+use crate::tests::setup_db;
+use crate::tests::start_postgres;
 use bitcoin::secp256k1::PublicKey;
 use orderbook_commons::OrderType;
 use rust_decimal_macros::dec;
@@ -0,0 +1,226 @@
+use crate::orderbook::Direction;
+use crate::schema::positions;
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use diesel::prelude::*;
+use diesel::result::QueryResult;
+use diesel::PgConnection;
+use dlc_manager::ChannelId;
+use dlc_manager::ContractId;
+use time::OffsetDateTime;
+use trade::ContractSymbol;
+
+/// A position of a trader, whose DLC lives in the DLC channel of the LN channel `channel_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    pub channel_id: ChannelId,
+    pub contract_symbol: ContractSymbol,
+    pub leverage: f64,
+    pub quantity: f64,
+    pub direction: trade::Direction,
+    pub trader: PublicKey,
+    pub average_entry_price: f64,
+    /// The ID of the DLC of this position, once it is established.
+    pub contract_id: Option<ContractId>,
+}
+
+#[derive(Queryable, Debug, Clone)]
+struct PositionRow {
+    pub channel_id: String,
+    pub contract_id: Option<String>,
+    pub trader_pubkey: String,
+    pub contract_symbol: String,
+    pub leverage: f64,
+    pub quantity: f64,
+    pub direction: Direction,
+    pub average_entry_price: f64,
+    // Only needed so that diesel can load the whole row
+    #[allow(dead_code)]
+    pub creation_timestamp: OffsetDateTime,
+}
+
+impl TryFrom<PositionRow> for Position {
+    type Error = anyhow::Error;
+
+    fn try_from(value: PositionRow) -> Result<Self> {
+        Ok(Position {
+            channel_id: decode_id(&value.channel_id)?,
+            contract_symbol: value.contract_symbol.parse()?,
+            leverage: value.leverage,
+            quantity: value.quantity,
+            direction: value.direction.into(),
+            trader: value
+                .trader_pubkey
+                .parse()
+                .context("Invalid trader pubkey")?,
+            average_entry_price: value.average_entry_price,
+            contract_id: value.contract_id.as_deref().map(decode_id).transpose()?,
+        })
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = positions)]
+struct NewPosition {
+    pub channel_id: String,
+    pub contract_id: Option<String>,
+    pub trader_pubkey: String,
+    pub contract_symbol: String,
+    pub leverage: f64,
+    pub quantity: f64,
+    pub direction: Direction,
+    pub average_entry_price: f64,
+}
+
+impl From<&Position> for NewPosition {
+    fn from(value: &Position) -> Self {
+        NewPosition {
+            channel_id: hex::encode(value.channel_id),
+            contract_id: value.contract_id.map(hex::encode),
+            trader_pubkey: value.trader.to_string(),
+            contract_symbol: value.contract_symbol.label(),
+            leverage: value.leverage,
+            quantity: value.quantity,
+            direction: value.direction.into(),
+            average_entry_price: value.average_entry_price,
+        }
+    }
+}
+
+pub fn insert(conn: &mut PgConnection, position: &Position) -> QueryResult<()> {
+    diesel::insert_into(positions::table)
+        .values(NewPosition::from(position))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Records the ID of the DLC of the position in the DLC channel of `channel_id`.
+///
+/// Fails if there is no such position.
+pub fn set_contract_id(
+    conn: &mut PgConnection,
+    channel_id: ChannelId,
+    contract_id: ContractId,
+) -> Result<()> {
+    let affected_rows = diesel::update(positions::table)
+        .filter(positions::channel_id.eq(hex::encode(channel_id)))
+        .set(positions::contract_id.eq(hex::encode(contract_id)))
+        .execute(conn)?;
+
+    ensure!(
+        affected_rows == 1,
+        "No position in channel {}",
+        hex::encode(channel_id)
+    );
+
+    Ok(())
+}
+
+/// Returns the position in the DLC channel of `channel_id`, if any.
+pub fn get(conn: &mut PgConnection, channel_id: ChannelId) -> Result<Option<Position>> {
+    let position = positions::table
+        .filter(positions::channel_id.eq(hex::encode(channel_id)))
+        .first::<PositionRow>(conn)
+        .optional()?;
+
+    position.map(Position::try_from).transpose()
+}
+
+pub fn all(conn: &mut PgConnection) -> Result<Vec<Position>> {
+    let positions = positions::table.load::<PositionRow>(conn)?;
+
+    positions.into_iter().map(Position::try_from).collect()
+}
+
+pub fn by_trader(conn: &mut PgConnection, trader: PublicKey) -> Result<Vec<Position>> {
+    let positions = positions::table
+        .filter(positions::trader_pubkey.eq(trader.to_string()))
+        .load::<PositionRow>(conn)?;
+
+    positions.into_iter().map(Position::try_from).collect()
+}
+
+/// Returns the number of affected rows: 0 if there is no such position, 1 otherwise.
+pub fn delete(conn: &mut PgConnection, channel_id: ChannelId) -> QueryResult<usize> {
+    diesel::delete(positions::table)
+        .filter(positions::channel_id.eq(hex::encode(channel_id)))
+        .execute(conn)
+}
+
+fn decode_id(id: &str) -> Result<[u8; 32]> {
+    hex::decode(id)
+        .context("Invalid hex encoded id")?
+        .try_into()
+        .map_err(|_| anyhow!("Id {id} is not 32 bytes long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::position;
+    use crate::position::Position;
+    use crate::tests::setup_db;
+    use crate::tests::start_postgres;
+    use bitcoin::secp256k1::PublicKey;
+    use std::str::FromStr;
+    use testcontainers::clients::Cli;
+    use trade::ContractSymbol;
+    use trade::Direction;
+
+    #[tokio::test]
+    async fn position_crud_test() {
+        let docker = Cli::default();
+        let (_container, conn_spec) = start_postgres(&docker).unwrap();
+
+        let mut conn = setup_db(conn_spec);
+
+        let trader = PublicKey::from_str(
+            "027f31ebc5462c1fdce1b737ecff52d37d75dea43ce11c74d25aa297165faa2007",
+        )
+        .unwrap();
+        let position = Position {
+            channel_id: [1; 32],
+            contract_symbol: ContractSymbol::BtcUsd,
+            leverage: 2.0,
+            quantity: 100.0,
+            direction: Direction::Long,
+            trader,
+            average_entry_price: 20_000.5,
+            contract_id: None,
+        };
+
+        position::insert(&mut conn, &position).unwrap();
+        position::insert(
+            &mut conn,
+            &Position {
+                channel_id: [2; 32],
+                ..position.clone()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            position::get(&mut conn, [1; 32]).unwrap(),
+            Some(position.clone())
+        );
+        assert_eq!(position::by_trader(&mut conn, trader).unwrap().len(), 2);
+
+        position::set_contract_id(&mut conn, [1; 32], [3; 32]).unwrap();
+        assert!(position::set_contract_id(&mut conn, [4; 32], [3; 32]).is_err());
+        assert_eq!(
+            position::get(&mut conn, [1; 32])
+                .unwrap()
+                .unwrap()
+                .contract_id,
+            Some([3; 32])
+        );
+
+        let deleted = position::delete(&mut conn, [1; 32]).unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(position::get(&mut conn, [1; 32]).unwrap(), None);
+        assert_eq!(position::all(&mut conn).unwrap().len(), 1);
+    }
+}
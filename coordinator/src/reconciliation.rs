@@ -0,0 +1,263 @@
+use crate::node::Node;
+use crate::position;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::ChannelId;
+use ln_dlc_node::DlcChannelDetails;
+use ln_dlc_node::SubChannelState;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// How often we check that every DLC channel belongs to a position and vice versa.
+pub const RECONCILIATION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The result of mapping every DLC channel to its position.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReconciliationReport {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub dlc_channels: Vec<ReconciledDlcChannel>,
+    pub orphans: Vec<Orphan>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReconciledDlcChannel {
+    #[serde(flatten)]
+    pub details: DlcChannelDetails,
+    /// Whether we have a position whose DLC lives in this DLC channel.
+    pub has_position: bool,
+}
+
+/// An inconsistency between the DLC channels and the positions, e.g. caused by a stuck trade.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Orphan {
+    /// The DLC channel is signed, but we have no position for it.
+    DlcChannelWithoutPosition {
+        channel_id: String,
+        trader_pk: String,
+    },
+    /// We have a position, but its DLC channel is not being set up, signed or closed.
+    PositionWithoutDlcChannel {
+        channel_id: String,
+        trader_pk: String,
+    },
+}
+
+impl Node {
+    /// Reconciles the DLC channels with the positions and keeps the resulting report around for
+    /// [`Node::last_reconciliation_report`].
+    pub fn reconcile_dlc_channels(&self) -> Result<ReconciliationReport> {
+        let dlc_channels = self
+            .inner
+            .list_dlc_channels()?
+            .into_iter()
+            .map(DlcChannelDetails::from)
+            .collect();
+
+        let positions = position::all(&mut self.pool.get()?)?
+            .into_iter()
+            .map(|position| (position.channel_id, position.trader))
+            .collect();
+
+        let report = reconcile(dlc_channels, &positions, OffsetDateTime::now_utc());
+
+        for orphan in report.orphans.iter() {
+            tracing::warn!(?orphan, "DLC channel and position are out of sync");
+        }
+
+        *self
+            .reconciliation_report
+            .lock()
+            .expect("to get lock on reconciliation report") = Some(report.clone());
+
+        Ok(report)
+    }
+
+    pub fn last_reconciliation_report(&self) -> Option<ReconciliationReport> {
+        self.reconciliation_report
+            .lock()
+            .expect("to get lock on reconciliation report")
+            .clone()
+    }
+
+    pub async fn reconcile_dlc_channels_periodically(&self) {
+        loop {
+            if let Err(e) = self.reconcile_dlc_channels() {
+                tracing::error!("Failed to reconcile DLC channels with positions: {e:#}");
+            }
+
+            tokio::time::sleep(RECONCILIATION_INTERVAL).await;
+        }
+    }
+}
+
+/// Maps the `dlc_channels` to the `positions`, which are given as the trader of the position keyed
+/// by the channel ID of its DLC channel.
+fn reconcile(
+    dlc_channels: Vec<DlcChannelDetails>,
+    positions: &HashMap<ChannelId, PublicKey>,
+    timestamp: OffsetDateTime,
+) -> ReconciliationReport {
+    let mut orphans = Vec::new();
+
+    for dlc_channel in dlc_channels.iter() {
+        if dlc_channel.state == SubChannelState::Signed
+            && !positions.contains_key(&dlc_channel.channel_id)
+        {
+            orphans.push(Orphan::DlcChannelWithoutPosition {
+                channel_id: hex::encode(dlc_channel.channel_id),
+                trader_pk: dlc_channel.counter_party.to_string(),
+            });
+        }
+    }
+
+    for (channel_id, trader) in positions {
+        let has_dlc_channel = dlc_channels.iter().any(|dlc_channel| {
+            dlc_channel.channel_id == *channel_id && is_in_use(dlc_channel.state)
+        });
+
+        if !has_dlc_channel {
+            orphans.push(Orphan::PositionWithoutDlcChannel {
+                channel_id: hex::encode(channel_id),
+                trader_pk: trader.to_string(),
+            });
+        }
+    }
+
+    let dlc_channels = dlc_channels
+        .into_iter()
+        .map(|details| ReconciledDlcChannel {
+            has_position: positions.contains_key(&details.channel_id),
+            details,
+        })
+        .collect();
+
+    ReconciliationReport {
+        timestamp,
+        dlc_channels,
+        orphans,
+    }
+}
+
+/// Whether the DLC channel is being set up, is signed or is being collaboratively closed.
+fn is_in_use(state: SubChannelState) -> bool {
+    matches!(
+        state,
+        SubChannelState::Offered
+            | SubChannelState::Accepted
+            | SubChannelState::Confirmed
+            | SubChannelState::Signed
+            | SubChannelState::CloseOffered
+            | SubChannelState::CloseAccepted
+            | SubChannelState::CloseConfirmed
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::secp256k1::SecretKey;
+
+    fn trader(byte: u8) -> PublicKey {
+        let secp = Secp256k1::new();
+        PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn dlc_channel(
+        channel_id: ChannelId,
+        trader: PublicKey,
+        state: SubChannelState,
+    ) -> DlcChannelDetails {
+        DlcChannelDetails {
+            channel_id,
+            counter_party: trader,
+            update_idx: 0,
+            state,
+            fee_rate_per_vb: 2,
+            fund_value_satoshis: 100_000,
+            is_offer: true,
+        }
+    }
+
+    #[test]
+    fn signed_dlc_channel_with_position_is_not_an_orphan() {
+        let trader = trader(1);
+
+        let report = reconcile(
+            vec![dlc_channel([1; 32], trader, SubChannelState::Signed)],
+            &HashMap::from([([1; 32], trader)]),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+
+        assert!(report.orphans.is_empty());
+        assert!(report.dlc_channels[0].has_position);
+    }
+
+    #[test]
+    fn signed_dlc_channel_without_position_is_an_orphan() {
+        let trader = trader(1);
+
+        let report = reconcile(
+            vec![
+                dlc_channel([1; 32], trader, SubChannelState::Signed),
+                dlc_channel([2; 32], trader, SubChannelState::Signed),
+            ],
+            &HashMap::from([([1; 32], trader)]),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+
+        assert_eq!(
+            report.orphans,
+            vec![Orphan::DlcChannelWithoutPosition {
+                channel_id: hex::encode([2; 32]),
+                trader_pk: trader.to_string(),
+            }]
+        );
+        assert!(report.dlc_channels[0].has_position);
+        assert!(!report.dlc_channels[1].has_position);
+    }
+
+    #[test]
+    fn position_without_dlc_channel_in_use_is_an_orphan() {
+        let trader = trader(1);
+
+        let report = reconcile(
+            vec![dlc_channel(
+                [1; 32],
+                trader,
+                SubChannelState::OffChainClosed,
+            )],
+            &HashMap::from([([1; 32], trader)]),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+
+        assert_eq!(
+            report.orphans,
+            vec![Orphan::PositionWithoutDlcChannel {
+                channel_id: hex::encode([1; 32]),
+                trader_pk: trader.to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn dlc_channels_being_set_up_or_closed_are_not_orphans() {
+        let trader_1 = trader(1);
+        let trader_2 = trader(2);
+
+        let report = reconcile(
+            vec![
+                dlc_channel([1; 32], trader_1, SubChannelState::Offered),
+                dlc_channel([2; 32], trader_2, SubChannelState::CloseOffered),
+            ],
+            &HashMap::from([([1; 32], trader_1)]),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+
+        assert!(report.orphans.is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+use crate::admin;
+use crate::backup;
+use crate::backup::MAX_BACKUP_SIZE;
 use crate::node::Node;
 use crate::orderbook::routes::delete_order;
 use crate::orderbook::routes::get_order;
@@ -5,20 +8,32 @@ use crate::orderbook::routes::get_orders;
 use crate::orderbook::routes::post_order;
 use crate::orderbook::routes::put_order;
 use crate::orderbook::routes::websocket_handler;
+use crate::reconciliation::ReconciliationReport;
 use crate::AppError;
 use axum::extract::Path;
 use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::routing::get;
 use axum::routing::post;
 use axum::Json;
 use axum::Router;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::BlockHash;
+use coordinator_commons::Backup;
+use coordinator_commons::LiquidityOffer;
+use coordinator_commons::LiquidityPurchase;
+use coordinator_commons::LiquidityPurchaseParams;
 use coordinator_commons::TradeParams;
+use coordinator_commons::UpgradeChannelParams;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use diesel::PgConnection;
+use ln_dlc_node::node::InterceptScid;
+use ln_dlc_node::node::JitFeePolicy;
+use ln_dlc_node::node::LeaseStatus;
 use ln_dlc_node::node::NodeInfo;
+use ln_dlc_node::node::SyncStatus;
 use ln_dlc_node::ChannelDetails;
 use ln_dlc_node::DlcChannelDetails;
 use orderbook_commons::OrderbookMsg;
@@ -26,29 +41,44 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 
+/// The biggest channel the coordinator is willing to open as a replacement for an app channel.
+const MAX_UPGRADED_CHANNEL_CAPACITY_SAT: u64 = 1_000_000;
+
 pub struct AppState {
     pub node: Node,
     // Channel used to send messages to all connected clients.
     pub tx_pricefeed: broadcast::Sender<OrderbookMsg>,
     pub pool: Pool<ConnectionManager<PgConnection>>,
     pub authenticated_users: Arc<Mutex<HashMap<PublicKey, mpsc::Sender<OrderbookMsg>>>>,
+    pub liquidity_offer: LiquidityOffer,
+    pub jit_fee_policy: JitFeePolicy,
 }
 
-pub fn router(node: Node, pool: Pool<ConnectionManager<PgConnection>>) -> Router {
+pub fn router(
+    node: Node,
+    pool: Pool<ConnectionManager<PgConnection>>,
+    liquidity_offer: LiquidityOffer,
+    jit_fee_policy: JitFeePolicy,
+) -> Router {
     let (tx, _rx) = broadcast::channel(100);
     let app_state = Arc::new(AppState {
         node,
         pool,
         tx_pricefeed: tx,
         authenticated_users: Default::default(),
+        liquidity_offer,
+        jit_fee_policy,
     });
 
     Router::new()
         .route("/", get(index))
+        .route("/api/health", get(get_health))
         .route("/api/fake_scid/:target_node", post(post_fake_scid))
         .route("/api/newaddress", get(get_new_address))
         .route("/api/node", get(get_node_info))
@@ -62,10 +92,27 @@ pub fn router(node: Node, pool: Pool<ConnectionManager<PgConnection>>) -> Router
         .route("/api/orderbook/websocket", get(websocket_handler))
         .route("/api/trade", post(post_trade))
         .route("/api/channels", get(list_channels))
+        .route("/api/channels/upgrade", post(post_upgrade_channel))
         .route("/api/dlc_channels", get(list_dlc_channels))
+        .route("/api/dlc_channels/fee_rate", get(get_dlc_fee_rate))
+        .route("/api/sweeps", get(list_pending_sweeps))
+        .route("/api/liquidity/offer", get(get_liquidity_offer))
+        .route("/api/liquidity/purchase", post(post_liquidity_purchase))
+        .route("/api/liquidity/leases", get(list_liquidity_leases))
+        .route("/api/backup/:node_id", get(get_backup).put(put_backup))
         .with_state(app_state)
 }
 
+/// The routes to manage the coordinator, which must only be served on a private interface.
+pub fn admin_router(node: Node) -> Router {
+    let wallet = admin::wallet_router(node.inner.clone());
+
+    Router::new()
+        .route("/api/admin/reconciliation", get(get_reconciliation_report))
+        .with_state(node)
+        .nest("/api/admin/wallet", wallet)
+}
+
 #[derive(serde::Serialize)]
 struct HelloWorld {
     hello: String,
@@ -80,7 +127,7 @@ pub async fn index() -> impl IntoResponse {
 pub async fn post_fake_scid(
     target_node: Path<String>,
     State(app_state): State<Arc<AppState>>,
-) -> Result<Json<u64>, AppError> {
+) -> Result<Json<InterceptScid>, AppError> {
     let target_node = target_node.0;
     let target_node: PublicKey = target_node.parse().map_err(|e| {
         AppError::BadRequest(format!(
@@ -88,9 +135,61 @@ pub async fn post_fake_scid(
         ))
     })?;
 
-    Ok(Json(
-        app_state.node.inner.create_intercept_scid(target_node),
-    ))
+    let intercept_scid = app_state
+        .node
+        .inner
+        .create_intercept_scid(target_node, app_state.jit_fee_policy)
+        .map_err(|e| {
+            AppError::InternalServerError(format!("Failed to create intercept scid: {e:#}"))
+        })?;
+
+    Ok(Json(intercept_scid))
+}
+
+/// The sync status of the node, without the error of the last failed sync, which may reveal
+/// details of our infrastructure (e.g. the URL of the chain source).
+#[derive(Serialize)]
+pub struct Health {
+    in_sync: bool,
+    synced_height: Option<u32>,
+    synced_block_hash: Option<BlockHash>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_successful_sync: Option<OffsetDateTime>,
+    chain_source_reachable: bool,
+    tip_height: Option<u32>,
+    blocks_behind: Option<u32>,
+    consecutive_failures: u32,
+}
+
+impl From<SyncStatus> for Health {
+    fn from(sync_status: SyncStatus) -> Self {
+        Self {
+            in_sync: sync_status.is_in_sync(),
+            synced_height: sync_status.synced_height,
+            synced_block_hash: sync_status.synced_block_hash,
+            last_successful_sync: sync_status.last_successful_sync,
+            chain_source_reachable: sync_status.chain_source_reachable,
+            tip_height: sync_status.tip_height,
+            blocks_behind: sync_status.blocks_behind,
+            consecutive_failures: sync_status.consecutive_failures,
+        }
+    }
+}
+
+/// Reports whether the node is in sync with the blockchain.
+///
+/// Responds with `503 Service Unavailable` while the node is out of sync, so that the endpoint can
+/// be used for health checks.
+pub async fn get_health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let health = Health::from(state.node.inner.sync_status());
+
+    let status_code = if health.in_sync {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status_code, Json(health))
 }
 
 pub async fn get_new_address(
@@ -106,7 +205,7 @@ pub async fn get_new_address(
 pub async fn get_node_info(
     State(app_state): State<Arc<AppState>>,
 ) -> Result<Json<NodeInfo>, AppError> {
-    let node_info = app_state.node.inner.info;
+    let node_info = app_state.node.inner.info.clone();
     Ok(Json(node_info))
 }
 
@@ -163,6 +262,48 @@ pub async fn list_channels(State(state): State<Arc<AppState>>) -> Json<Vec<Chann
     Json(channels)
 }
 
+pub async fn post_upgrade_channel(
+    State(state): State<Arc<AppState>>,
+    params: Json<UpgradeChannelParams>,
+) -> Result<Json<String>, AppError> {
+    params
+        .verify()
+        .map_err(|e| AppError::Unauthorized(format!("{e:#}")))?;
+
+    let UpgradeChannelParams {
+        pubkey,
+        channel_capacity_sat,
+        ..
+    } = params.0;
+
+    let has_usable_channel = state
+        .node
+        .inner
+        .list_usable_channels()
+        .iter()
+        .any(|channel| channel.counterparty.node_id == pubkey);
+    if !has_usable_channel {
+        return Err(AppError::BadRequest(format!(
+            "No usable channel with {pubkey} to upgrade"
+        )));
+    }
+
+    if channel_capacity_sat > MAX_UPGRADED_CHANNEL_CAPACITY_SAT {
+        return Err(AppError::BadRequest(format!(
+            "Requested channel capacity of {channel_capacity_sat} sats exceeds the maximum of \
+             {MAX_UPGRADED_CHANNEL_CAPACITY_SAT} sats"
+        )));
+    }
+
+    let temp_channel_id = state
+        .node
+        .inner
+        .open_replacement_channel(pubkey, channel_capacity_sat)
+        .map_err(|e| AppError::BadRequest(format!("Could not upgrade channel: {e:#}")))?;
+
+    Ok(Json(hex::encode(temp_channel_id)))
+}
+
 pub async fn list_dlc_channels(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<Vec<DlcChannelDetails>>, AppError> {
@@ -178,3 +319,233 @@ pub async fn list_dlc_channels(
 
     Ok(Json(dlc_channels))
 }
+
+/// Returns the result of the last reconciliation of DLC channels with positions, reconciling now
+/// if there was none yet.
+pub async fn get_reconciliation_report(
+    State(node): State<Node>,
+) -> Result<Json<ReconciliationReport>, AppError> {
+    let report = match node.last_reconciliation_report() {
+        Some(report) => report,
+        None => node.reconcile_dlc_channels().map_err(|e| {
+            AppError::InternalServerError(format!("Failed to reconcile DLC channels: {e:#}"))
+        })?,
+    };
+
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+pub struct PendingSweep {
+    outpoint: String,
+    value_sat: u64,
+    spending_txids: Vec<String>,
+    fee_rate_sat_per_1000_weight: u32,
+    attempts: u32,
+    last_attempt_timestamp: u64,
+}
+
+impl From<ln_dlc_node::node::PendingSweep> for PendingSweep {
+    fn from(value: ln_dlc_node::node::PendingSweep) -> Self {
+        Self {
+            outpoint: format!("{}:{}", value.outpoint.txid, value.outpoint.index),
+            value_sat: value.value_sat,
+            spending_txids: value
+                .spending_txids
+                .iter()
+                .map(|txid| txid.to_string())
+                .collect(),
+            fee_rate_sat_per_1000_weight: value.fee_rate_sat_per_1000_weight,
+            attempts: value.attempts,
+            last_attempt_timestamp: value.last_attempt_timestamp,
+        }
+    }
+}
+
+pub async fn list_pending_sweeps(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<PendingSweep>>, AppError> {
+    let pending_sweeps = state.node.inner.list_pending_sweeps().map_err(|e| {
+        AppError::InternalServerError(format!("Failed to list pending sweeps: {e:#}"))
+    })?;
+
+    let pending_sweeps = pending_sweeps
+        .into_iter()
+        .map(PendingSweep::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(pending_sweeps))
+}
+
+/// The fee rate in sats per vbyte of the DLC channels we currently propose.
+pub async fn get_dlc_fee_rate(State(state): State<Arc<AppState>>) -> Result<Json<u64>, AppError> {
+    let fee_rate = state
+        .node
+        .inner
+        .dlc_fee_rate(state.node.dlc_fee_rate_policy)
+        .await
+        .map_err(|e| {
+            AppError::InternalServerError(format!("Failed to estimate DLC fee rate: {e:#}"))
+        })?;
+
+    Ok(Json(fee_rate))
+}
+
+pub async fn get_liquidity_offer(State(state): State<Arc<AppState>>) -> Json<LiquidityOffer> {
+    Json(state.liquidity_offer)
+}
+
+pub async fn post_liquidity_purchase(
+    State(state): State<Arc<AppState>>,
+    params: Json<LiquidityPurchaseParams>,
+) -> Result<Json<LiquidityPurchase>, AppError> {
+    params
+        .verify()
+        .map_err(|e| AppError::Unauthorized(format!("{e:#}")))?;
+
+    let LiquidityPurchaseParams {
+        pubkey,
+        inbound_liquidity_sat,
+        ..
+    } = params.0;
+    let offer = state.liquidity_offer;
+
+    if inbound_liquidity_sat < offer.min_inbound_liquidity_sat
+        || inbound_liquidity_sat > offer.max_inbound_liquidity_sat
+    {
+        return Err(AppError::BadRequest(format!(
+            "Inbound liquidity of {inbound_liquidity_sat} sats has to be between {} and {} sats",
+            offer.min_inbound_liquidity_sat, offer.max_inbound_liquidity_sat
+        )));
+    }
+
+    let fee_sat = offer.fee_sat(inbound_liquidity_sat);
+    let (invoice, _) = state
+        .node
+        .inner
+        .create_liquidity_lease(
+            pubkey,
+            inbound_liquidity_sat,
+            fee_sat,
+            Duration::from_secs(offer.lease_duration_secs),
+        )
+        .map_err(|e| {
+            AppError::InternalServerError(format!("Failed to create liquidity lease: {e:#}"))
+        })?;
+
+    Ok(Json(LiquidityPurchase {
+        invoice: invoice.to_string(),
+        fee_sat,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct LiquidityLease {
+    payment_hash: String,
+    counterparty: PublicKey,
+    inbound_liquidity_sat: u64,
+    fee_sat: u64,
+    lease_duration_secs: u64,
+    status: String,
+    expiry_timestamp: Option<u64>,
+}
+
+impl From<ln_dlc_node::node::LiquidityLease> for LiquidityLease {
+    fn from(value: ln_dlc_node::node::LiquidityLease) -> Self {
+        let (status, expiry_timestamp) = match value.status {
+            LeaseStatus::Unpaid => ("Unpaid", None),
+            LeaseStatus::Paid => ("Paid", None),
+            LeaseStatus::Providing { .. } => ("Providing", None),
+            LeaseStatus::Active { expiry_timestamp } => ("Active", Some(expiry_timestamp)),
+        };
+
+        Self {
+            payment_hash: hex::encode(value.payment_hash.0),
+            counterparty: value.counterparty,
+            inbound_liquidity_sat: value.inbound_liquidity_sat,
+            fee_sat: value.fee_sat,
+            lease_duration_secs: value.lease_duration_secs,
+            status: status.to_string(),
+            expiry_timestamp,
+        }
+    }
+}
+
+pub async fn list_liquidity_leases(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<LiquidityLease>>, AppError> {
+    let leases = state.node.inner.list_liquidity_leases().map_err(|e| {
+        AppError::InternalServerError(format!("Failed to list liquidity leases: {e:#}"))
+    })?;
+
+    let leases = leases
+        .into_iter()
+        .map(LiquidityLease::from)
+        .collect::<Vec<_>>();
+
+    Ok(Json(leases))
+}
+
+/// Stores the encrypted backup of the app's node, which has to be signed by the node.
+///
+/// Backups whose sequence number is not higher than the one of the stored backup are rejected, so
+/// that an old backup cannot be replayed over a newer one.
+pub async fn put_backup(
+    Path(node_id): Path<PublicKey>,
+    State(state): State<Arc<AppState>>,
+    backup: Json<Backup>,
+) -> Result<(), AppError> {
+    let Json(backup) = backup;
+    let sequence = backup.sequence;
+    let backup_bytes = backup
+        .verify(&node_id)
+        .map_err(|e| AppError::BadRequest(format!("Invalid backup: {e:#}")))?;
+    let signature = backup
+        .signature
+        .ok_or_else(|| AppError::BadRequest("Backup is not signed".to_string()))?;
+    let backup = backup_bytes;
+
+    if backup.len() > MAX_BACKUP_SIZE {
+        return Err(AppError::BadRequest(format!(
+            "Backup of {} bytes exceeds the maximum of {MAX_BACKUP_SIZE} bytes",
+            backup.len()
+        )));
+    }
+
+    let mut conn =
+        state.pool.clone().get().map_err(|e| {
+            AppError::InternalServerError(format!("Failed to get db access: {e:#}"))
+        })?;
+
+    let stored = backup::upsert(&mut conn, node_id, backup, sequence, signature)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to store backup: {e:#}")))?;
+
+    if !stored {
+        return Err(AppError::BadRequest(format!(
+            "Backup sequence {sequence} is not higher than the one of the stored backup"
+        )));
+    }
+
+    tracing::debug!(%node_id, sequence, "Stored backup");
+
+    Ok(())
+}
+
+/// Returns the encrypted backup of the app's node, signed by the node.
+///
+/// The backup can only be decrypted with the seed of the node, so we hand it out to anyone.
+pub async fn get_backup(
+    Path(node_id): Path<PublicKey>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Backup>, AppError> {
+    let mut conn =
+        state.pool.clone().get().map_err(|e| {
+            AppError::InternalServerError(format!("Failed to get db access: {e:#}"))
+        })?;
+
+    let backup = backup::get(&mut conn, node_id)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to load backup: {e:#}")))?
+        .ok_or_else(|| AppError::NotFound(format!("No backup for {node_id}")))?;
+
+    Ok(Json(backup))
+}
@@ -10,6 +10,16 @@ pub mod sql_types {
     pub struct OrderTypeType;
 }
 
+diesel::table! {
+    backups (node_id) {
+        node_id -> Text,
+        backup -> Bytea,
+        timestamp -> Timestamptz,
+        sequence -> Nullable<Int8>,
+        signature -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::DirectionType;
@@ -27,3 +37,22 @@ diesel::table! {
         order_type -> OrderTypeType,
     }
 }
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DirectionType;
+
+    positions (channel_id) {
+        channel_id -> Text,
+        contract_id -> Nullable<Text>,
+        trader_pubkey -> Text,
+        contract_symbol -> Text,
+        leverage -> Float8,
+        quantity -> Float8,
+        direction -> DirectionType,
+        average_entry_price -> Float8,
+        creation_timestamp -> Timestamptz,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(backups, orders, positions,);
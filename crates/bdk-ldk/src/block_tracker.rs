@@ -0,0 +1,218 @@
+use bdk::bitcoin::BlockHash;
+use std::collections::BTreeMap;
+
+/// How many of the most recent blocks we remember to find the fork point of a reorg
+const MAX_TRACKED_BLOCKS: usize = 144;
+
+/// Keeps track of the hashes of the chain tips we have synced to, so that
+/// we can tell when they are reorged out of the best chain
+///
+/// the tracked blocks can be persisted and restored, so that a reorg which
+/// happened while we were offline is detected as well
+#[derive(Default)]
+pub(crate) struct BlockTracker {
+    blocks: BTreeMap<u32, BlockHash>,
+}
+
+impl BlockTracker {
+    /// continues tracking previously tracked `blocks`
+    pub(crate) fn new(blocks: Vec<(u32, BlockHash)>) -> Self {
+        let mut tracker = Self::default();
+        for (height, hash) in blocks {
+            tracker.blocks.insert(height, hash);
+        }
+
+        tracker.forget_oldest_blocks();
+
+        tracker
+    }
+
+    /// the tracked blocks in chain order
+    pub(crate) fn blocks(&self) -> Vec<(u32, BlockHash)> {
+        self.blocks
+            .iter()
+            .map(|(height, hash)| (*height, *hash))
+            .collect()
+    }
+
+    /// the last block we synced to
+    pub(crate) fn best_block(&self) -> Option<(u32, BlockHash)> {
+        self.blocks
+            .iter()
+            .next_back()
+            .map(|(height, hash)| (*height, *hash))
+    }
+
+    /// records `hash` as the block at `height`
+    ///
+    /// any tracked block above `height` is forgotten, since it can no longer
+    /// be part of the best chain
+    pub(crate) fn connect(&mut self, height: u32, hash: BlockHash) {
+        self.blocks.split_off(&height);
+        self.blocks.insert(height, hash);
+
+        self.forget_oldest_blocks();
+    }
+
+    fn forget_oldest_blocks(&mut self) {
+        while self.blocks.len() > MAX_TRACKED_BLOCKS {
+            let oldest = *self.blocks.keys().next().expect("non-empty");
+            self.blocks.remove(&oldest);
+        }
+    }
+
+    /// forgets every tracked block which is no longer part of the best chain,
+    /// as told by `tip_height` and `block_hash_at`
+    ///
+    /// returns the height of the lowest disconnected block, i.e. the block
+    /// right after the fork point, if there was a reorg
+    pub(crate) fn disconnect_stale_blocks<E>(
+        &mut self,
+        tip_height: u32,
+        block_hash_at: impl Fn(u32) -> Result<BlockHash, E>,
+    ) -> Result<Option<u32>, E> {
+        let mut lowest_disconnected = None;
+
+        while let Some((height, hash)) = self.best_block() {
+            if height <= tip_height && block_hash_at(height)? == hash {
+                break;
+            }
+
+            self.blocks.remove(&height);
+            lowest_disconnected = Some(height);
+        }
+
+        Ok(lowest_disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+    use std::convert::Infallible;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_inner([n; 32])
+    }
+
+    #[test]
+    fn no_reorg_if_tracked_blocks_are_in_chain() {
+        let mut tracker = BlockTracker::default();
+        tracker.connect(100, hash(100));
+
+        let disconnected = tracker
+            .disconnect_stale_blocks(105, |height| Ok::<_, Infallible>(hash(height as u8)))
+            .unwrap();
+
+        assert_eq!(disconnected, None);
+        assert_eq!(tracker.best_block(), Some((100, hash(100))));
+    }
+
+    #[test]
+    fn reorged_blocks_are_disconnected_down_to_fork_point() {
+        let mut tracker = BlockTracker::default();
+        for height in 100..=103 {
+            tracker.connect(height, hash(height as u8));
+        }
+
+        // the chain forked after block 101
+        let disconnected = tracker
+            .disconnect_stale_blocks(104, |height| {
+                if height <= 101 {
+                    Ok::<_, Infallible>(hash(height as u8))
+                } else {
+                    Ok(hash(height as u8 + 100))
+                }
+            })
+            .unwrap();
+
+        assert_eq!(disconnected, Some(102));
+        assert_eq!(tracker.best_block(), Some((101, hash(101))));
+    }
+
+    #[test]
+    fn blocks_above_a_shorter_tip_are_disconnected() {
+        let mut tracker = BlockTracker::default();
+        tracker.connect(100, hash(100));
+        tracker.connect(101, hash(101));
+
+        let disconnected = tracker
+            .disconnect_stale_blocks(100, |height| Ok::<_, Infallible>(hash(height as u8)))
+            .unwrap();
+
+        assert_eq!(disconnected, Some(101));
+        assert_eq!(tracker.best_block(), Some((100, hash(100))));
+    }
+
+    #[test]
+    fn connecting_a_lower_block_forgets_the_blocks_above() {
+        let mut tracker = BlockTracker::default();
+        tracker.connect(100, hash(100));
+        tracker.connect(101, hash(101));
+
+        tracker.connect(101, hash(42));
+
+        assert_eq!(tracker.best_block(), Some((101, hash(42))));
+    }
+
+    #[test]
+    fn reorg_is_detected_with_restored_blocks() {
+        let mut tracker = BlockTracker::default();
+        for height in 100..=103 {
+            tracker.connect(height, hash(height as u8));
+        }
+
+        // the node restarts and the chain forked after block 102 in the meantime
+        let mut tracker = BlockTracker::new(tracker.blocks());
+        let disconnected = tracker
+            .disconnect_stale_blocks(110, |height| {
+                if height <= 102 {
+                    Ok::<_, Infallible>(hash(height as u8))
+                } else {
+                    Ok(hash(height as u8 + 100))
+                }
+            })
+            .unwrap();
+
+        assert_eq!(disconnected, Some(103));
+        assert_eq!(tracker.best_block(), Some((102, hash(102))));
+    }
+
+    #[test]
+    fn failing_block_lookup_keeps_the_tracked_blocks() {
+        let mut tracker = BlockTracker::default();
+        tracker.connect(100, hash(100));
+        tracker.connect(101, hash(101));
+
+        let result = tracker.disconnect_stale_blocks(101, |_| Err("chain source unavailable"));
+
+        assert!(result.is_err());
+        assert_eq!(tracker.best_block(), Some((101, hash(101))));
+    }
+
+    #[test]
+    fn only_the_most_recent_blocks_are_restored() {
+        let blocks = (0..(MAX_TRACKED_BLOCKS as u32 + 10))
+            .map(|height| (height, hash(height as u8)))
+            .collect();
+
+        let tracker = BlockTracker::new(blocks);
+
+        assert_eq!(tracker.blocks.len(), MAX_TRACKED_BLOCKS);
+        assert_eq!(
+            tracker.blocks().first().map(|(height, _)| *height),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn only_the_most_recent_blocks_are_tracked() {
+        let mut tracker = BlockTracker::default();
+        for height in 0..(MAX_TRACKED_BLOCKS as u32 + 10) {
+            tracker.connect(height, hash(height as u8));
+        }
+
+        assert_eq!(tracker.blocks.len(), MAX_TRACKED_BLOCKS);
+    }
+}
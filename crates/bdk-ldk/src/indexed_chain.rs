@@ -2,6 +2,7 @@ use bdk::bitcoin::BlockHeader;
 use bdk::bitcoin::Script;
 use bdk::bitcoin::Transaction;
 use bdk::bitcoin::Txid;
+use bdk::blockchain::AnyBlockchain;
 use bdk::blockchain::ElectrumBlockchain;
 use bdk::electrum_client::ElectrumApi;
 use bdk::electrum_client::{self};
@@ -30,6 +31,53 @@ pub trait IndexedChain {
     /// Includes the confirmation status and height for each transaction
     fn get_script_tx_history(&self, script: &Script)
         -> Result<Vec<(TxStatus, Transaction)>, Error>;
+
+    /// Start watching Scripts which are not part of the wallet, so that
+    /// `get_script_tx_history` can find their transactions
+    ///
+    /// Only needed by backends without an address index
+    fn watch_scripts(&self, _scripts: &[Script]) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl IndexedChain for AnyBlockchain {
+    fn get_header(&self, height: u32) -> Result<BlockHeader, Error> {
+        indexed_chain(self)?.get_header(height)
+    }
+
+    fn get_position_in_block(&self, txid: &Txid, height: usize) -> Result<Option<usize>, Error> {
+        indexed_chain(self)?.get_position_in_block(txid, height)
+    }
+
+    fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Error> {
+        indexed_chain(self)?.get_tx_status(txid)
+    }
+
+    fn get_script_tx_history(
+        &self,
+        script: &Script,
+    ) -> Result<Vec<(TxStatus, Transaction)>, Error> {
+        indexed_chain(self)?.get_script_tx_history(script)
+    }
+
+    fn watch_scripts(&self, scripts: &[Script]) -> Result<(), Error> {
+        indexed_chain(self)?.watch_scripts(scripts)
+    }
+}
+
+fn indexed_chain(blockchain: &AnyBlockchain) -> Result<&dyn IndexedChain, Error> {
+    match blockchain {
+        AnyBlockchain::Electrum(inner) => Ok(inner.as_ref()),
+        #[cfg(feature = "esplora")]
+        AnyBlockchain::Esplora(inner) => Ok(inner.as_ref()),
+        #[cfg(feature = "rpc")]
+        AnyBlockchain::Rpc(inner) => Ok(inner.as_ref()),
+        #[allow(unreachable_patterns)]
+        _ => Err(Error::Generic(
+            "Blockchain backend does not implement IndexedChain".to_string(),
+        )),
+    }
 }
 
 impl IndexedChain for ElectrumBlockchain {
@@ -41,24 +89,52 @@ impl IndexedChain for ElectrumBlockchain {
         Ok(Some(self.transaction_get_merkle(txid, height)?.pos))
     }
 
-    // TODO: This isn't great and I'm not even sure it works correctly
-    //       it requires support for fetching `verbose` tx from electrum
-    //       so that we can check for confirmations / confirmation height
+    /// Electrum servers (electrs in particular) do not support verbose
+    /// transactions, so we look the transaction up in the history of its
+    /// output scripts, which includes the confirmation height
+    ///
+    /// servers do not index every script (e.g. `OP_RETURN` outputs), so we
+    /// try the outputs one after the other
     fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Error> {
-        match self.transaction_get(txid) {
-            Ok(_tx) => Ok(Some(TxStatus {
-                confirmed: true,
-                block_height: None,
-            })),
-            Err(e) => match e {
-                electrum_client::Error::Protocol(serde_json::Value::String(str))
-                    if str.eq("missing transaction") =>
-                {
-                    Ok(None)
-                }
-                _ => Err(Error::Electrum(e)),
-            },
+        let tx = match self.transaction_get(txid) {
+            Ok(tx) => tx,
+            Err(electrum_client::Error::Protocol(serde_json::Value::String(str)))
+                if str.eq("missing transaction") =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(Error::Electrum(e)),
+        };
+
+        for output in tx.output.iter() {
+            if output.script_pubkey.is_provably_unspendable() {
+                continue;
+            }
+
+            let status = self
+                .script_get_history(&output.script_pubkey)?
+                .into_iter()
+                .find(|history| history.tx_hash == *txid)
+                .map(|history| {
+                    if history.height <= 0 {
+                        TxStatus {
+                            confirmed: false,
+                            block_height: None,
+                        }
+                    } else {
+                        TxStatus {
+                            confirmed: true,
+                            block_height: Some(history.height as u32),
+                        }
+                    }
+                });
+
+            if status.is_some() {
+                return Ok(status);
+            }
         }
+
+        Ok(None)
     }
 
     fn get_script_tx_history(
@@ -94,3 +170,304 @@ impl IndexedChain for ElectrumBlockchain {
         res
     }
 }
+
+#[cfg(feature = "esplora")]
+mod esplora {
+    use super::IndexedChain;
+    use super::TxStatus;
+    use bdk::bitcoin::BlockHeader;
+    use bdk::bitcoin::Script;
+    use bdk::bitcoin::Transaction;
+    use bdk::bitcoin::Txid;
+    use bdk::blockchain::EsploraBlockchain;
+    use bdk::Error;
+
+    /// Esplora returns the confirmed transactions of a script in pages of this size
+    const CONFIRMED_TXS_PER_PAGE: usize = 25;
+
+    impl IndexedChain for EsploraBlockchain {
+        fn get_header(&self, height: u32) -> Result<BlockHeader, Error> {
+            Ok((**self).get_header(height)?)
+        }
+
+        fn get_position_in_block(
+            &self,
+            txid: &Txid,
+            _height: usize,
+        ) -> Result<Option<usize>, Error> {
+            let proof = self.get_merkle_proof(txid)?;
+            Ok(proof.map(|proof| proof.pos))
+        }
+
+        fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Error> {
+            let status = (**self).get_tx_status(txid)?;
+            Ok(status.map(|status| TxStatus {
+                confirmed: status.confirmed,
+                block_height: status.block_height,
+            }))
+        }
+
+        fn get_script_tx_history(
+            &self,
+            script: &Script,
+        ) -> Result<Vec<(TxStatus, Transaction)>, Error> {
+            let mut history = vec![];
+            let mut last_seen = None;
+
+            loop {
+                let txs = self.scripthash_txs(script, last_seen)?;
+                let n_confirmed = txs.iter().filter(|tx| tx.status.confirmed).count();
+
+                last_seen = txs.last().map(|tx| tx.txid);
+                history.extend(txs.into_iter().map(|tx| {
+                    let status = TxStatus {
+                        confirmed: tx.status.confirmed,
+                        block_height: tx.status.block_height,
+                    };
+                    (status, tx.to_tx())
+                }));
+
+                // the first page also contains all mempool transactions, later
+                // pages only confirmed ones
+                if n_confirmed < CONFIRMED_TXS_PER_PAGE {
+                    break;
+                }
+            }
+
+            Ok(history)
+        }
+    }
+}
+
+#[cfg(feature = "rpc")]
+mod rpc {
+    use super::IndexedChain;
+    use super::TxStatus;
+    use bdk::bitcoin::Address;
+    use bdk::bitcoin::BlockHeader;
+    use bdk::bitcoin::OutPoint;
+    use bdk::bitcoin::Script;
+    use bdk::bitcoin::Transaction;
+    use bdk::bitcoin::Txid;
+    use bdk::bitcoincore_rpc::jsonrpc::error::RpcError;
+    use bdk::bitcoincore_rpc::RpcApi;
+    use bdk::bitcoincore_rpc::{self};
+    use bdk::blockchain::RpcBlockchain;
+    use bdk::Error;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    /// bitcoind's error code for unknown transactions
+    const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+    /// Label of the scripts imported by `watch_scripts`
+    const WATCHED_SCRIPT_LABEL: &str = "bdk-ldk";
+
+    /// How many blocks back we rescan for transactions of newly watched scripts
+    ///
+    /// Scripts are usually watched before their first transaction is broadcast, the rescan only
+    /// covers transactions confirmed shortly before.
+    const WATCH_SCRIPT_RESCAN_BLOCKS: u64 = 1_008;
+
+    /// bitcoind has no address index, so we rely on the (watch-only) bitcoind
+    /// wallet used by the `RpcBlockchain`: the scripts we are interested in are
+    /// imported into it via `watch_scripts`, the transactions funding them are
+    /// looked up by address and the transactions spending them among the wallet
+    /// transactions since. Looking up transactions by txid which are neither in
+    /// the wallet nor in the mempool requires `txindex=1`.
+    impl IndexedChain for RpcBlockchain {
+        fn get_header(&self, height: u32) -> Result<BlockHeader, Error> {
+            let block_hash = self.get_block_hash(height as u64)?;
+            Ok(self.get_block_header(&block_hash)?)
+        }
+
+        fn get_position_in_block(
+            &self,
+            txid: &Txid,
+            height: usize,
+        ) -> Result<Option<usize>, Error> {
+            let block_hash = self.get_block_hash(height as u64)?;
+            let block = self.get_block(&block_hash)?;
+
+            Ok(block.txdata.iter().position(|tx| tx.txid() == *txid))
+        }
+
+        fn get_tx_status(&self, txid: &Txid) -> Result<Option<TxStatus>, Error> {
+            let tx = match self.get_raw_transaction_info(txid, None) {
+                Ok(tx) => tx,
+                Err(bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(
+                    RpcError { code, .. },
+                ))) if code == RPC_INVALID_ADDRESS_OR_KEY => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let block_height = match tx.blockhash {
+                Some(block_hash) => Some(self.get_block_header_info(&block_hash)?.height as u32),
+                None => None,
+            };
+
+            Ok(Some(TxStatus {
+                confirmed: block_height.is_some(),
+                block_height,
+            }))
+        }
+
+        fn get_script_tx_history(
+            &self,
+            script: &Script,
+        ) -> Result<Vec<(TxStatus, Transaction)>, Error> {
+            let address = match watched_addresses(self)?
+                .into_iter()
+                .find(|address| address.script_pubkey() == *script)
+            {
+                Some(address) => address,
+                // the script is watched with the next sync
+                None => return Ok(vec![]),
+            };
+
+            let funding_txids = self
+                .list_received_by_address(Some(&address), Some(0), Some(false), Some(true))?
+                .into_iter()
+                .flat_map(|received| received.txids)
+                .collect::<HashSet<_>>();
+
+            let mut history = funding_txids
+                .iter()
+                .map(|txid| wallet_tx(self, txid))
+                .collect::<Result<Vec<_>, Error>>()?;
+
+            let unspent_outpoints = self
+                .list_unspent(Some(0), None, Some(&[&address]), Some(true), None)?
+                .into_iter()
+                .map(|unspent| OutPoint::new(unspent.txid, unspent.vout))
+                .collect::<HashSet<_>>();
+
+            let mut spent_outpoints = HashSet::new();
+            let mut earliest_spent_height = None;
+            for (status, tx) in history.iter() {
+                let txid = tx.txid();
+                for (vout, output) in tx.output.iter().enumerate() {
+                    let outpoint = OutPoint::new(txid, vout as u32);
+                    if output.script_pubkey != *script || unspent_outpoints.contains(&outpoint) {
+                        continue;
+                    }
+
+                    spent_outpoints.insert(outpoint);
+                    if let Some(height) = status.block_height {
+                        earliest_spent_height = Some(
+                            earliest_spent_height
+                                .map_or(height, |earliest: u32| earliest.min(height)),
+                        );
+                    }
+                }
+            }
+
+            if spent_outpoints.is_empty() {
+                return Ok(history);
+            }
+
+            // bitcoind cannot look up the transaction spending an output, so we look for it among
+            // the wallet transactions since the spent output was confirmed
+            let since_block = match earliest_spent_height {
+                Some(height) => self.get_block_hash(height.saturating_sub(1) as u64)?,
+                None => self.get_best_block_hash()?,
+            };
+            let candidate_txids = self
+                .list_since_block(Some(&since_block), None, Some(true), None)?
+                .transactions
+                .into_iter()
+                .map(|entry| entry.info.txid)
+                .filter(|txid| !funding_txids.contains(txid))
+                .collect::<HashSet<_>>();
+
+            for txid in candidate_txids {
+                let (status, tx) = wallet_tx(self, &txid)?;
+                if tx
+                    .input
+                    .iter()
+                    .any(|input| spent_outpoints.contains(&input.previous_output))
+                {
+                    history.push((status, tx));
+                }
+            }
+
+            Ok(history)
+        }
+
+        /// Imports the `scripts` which are not watched yet into the bitcoind wallet without
+        /// rescanning for each of them, and then rescans the last [`WATCH_SCRIPT_RESCAN_BLOCKS`]
+        /// blocks once for the transactions of all of them.
+        fn watch_scripts(&self, scripts: &[Script]) -> Result<(), Error> {
+            let watched_scripts = watched_addresses(self)?
+                .into_iter()
+                .map(|address| address.script_pubkey())
+                .collect::<HashSet<_>>();
+            let new_scripts = scripts
+                .iter()
+                .filter(|script| !watched_scripts.contains(script))
+                .collect::<HashSet<_>>();
+            if new_scripts.is_empty() {
+                return Ok(());
+            }
+
+            for script in new_scripts {
+                let legacy_import = self.import_address_script(
+                    script,
+                    Some(WATCHED_SCRIPT_LABEL),
+                    Some(false),
+                    None,
+                );
+
+                if let Err(e) = legacy_import {
+                    tracing::debug!("Legacy import failed, importing as descriptor: {e}");
+
+                    let descriptor = self
+                        .get_descriptor_info(&format!("raw({script:x})"))?
+                        .descriptor;
+                    self.call::<serde_json::Value>(
+                        "importdescriptors",
+                        &[json!([{
+                            "desc": descriptor,
+                            "timestamp": "now",
+                            "label": WATCHED_SCRIPT_LABEL,
+                        }])],
+                    )?;
+                }
+            }
+
+            let height = self.get_block_count()?;
+            self.rescan_blockchain(
+                Some(height.saturating_sub(WATCH_SCRIPT_RESCAN_BLOCKS) as usize),
+                None,
+            )?;
+
+            Ok(())
+        }
+    }
+
+    /// Returns the addresses of the scripts imported into the bitcoind wallet by `watch_scripts`.
+    fn watched_addresses(client: &RpcBlockchain) -> Result<Vec<Address>, Error> {
+        match client.get_addresses_by_label(WATCHED_SCRIPT_LABEL) {
+            Ok(addresses) => Ok(addresses.into_keys().collect()),
+            // bitcoind fails if no address has the label yet
+            Err(e) => {
+                tracing::debug!("No watched scripts yet: {e}");
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Returns a transaction of the bitcoind wallet with its confirmation status.
+    fn wallet_tx(client: &RpcBlockchain, txid: &Txid) -> Result<(TxStatus, Transaction), Error> {
+        let result = client.get_transaction(txid, Some(true))?;
+        let tx = result
+            .transaction()
+            .map_err(|e| Error::Generic(format!("Invalid transaction {txid}: {e}")))?;
+        let status = TxStatus {
+            confirmed: result.info.confirmations > 0,
+            block_height: result.info.blockheight,
+        };
+
+        Ok((status, tx))
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Context;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::BlockHash;
 use bdk::bitcoin::BlockHeader;
+use bdk::bitcoin::OutPoint;
 use bdk::bitcoin::Script;
 use bdk::bitcoin::Transaction;
 use bdk::bitcoin::Txid;
@@ -12,9 +13,13 @@ use bdk::database::BatchDatabase;
 use bdk::wallet::AddressIndex;
 use bdk::wallet::Wallet;
 use bdk::Balance;
+use bdk::FeeRate;
+use bdk::LocalUtxo;
 use bdk::SignOptions;
 use bdk::SyncOptions;
+use bdk::TransactionDetails;
 
+use block_tracker::BlockTracker;
 pub use indexed_chain::IndexedChain;
 pub use indexed_chain::TxStatus;
 use lightning::chain::chaininterface::BroadcasterInterface;
@@ -23,7 +28,9 @@ use lightning::chain::chaininterface::FeeEstimator;
 use lightning::chain::Confirm;
 use lightning::chain::Filter;
 use lightning::chain::WatchedOutput;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
@@ -32,8 +39,18 @@ pub type TransactionWithHeight = (u32, Transaction);
 pub type TransactionWithPosition = (usize, Transaction);
 pub type TransactionWithHeightAndPosition = (u32, Transaction, usize);
 
+mod block_tracker;
 mod indexed_chain;
 
+/// How often we restart a sync because the chain tip changed in the meantime
+const MAX_SYNC_ATTEMPTS: usize = 5;
+
+/// The virtual size of the witness of the P2WPKH input of a CPFP child transaction.
+///
+/// The witness consists of the item count, a signature of up to 72 bytes and a 33 byte public key
+/// with their length prefixes, i.e. 108 weight units.
+const CPFP_CHILD_INPUT_WITNESS_VSIZE: u64 = 27;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("BDK wallet error")]
@@ -45,6 +62,15 @@ pub enum Error {
 struct TxFilter {
     watched_transactions: Vec<(Txid, Script)>,
     watched_outputs: Vec<WatchedOutput>,
+    /// the scripts registered since the chain source was last told to watch them
+    unwatched_scripts: Vec<Script>,
+    /// the heights at which the watched transactions confirmed, as of the last sync
+    confirmation_heights: HashMap<Txid, u32>,
+    /// the heights at which the watched outputs were spent, as of the last sync
+    spend_heights: HashMap<OutPoint, u32>,
+    /// the transactions and outputs which are no longer watched, even if they are registered again
+    pruned_transactions: HashSet<Txid>,
+    pruned_outputs: HashSet<OutPoint>,
 }
 
 impl TxFilter {
@@ -52,15 +78,77 @@ impl TxFilter {
         Self {
             watched_transactions: vec![],
             watched_outputs: vec![],
+            unwatched_scripts: vec![],
+            confirmation_heights: HashMap::new(),
+            spend_heights: HashMap::new(),
+            pruned_transactions: HashSet::new(),
+            pruned_outputs: HashSet::new(),
         }
     }
 
     fn register_tx(&mut self, txid: Txid, script: Script) {
-        self.watched_transactions.push((txid, script));
+        if self.pruned_transactions.contains(&txid) {
+            return;
+        }
+
+        if !self.watched_transactions.contains(&(txid, script.clone())) {
+            self.watched_transactions.push((txid, script.clone()));
+            self.unwatched_scripts.push(script);
+        }
     }
 
     fn register_output(&mut self, output: WatchedOutput) {
-        self.watched_outputs.push(output);
+        if self.pruned_outputs.contains(&output.outpoint) {
+            return;
+        }
+
+        if !self.watched_outputs.contains(&output) {
+            self.unwatched_scripts.push(output.script_pubkey.clone());
+            self.watched_outputs.push(output);
+        }
+    }
+
+    /// stops watching the transactions which confirmed at or below
+    /// `max_height` and returns them
+    fn prune_transactions(&mut self, max_height: u32) -> Vec<(Txid, Script)> {
+        let (pruned, watched) = std::mem::take(&mut self.watched_transactions)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(txid, _)| {
+                let confirmation_height = self.confirmation_heights.get(txid);
+                matches!(confirmation_height, Some(height) if *height <= max_height)
+            });
+        self.watched_transactions = watched;
+
+        for (txid, _) in pruned.iter() {
+            self.confirmation_heights.remove(txid);
+            self.pruned_transactions.insert(*txid);
+        }
+
+        pruned
+    }
+
+    /// stops watching the outputs which were spent at or below `max_height`
+    /// and returns them
+    fn prune_outputs(&mut self, max_height: u32) -> Vec<WatchedOutput> {
+        let (pruned, watched) = std::mem::take(&mut self.watched_outputs)
+            .into_iter()
+            .partition::<Vec<_>, _>(|output| {
+                let spend_height = self.spend_heights.get(&output.outpoint);
+                matches!(spend_height, Some(height) if *height <= max_height)
+            });
+        self.watched_outputs = watched;
+
+        for output in pruned.iter() {
+            self.spend_heights.remove(&output.outpoint);
+            self.pruned_outputs.insert(output.outpoint);
+        }
+
+        pruned
+    }
+
+    /// the number of watched transactions and outputs
+    fn len(&self) -> usize {
+        self.watched_transactions.len() + self.watched_outputs.len()
     }
 }
 
@@ -79,6 +167,7 @@ pub struct LightningWallet<B, D> {
     client: Arc<B>,
     wallet: Mutex<Wallet<D>>,
     filter: Mutex<TxFilter>,
+    block_tracker: Mutex<BlockTracker>,
 }
 
 impl<B, D> LightningWallet<B, D>
@@ -92,47 +181,115 @@ where
             client,
             wallet: Mutex::new(wallet),
             filter: Mutex::new(TxFilter::new()),
+            block_tracker: Mutex::new(BlockTracker::default()),
         }
     }
 
     /// syncs both your onchain and lightning wallet to current tip
     /// utilizes ldk's Confirm trait to provide chain data
+    ///
+    /// the confirmables are told about chain data in the order required by
+    /// LDK: first the transactions which are no longer confirmed (e.g. after
+    /// a reorg), then the new best block and finally the confirmed
+    /// transactions, in chain order
+    ///
+    /// the transactions of reorged blocks are found by comparing the block
+    /// hashes the confirmables report for their relevant transactions with
+    /// the best chain
     pub fn sync(&self, confirmables: Vec<&dyn Confirm>) -> Result<(), Error> {
         self.sync_onchain_wallet()?;
 
-        let (tip_height, tip_header) = self.get_tip()?;
+        for _ in 0..MAX_SYNC_ATTEMPTS {
+            let (tip_height, tip_header) = self.get_tip()?;
+            let tip_hash = tip_header.block_hash();
 
-        for confirmable in confirmables.iter() {
-            confirmable.best_block_updated(&tip_header, tip_height);
-        }
+            self.detect_reorg(tip_height)?;
 
-        let mut relevant_txids = confirmables
-            .iter()
-            .flat_map(|confirmable| confirmable.get_relevant_txids())
-            .collect::<Vec<(Txid, Option<BlockHash>)>>();
+            let mut relevant_txids = confirmables
+                .iter()
+                .flat_map(|confirmable| confirmable.get_relevant_txids())
+                .collect::<Vec<(Txid, Option<BlockHash>)>>();
 
-        tracing::info!(?relevant_txids);
+            tracing::info!(?relevant_txids);
 
-        relevant_txids.sort_unstable();
-        relevant_txids.dedup();
+            relevant_txids.sort_unstable();
+            relevant_txids.dedup();
+
+            let unconfirmed_txids = self.get_unconfirmed(relevant_txids)?;
+            for unconfirmed_txid in unconfirmed_txids {
+                tracing::info!(txid = %unconfirmed_txid, "Transaction is no longer confirmed");
+                for confirmable in confirmables.iter() {
+                    confirmable.transaction_unconfirmed(&unconfirmed_txid);
+                }
+            }
 
-        let unconfirmed_txids = self.get_unconfirmed(relevant_txids)?;
-        for unconfirmed_txid in unconfirmed_txids {
             for confirmable in confirmables.iter() {
-                confirmable.transaction_unconfirmed(&unconfirmed_txid);
+                confirmable.best_block_updated(&tip_header, tip_height);
             }
-        }
 
-        let confirmed_txs = self.get_confirmed_txs_by_block()?;
-        for (height, header, tx_list) in confirmed_txs {
-            let tx_list_ref = tx_list
-                .iter()
-                .map(|(height, tx)| (height.to_owned(), tx))
-                .collect::<Vec<(usize, &Transaction)>>();
+            // processing confirmed transactions can make the confirmables
+            // register new transactions and outputs (e.g. the outputs of a
+            // confirmed funding transaction), which may have been spent in
+            // the very same block, so we repeat until nothing new is watched
+            loop {
+                self.watch_registered_scripts()?;
+
+                let filter_len = self.filter.lock().unwrap().len();
+
+                let confirmed_txs = self.get_confirmed_txs_by_block()?;
+                for (height, header, tx_list) in confirmed_txs {
+                    let tx_list_ref = tx_list
+                        .iter()
+                        .map(|(height, tx)| (height.to_owned(), tx))
+                        .collect::<Vec<(usize, &Transaction)>>();
+
+                    for confirmable in confirmables.iter() {
+                        confirmable.transactions_confirmed(&header, tx_list_ref.as_slice(), height);
+                    }
+                }
 
-            for confirmable in confirmables.iter() {
-                confirmable.transactions_confirmed(&header, tx_list_ref.as_slice(), height);
+                if self.filter.lock().unwrap().len() == filter_len {
+                    break;
+                }
+            }
+
+            // if the tip moved while we were syncing, the data we passed on
+            // might be inconsistent, so we sync again
+            let (_, current_tip_header) = self.get_tip()?;
+            if current_tip_header.block_hash() == tip_hash {
+                self.block_tracker
+                    .lock()
+                    .unwrap()
+                    .connect(tip_height, tip_hash);
+                return Ok(());
             }
+
+            tracing::debug!("Chain tip changed during sync, syncing again");
+        }
+
+        Err(anyhow::anyhow!("Chain tip kept changing during sync").into())
+    }
+
+    /// forgets the synced blocks which were reorged out of the best chain
+    ///
+    /// the transactions confirmed in those blocks are unconfirmed as part of
+    /// the sync, since the confirmables report them with their block hash
+    fn detect_reorg(&self, tip_height: u32) -> Result<(), Error> {
+        let mut block_tracker = self.block_tracker.lock().unwrap();
+        let previous_best_block = block_tracker.best_block();
+
+        let fork = block_tracker.disconnect_stale_blocks(tip_height, |height| {
+            self.client
+                .get_header(height)
+                .map(|header| header.block_hash())
+        })?;
+
+        if let Some(first_stale_height) = fork {
+            tracing::warn!(
+                ?previous_best_block,
+                fork_height = first_stale_height - 1,
+                "Detected chain reorganization"
+            );
         }
 
         Ok(())
@@ -171,12 +328,212 @@ where
         Ok(psbt.extract_tx())
     }
 
+    /// builds and signs a transaction paying to `script_pubkey`
+    ///
+    /// if `amount` is `None` the wallet is drained, i.e. every spendable
+    /// utxo (or only the ones in `utxos`) goes to `script_pubkey` minus fees.
+    /// if `utxos` is not empty, only those utxos are spent.
+    pub fn construct_spend_transaction(
+        &self,
+        script_pubkey: &Script,
+        amount: Option<u64>,
+        fee_rate: FeeRate,
+        utxos: &[OutPoint],
+    ) -> Result<(Transaction, TransactionDetails), Error> {
+        let wallet = self.get_wallet_lock()?;
+        let mut tx_builder = wallet.build_tx();
+
+        if !utxos.is_empty() {
+            tx_builder.add_utxos(utxos)?.manually_selected_only();
+        }
+
+        match amount {
+            Some(amount) => {
+                tx_builder.add_recipient(script_pubkey.clone(), amount);
+            }
+            None => {
+                if utxos.is_empty() {
+                    tx_builder.drain_wallet();
+                }
+                tx_builder.drain_to(script_pubkey.clone());
+            }
+        }
+
+        tx_builder.fee_rate(fee_rate).enable_rbf();
+
+        let (mut psbt, tx_details) = tx_builder.finish()?;
+
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        if !finalized {
+            return Err(anyhow::anyhow!("Could not finalize spend transaction").into());
+        }
+
+        Ok((psbt.extract_tx(), tx_details))
+    }
+
+    /// builds and signs a replacement (RBF) for the unconfirmed wallet
+    /// transaction `txid`, paying `fee_rate`
+    pub fn construct_fee_bump_transaction(
+        &self,
+        txid: Txid,
+        fee_rate: FeeRate,
+    ) -> Result<(Transaction, TransactionDetails), Error> {
+        let wallet = self.get_wallet_lock()?;
+        let mut tx_builder = wallet.build_fee_bump(txid)?;
+
+        tx_builder.fee_rate(fee_rate).enable_rbf();
+
+        let (mut psbt, tx_details) = tx_builder.finish()?;
+
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        if !finalized {
+            return Err(anyhow::anyhow!("Could not finalize fee bump transaction").into());
+        }
+
+        Ok((psbt.extract_tx(), tx_details))
+    }
+
+    /// builds and signs a child transaction (CPFP) spending our output `outpoint`
+    /// of an unconfirmed transaction back to the wallet, so that parent and child
+    /// together pay `fee_rate`
+    pub fn construct_cpfp_transaction(
+        &self,
+        outpoint: OutPoint,
+        fee_rate: FeeRate,
+    ) -> Result<(Transaction, TransactionDetails), Error> {
+        let wallet = self.get_wallet_lock()?;
+
+        let parent = wallet
+            .get_tx(&outpoint.txid, true)?
+            .context("Unknown parent transaction")?;
+        if parent.confirmation_time.is_some() {
+            return Err(anyhow::anyhow!("Parent transaction is already confirmed").into());
+        }
+        let parent_fee = parent.fee.context("Unknown fee of parent transaction")?;
+        let parent_vsize = parent
+            .transaction
+            .context("Missing raw parent transaction")?
+            .vsize() as u64;
+
+        let drain_script = wallet
+            .get_internal_address(AddressIndex::New)?
+            .address
+            .script_pubkey();
+
+        // build the child once at the target fee rate, only to learn its size
+        let child_vsize = {
+            let mut tx_builder = wallet.build_tx();
+            tx_builder
+                .add_utxo(outpoint)?
+                .manually_selected_only()
+                .drain_to(drain_script.clone())
+                .fee_rate(fee_rate);
+            let (psbt, _) = tx_builder.finish()?;
+            // signatures are not there yet, account for the witness of the input
+            psbt.unsigned_tx.vsize() as u64 + CPFP_CHILD_INPUT_WITNESS_VSIZE
+        };
+
+        let package_fee = fee_rate.fee_vb((parent_vsize + child_vsize) as usize);
+        let child_fee = package_fee.saturating_sub(parent_fee);
+        let child_fee =
+            child_fee.max(FeeRate::default_min_relay_fee().fee_vb(child_vsize as usize));
+
+        let mut tx_builder = wallet.build_tx();
+        tx_builder
+            .add_utxo(outpoint)?
+            .manually_selected_only()
+            .drain_to(drain_script)
+            .fee_absolute(child_fee)
+            .enable_rbf();
+
+        let (mut psbt, tx_details) = tx_builder.finish()?;
+
+        let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+        if !finalized {
+            return Err(anyhow::anyhow!("Could not finalize CPFP transaction").into());
+        }
+
+        Ok((psbt.extract_tx(), tx_details))
+    }
+
+    /// lists the unspent outputs of the inner onchain bdk wallet
+    pub fn list_unspent(&self) -> Result<Vec<LocalUtxo>, Error> {
+        let wallet = self.get_wallet_lock()?;
+        wallet.list_unspent().map_err(Error::Bdk)
+    }
+
     /// get the balance of the inner onchain bdk wallet
     pub fn get_balance(&self) -> Result<Balance, Error> {
         let wallet = self.get_wallet_lock()?;
         wallet.get_balance().map_err(Error::Bdk)
     }
 
+    /// the height and hash of the block the confirmables were last synced to
+    pub fn best_block(&self) -> Option<(u32, BlockHash)> {
+        self.block_tracker.lock().unwrap().best_block()
+    }
+
+    /// the most recent blocks the confirmables were synced to, in chain
+    /// order, to be persisted and passed to `restore_tracked_blocks` after a
+    /// restart
+    pub fn tracked_blocks(&self) -> Vec<(u32, BlockHash)> {
+        self.block_tracker.lock().unwrap().blocks()
+    }
+
+    /// continues tracking the blocks returned by `tracked_blocks` before a
+    /// restart, so that a reorg which happened in the meantime is detected
+    /// with the next sync
+    pub fn restore_tracked_blocks(&self, blocks: Vec<(u32, BlockHash)>) {
+        *self.block_tracker.lock().unwrap() = BlockTracker::new(blocks);
+    }
+
+    /// the outputs whose spends are passed on to the confirmables during a sync
+    pub fn watched_outputs(&self) -> Vec<WatchedOutput> {
+        self.filter.lock().unwrap().watched_outputs.clone()
+    }
+
+    /// the transactions whose confirmation is passed on to the confirmables
+    /// during a sync
+    pub fn watched_transactions(&self) -> Vec<(Txid, Script)> {
+        self.filter.lock().unwrap().watched_transactions.clone()
+    }
+
+    /// stops watching the transactions and outputs which confirmed or were
+    /// spent at least `min_confirmations` deep as of the last sync, as the
+    /// confirmables do not need to learn about them again
+    ///
+    /// pruned transactions and outputs are not watched again if they are
+    /// registered again, e.g. when the channel monitors are reloaded, see
+    /// `restore_pruned`
+    ///
+    /// returns the transactions and outputs which are no longer watched
+    pub fn prune(&self, min_confirmations: u32) -> (Vec<(Txid, Script)>, Vec<WatchedOutput>) {
+        let best_height = match self.best_block() {
+            Some((height, _)) => height,
+            None => return (vec![], vec![]),
+        };
+        let max_height = match (best_height + 1).checked_sub(min_confirmations) {
+            Some(max_height) => max_height,
+            None => return (vec![], vec![]),
+        };
+
+        let mut filter = self.filter.lock().unwrap();
+        let transactions = filter.prune_transactions(max_height);
+        let outputs = filter.prune_outputs(max_height);
+
+        (transactions, outputs)
+    }
+
+    /// keeps ignoring the transactions and outputs returned by `prune` before
+    /// a restart
+    ///
+    /// has to be called before they are registered again
+    pub fn restore_pruned(&self, txids: Vec<Txid>, outpoints: Vec<OutPoint>) {
+        let mut filter = self.filter.lock().unwrap();
+        filter.pruned_transactions.extend(txids);
+        filter.pruned_outputs.extend(outpoints);
+    }
+
     /// get a reference to the inner bdk wallet
     /// be careful using this because it will hold the lock
     /// on the inner wallet until the guard is dropped
@@ -186,16 +543,40 @@ where
         Ok(self.get_wallet_lock()?)
     }
 
-    fn sync_onchain_wallet(&self) -> Result<(), Error> {
+    /// syncs only the inner onchain bdk wallet, e.g. to learn
+    /// about a transaction we just broadcast
+    pub fn sync_onchain_wallet(&self) -> Result<(), Error> {
         let wallet = self.get_wallet_lock()?;
         wallet.sync(self.client.as_ref(), SyncOptions::default())?;
         Ok(())
     }
 
+    /// tells the chain source about the scripts registered since the last
+    /// call in one batch, as watching a script can be expensive (e.g. a
+    /// rescan with bitcoind)
+    fn watch_registered_scripts(&self) -> Result<(), Error> {
+        let scripts = std::mem::take(&mut self.filter.lock().unwrap().unwatched_scripts);
+        if scripts.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = self.client.watch_scripts(&scripts) {
+            // we try again with the next sync
+            self.filter
+                .lock()
+                .unwrap()
+                .unwatched_scripts
+                .extend(scripts);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
     fn get_unconfirmed(&self, txids: Vec<(Txid, Option<BlockHash>)>) -> Result<Vec<Txid>, Error> {
         Ok(txids
             .into_iter()
-            .map(|txid| self.augment_txid_with_confirmation_status(txid.0))
+            .map(|(txid, block_hash)| self.augment_txid_with_confirmation_status(txid, block_hash))
             .collect::<Result<Vec<(Txid, bool)>, Error>>()?
             .into_iter()
             .filter(|(_txid, confirmed)| !*confirmed)
@@ -206,9 +587,10 @@ where
     fn get_confirmed_txs_by_block(
         &self,
     ) -> Result<Vec<(u32, BlockHeader, Vec<TransactionWithPosition>)>, Error> {
-        let mut txs_by_block: HashMap<u32, Vec<TransactionWithPosition>> = HashMap::new();
+        // LDK expects confirmed transactions in chain order
+        let mut txs_by_block: BTreeMap<u32, Vec<TransactionWithPosition>> = BTreeMap::new();
 
-        let filter = self.filter.lock().unwrap();
+        let mut filter = self.filter.lock().unwrap();
 
         tracing::info!(watched_transactions = ?filter.watched_transactions);
 
@@ -230,6 +612,32 @@ where
             .flatten()
             .collect::<Vec<TransactionWithHeight>>();
 
+        // remember where the watched transactions and spends are buried, so
+        // that they can be pruned once a reorg is no longer expected to
+        // undo them
+        let confirmation_heights = confirmed_txs
+            .iter()
+            .map(|(height, tx)| (tx.txid(), *height))
+            .collect();
+        let spend_heights = confirmed_spent
+            .iter()
+            .flat_map(|(height, tx)| {
+                tx.input
+                    .iter()
+                    .map(move |input| (input.previous_output, *height))
+            })
+            .filter(|(outpoint, _)| {
+                filter
+                    .watched_outputs
+                    .iter()
+                    .any(|output| output.outpoint == *outpoint)
+            })
+            .collect();
+        filter.confirmation_heights = confirmation_heights;
+        filter.spend_heights = spend_heights;
+
+        drop(filter);
+
         confirmed_txs.append(&mut confirmed_spent);
 
         let confirmed_txs_with_position = confirmed_txs
@@ -241,7 +649,14 @@ where
             .collect::<Vec<TransactionWithHeightAndPosition>>();
 
         for (height, tx, pos) in confirmed_txs_with_position {
-            txs_by_block.entry(height).or_default().push((pos, tx))
+            let txs = txs_by_block.entry(height).or_default();
+            if !txs.iter().any(|(_, known)| known.txid() == tx.txid()) {
+                txs.push((pos, tx));
+            }
+        }
+
+        for txs in txs_by_block.values_mut() {
+            txs.sort_unstable_by_key(|(pos, _)| *pos);
         }
 
         txs_by_block
@@ -257,14 +672,33 @@ where
         Ok((tip_height, tip_header))
     }
 
-    fn augment_txid_with_confirmation_status(&self, txid: Txid) -> Result<(Txid, bool), Error> {
-        self.client
-            .get_tx_status(&txid)
-            .map(|status| match status {
-                Some(status) => (txid, status.confirmed),
-                None => (txid, false),
-            })
-            .map_err(Error::Bdk)
+    /// a transaction only counts as confirmed if it is still confirmed in
+    /// the block we previously reported, if any
+    fn augment_txid_with_confirmation_status(
+        &self,
+        txid: Txid,
+        block_hash: Option<BlockHash>,
+    ) -> Result<(Txid, bool), Error> {
+        let status = self.client.get_tx_status(&txid)?;
+
+        let confirmed = match (status, block_hash) {
+            (
+                Some(TxStatus {
+                    confirmed: true,
+                    block_height: Some(height),
+                }),
+                Some(block_hash),
+            ) => self.client.get_header(height)?.block_hash() == block_hash,
+            (
+                Some(TxStatus {
+                    confirmed: true, ..
+                }),
+                _,
+            ) => true,
+            _ => false,
+        };
+
+        Ok((txid, confirmed))
     }
 
     fn get_confirmed_tx(
@@ -365,6 +799,14 @@ where
         Ok(sats_per_vbyte)
     }
 
+    /// Estimates the fee rate for a transaction to be confirmed within `target_blocks`.
+    ///
+    /// Unlike [`LightningWallet::estimate_fee`], this does not hide estimation failures.
+    pub fn estimate_fee_rate(&self, target_blocks: usize) -> Result<FeeRate, Error> {
+        let fee_rate = self.client.estimate_fee(target_blocks)?;
+        Ok(fee_rate)
+    }
+
     // Proxy call to wrap lock into anyhow Error
     fn get_wallet_lock(&self) -> anyhow::Result<MutexGuard<Wallet<D>>> {
         self.wallet
@@ -417,14 +859,17 @@ where
     D: BatchDatabase,
 {
     fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+        // the chain source is told to watch the script during the ongoing or
+        // the next sync
         let mut filter = self.filter.lock().unwrap();
         filter.register_tx(*txid, script_pubkey.clone());
     }
 
     fn register_output(&self, output: WatchedOutput) {
+        // if the output was already spent, the spending transaction is found
+        // during the ongoing or the next sync
         let mut filter = self.filter.lock().unwrap();
         filter.register_output(output);
-        // TODO: do we need to check for tx here or wait for next sync?
     }
 }
 
@@ -438,9 +883,77 @@ pub enum ScriptStatus {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn only_deeply_spent_outputs_are_pruned() {
+        let mut filter = TxFilter::new();
+        let deeply_spent = watched_output(0);
+        let recently_spent = watched_output(1);
+        let unspent = watched_output(2);
+        for output in [&deeply_spent, &recently_spent, &unspent] {
+            filter.register_output(output.clone());
+        }
+        filter.spend_heights =
+            HashMap::from([(deeply_spent.outpoint, 100), (recently_spent.outpoint, 101)]);
+
+        let pruned = filter.prune_outputs(100);
+
+        assert_eq!(pruned, vec![deeply_spent]);
+        assert_eq!(filter.watched_outputs, vec![recently_spent, unspent]);
+    }
+
+    #[test]
+    fn only_deeply_confirmed_transactions_are_pruned() {
+        let mut filter = TxFilter::new();
+        let script = Script::from(vec![0x00, 0x14]);
+        let deeply_confirmed = Txid::from_inner([1; 32]);
+        let unconfirmed = Txid::from_inner([2; 32]);
+        filter.register_tx(deeply_confirmed, script.clone());
+        filter.register_tx(unconfirmed, script.clone());
+        filter.confirmation_heights = HashMap::from([(deeply_confirmed, 100)]);
+
+        let pruned = filter.prune_transactions(100);
+
+        assert_eq!(pruned, vec![(deeply_confirmed, script.clone())]);
+        assert_eq!(filter.watched_transactions, vec![(unconfirmed, script)]);
+    }
+
+    #[test]
+    fn pruned_transactions_and_outputs_are_not_watched_again() {
+        let mut filter = TxFilter::new();
+        let script = Script::from(vec![0x00, 0x14]);
+        let txid = Txid::from_inner([1; 32]);
+        let output = watched_output(0);
+        filter.register_tx(txid, script.clone());
+        filter.register_output(output.clone());
+        filter.confirmation_heights = HashMap::from([(txid, 100)]);
+        filter.spend_heights = HashMap::from([(output.outpoint, 100)]);
+        filter.prune_transactions(100);
+        filter.prune_outputs(100);
+
+        filter.register_tx(txid, script);
+        filter.register_output(output);
+
+        assert!(filter.watched_transactions.is_empty());
+        assert!(filter.watched_outputs.is_empty());
+    }
+
+    fn watched_output(vout: u32) -> WatchedOutput {
+        WatchedOutput {
+            block_hash: None,
+            outpoint: OutPoint {
+                txid: Txid::from_inner([0; 32]),
+                vout,
+            },
+            script_pubkey: Script::from(vec![0x00, 0x20, vout as u8]),
+        }
+    }
 }
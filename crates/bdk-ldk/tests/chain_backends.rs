@@ -0,0 +1,301 @@
+use bdk::bitcoin;
+use bdk::blockchain::AnyBlockchain;
+use bdk::blockchain::Blockchain;
+use bdk::blockchain::ElectrumBlockchain;
+use bdk::blockchain::GetHeight;
+use bdk::blockchain::WalletSync;
+use bdk::database::MemoryDatabase;
+use bdk::electrum_client::Client;
+use bdk_ldk::IndexedChain;
+use bdk_ldk::LightningWallet;
+use std::sync::Arc;
+
+#[test]
+fn can_init_sample_any_blockchain_lightning_wallet() {
+    let client = Client::new("ssl://electrum.blockstream.info:60002").unwrap();
+    let blockchain = AnyBlockchain::from(ElectrumBlockchain::from(client));
+
+    let _lightning = sample_lightning_wallet(blockchain);
+}
+
+#[cfg(feature = "esplora")]
+#[test]
+fn can_init_sample_esplora_lightning_wallet() {
+    let blockchain =
+        bdk::blockchain::EsploraBlockchain::new("https://blockstream.info/testnet/api", 20);
+
+    let _lightning = sample_lightning_wallet(blockchain);
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+#[ignore = "requires a local bitcoind in testnet mode"]
+fn can_init_sample_bitcoind_lightning_wallet() {
+    use bdk::blockchain::rpc::Auth;
+    use bdk::blockchain::rpc::RpcConfig;
+    use bdk::blockchain::ConfigurableBlockchain;
+    use bdk::blockchain::RpcBlockchain;
+
+    let config = RpcConfig {
+        url: "127.0.0.1:18332".to_string(),
+        auth: Auth::UserPass {
+            username: "admin1".to_string(),
+            password: "123".to_string(),
+        },
+        network: bitcoin::Network::Testnet,
+        wallet_name: "bdk-ldk-test".to_string(),
+        sync_params: None,
+    };
+    let blockchain = RpcBlockchain::from_config(&config).unwrap();
+
+    let _lightning = sample_lightning_wallet(blockchain);
+}
+
+fn sample_lightning_wallet<B>(blockchain: B) -> LightningWallet<B, MemoryDatabase>
+where
+    B: Blockchain + GetHeight + WalletSync + IndexedChain,
+{
+    let wallet = bdk::Wallet::new(
+        "wpkh([c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*)",
+        Some("wpkh([c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/1/*)"),
+        bitcoin::Network::Testnet,
+        MemoryDatabase::default(),
+    ).unwrap();
+
+    LightningWallet::new(Arc::new(blockchain), wallet)
+}
+
+/// Tests against the regtest setup of `docker-compose.yml`, using its bitcoind to create the
+/// transactions
+#[cfg(feature = "rpc")]
+mod regtest {
+    use bdk::bitcoin;
+    use bdk::bitcoin::hashes::Hash;
+    use bdk::bitcoin::Amount;
+    use bdk::bitcoin::OutPoint;
+    use bdk::bitcoin::Txid;
+    use bdk::bitcoincore_rpc::json::CreateRawTransactionInput;
+    use bdk::bitcoincore_rpc::Auth;
+    use bdk::bitcoincore_rpc::Client;
+    use bdk::bitcoincore_rpc::RpcApi;
+    use bdk::blockchain::rpc::RpcConfig;
+    use bdk::blockchain::ConfigurableBlockchain;
+    use bdk::blockchain::ElectrumBlockchain;
+    use bdk::blockchain::RpcBlockchain;
+    use bdk_ldk::IndexedChain;
+    use bdk_ldk::TxStatus;
+    use std::collections::HashMap;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    const BITCOIND_RPC: &str = "127.0.0.1:18443";
+    const ELECTRS_ORIGIN: &str = "tcp://localhost:50000";
+    #[cfg(feature = "esplora")]
+    const ESPLORA_ORIGIN: &str = "http://localhost:3000";
+
+    /// Prefix of the bitcoind wallets created by the tests
+    const TEST_WALLET_PREFIX: &str = "bdk-ldk-test";
+
+    #[test]
+    #[ignore = "requires the regtest setup"]
+    fn electrum_reports_tx_status_and_script_history() {
+        let client = bdk::electrum_client::Client::new(ELECTRS_ORIGIN).unwrap();
+        let blockchain = ElectrumBlockchain::from(client);
+
+        assert_tx_status_and_script_history(&blockchain);
+    }
+
+    #[cfg(feature = "esplora")]
+    #[test]
+    #[ignore = "requires the regtest setup"]
+    fn esplora_reports_tx_status_and_script_history() {
+        let blockchain = bdk::blockchain::EsploraBlockchain::new(ESPLORA_ORIGIN, 20);
+
+        assert_tx_status_and_script_history(&blockchain);
+    }
+
+    #[test]
+    #[ignore = "requires the regtest setup"]
+    fn bitcoind_rpc_reports_tx_status_and_script_history() {
+        let wallet_name = format!(
+            "{TEST_WALLET_PREFIX}-{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        let blockchain = RpcBlockchain::from_config(&RpcConfig {
+            url: BITCOIND_RPC.to_string(),
+            auth: bdk::blockchain::rpc::Auth::UserPass {
+                username: "admin1".to_string(),
+                password: "123".to_string(),
+            },
+            network: bitcoin::Network::Regtest,
+            wallet_name: wallet_name.clone(),
+            sync_params: None,
+        })
+        .unwrap();
+
+        assert_tx_status_and_script_history(&blockchain);
+
+        // Other clients of bitcoind do not specify a wallet, which fails if more than one wallet
+        // is loaded
+        bitcoind().unload_wallet(Some(&wallet_name)).unwrap();
+    }
+
+    fn assert_tx_status_and_script_history<B: IndexedChain>(blockchain: &B) {
+        let wallet = bitcoind_wallet();
+
+        let address = wallet.get_new_address(None, None).unwrap();
+        let script = address.script_pubkey();
+        blockchain.watch_scripts(&[script.clone()]).unwrap();
+
+        assert!(blockchain
+            .get_tx_status(&Txid::all_zeros())
+            .unwrap()
+            .is_none());
+
+        // Funding the script
+
+        let funding_txid = wallet
+            .send_to_address(
+                &address,
+                Amount::from_sat(100_000),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let status = wait_for_tx_status(blockchain, &funding_txid, |_| true);
+        assert!(!status.confirmed);
+        assert_eq!(status.block_height, None);
+
+        mine(&wallet, 1);
+        let height = wallet.get_block_count().unwrap() as u32;
+
+        let status = wait_for_tx_status(blockchain, &funding_txid, |status| status.confirmed);
+        assert_eq!(status.block_height, Some(height));
+
+        // Spending the funding output
+
+        let funding_tx = wallet.get_transaction(&funding_txid, None).unwrap();
+        let funding_tx = funding_tx.transaction().unwrap();
+        let vout = funding_tx
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == script)
+            .unwrap() as u32;
+
+        let spend_to = wallet.get_new_address(None, None).unwrap();
+        let spending_tx = wallet
+            .create_raw_transaction_hex(
+                &[CreateRawTransactionInput {
+                    txid: funding_txid,
+                    vout,
+                    sequence: None,
+                }],
+                &HashMap::from([(spend_to.to_string(), Amount::from_sat(90_000))]),
+                None,
+                None,
+            )
+            .unwrap();
+        let spending_tx = wallet
+            .sign_raw_transaction_with_wallet(spending_tx, None, None)
+            .unwrap();
+        assert!(spending_tx.complete);
+        let spending_txid = wallet.send_raw_transaction(&spending_tx.hex).unwrap();
+
+        let history = wait_for_script_history(blockchain, &script, 2);
+        let funding = history
+            .iter()
+            .find(|(_, tx)| tx.txid() == funding_txid)
+            .unwrap();
+        assert!(funding.0.confirmed);
+        assert_eq!(funding.0.block_height, Some(height));
+
+        let spending = history
+            .iter()
+            .find(|(_, tx)| tx.txid() == spending_txid)
+            .unwrap();
+        assert!(!spending.0.confirmed);
+        assert!(spending
+            .1
+            .input
+            .iter()
+            .any(|input| input.previous_output == OutPoint::new(funding_txid, vout)));
+
+        mine(&wallet, 1);
+
+        let status = wait_for_tx_status(blockchain, &spending_txid, |status| status.confirmed);
+        assert_eq!(status.block_height, Some(height + 1));
+    }
+
+    fn wait_for_tx_status<B: IndexedChain>(
+        blockchain: &B,
+        txid: &Txid,
+        predicate: impl Fn(&TxStatus) -> bool,
+    ) -> TxStatus {
+        for _ in 0..30 {
+            if let Some(status) = blockchain.get_tx_status(txid).unwrap() {
+                if predicate(&status) {
+                    return status;
+                }
+            }
+
+            sleep(Duration::from_secs(1));
+        }
+
+        panic!("Transaction {txid} did not reach the expected status");
+    }
+
+    fn wait_for_script_history<B: IndexedChain>(
+        blockchain: &B,
+        script: &bitcoin::Script,
+        n_txs: usize,
+    ) -> Vec<(TxStatus, bitcoin::Transaction)> {
+        for _ in 0..30 {
+            let history = blockchain.get_script_tx_history(script).unwrap();
+            if history.len() == n_txs {
+                return history;
+            }
+
+            sleep(Duration::from_secs(1));
+        }
+
+        panic!("Script history did not reach {n_txs} transactions");
+    }
+
+    fn mine(wallet: &Client, n_blocks: u64) {
+        let address = wallet.get_new_address(None, None).unwrap();
+        wallet.generate_to_address(n_blocks, &address).unwrap();
+    }
+
+    fn auth() -> Auth {
+        Auth::UserPass("admin1".to_string(), "123".to_string())
+    }
+
+    fn bitcoind() -> Client {
+        Client::new(&format!("http://{BITCOIND_RPC}"), auth()).unwrap()
+    }
+
+    /// The wallet of the regtest bitcoind, which holds the mined coins
+    fn bitcoind_wallet() -> Client {
+        let wallet_name = bitcoind()
+            .list_wallets()
+            .unwrap()
+            .into_iter()
+            .find(|wallet| !wallet.starts_with(TEST_WALLET_PREFIX))
+            .expect("bitcoind to have a wallet");
+
+        Client::new(
+            &format!("http://{BITCOIND_RPC}/wallet/{wallet_name}"),
+            auth(),
+        )
+        .unwrap()
+    }
+}
@@ -0,0 +1,319 @@
+use crate::TradeParams;
+use anyhow::anyhow;
+use anyhow::Result;
+use dlc_manager::contract::numerical_descriptor::NumericalDescriptor;
+use dlc_manager::contract::ContractDescriptor;
+use dlc_manager::payout_curve::PayoutFunction;
+use dlc_manager::payout_curve::PayoutFunctionPiece;
+use dlc_manager::payout_curve::PayoutPoint;
+use dlc_manager::payout_curve::PolynomialPayoutCurvePiece;
+use dlc_manager::payout_curve::RoundingInterval;
+use dlc_manager::payout_curve::RoundingIntervals;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use trade::cfd::calculate_long_liquidation_price;
+use trade::cfd::calculate_margin;
+use trade::cfd::calculate_pnl;
+use trade::cfd::calculate_short_liquidation_price;
+use trade::cfd::BTCUSD_MAX_PRICE;
+use trade::Direction;
+
+/// The leverage used by the coordinator for all trades.
+pub const COORDINATOR_LEVERAGE: f64 = 1.0;
+
+impl TradeParams {
+    pub fn margin_trader(&self) -> u64 {
+        calculate_margin(self.average_execution_price(), self.quantity, self.leverage)
+    }
+
+    pub fn margin_coordinator(&self) -> u64 {
+        calculate_margin(
+            self.average_execution_price(),
+            self.quantity,
+            COORDINATOR_LEVERAGE,
+        )
+    }
+
+    pub fn leverage_long(&self) -> f64 {
+        match self.direction {
+            Direction::Long => self.leverage,
+            Direction::Short => COORDINATOR_LEVERAGE,
+        }
+    }
+
+    pub fn leverage_short(&self) -> f64 {
+        match self.direction {
+            Direction::Long => COORDINATOR_LEVERAGE,
+            Direction::Short => self.leverage,
+        }
+    }
+
+    /// The unix timestamp at which the contract expires, i.e. the maturity of the oracle event.
+    pub fn maturity_time(&self) -> i64 {
+        self.filled_with.expiry_timestamp.unix_timestamp()
+    }
+
+    /// The ID of the oracle event the contract is settled with.
+    pub fn event_id(&self) -> String {
+        let contract_symbol = self.contract_symbol.label();
+        let maturity_time = self.maturity_time();
+
+        format!("{contract_symbol}{maturity_time}")
+    }
+
+    /// What the trader gets out of the DLC when closing a position opened at `opening_price` at the
+    /// execution price of these trade params.
+    pub fn accept_settlement_amount(&self, opening_price: Decimal) -> Result<u64> {
+        calculate_accept_settlement_amount(
+            opening_price,
+            self.average_execution_price(),
+            self.quantity,
+            self.leverage_long(),
+            self.leverage_short(),
+            self.direction,
+        )
+    }
+
+    /// Builds the contract descriptor of the DLC the coordinator offers for these trade params.
+    pub fn contract_descriptor(&self) -> Result<ContractDescriptor> {
+        build_contract_descriptor(
+            self.margin_coordinator() + self.margin_trader(),
+            self.average_execution_price(),
+            self.leverage_long(),
+            self.leverage_short(),
+        )
+    }
+}
+
+/// Calculates the accept settlement amount based on the pnl.
+fn calculate_accept_settlement_amount(
+    opening_price: Decimal,
+    closing_price: Decimal,
+    quantity: f64,
+    long_leverage: f64,
+    short_leverage: f64,
+    direction: Direction,
+) -> Result<u64> {
+    let pnl = calculate_pnl(
+        opening_price,
+        closing_price,
+        quantity,
+        long_leverage,
+        short_leverage,
+        direction,
+    )?;
+
+    let leverage = match direction {
+        Direction::Long => long_leverage,
+        Direction::Short => short_leverage,
+    };
+
+    let margin_trader = calculate_margin(opening_price, quantity, leverage);
+
+    let accept_settlement_amount = Decimal::from(margin_trader) + Decimal::from(pnl);
+    // the amount can only be positive, adding a safeguard here with the max comparison to
+    // ensure the i64 fits into u64
+    let accept_settlement_amount = accept_settlement_amount
+        .max(Decimal::ZERO)
+        .to_u64()
+        .expect("to fit into u64");
+    Ok(accept_settlement_amount)
+}
+
+fn get_rounding_intervals() -> RoundingIntervals {
+    RoundingIntervals {
+        intervals: vec![RoundingInterval {
+            begin_interval: 0,
+            rounding_mod: 500,
+        }],
+    }
+}
+
+/// Builds the contract descriptor from the point of view of the trader.
+fn build_contract_descriptor(
+    total_collateral: u64,
+    initial_price: Decimal,
+    leverage_long: f64,
+    leverage_short: f64,
+) -> Result<ContractDescriptor> {
+    Ok(ContractDescriptor::Numerical(NumericalDescriptor {
+        payout_function: build_payout_function(
+            total_collateral,
+            initial_price,
+            leverage_long,
+            leverage_short,
+        )?,
+        rounding_intervals: get_rounding_intervals(),
+        difference_params: None,
+        oracle_numeric_infos: dlc_trie::OracleNumericInfo {
+            base: 2,
+            nb_digits: vec![20],
+        },
+    }))
+}
+
+/// Builds a [`PayoutFunction`].
+///
+/// TODO: We are currently building a linear payout function for
+/// simplicity. This is *wrong*. We should build an inverse payout
+/// function like we used to do in ItchySats.
+fn build_payout_function(
+    total_collateral: u64,
+    initial_price: Decimal,
+    leverage_long: f64,
+    leverage_short: f64,
+) -> Result<PayoutFunction> {
+    let leverage_short = Decimal::try_from(leverage_short)?;
+    let liquidation_price_short = calculate_short_liquidation_price(leverage_short, initial_price);
+
+    let leverage_long = Decimal::try_from(leverage_long)?;
+    let liquidation_price_long = calculate_long_liquidation_price(leverage_long, initial_price);
+
+    let lower_limit = liquidation_price_long
+        .floor()
+        .to_u64()
+        .expect("Failed to fit floored liquidation price to u64");
+    let upper_limit = liquidation_price_short
+        .floor()
+        .to_u64()
+        .expect("Failed to fit floored liquidation price to u64");
+
+    let lower_range = PolynomialPayoutCurvePiece::new(vec![
+        PayoutPoint {
+            event_outcome: 0,
+            outcome_payout: 0,
+            extra_precision: 0,
+        },
+        PayoutPoint {
+            event_outcome: lower_limit,
+            outcome_payout: 0,
+            extra_precision: 0,
+        },
+    ])
+    .map_err(|e| anyhow!("{e:#}"))?;
+
+    let middle_range = PolynomialPayoutCurvePiece::new(vec![
+        PayoutPoint {
+            event_outcome: lower_limit,
+            outcome_payout: 0,
+            extra_precision: 0,
+        },
+        PayoutPoint {
+            event_outcome: upper_limit,
+            outcome_payout: total_collateral,
+            extra_precision: 0,
+        },
+    ])
+    .map_err(|e| anyhow!("{e:#}"))?;
+
+    let mut pieces = vec![
+        PayoutFunctionPiece::PolynomialPayoutCurvePiece(lower_range),
+        PayoutFunctionPiece::PolynomialPayoutCurvePiece(middle_range),
+    ];
+
+    // When the upper limit is greater than or equal to the
+    // `BTCUSD_MAX_PRICE`, we don't have to add another curve piece.
+    if upper_limit < BTCUSD_MAX_PRICE {
+        let upper_range = PolynomialPayoutCurvePiece::new(vec![
+            PayoutPoint {
+                event_outcome: upper_limit,
+                outcome_payout: total_collateral,
+                extra_precision: 0,
+            },
+            PayoutPoint {
+                event_outcome: BTCUSD_MAX_PRICE,
+                outcome_payout: total_collateral,
+                extra_precision: 0,
+            },
+        ])
+        .map_err(|e| anyhow!("{e:#}"))?;
+
+        pieces.push(PayoutFunctionPiece::PolynomialPayoutCurvePiece(upper_range));
+    }
+
+    PayoutFunction::new(pieces).map_err(|e| anyhow!("{e:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // some basic sanity tests, that in case the position goes the right or wrong way the settlement
+    // amount is moving correspondingly up or down.
+
+    #[test]
+    fn given_a_long_position_and_a_larger_closing_price() {
+        let opening_price = Decimal::from(22000);
+        let closing_price = Decimal::from(23000);
+        let quantity: f64 = 1.0;
+        let accept_settlement_amount = calculate_accept_settlement_amount(
+            opening_price,
+            closing_price,
+            quantity,
+            1.0,
+            1.0,
+            Direction::Long,
+        )
+        .unwrap();
+
+        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
+        assert!(accept_settlement_amount > margin_trader);
+    }
+
+    #[test]
+    fn given_a_short_position_and_a_larger_closing_price() {
+        let opening_price = Decimal::from(22000);
+        let closing_price = Decimal::from(23000);
+        let quantity: f64 = 1.0;
+        let accept_settlement_amount = calculate_accept_settlement_amount(
+            opening_price,
+            closing_price,
+            quantity,
+            1.0,
+            1.0,
+            Direction::Short,
+        )
+        .unwrap();
+
+        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
+        assert!(accept_settlement_amount < margin_trader);
+    }
+
+    #[test]
+    fn given_a_long_position_and_a_smaller_closing_price() {
+        let opening_price = Decimal::from(23000);
+        let closing_price = Decimal::from(22000);
+        let quantity: f64 = 1.0;
+        let accept_settlement_amount = calculate_accept_settlement_amount(
+            opening_price,
+            closing_price,
+            quantity,
+            1.0,
+            1.0,
+            Direction::Long,
+        )
+        .unwrap();
+
+        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
+        assert!(accept_settlement_amount < margin_trader);
+    }
+
+    #[test]
+    fn given_a_short_position_and_a_smaller_closing_price() {
+        let opening_price = Decimal::from(23000);
+        let closing_price = Decimal::from(22000);
+        let quantity: f64 = 1.0;
+        let accept_settlement_amount = calculate_accept_settlement_amount(
+            opening_price,
+            closing_price,
+            quantity,
+            1.0,
+            1.0,
+            Direction::Short,
+        )
+        .unwrap();
+
+        let margin_trader = calculate_margin(opening_price, quantity, 1.0);
+        assert!(accept_settlement_amount > margin_trader);
+    }
+}
@@ -1,11 +1,22 @@
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
 use bdk::bitcoin::secp256k1::PublicKey;
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::secp256k1::SecretKey;
 use orderbook_commons::FilledWith;
 use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde::Serialize;
+use time::OffsetDateTime;
 use trade::ContractSymbol;
 use trade::Direction;
 
+mod contract;
+mod signature;
+
+pub use contract::COORDINATOR_LEVERAGE;
+
 /// The trade parameters defining the trade execution
 ///
 /// Emitted by the orderbook when a match is found.
@@ -48,3 +59,354 @@ impl TradeParams {
         self.filled_with.average_execution_price()
     }
 }
+
+/// The highest fee rate the app accepts for the transactions of a DLC channel offered by the
+/// coordinator, as both parties pay their share of the fees out of their collateral.
+pub const MAX_DLC_FEE_RATE_SATS_PER_VBYTE: u64 = 100;
+
+/// How long a signed request from the app stays valid, so that it cannot be replayed later on.
+pub const SIGNED_REQUEST_VALIDITY_SECS: i64 = 5 * 60;
+
+/// A request from the app to replace its channel with the coordinator with a bigger one
+///
+/// The coordinator opens a new channel of `channel_capacity_sat` to the app. The app then moves its
+/// balance over to the new channel and cooperatively closes the old one.
+///
+/// The request is signed with the node key of the app, so that nobody else can make the coordinator
+/// lock up funds in a channel with the app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpgradeChannelParams {
+    /// The identity of the app requesting the upgrade
+    pub pubkey: PublicKey,
+
+    /// The capacity of the replacement channel
+    pub channel_capacity_sat: u64,
+
+    /// The unix timestamp at which the request was made
+    pub timestamp: i64,
+
+    /// The DER-encoded ECDSA signature of the app over the SHA256 hash of the request
+    pub signature: String,
+}
+
+impl UpgradeChannelParams {
+    pub fn new(channel_capacity_sat: u64, node_key: &SecretKey) -> Self {
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::signing_only(), node_key);
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        Self {
+            pubkey,
+            channel_capacity_sat,
+            timestamp,
+            signature: signature::sign(
+                &upgrade_channel_message(&pubkey, channel_capacity_sat, timestamp),
+                node_key,
+            ),
+        }
+    }
+
+    /// Checks that the request was signed by `pubkey` within the last
+    /// [`SIGNED_REQUEST_VALIDITY_SECS`]
+    pub fn verify(&self) -> Result<()> {
+        let age = OffsetDateTime::now_utc().unix_timestamp() - self.timestamp;
+        ensure!(
+            age.abs() <= SIGNED_REQUEST_VALIDITY_SECS,
+            "Request timestamp {} is too far off",
+            self.timestamp
+        );
+
+        signature::verify(
+            &upgrade_channel_message(&self.pubkey, self.channel_capacity_sat, self.timestamp),
+            &self.signature,
+            &self.pubkey,
+        )
+        .context("Invalid channel upgrade request signature")
+    }
+}
+
+fn upgrade_channel_message(
+    pubkey: &PublicKey,
+    channel_capacity_sat: u64,
+    timestamp: i64,
+) -> Vec<u8> {
+    let mut message = b"upgrade-channel".to_vec();
+    message.extend_from_slice(&pubkey.serialize());
+    message.extend_from_slice(&channel_capacity_sat.to_be_bytes());
+    message.extend_from_slice(&timestamp.to_be_bytes());
+
+    message
+}
+
+/// The terms under which the coordinator sells inbound liquidity to the app
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LiquidityOffer {
+    /// The price in millisats the app pays per sat of inbound liquidity
+    pub price_msat_per_sat: u64,
+
+    /// For how long the coordinator keeps the purchased liquidity in the channel with the app
+    pub lease_duration_secs: u64,
+
+    pub min_inbound_liquidity_sat: u64,
+    pub max_inbound_liquidity_sat: u64,
+}
+
+impl LiquidityOffer {
+    /// The fee in sats the app pays for `inbound_liquidity_sat`, rounded up
+    pub fn fee_sat(&self, inbound_liquidity_sat: u64) -> u64 {
+        (inbound_liquidity_sat * self.price_msat_per_sat + 999) / 1000
+    }
+}
+
+/// A request from the app to buy inbound liquidity according to the coordinator's
+/// [`LiquidityOffer`]
+///
+/// The request is signed with the node key of the app, so that nobody else can buy liquidity which
+/// the app would then be expected to pay for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityPurchaseParams {
+    /// The identity of the app buying the liquidity
+    pub pubkey: PublicKey,
+
+    pub inbound_liquidity_sat: u64,
+
+    /// The unix timestamp at which the request was made
+    pub timestamp: i64,
+
+    /// The DER-encoded ECDSA signature of the app over the SHA256 hash of the request
+    pub signature: String,
+}
+
+impl LiquidityPurchaseParams {
+    pub fn new(inbound_liquidity_sat: u64, node_key: &SecretKey) -> Self {
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::signing_only(), node_key);
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+
+        Self {
+            pubkey,
+            inbound_liquidity_sat,
+            timestamp,
+            signature: signature::sign(
+                &liquidity_purchase_message(&pubkey, inbound_liquidity_sat, timestamp),
+                node_key,
+            ),
+        }
+    }
+
+    /// Checks that the request was signed by `pubkey` within the last
+    /// [`SIGNED_REQUEST_VALIDITY_SECS`]
+    pub fn verify(&self) -> Result<()> {
+        let age = OffsetDateTime::now_utc().unix_timestamp() - self.timestamp;
+        ensure!(
+            age.abs() <= SIGNED_REQUEST_VALIDITY_SECS,
+            "Request timestamp {} is too far off",
+            self.timestamp
+        );
+
+        signature::verify(
+            &liquidity_purchase_message(&self.pubkey, self.inbound_liquidity_sat, self.timestamp),
+            &self.signature,
+            &self.pubkey,
+        )
+        .context("Invalid liquidity purchase request signature")
+    }
+}
+
+fn liquidity_purchase_message(
+    pubkey: &PublicKey,
+    inbound_liquidity_sat: u64,
+    timestamp: i64,
+) -> Vec<u8> {
+    let mut message = b"liquidity-purchase".to_vec();
+    message.extend_from_slice(&pubkey.serialize());
+    message.extend_from_slice(&inbound_liquidity_sat.to_be_bytes());
+    message.extend_from_slice(&timestamp.to_be_bytes());
+
+    message
+}
+
+/// The coordinator's answer to a [`LiquidityPurchaseParams`] request
+///
+/// Once the app has paid the `invoice`, it upgrades its channel with the coordinator to one which
+/// includes the purchased inbound liquidity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiquidityPurchase {
+    pub invoice: String,
+    pub fee_sat: u64,
+}
+
+/// An encrypted backup of the app's node, which the coordinator stores under the node ID
+///
+/// The backup is signed with the node key together with the node ID and a sequence number, so
+/// that nobody else can replace it and an older backup cannot be replayed over a newer one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    /// Incremented with every backup of the node; the coordinator only accepts increasing ones
+    pub sequence: u64,
+
+    /// The hex-encoded encrypted backup
+    pub backup: String,
+
+    /// The DER-encoded ECDSA signature of the node over the SHA256 hash of the node ID, the
+    /// sequence number and the backup
+    ///
+    /// Missing for backups stored before backups were signed
+    pub signature: Option<String>,
+}
+
+impl Backup {
+    pub fn new(backup: &[u8], sequence: u64, node_key: &SecretKey) -> Self {
+        let node_id = PublicKey::from_secret_key(&Secp256k1::signing_only(), node_key);
+
+        Self {
+            sequence,
+            backup: hex::encode(backup),
+            signature: Some(signature::sign(
+                &backup_message(&node_id, sequence, backup),
+                node_key,
+            )),
+        }
+    }
+
+    /// Returns the backup if it was signed by `node_id`
+    pub fn verify(&self, node_id: &PublicKey) -> Result<Vec<u8>> {
+        let backup = self.unverified_backup()?;
+        let signature = self.signature.as_ref().context("Backup is not signed")?;
+
+        signature::verify(
+            &backup_message(node_id, self.sequence, &backup),
+            signature,
+            node_id,
+        )
+        .context("Backup was not signed by the node")?;
+
+        Ok(backup)
+    }
+
+    /// Returns the backup without checking who signed it, e.g. because it was stored before
+    /// backups were signed.
+    ///
+    /// Only the seed of the node can decrypt the backup, but it may not be the latest one.
+    pub fn unverified_backup(&self) -> Result<Vec<u8>> {
+        hex::decode(&self.backup).context("Invalid backup encoding")
+    }
+}
+
+fn backup_message(node_id: &PublicKey, sequence: u64, backup: &[u8]) -> Vec<u8> {
+    let mut message = b"backup".to_vec();
+    message.extend_from_slice(&node_id.serialize());
+    message.extend_from_slice(&sequence.to_be_bytes());
+    message.extend_from_slice(backup);
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_signed_by_other_node_is_rejected() {
+        let secp = Secp256k1::new();
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let other_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let node_id = PublicKey::from_secret_key(&secp, &node_key);
+
+        let backup = Backup::new(b"backup", 1, &node_key);
+        assert_eq!(backup.verify(&node_id).unwrap(), b"backup");
+
+        let forged = Backup::new(b"backup", 1, &other_key);
+        assert!(forged.verify(&node_id).is_err());
+    }
+
+    #[test]
+    fn unsigned_backup_is_rejected() {
+        let secp = Secp256k1::new();
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let node_id = PublicKey::from_secret_key(&secp, &node_key);
+
+        let unsigned = Backup {
+            signature: None,
+            ..Backup::new(b"backup", 1, &node_key)
+        };
+
+        assert!(unsigned.verify(&node_id).is_err());
+        assert_eq!(unsigned.unverified_backup().unwrap(), b"backup");
+    }
+
+    #[test]
+    fn backup_with_tampered_sequence_is_rejected() {
+        let secp = Secp256k1::new();
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let node_id = PublicKey::from_secret_key(&secp, &node_key);
+
+        let backup = Backup::new(b"backup", 1, &node_key);
+        let tampered = Backup {
+            sequence: 2,
+            ..backup
+        };
+
+        assert!(tampered.verify(&node_id).is_err());
+    }
+
+    #[test]
+    fn upgrade_channel_request_has_to_be_signed_by_the_app() {
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let other_key = SecretKey::from_slice(&[2; 32]).unwrap();
+
+        let params = UpgradeChannelParams::new(500_000, &node_key);
+        params.verify().unwrap();
+
+        let forged = UpgradeChannelParams {
+            pubkey: params.pubkey,
+            ..UpgradeChannelParams::new(500_000, &other_key)
+        };
+        assert!(forged.verify().is_err());
+
+        let tampered = UpgradeChannelParams {
+            channel_capacity_sat: 1_000_000,
+            ..params.clone()
+        };
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn stale_upgrade_channel_request_is_rejected() {
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &node_key);
+        let timestamp =
+            OffsetDateTime::now_utc().unix_timestamp() - SIGNED_REQUEST_VALIDITY_SECS - 1;
+
+        let params = UpgradeChannelParams {
+            pubkey,
+            channel_capacity_sat: 500_000,
+            timestamp,
+            signature: signature::sign(
+                &upgrade_channel_message(&pubkey, 500_000, timestamp),
+                &node_key,
+            ),
+        };
+
+        assert!(params.verify().is_err());
+    }
+
+    #[test]
+    fn liquidity_purchase_request_has_to_be_signed_by_the_app() {
+        let node_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let other_key = SecretKey::from_slice(&[2; 32]).unwrap();
+
+        let params = LiquidityPurchaseParams::new(100_000, &node_key);
+        params.verify().unwrap();
+
+        let forged = LiquidityPurchaseParams {
+            pubkey: params.pubkey,
+            ..LiquidityPurchaseParams::new(100_000, &other_key)
+        };
+        assert!(forged.verify().is_err());
+
+        let tampered = LiquidityPurchaseParams {
+            inbound_liquidity_sat: 200_000,
+            ..params.clone()
+        };
+        assert!(tampered.verify().is_err());
+    }
+}
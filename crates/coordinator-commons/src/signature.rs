@@ -0,0 +1,31 @@
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::secp256k1::ecdsa::Signature;
+use bdk::bitcoin::secp256k1::Message;
+use bdk::bitcoin::secp256k1::PublicKey;
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::secp256k1::SecretKey;
+use std::str::FromStr;
+
+/// Signs the SHA256 hash of `message` with the node key, returning the DER-encoded signature.
+pub(crate) fn sign(message: &[u8], node_key: &SecretKey) -> String {
+    Secp256k1::signing_only()
+        .sign_ecdsa(&hashed(message), node_key)
+        .to_string()
+}
+
+/// Checks that `signature` is a signature of `node_id` over the SHA256 hash of `message`.
+pub(crate) fn verify(message: &[u8], signature: &str, node_id: &PublicKey) -> Result<()> {
+    let signature = Signature::from_str(signature).context("Invalid signature")?;
+
+    Secp256k1::verification_only()
+        .verify_ecdsa(&hashed(message), &signature, node_id)
+        .context("Not signed by the node")?;
+
+    Ok(())
+}
+
+fn hashed(message: &[u8]) -> Message {
+    Message::from_hashed_data::<sha256::Hash>(message)
+}
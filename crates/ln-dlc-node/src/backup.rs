@@ -0,0 +1,558 @@
+//! Encrypted backups of the state needed to recover the LN channels and DLCs of a node on a new
+//! device.
+//!
+//! A backup contains the channel manager and channel monitors persisted by LDK and the DLC
+//! sub-channels and signed contracts of `rust-dlc`. It is encrypted with a key derived from the
+//! [`Bip39Seed`], so it can be stored with an untrusted party and only be restored together with
+//! the seed.
+
+use crate::dlc_custom_signer::CustomKeysManager;
+use crate::node::ChannelManager;
+use crate::node::Node;
+use crate::node::SubChannelManager;
+use crate::seed::Bip39Seed;
+use crate::storage::SqliteStorage;
+use crate::storage::STORAGE_FILE;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::Secp256k1;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::Key;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::Nonce;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::subchannel::SubChannel;
+use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::ChannelId;
+use dlc_manager::Storage;
+use lightning::chain::keysinterface::KeysInterface;
+use lightning::chain::keysinterface::Recipient;
+use lightning::ln::msgs::DecodeError;
+use lightning::util::persist::KVStorePersister;
+use lightning::util::ser::Readable;
+use lightning::util::ser::Writeable;
+use lightning::util::ser::Writer;
+use lightning_persister::FilesystemPersister;
+use rand::RngCore;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::watch;
+
+const BACKUP_VERSION: u8 = 1;
+
+const NONCE_LEN: usize = 12;
+
+/// How many DLC keys we re-derive from the seed when restoring a backup.
+///
+/// The keys are derived from consecutive indices, so this covers every DLC key of a node which
+/// has used fewer keys.
+const DLC_KEYS_LOOKAHEAD: u32 = 1_000;
+
+/// Present in the data dir of a node restored from a backup until its channels have been closed.
+///
+/// Empty until the restored channels have been looked at on startup, then it lists the DLC
+/// channels which still have to be force-closed.
+const RESTORED_FROM_BACKUP_MARKER: &str = "restored_from_backup";
+
+const SIGNED_CONTRACT: u8 = 0;
+const CONFIRMED_CONTRACT: u8 = 1;
+
+/// Persists LDK's state like the [`FilesystemPersister`] and signals that a new backup is needed
+/// whenever the channel manager or a channel monitor changes.
+pub(crate) struct BackupPersister {
+    inner: FilesystemPersister,
+    backup_needed: Arc<watch::Sender<()>>,
+}
+
+impl BackupPersister {
+    pub(crate) fn new(inner: FilesystemPersister, backup_needed: Arc<watch::Sender<()>>) -> Self {
+        Self {
+            inner,
+            backup_needed,
+        }
+    }
+
+    pub(crate) fn inner(&self) -> &FilesystemPersister {
+        &self.inner
+    }
+}
+
+impl KVStorePersister for BackupPersister {
+    fn persist<W: Writeable>(&self, key: &str, object: &W) -> std::io::Result<()> {
+        self.inner.persist(key, object)?;
+
+        if is_backed_up(key) {
+            self.backup_needed.send_replace(());
+        }
+
+        Ok(())
+    }
+}
+
+/// The state of a node needed to recover its LN channels and DLCs.
+struct Snapshot {
+    /// Files written by LDK, keyed by their path relative to the data dir.
+    ldk_files: BTreeMap<String, Vec<u8>>,
+    sub_channels: Vec<SubChannel>,
+    /// Signed contracts, tagged with whether they are confirmed.
+    contracts: Vec<(u8, SignedContract)>,
+}
+
+impl Node {
+    /// Notifies about every change to the state included in [`Node::create_backup`].
+    pub fn subscribe_to_backup_updates(&self) -> watch::Receiver<()> {
+        self.backup_needed.subscribe()
+    }
+
+    /// Creates an encrypted backup which can be restored with [`restore`].
+    pub fn create_backup(&self) -> Result<Vec<u8>> {
+        let ldk_files = read_ldk_files(&self.data_dir)?;
+
+        let store = self.dlc_manager.get_store();
+        let sub_channels = store
+            .get_sub_channels()
+            .map_err(|e| anyhow!("Failed to get DLC channels: {e:#}"))?;
+        let contracts = store
+            .get_contracts()
+            .map_err(|e| anyhow!("Failed to get contracts: {e:#}"))?
+            .into_iter()
+            .filter_map(|contract| match contract {
+                Contract::Signed(contract) => Some((SIGNED_CONTRACT, contract)),
+                Contract::Confirmed(contract) => Some((CONFIRMED_CONTRACT, contract)),
+                _ => None,
+            })
+            .collect();
+
+        let snapshot = Snapshot {
+            ldk_files,
+            sub_channels,
+            contracts,
+        };
+
+        encrypt(&snapshot.encode(), &self.backup_encryption_key)
+    }
+
+    /// Returns the sequence number for the next backup, which has to be higher than the one of
+    /// every previous backup.
+    pub fn next_backup_sequence(&self) -> Result<u64> {
+        self.dlc_manager
+            .get_store()
+            .next_backup_sequence()
+            .map_err(|e| anyhow!("Failed to get next backup sequence: {e:#}"))
+    }
+
+    /// Closes the channels restored from a backup.
+    ///
+    /// The restored state may be outdated, so broadcasting our own commitment transaction could
+    /// get our funds penalised. Channels without a DLC are therefore closed by asking the
+    /// counterparty to broadcast its commitment transaction, while the channel monitors stay
+    /// around to claim our outputs.
+    ///
+    /// The DLC of a DLC channel can only be recovered on-chain through the split transaction, so
+    /// we have to force-close those channels ourselves. We only do so once the counterparty has
+    /// confirmed that the restored state is the latest one, see
+    /// [`close_dlc_channels_restored_from_backup`]. Until then, these channels are recorded in the
+    /// marker.
+    pub(crate) fn close_channels_restored_from_backup(&self) -> Result<()> {
+        let marker = self.data_dir.join(RESTORED_FROM_BACKUP_MARKER);
+        if !marker.exists() {
+            return Ok(());
+        }
+
+        let store = self.dlc_manager.get_store();
+
+        let mut dlc_channels = Vec::new();
+        for channel in self.channel_manager.list_channels() {
+            let channel_id = channel.channel_id;
+
+            let has_signed_dlc = match store.get_sub_channel(channel_id) {
+                Ok(Some(sub_channel)) => matches!(sub_channel.state, SubChannelState::Signed(_)),
+                Ok(None) => false,
+                Err(e) => {
+                    tracing::error!(
+                        channel_id = %hex::encode(channel_id),
+                        "Failed to get DLC channel restored from backup: {e:#}"
+                    );
+                    false
+                }
+            };
+
+            if has_signed_dlc {
+                tracing::info!(
+                    channel_id = %hex::encode(channel_id),
+                    counterparty = %channel.counterparty.node_id,
+                    "Closing DLC channel restored from backup once the counterparty confirms its state"
+                );
+                dlc_channels.push(channel_id);
+                continue;
+            }
+
+            tracing::info!(
+                channel_id = %hex::encode(channel_id),
+                counterparty = %channel.counterparty.node_id,
+                "Closing channel restored from backup"
+            );
+
+            if let Err(e) = self
+                .channel_manager
+                .force_close_without_broadcasting_txn(&channel_id, &channel.counterparty.node_id)
+            {
+                tracing::error!(
+                    channel_id = %hex::encode(channel_id),
+                    "Failed to close channel restored from backup: {e:?}"
+                );
+            }
+        }
+
+        write_restored_dlc_channels(&marker, &dlc_channels)
+    }
+}
+
+/// Force-closes the DLC channels restored from a backup, once the counterparty has confirmed that
+/// the restored state is the latest one.
+///
+/// A channel only becomes usable again after reestablishing it with the counterparty. If the
+/// counterparty proves that our state is outdated, LDK closes the channel without broadcasting
+/// our commitment transaction instead, and the DLC can only be settled by the counterparty.
+///
+/// Returns whether all DLC channels restored from a backup are dealt with.
+pub(crate) fn close_dlc_channels_restored_from_backup(
+    data_dir: &Path,
+    channel_manager: &ChannelManager,
+    sub_channel_manager: &SubChannelManager,
+) -> Result<bool> {
+    let marker = data_dir.join(RESTORED_FROM_BACKUP_MARKER);
+    if !marker.exists() {
+        return Ok(true);
+    }
+
+    // The channels restored from the backup have not been looked at yet
+    let restored_dlc_channels = fs::read_to_string(&marker)?;
+    if restored_dlc_channels.is_empty() {
+        return Ok(false);
+    }
+
+    let channels = channel_manager.list_channels();
+
+    let mut pending = Vec::new();
+    for channel_id in restored_dlc_channels.lines() {
+        let channel_id: ChannelId = hex::decode(channel_id)?
+            .try_into()
+            .map_err(|_| anyhow!("Invalid channel ID {channel_id}"))?;
+
+        match channels
+            .iter()
+            .find(|channel| channel.channel_id == channel_id)
+        {
+            Some(channel) if channel.is_usable => {
+                tracing::info!(
+                    channel_id = %hex::encode(channel_id),
+                    "Force-closing DLC channel restored from backup"
+                );
+
+                if let Err(e) = sub_channel_manager.force_close_sub_channel(&channel_id) {
+                    tracing::error!(
+                        channel_id = %hex::encode(channel_id),
+                        "Failed to force-close DLC channel restored from backup: {e:?}"
+                    );
+                    pending.push(channel_id);
+                }
+            }
+            Some(_) => pending.push(channel_id),
+            None => {
+                tracing::warn!(
+                    channel_id = %hex::encode(channel_id),
+                    "DLC channel restored from backup was closed without us, its state was probably outdated"
+                );
+            }
+        }
+    }
+
+    write_restored_dlc_channels(&marker, &pending)?;
+
+    Ok(pending.is_empty())
+}
+
+/// Records the DLC channels restored from a backup which still have to be force-closed in the
+/// `marker`, removing it if there are none.
+fn write_restored_dlc_channels(marker: &Path, dlc_channels: &[ChannelId]) -> Result<()> {
+    if dlc_channels.is_empty() {
+        fs::remove_file(marker)?;
+        return Ok(());
+    }
+
+    let dlc_channels = dlc_channels
+        .iter()
+        .map(|channel_id| format!("{}\n", hex::encode(channel_id)))
+        .collect::<String>();
+    fs::write(marker, dlc_channels)?;
+
+    Ok(())
+}
+
+/// The ID of the node of the `seed`, under which its backups are stored.
+pub fn node_id(seed: &Bip39Seed) -> Result<PublicKey> {
+    let keys_manager = CustomKeysManager::new(
+        seed.lightning_seed(),
+        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?,
+    )?;
+    let node_secret = keys_manager
+        .get_node_secret(Recipient::Node)
+        .map_err(|()| anyhow!("Failed to get node secret"))?;
+
+    Ok(PublicKey::from_secret_key(&Secp256k1::new(), &node_secret))
+}
+
+/// Restores the encrypted `backup` with the given `sequence` number of the node of the `seed` into
+/// the empty `data_dir`.
+///
+/// The channels of the restored node are closed when it is started.
+pub fn restore(data_dir: &Path, seed: &Bip39Seed, backup: &[u8], sequence: u64) -> Result<()> {
+    ensure!(
+        !data_dir.join("manager").exists(),
+        "Refusing to restore backup over existing channel state in {data_dir:?}"
+    );
+
+    let snapshot = decrypt(backup, &seed.backup_encryption_key())?;
+    let snapshot = Snapshot::read(&mut Cursor::new(snapshot))
+        .map_err(|e| anyhow!("Failed to decode backup: {e:?}"))?;
+
+    for (path, contents) in snapshot.ldk_files.iter() {
+        let path = data_dir.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, contents)?;
+    }
+
+    let storage = SqliteStorage::open(&data_dir.join(STORAGE_FILE))?;
+
+    storage
+        .set_next_backup_sequence(sequence + 1)
+        .map_err(|e| anyhow!("Failed to restore backup sequence: {e:#}"))?;
+
+    for sub_channel in snapshot.sub_channels.iter() {
+        storage
+            .upsert_sub_channel(sub_channel)
+            .map_err(|e| anyhow!("Failed to restore DLC channel: {e:#}"))?;
+    }
+
+    for (tag, contract) in snapshot.contracts {
+        let contract = match tag {
+            SIGNED_CONTRACT => Contract::Signed(contract),
+            CONFIRMED_CONTRACT => Contract::Confirmed(contract),
+            _ => bail!("Unknown contract tag {tag}"),
+        };
+
+        storage
+            .update_contract(&contract)
+            .map_err(|e| anyhow!("Failed to restore contract: {e:#}"))?;
+    }
+
+    let keys_manager = CustomKeysManager::new(
+        seed.lightning_seed(),
+        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?,
+    )?;
+    let secp = Secp256k1::new();
+    for index in 0..DLC_KEYS_LOOKAHEAD {
+        let sk = keys_manager.derive_dlc_secret_key(index)?;
+        let pk = PublicKey::from_secret_key(&secp, &sk);
+
+        storage
+            .upsert_key_pair(&pk, &sk)
+            .map_err(|e| anyhow!("Failed to restore DLC key: {e:#}"))?;
+    }
+
+    fs::write(data_dir.join(RESTORED_FROM_BACKUP_MARKER), [])?;
+
+    tracing::info!(
+        ldk_files = snapshot.ldk_files.len(),
+        sub_channels = snapshot.sub_channels.len(),
+        "Restored backup"
+    );
+
+    Ok(())
+}
+
+/// Whether the file persisted by LDK under `key` is part of the backup.
+///
+/// The network graph and the scorer can be rebuilt, so we leave them out.
+fn is_backed_up(key: &str) -> bool {
+    key == "manager" || key.starts_with("monitors")
+}
+
+fn read_ldk_files(data_dir: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+
+    let manager = data_dir.join("manager");
+    if manager.exists() {
+        files.insert("manager".to_string(), fs::read(manager)?);
+    }
+
+    let monitors = data_dir.join("monitors");
+    if monitors.exists() {
+        for entry in fs::read_dir(monitors)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let file_name = file_name.to_str().context("Invalid monitor file name")?;
+
+            // Temporary files of the `FilesystemPersister`
+            if file_name.ends_with(".tmp") {
+                continue;
+            }
+
+            files.insert(format!("monitors/{file_name}"), fs::read(entry.path())?);
+        }
+    }
+
+    Ok(files)
+}
+
+fn encrypt(plaintext: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt backup"))?;
+
+    Ok([&[BACKUP_VERSION][..], &nonce, &ciphertext].concat())
+}
+
+fn decrypt(backup: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
+    let (version, backup) = backup.split_first().context("Empty backup")?;
+    ensure!(
+        *version == BACKUP_VERSION,
+        "Unsupported backup version {version}"
+    );
+    ensure!(backup.len() > NONCE_LEN, "Backup is too short");
+
+    let (nonce, ciphertext) = backup.split_at(NONCE_LEN);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt backup, was it created with a different seed?"))
+}
+
+impl Writeable for Snapshot {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        (self.ldk_files.len() as u32).write(writer)?;
+        for (path, contents) in self.ldk_files.iter() {
+            write_bytes(writer, path.as_bytes())?;
+            write_bytes(writer, contents)?;
+        }
+
+        (self.sub_channels.len() as u32).write(writer)?;
+        for sub_channel in self.sub_channels.iter() {
+            write_bytes(writer, &sub_channel.serialize()?)?;
+        }
+
+        (self.contracts.len() as u32).write(writer)?;
+        for (tag, contract) in self.contracts.iter() {
+            tag.write(writer)?;
+            write_bytes(writer, &contract.serialize()?)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Readable for Snapshot {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let n_ldk_files: u32 = Readable::read(reader)?;
+        let mut ldk_files = BTreeMap::new();
+        for _ in 0..n_ldk_files {
+            let path =
+                String::from_utf8(read_bytes(reader)?).map_err(|_| DecodeError::InvalidValue)?;
+            ldk_files.insert(path, read_bytes(reader)?);
+        }
+
+        let n_sub_channels: u32 = Readable::read(reader)?;
+        let mut sub_channels = Vec::new();
+        for _ in 0..n_sub_channels {
+            let sub_channel = read_bytes(reader)?;
+            sub_channels.push(SubChannel::deserialize(&mut Cursor::new(sub_channel))?);
+        }
+
+        let n_contracts: u32 = Readable::read(reader)?;
+        let mut contracts = Vec::new();
+        for _ in 0..n_contracts {
+            let tag: u8 = Readable::read(reader)?;
+            let contract = read_bytes(reader)?;
+            contracts.push((
+                tag,
+                SignedContract::deserialize(&mut Cursor::new(contract))?,
+            ));
+        }
+
+        Ok(Self {
+            ldk_files,
+            sub_channels,
+            contracts,
+        })
+    }
+}
+
+/// Writes length-prefixed `bytes`, which unlike LDK's serialisation of `Vec<u8>` supports more
+/// than `u16::MAX` bytes.
+fn write_bytes<W: Writer>(writer: &mut W, bytes: &[u8]) -> Result<(), std::io::Error> {
+    (bytes.len() as u32).write(writer)?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, DecodeError> {
+    let len: u32 = Readable::read(reader)?;
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_can_only_be_decrypted_with_the_same_seed() {
+        let seed = Bip39Seed::new().unwrap();
+        let other_seed = Bip39Seed::new().unwrap();
+
+        let snapshot = Snapshot {
+            ldk_files: BTreeMap::from([
+                ("manager".to_string(), vec![1; 70_000]),
+                ("monitors/abc_0".to_string(), vec![2; 100]),
+            ]),
+            sub_channels: Vec::new(),
+            contracts: Vec::new(),
+        };
+
+        let backup = encrypt(&snapshot.encode(), &seed.backup_encryption_key()).unwrap();
+
+        let decrypted = decrypt(&backup, &seed.backup_encryption_key()).unwrap();
+        let restored = Snapshot::read(&mut Cursor::new(decrypted)).unwrap();
+        assert_eq!(restored.ldk_files, snapshot.ldk_files);
+
+        assert!(decrypt(&backup, &other_seed.backup_encryption_key()).is_err());
+    }
+
+    #[test]
+    fn only_channel_state_is_backed_up() {
+        assert!(is_backed_up("manager"));
+        assert!(is_backed_up("monitors/abc_0"));
+        assert!(!is_backed_up("network_graph"));
+        assert!(!is_backed_up("scorer"));
+    }
+}
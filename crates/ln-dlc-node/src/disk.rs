@@ -1,12 +1,21 @@
 use crate::ln::TracingLogger;
 use crate::NetworkGraph;
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
 use bitcoin::BlockHash;
 use lightning::routing::scoring::ProbabilisticScorer;
 use lightning::routing::scoring::ProbabilisticScoringParameters;
+use lightning::util::ser::Readable;
 use lightning::util::ser::ReadableArgs;
+use lightning::util::ser::Writeable;
+use std::fs;
 use std::fs::File;
 use std::io::BufReader;
+use std::io::Cursor;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub(crate) fn read_scorer(
@@ -38,3 +47,122 @@ pub(crate) fn read_network(
     }
     NetworkGraph::new(genesis_hash, logger)
 }
+
+/// The version of the format of the files of a [`FileStore`], written as their first byte.
+///
+/// Must be increased whenever the encoding of a stored value changes incompatibly.
+const FILE_STORE_VERSION: u8 = 1;
+
+/// Persists values in a directory, one file per key, so that each of them can be updated
+/// atomically.
+pub(crate) struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        if !dir.exists() {
+            fs::create_dir_all(dir).with_context(|| format!("Could not create dir ({dir:?})"))?;
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub(crate) fn insert(&self, key: &str, value: &impl Writeable) -> Result<()> {
+        let path = self.dir.join(key);
+        let tmp_path = path.with_extension("tmp");
+
+        let mut bytes = vec![FILE_STORE_VERSION];
+        bytes.extend(value.encode());
+
+        fs::write(&tmp_path, bytes).with_context(|| format!("Could not write to {tmp_path:?}"))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Could not move {tmp_path:?} to {path:?}"))?;
+
+        Ok(())
+    }
+
+    pub(crate) fn get<T: Readable>(&self, key: &str) -> Result<Option<T>> {
+        let path = self.dir.join(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(&path)?;
+        let value = decode(&bytes).with_context(|| format!("Failed to read {path:?}"))?;
+
+        Ok(Some(value))
+    }
+
+    /// Removes the value stored under `key`, if any.
+    pub(crate) fn remove(&self, key: &str) -> Result<()> {
+        let path = self.dir.join(key);
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| format!("Could not remove {path:?}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns all stored values. Values which cannot be read are logged and skipped.
+    pub(crate) fn all<T: Readable>(&self) -> Result<Vec<T>> {
+        let mut values = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some() {
+                // Ignore partially written files
+                continue;
+            }
+
+            let bytes = fs::read(&path)?;
+            match decode(&bytes) {
+                Ok(value) => values.push(value),
+                Err(e) => tracing::error!(?path, "Failed to read stored value: {e:#}"),
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+fn decode<T: Readable>(bytes: &[u8]) -> Result<T> {
+    let (version, value) = bytes.split_first().context("Empty file")?;
+    ensure!(
+        *version == FILE_STORE_VERSION,
+        "Unsupported format version {version}"
+    );
+
+    T::read(&mut Cursor::new(value)).map_err(|e| anyhow!("Invalid value: {e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn stored_value_round_trips() {
+        let dir = TempDir::new();
+        let store = FileStore::new(dir.path()).unwrap();
+
+        store.insert("key", &42u64).unwrap();
+
+        assert_eq!(store.get::<u64>("key").unwrap(), Some(42));
+        assert_eq!(store.all::<u64>().unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn value_of_unknown_format_version_is_rejected() {
+        let dir = TempDir::new();
+        let store = FileStore::new(dir.path()).unwrap();
+
+        let mut bytes = vec![FILE_STORE_VERSION + 1];
+        bytes.extend(42u64.encode());
+        fs::write(dir.path().join("key"), bytes).unwrap();
+
+        assert!(store.get::<u64>("key").is_err());
+        assert!(store.all::<u64>().unwrap().is_empty());
+    }
+}
@@ -1,8 +1,15 @@
-//! This file has temporarily been copied from `https://github.com/p2pderivatives/rust-dlc/pull/97`.
-//! We should reimplement some of these traits for production.
+//! Signer and keys manager which allow `rust-dlc` to sign the transactions of DLC sub-channels
+//! with the keys of the LN channel they live in.
+//!
+//! Originally based on `https://github.com/p2pderivatives/rust-dlc/pull/97`.
 
+use crate::seed::LightningSeed;
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
+use bitcoin::util::bip32::ChildNumber;
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin::Network;
 use bitcoin::Script;
 use bitcoin::Transaction;
 use bitcoin::TxOut;
@@ -19,34 +26,72 @@ use lightning::ln::chan_utils::ChannelPublicKeys;
 use lightning::ln::msgs::DecodeError;
 use lightning::ln::script::ShutdownScript;
 use lightning::util::ser::Writeable;
+use lightning::util::ser::Writer;
 use secp256k1_zkp::ecdsa::RecoverableSignature;
+use secp256k1_zkp::All;
 use secp256k1_zkp::Secp256k1;
 use secp256k1_zkp::SecretKey;
 use secp256k1_zkp::Signing;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
+/// Marks a serialized [`CustomSigner`].
+///
+/// Signers persisted before we versioned the serialization are plain [`InMemorySigner`]s, which
+/// start with LDK's serialization version instead.
+const SERIALIZATION_MARKER: u8 = 0xdc;
+
+const SERIALIZATION_VERSION: u8 = 1;
+
+/// The hardened child of the master key derived from the [`LightningSeed`] under which we derive
+/// the keys of our DLCs.
+///
+/// LDK's [`KeysManager`] derives its keys from the same master key, using the children 0' to 6'.
+const DLC_KEYS_CHILD_NUMBER: u32 = 10101;
+
+/// Signer of an LN channel which may host a DLC sub-channel.
+#[derive(Clone)]
 pub struct CustomSigner {
-    in_memory_signer: Arc<Mutex<InMemorySigner>>,
-    // TODO(tibo): this might not be safe.
-    channel_public_keys: ChannelPublicKeys,
+    in_memory_signer: InMemorySigner,
+    /// The channel value set through [`ExtraSign::set_channel_value_satoshis`] when the DLC
+    /// sub-channel is set up or closed.
+    ///
+    /// Clones share it, so that the copies held by the channel and its channel monitor sign with
+    /// the updated channel value.
+    channel_value_satoshis: Arc<Mutex<Option<u64>>>,
 }
 
 impl CustomSigner {
     pub fn new(in_memory_signer: InMemorySigner) -> Self {
         Self {
-            channel_public_keys: in_memory_signer.pubkeys().clone(),
-            in_memory_signer: Arc::new(Mutex::new(in_memory_signer)),
+            in_memory_signer,
+            channel_value_satoshis: Arc::new(Mutex::new(None)),
         }
     }
-}
 
-impl Clone for CustomSigner {
-    fn clone(&self) -> Self {
-        Self {
-            in_memory_signer: self.in_memory_signer.clone(),
-            channel_public_keys: self.channel_public_keys.clone(),
+    /// The [`InMemorySigner`] with the current channel value.
+    fn signer(&self) -> InMemorySigner {
+        let mut signer = self.in_memory_signer.clone();
+        if let Some(channel_value_satoshis) = *self.channel_value_satoshis.lock().unwrap() {
+            signer.set_channel_value_satoshis(channel_value_satoshis);
         }
+
+        signer
+    }
+
+    /// Reads a signer written by [`CustomSigner::write`] or, if it was persisted before we
+    /// versioned the serialization, a plain [`InMemorySigner`].
+    fn read(keys_manager: &KeysManager, reader: &[u8]) -> Result<Self, DecodeError> {
+        let in_memory_signer = match reader {
+            [SERIALIZATION_MARKER, SERIALIZATION_VERSION, in_memory_signer @ ..] => {
+                keys_manager.read_chan_signer(in_memory_signer)?
+            }
+            [SERIALIZATION_MARKER, ..] => return Err(DecodeError::UnknownVersion),
+            legacy => keys_manager.read_chan_signer(legacy)?,
+        };
+
+        Ok(Self::new(in_memory_signer))
     }
 }
 
@@ -57,16 +102,11 @@ impl BaseSign for CustomSigner {
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> secp256k1_zkp::PublicKey {
         self.in_memory_signer
-            .lock()
-            .unwrap()
             .get_per_commitment_point(idx, secp_ctx)
     }
 
     fn release_commitment_secret(&self, idx: u64) -> [u8; 32] {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .release_commitment_secret(idx)
+        self.in_memory_signer.release_commitment_secret(idx)
     }
 
     fn validate_holder_commitment(
@@ -74,18 +114,16 @@ impl BaseSign for CustomSigner {
         holder_tx: &lightning::ln::chan_utils::HolderCommitmentTransaction,
         preimages: Vec<lightning::ln::PaymentPreimage>,
     ) -> Result<(), ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
+        self.signer()
             .validate_holder_commitment(holder_tx, preimages)
     }
 
     fn pubkeys(&self) -> &ChannelPublicKeys {
-        &self.channel_public_keys
+        self.in_memory_signer.pubkeys()
     }
 
     fn channel_keys_id(&self) -> [u8; 32] {
-        self.in_memory_signer.lock().unwrap().channel_keys_id()
+        self.in_memory_signer.channel_keys_id()
     }
 
     fn sign_counterparty_commitment(
@@ -100,17 +138,12 @@ impl BaseSign for CustomSigner {
         ),
         (),
     > {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
+        self.signer()
             .sign_counterparty_commitment(commitment_tx, preimages, secp_ctx)
     }
 
     fn validate_counterparty_revocation(&self, idx: u64, secret: &SecretKey) -> Result<(), ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .validate_counterparty_revocation(idx, secret)
+        self.signer().validate_counterparty_revocation(idx, secret)
     }
 
     fn sign_holder_commitment_and_htlcs(
@@ -124,9 +157,7 @@ impl BaseSign for CustomSigner {
         ),
         (),
     > {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
+        self.signer()
             .sign_holder_commitment_and_htlcs(commitment_tx, secp_ctx)
     }
 
@@ -138,10 +169,13 @@ impl BaseSign for CustomSigner {
         per_commitment_key: &SecretKey,
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> Result<secp256k1_zkp::ecdsa::Signature, ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_justice_revoked_output(justice_tx, input, amount, per_commitment_key, secp_ctx)
+        self.signer().sign_justice_revoked_output(
+            justice_tx,
+            input,
+            amount,
+            per_commitment_key,
+            secp_ctx,
+        )
     }
 
     fn sign_justice_revoked_htlc(
@@ -153,17 +187,14 @@ impl BaseSign for CustomSigner {
         htlc: &lightning::ln::chan_utils::HTLCOutputInCommitment,
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> Result<secp256k1_zkp::ecdsa::Signature, ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_justice_revoked_htlc(
-                justice_tx,
-                input,
-                amount,
-                per_commitment_key,
-                htlc,
-                secp_ctx,
-            )
+        self.signer().sign_justice_revoked_htlc(
+            justice_tx,
+            input,
+            amount,
+            per_commitment_key,
+            htlc,
+            secp_ctx,
+        )
     }
 
     fn sign_counterparty_htlc_transaction(
@@ -175,17 +206,14 @@ impl BaseSign for CustomSigner {
         htlc: &lightning::ln::chan_utils::HTLCOutputInCommitment,
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> Result<secp256k1_zkp::ecdsa::Signature, ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_counterparty_htlc_transaction(
-                htlc_tx,
-                input,
-                amount,
-                per_commitment_point,
-                htlc,
-                secp_ctx,
-            )
+        self.signer().sign_counterparty_htlc_transaction(
+            htlc_tx,
+            input,
+            amount,
+            per_commitment_point,
+            htlc,
+            secp_ctx,
+        )
     }
 
     fn sign_closing_transaction(
@@ -193,10 +221,7 @@ impl BaseSign for CustomSigner {
         closing_tx: &lightning::ln::chan_utils::ClosingTransaction,
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> Result<secp256k1_zkp::ecdsa::Signature, ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_closing_transaction(closing_tx, secp_ctx)
+        self.signer().sign_closing_transaction(closing_tx, secp_ctx)
     }
 
     fn sign_channel_announcement(
@@ -210,10 +235,7 @@ impl BaseSign for CustomSigner {
         ),
         (),
     > {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_channel_announcement(msg, secp_ctx)
+        self.signer().sign_channel_announcement(msg, secp_ctx)
     }
 
     fn sign_holder_anchor_input(
@@ -222,9 +244,7 @@ impl BaseSign for CustomSigner {
         input: usize,
         secp_ctx: &Secp256k1<bitcoin::secp256k1::All>,
     ) -> Result<secp256k1_zkp::ecdsa::Signature, ()> {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
+        self.signer()
             .sign_holder_anchor_input(anchor_tx, input, secp_ctx)
     }
 
@@ -233,8 +253,6 @@ impl BaseSign for CustomSigner {
         channel_parameters: &lightning::ln::chan_utils::ChannelTransactionParameters,
     ) {
         self.in_memory_signer
-            .lock()
-            .unwrap()
             .provide_channel_parameters(channel_parameters);
     }
 }
@@ -244,35 +262,68 @@ impl ExtraSign for CustomSigner {
     where
         F: FnMut(&SecretKey),
     {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .sign_with_fund_key_callback(cb)
+        self.in_memory_signer.sign_with_fund_key_callback(cb)
     }
 
     fn set_channel_value_satoshis(&mut self, value: u64) {
-        self.in_memory_signer
-            .lock()
-            .unwrap()
-            .set_channel_value_satoshis(value)
+        *self.channel_value_satoshis.lock().unwrap() = Some(value);
     }
 }
 
 impl Writeable for CustomSigner {
-    fn write<W: lightning::util::ser::Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
-        self.in_memory_signer.lock().unwrap().write(writer)
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        writer.write_all(&[SERIALIZATION_MARKER, SERIALIZATION_VERSION])?;
+        self.signer().write(writer)
     }
 }
 
 impl Sign for CustomSigner {}
 
+/// Keys manager deriving every key needed to sign LN channel and DLC sub-channel transactions
+/// from the [`LightningSeed`].
 pub struct CustomKeysManager {
     keys_manager: KeysManager,
+    dlc_keys_master_key: ExtendedPrivKey,
+    secp: Secp256k1<All>,
 }
 
 impl CustomKeysManager {
-    pub fn new(keys_manager: KeysManager) -> Self {
-        Self { keys_manager }
+    /// Creates a keys manager for the `seed`.
+    ///
+    /// The `starting_time` only seeds the ephemeral randomness of LDK's [`KeysManager`], so a
+    /// keys manager restored from the same seed derives the same keys.
+    pub fn new(seed: LightningSeed, starting_time: Duration) -> Result<Self> {
+        let keys_manager =
+            KeysManager::new(&seed, starting_time.as_secs(), starting_time.subsec_nanos());
+
+        let secp = Secp256k1::new();
+
+        // The network is irrelevant for the keys we derive. We use the same as LDK's
+        // `KeysManager` for consistency.
+        let dlc_keys_master_key = ExtendedPrivKey::new_master(Network::Testnet, &seed)?.ckd_priv(
+            &secp,
+            ChildNumber::from_hardened_idx(DLC_KEYS_CHILD_NUMBER)?,
+        )?;
+
+        Ok(Self {
+            keys_manager,
+            dlc_keys_master_key,
+            secp,
+        })
+    }
+
+    /// Derives the `index`th secret key for the DLCs of our DLC sub-channels.
+    ///
+    /// Unlike the keys of the LN channels, these keys are not tied to a channel keys ID, so the
+    /// caller is responsible for picking an unused `index`.
+    pub fn derive_dlc_secret_key(&self, index: u32) -> Result<SecretKey> {
+        let child_number = ChildNumber::from_hardened_idx(index)
+            .with_context(|| format!("Invalid DLC key index {index}"))?;
+        let key = self
+            .dlc_keys_master_key
+            .ckd_priv(&self.secp, child_number)?;
+
+        Ok(key.private_key)
     }
 }
 
@@ -322,8 +373,7 @@ impl KeysInterface for CustomKeysManager {
     }
 
     fn read_chan_signer(&self, reader: &[u8]) -> Result<Self::Signer, DecodeError> {
-        let in_memory = self.keys_manager.read_chan_signer(reader)?;
-        Ok(CustomSigner::new(in_memory))
+        CustomSigner::read(&self.keys_manager, reader)
     }
 
     fn sign_invoice(
@@ -360,14 +410,150 @@ impl KeysInterface for CustomKeysManager {
         channel_value_satoshis: u64,
         channel_keys_id: [u8; 32],
     ) -> Self::Signer {
-        let inner = self
+        let in_memory_signer = self
             .keys_manager
             .derive_channel_signer(channel_value_satoshis, channel_keys_id);
-        let pubkeys = inner.pubkeys();
 
-        CustomSigner {
-            channel_public_keys: pubkeys.clone(),
-            in_memory_signer: Arc::new(Mutex::new(inner)),
+        CustomSigner::new(in_memory_signer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: LightningSeed = [42; 32];
+
+    /// Two keys managers for the same seed, as if the node had been restored from its seed.
+    fn original_and_restored() -> (CustomKeysManager, CustomKeysManager) {
+        let original = CustomKeysManager::new(SEED, Duration::from_secs(1_600_000_000)).unwrap();
+        let restored = CustomKeysManager::new(SEED, Duration::from_secs(1_700_000_000)).unwrap();
+
+        (original, restored)
+    }
+
+    fn funding_key(signer: &CustomSigner) -> SecretKey {
+        let mut funding_key = None;
+        signer.sign_with_fund_key_callback(&mut |key| funding_key = Some(*key));
+
+        funding_key.unwrap()
+    }
+
+    #[test]
+    fn restored_keys_manager_derives_the_same_channel_keys() {
+        let (original, restored) = original_and_restored();
+        let secp = Secp256k1::new();
+
+        let channel_keys_id = original.generate_channel_keys_id(false, 100_000, 1);
+        let original_signer = original.derive_channel_signer(100_000, channel_keys_id);
+        let restored_signer = restored.derive_channel_signer(100_000, channel_keys_id);
+
+        assert_eq!(
+            original.get_node_secret(Recipient::Node).unwrap(),
+            restored.get_node_secret(Recipient::Node).unwrap()
+        );
+        assert_eq!(original_signer.pubkeys(), restored_signer.pubkeys());
+        assert_eq!(funding_key(&original_signer), funding_key(&restored_signer));
+        assert_eq!(
+            original_signer.get_per_commitment_point(0, &secp),
+            restored_signer.get_per_commitment_point(0, &secp)
+        );
+        assert_eq!(
+            original_signer.release_commitment_secret(0),
+            restored_signer.release_commitment_secret(0)
+        );
+    }
+
+    #[test]
+    fn restored_keys_manager_derives_the_same_dlc_keys() {
+        let (original, restored) = original_and_restored();
+
+        for index in 0..3 {
+            assert_eq!(
+                original.derive_dlc_secret_key(index).unwrap(),
+                restored.derive_dlc_secret_key(index).unwrap()
+            );
         }
+
+        assert_ne!(
+            original.derive_dlc_secret_key(0).unwrap(),
+            original.derive_dlc_secret_key(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn dlc_keys_differ_from_channel_keys() {
+        let (keys_manager, _) = original_and_restored();
+
+        let channel_keys_id = keys_manager.generate_channel_keys_id(false, 100_000, 1);
+        let signer = keys_manager.derive_channel_signer(100_000, channel_keys_id);
+
+        assert_ne!(
+            keys_manager.derive_dlc_secret_key(0).unwrap(),
+            funding_key(&signer)
+        );
+    }
+
+    #[test]
+    fn signer_survives_serialization_roundtrip() {
+        let (original, restored) = original_and_restored();
+
+        let channel_keys_id = original.generate_channel_keys_id(false, 100_000, 1);
+        let signer = original.derive_channel_signer(100_000, channel_keys_id);
+
+        let serialized = signer.encode();
+        assert_eq!(
+            serialized[..2],
+            [SERIALIZATION_MARKER, SERIALIZATION_VERSION]
+        );
+
+        let read = restored.read_chan_signer(&serialized).unwrap();
+        assert_eq!(read.pubkeys(), signer.pubkeys());
+        assert_eq!(read.channel_keys_id(), signer.channel_keys_id());
+        assert_eq!(funding_key(&read), funding_key(&signer));
+    }
+
+    #[test]
+    fn legacy_signer_can_be_read() {
+        let (keys_manager, _) = original_and_restored();
+
+        let channel_keys_id = keys_manager.generate_channel_keys_id(false, 100_000, 1);
+        let signer = keys_manager.derive_channel_signer(100_000, channel_keys_id);
+        let legacy = signer.in_memory_signer.encode();
+
+        let read = keys_manager.read_chan_signer(&legacy).unwrap();
+        assert_eq!(read.pubkeys(), signer.pubkeys());
+    }
+
+    #[test]
+    fn signer_with_unknown_version_is_rejected() {
+        let (keys_manager, _) = original_and_restored();
+
+        let channel_keys_id = keys_manager.generate_channel_keys_id(false, 100_000, 1);
+        let mut serialized = keys_manager
+            .derive_channel_signer(100_000, channel_keys_id)
+            .encode();
+        serialized[1] = SERIALIZATION_VERSION + 1;
+
+        assert!(matches!(
+            keys_manager.read_chan_signer(&serialized),
+            Err(DecodeError::UnknownVersion)
+        ));
+    }
+
+    #[test]
+    fn channel_value_change_is_shared_between_clones() {
+        let (keys_manager, _) = original_and_restored();
+
+        let channel_keys_id = keys_manager.generate_channel_keys_id(false, 100_000, 1);
+        let mut signer = keys_manager.derive_channel_signer(100_000, channel_keys_id);
+        let clone = signer.clone();
+        let before = clone.encode();
+
+        signer.set_channel_value_satoshis(50_000);
+
+        assert_ne!(clone.encode(), before);
+        assert_eq!(clone.encode(), signer.encode());
+        assert_eq!(clone.pubkeys(), signer.pubkeys());
     }
 }
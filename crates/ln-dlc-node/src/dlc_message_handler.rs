@@ -0,0 +1,81 @@
+use bitcoin::secp256k1::PublicKey;
+use dlc_messages::message_handler::MessageHandler;
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::msgs::LightningError;
+use lightning::ln::peer_handler::CustomMessageHandler;
+use lightning::ln::wire::CustomMessageReader;
+use std::io::Read;
+use std::ops::Deref;
+use tokio::sync::Notify;
+
+type CustomMessage = <MessageHandler as CustomMessageReader>::CustomMessage;
+
+/// Wraps the rust-dlc [`MessageHandler`] to notify the DLC message processing task as soon as we
+/// receive a DLC message from a peer, instead of having it poll for new messages.
+pub struct DlcMessageHandler {
+    inner: MessageHandler,
+    message_received: Notify,
+}
+
+impl DlcMessageHandler {
+    pub fn new() -> Self {
+        Self {
+            inner: MessageHandler::new(),
+            message_received: Notify::new(),
+        }
+    }
+
+    /// Waits until we receive a DLC message.
+    ///
+    /// Returns immediately if a message was received since the last time this was awaited, so no
+    /// message can be missed between two calls.
+    pub async fn message_received(&self) {
+        self.message_received.notified().await;
+    }
+}
+
+impl Default for DlcMessageHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for DlcMessageHandler {
+    type Target = MessageHandler;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl CustomMessageReader for DlcMessageHandler {
+    type CustomMessage = CustomMessage;
+
+    fn read<R: Read>(
+        &self,
+        message_type: u16,
+        buffer: &mut R,
+    ) -> Result<Option<Self::CustomMessage>, DecodeError> {
+        self.inner.read(message_type, buffer)
+    }
+}
+
+impl CustomMessageHandler for DlcMessageHandler {
+    fn handle_custom_message(
+        &self,
+        msg: Self::CustomMessage,
+        sender_node_id: &PublicKey,
+    ) -> Result<(), LightningError> {
+        self.inner.handle_custom_message(msg, sender_node_id)?;
+
+        // `Notify` stores a permit if the processing task is busy, so it will process this message
+        // as soon as it is done
+        self.message_received.notify_one();
+
+        Ok(())
+    }
+
+    fn get_and_clear_pending_msg(&self) -> Vec<(PublicKey, Self::CustomMessage)> {
+        self.inner.get_and_clear_pending_msg()
+    }
+}
@@ -1,7 +1,7 @@
+use crate::backup::BackupPersister;
 use crate::ln::TracingLogger;
 use bitcoin::secp256k1::PublicKey;
 use dlc_custom_signer::CustomSigner;
-use dlc_messages::message_handler::MessageHandler as DlcMessageHandler;
 use lightning::chain;
 use lightning::chain::chainmonitor;
 use lightning::chain::channelmonitor::ChannelMonitor;
@@ -17,11 +17,11 @@ use lightning::routing::router::DefaultRouter;
 use lightning::routing::scoring::ProbabilisticScorer;
 use lightning_invoice::payment;
 use lightning_net_tokio::SocketDescriptor;
-use lightning_persister::FilesystemPersister;
 use ln_dlc_wallet::LnDlcWallet;
 use node::invoice::HTLCStatus;
 use node::ChannelManager;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 use time::OffsetDateTime;
@@ -29,19 +29,24 @@ use time::OffsetDateTime;
 mod await_with_timeout;
 mod disk;
 mod dlc_custom_signer;
+mod dlc_message_handler;
 mod ln;
 mod ln_dlc_wallet;
 mod on_chain_wallet;
 mod util;
 
+pub mod backup;
 pub mod node;
 pub mod seed;
+pub mod storage;
 
 #[cfg(test)]
 mod tests;
 
+pub use dlc_message_handler::DlcMessageHandler;
 pub use ln::ChannelDetails;
 pub use ln::DlcChannelDetails;
+pub use ln::SubChannelState;
 pub use node::dlc_channel::Dlc;
 
 type ConfirmableMonitor = (
@@ -57,7 +62,7 @@ type ChainMonitor = chainmonitor::ChainMonitor<
     Arc<LnDlcWallet>,
     Arc<LnDlcWallet>,
     Arc<TracingLogger>,
-    Arc<FilesystemPersister>,
+    Arc<BackupPersister>,
 >;
 
 pub type PeerManager = lightning::ln::peer_handler::PeerManager<
@@ -86,10 +91,11 @@ type Router = DefaultRouter<
 
 type NetworkGraph = gossip::NetworkGraph<Arc<TracingLogger>>;
 
-type RequestedScid = u64;
 type PaymentInfoStorage = Arc<Mutex<HashMap<PaymentHash, PaymentInfo>>>;
-type FakeChannelPaymentRequests = Arc<Mutex<HashMap<RequestedScid, PublicKey>>>;
-type PendingInterceptedHtlcs = Arc<Mutex<HashMap<PublicKey, (InterceptId, u64)>>>;
+/// The intercepted HTLCs waiting for the just-in-time channel we are opening to each peer.
+type PendingInterceptedHtlcs = Arc<Mutex<HashMap<PublicKey, Vec<PendingInterceptedHtlc>>>>;
+/// Peers whose channels we accept without confirmations.
+type TrustedPeers = Arc<Mutex<HashSet<PublicKey>>>;
 
 struct PaymentInfo {
     preimage: Option<PaymentPreimage>,
@@ -97,7 +103,14 @@ struct PaymentInfo {
     status: HTLCStatus,
     amt_msat: MillisatAmount,
     timestamp: OffsetDateTime,
+    description: String,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct MillisatAmount(Option<u64>);
+
+/// An intercepted HTLC which we forward once the just-in-time channel we opened for it is ready.
+struct PendingInterceptedHtlc {
+    intercept_id: InterceptId,
+    expected_outbound_amount_msat: u64,
+}
@@ -5,7 +5,7 @@ use dlc_manager::ChannelId;
 use serde::Serialize;
 use serde::Serializer;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub struct DlcChannelDetails {
     #[serde(serialize_with = "channel_id_as_hex")]
     pub channel_id: ChannelId,
@@ -19,7 +19,7 @@ pub struct DlcChannelDetails {
     pub is_offer: bool,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SubChannelState {
     Offered,
     Accepted,
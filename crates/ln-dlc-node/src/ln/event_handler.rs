@@ -1,23 +1,28 @@
 use crate::dlc_custom_signer::CustomKeysManager;
 use crate::ln::coordinator_config;
 use crate::ln::JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT;
+use crate::ln::MULTI_PART_PAYMENT_COLLECTION_DELAY;
 use crate::ln_dlc_wallet::LnDlcWallet;
+use crate::node::intercept_scid::InterceptScidStore;
 use crate::node::invoice::HTLCStatus;
+use crate::node::liquidity::LiquidityLeaseStore;
+use crate::node::sweeper;
+use crate::node::sweeper::SpendableOutputStore;
 use crate::node::ChannelManager;
 use crate::util;
-use crate::FakeChannelPaymentRequests;
+use crate::util::unix_timestamp;
 use crate::MillisatAmount;
 use crate::NetworkGraph;
 use crate::PaymentInfo;
 use crate::PaymentInfoStorage;
+use crate::PendingInterceptedHtlc;
 use crate::PendingInterceptedHtlcs;
+use crate::TrustedPeers;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
-use bitcoin::secp256k1::Secp256k1;
-use lightning::chain::chaininterface::BroadcasterInterface;
-use lightning::chain::chaininterface::ConfirmationTarget;
-use lightning::chain::chaininterface::FeeEstimator;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::channelmanager::InterceptId;
 use lightning::routing::gossip::NodeId;
 use lightning::util::events::Event;
 use lightning::util::events::PaymentPurpose;
@@ -38,8 +43,11 @@ pub struct EventHandler {
     keys_manager: Arc<CustomKeysManager>,
     inbound_payments: PaymentInfoStorage,
     outbound_payments: PaymentInfoStorage,
-    fake_channel_payments: FakeChannelPaymentRequests,
+    intercept_scids: Arc<InterceptScidStore>,
     pending_intercepted_htlcs: PendingInterceptedHtlcs,
+    spendable_outputs: Arc<SpendableOutputStore>,
+    liquidity_leases: Arc<LiquidityLeaseStore>,
+    trusted_peers: TrustedPeers,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -52,8 +60,11 @@ impl EventHandler {
         keys_manager: Arc<CustomKeysManager>,
         inbound_payments: PaymentInfoStorage,
         outbound_payments: PaymentInfoStorage,
-        fake_channel_payments: FakeChannelPaymentRequests,
+        intercept_scids: Arc<InterceptScidStore>,
         pending_intercepted_htlcs: PendingInterceptedHtlcs,
+        spendable_outputs: Arc<SpendableOutputStore>,
+        liquidity_leases: Arc<LiquidityLeaseStore>,
+        trusted_peers: TrustedPeers,
     ) -> Self {
         Self {
             runtime_handle,
@@ -63,8 +74,11 @@ impl EventHandler {
             keys_manager,
             inbound_payments,
             outbound_payments,
-            fake_channel_payments,
+            intercept_scids,
             pending_intercepted_htlcs,
+            spendable_outputs,
+            liquidity_leases,
+            trusted_peers,
         }
     }
 
@@ -132,6 +146,14 @@ impl EventHandler {
                     } => (payment_preimage, Some(payment_secret)),
                     PaymentPurpose::SpontaneousPayment(preimage) => (Some(preimage), None),
                 };
+
+                if self.liquidity_leases.mark_paid(&payment_hash)? {
+                    tracing::info!(
+                        payment_hash = %hex::encode(payment_hash.0),
+                        "Liquidity lease paid"
+                    );
+                }
+
                 let mut payments = self.inbound_payments.lock().unwrap();
                 match payments.entry(payment_hash) {
                     Entry::Occupied(mut e) => {
@@ -139,6 +161,8 @@ impl EventHandler {
                         payment.status = HTLCStatus::Succeeded;
                         payment.preimage = payment_preimage;
                         payment.secret = payment_secret;
+                        payment.amt_msat = MillisatAmount(Some(amount_msat));
+                        payment.timestamp = OffsetDateTime::now_utc();
                     }
                     Entry::Vacant(e) => {
                         e.insert(PaymentInfo {
@@ -147,6 +171,7 @@ impl EventHandler {
                             status: HTLCStatus::Succeeded,
                             amt_msat: MillisatAmount(Some(amount_msat)),
                             timestamp: OffsetDateTime::now_utc(),
+                            description: String::new(),
                         });
                     }
                 }
@@ -178,6 +203,7 @@ impl EventHandler {
                             status: HTLCStatus::Succeeded,
                             amt_msat,
                             timestamp: OffsetDateTime::now_utc(),
+                            description: String::new(),
                         });
 
                         amt_msat
@@ -199,25 +225,44 @@ impl EventHandler {
                 push_msat,
                 ..
             } => {
-                // TODO: only accept 0-conf from the coordinator.
-                // right now we are using the same conf for app and coordinator, meaning this will
-                // be called for both. We however do not want to accept 0-conf channels from someone
-                // outside of our domain.
                 let counterparty = counterparty_node_id.to_string();
-                tracing::info!(
-                    counterparty,
-                    funding_satoshis,
-                    push_msat,
-                    "Accepting 0-conf channel request"
-                );
-                self.channel_manager
-                    .accept_inbound_channel_from_trusted_peer_0conf(
-                        &temporary_channel_id,
-                        &counterparty_node_id,
-                        0,
-                    )
-                    .map_err(|e| anyhow!("{e:?}"))
-                    .context("To be able to accept a 0-conf channel")?;
+
+                let is_trusted = self
+                    .trusted_peers
+                    .lock()
+                    .unwrap()
+                    .contains(&counterparty_node_id);
+
+                // We only accept 0-conf channels from peers within our domain, e.g. the
+                // coordinator. Channels from anyone else are subject to the usual confirmation
+                // requirements.
+                if is_trusted {
+                    tracing::info!(
+                        counterparty,
+                        funding_satoshis,
+                        push_msat,
+                        "Accepting 0-conf channel request"
+                    );
+                    self.channel_manager
+                        .accept_inbound_channel_from_trusted_peer_0conf(
+                            &temporary_channel_id,
+                            &counterparty_node_id,
+                            0,
+                        )
+                        .map_err(|e| anyhow!("{e:?}"))
+                        .context("To be able to accept a 0-conf channel")?;
+                } else {
+                    tracing::info!(
+                        counterparty,
+                        funding_satoshis,
+                        push_msat,
+                        "Accepting channel request from untrusted peer"
+                    );
+                    self.channel_manager
+                        .accept_inbound_channel(&temporary_channel_id, &counterparty_node_id, 0)
+                        .map_err(|e| anyhow!("{e:?}"))
+                        .context("To be able to accept a channel")?;
+                }
             }
             Event::PaymentPathSuccessful { .. } => {}
             Event::PaymentPathFailed { .. } => {}
@@ -240,6 +285,7 @@ impl EventHandler {
                             status: HTLCStatus::Failed,
                             amt_msat: MillisatAmount(None),
                             timestamp: OffsetDateTime::now_utc(),
+                            description: String::new(),
                         });
                     }
                 }
@@ -309,6 +355,28 @@ impl EventHandler {
                         from_onchain_str
                     );
                 }
+
+                // A replacement channel only charges the upgrade fee until the balance has been
+                // moved over, afterwards it charges our usual forwarding fees.
+                let default_channel_config = self
+                    .channel_manager
+                    .get_current_default_configuration()
+                    .channel_config;
+                if let Some(channel) = channels.iter().find(|channel| {
+                    Some(channel.channel_id) == next_channel_id
+                        && channel.config != Some(default_channel_config)
+                }) {
+                    if let Err(e) = self.channel_manager.update_channel_config(
+                        &channel.counterparty.node_id,
+                        &[channel.channel_id],
+                        &default_channel_config,
+                    ) {
+                        tracing::error!(
+                            channel_id = %hex::encode(channel.channel_id),
+                            "Failed to reset the forwarding fees of the channel: {e:?}"
+                        );
+                    }
+                }
             }
             Event::PendingHTLCsForwardable { time_forwardable } => {
                 let forwarding_channel_manager = self.channel_manager.clone();
@@ -320,19 +388,14 @@ impl EventHandler {
                 });
             }
             Event::SpendableOutputs { outputs } => {
-                let destination_address = self.wallet.inner().get_unused_address()?;
-                let output_descriptors = &outputs.iter().collect::<Vec<_>>();
-                let tx_feerate = self
-                    .wallet
-                    .get_est_sat_per_1000_weight(ConfirmationTarget::Normal);
-                let spending_tx = self.keys_manager.spend_spendable_outputs(
-                    output_descriptors,
-                    Vec::new(),
-                    destination_address.script_pubkey(),
-                    tx_feerate,
-                    &Secp256k1::new(),
+                // The outputs are persisted before attempting to sweep them, so that we can keep
+                // retrying in the background if the sweep transaction does not make it on-chain.
+                sweeper::track_spendable_outputs(
+                    &self.spendable_outputs,
+                    &self.keys_manager,
+                    &self.wallet,
+                    outputs,
                 )?;
-                self.wallet.broadcast_transaction(&spending_tx);
             }
             Event::ChannelClosed {
                 channel_id,
@@ -355,6 +418,7 @@ impl EventHandler {
             Event::ProbeFailed { .. } => {}
             Event::ChannelReady {
                 channel_id,
+                user_channel_id,
                 counterparty_node_id,
                 ..
             } => {
@@ -364,10 +428,20 @@ impl EventHandler {
                     "Channel ready"
                 );
 
-                let pending_intercepted_htlcs = self.pending_intercepted_htlcs.lock().unwrap();
+                let pending_intercepted_htlcs = self
+                    .pending_intercepted_htlcs
+                    .lock()
+                    .unwrap()
+                    .remove(&counterparty_node_id)
+                    .unwrap_or_default();
+
+                // If the channel includes leased liquidity, the lease period starts now
+                self.liquidity_leases.activate(user_channel_id)?;
 
-                if let Some((intercept_id, expected_outbound_amount_msat)) =
-                    pending_intercepted_htlcs.get(&counterparty_node_id)
+                for PendingInterceptedHtlc {
+                    intercept_id,
+                    expected_outbound_amount_msat,
+                } in pending_intercepted_htlcs
                 {
                     tracing::info!(
                         intercept_id = %hex::encode(intercept_id.0),
@@ -376,15 +450,16 @@ impl EventHandler {
                         "Pending intercepted HTLC found, forwarding payment"
                     );
 
-                    self.channel_manager
-                        .forward_intercepted_htlc(
-                            *intercept_id,
-                            &channel_id,
-                            counterparty_node_id,
-                            *expected_outbound_amount_msat,
-                        )
-                        .map_err(|e| anyhow!("{e:?}"))
-                        .context("Failed to forward intercepted HTLC")?;
+                    if let Err(error) = self.channel_manager.forward_intercepted_htlc(
+                        intercept_id,
+                        &channel_id,
+                        counterparty_node_id,
+                        expected_outbound_amount_msat,
+                    ) {
+                        tracing::warn!(?error, "Failed to forward intercepted HTLC");
+
+                        self.fail_intercepted_htlc(intercept_id);
+                    }
                 }
             }
             Event::HTLCHandlingFailed { .. } => {}
@@ -422,44 +497,113 @@ impl EventHandler {
                 expected_outbound_amount_msat,
             } => {
                 let intercepted_id = hex::encode(intercept_id.0);
-                let payment_hash = hex::encode(payment_hash.0);
                 tracing::info!(
                     intercepted_id,
                     requested_next_hop_scid,
-                    payment_hash,
+                    payment_hash = %hex::encode(payment_hash.0),
                     inbound_amount_msat,
                     expected_outbound_amount_msat,
                     "Intercepted HTLC"
                 );
 
-                let target_node_id = {
-                    let fake_channel_payments = self.fake_channel_payments.lock().unwrap();
-                    match fake_channel_payments.get(&requested_next_hop_scid) {
-                        None => {
-                            tracing::warn!(fake_scid = requested_next_hop_scid, "Could not forward the intercepted HTLC because we didn't have a node registered with said fake scid");
+                let intercept_scid = match self.intercept_scids.get(requested_next_hop_scid)? {
+                    Some(intercept_scid) if !intercept_scid.is_expired(unix_timestamp()) => {
+                        intercept_scid
+                    }
+                    Some(_) => {
+                        tracing::warn!(
+                            fake_scid = requested_next_hop_scid,
+                            "Could not forward the intercepted HTLC because the fake scid expired"
+                        );
 
-                            if let Err(err) =
-                                self.channel_manager.fail_intercepted_htlc(intercept_id)
-                            {
-                                tracing::error!("Could not fail intercepted htlc {err:?}")
-                            }
+                        self.fail_intercepted_htlc(intercept_id);
 
-                            return Ok(());
-                        }
-                        Some(target_node_id) => *target_node_id,
+                        return Ok(());
+                    }
+                    None => {
+                        tracing::warn!(fake_scid = requested_next_hop_scid, "Could not forward the intercepted HTLC because we didn't have a node registered with said fake scid");
+
+                        self.fail_intercepted_htlc(intercept_id);
+
+                        return Ok(());
                     }
                 };
+                let target_node_id = intercept_scid.target_node;
+
+                let required_fee_msat = intercept_scid
+                    .fee_policy
+                    .fee_msat(expected_outbound_amount_msat);
+                let paid_fee_msat =
+                    inbound_amount_msat.saturating_sub(expected_outbound_amount_msat);
+                if paid_fee_msat < required_fee_msat {
+                    tracing::warn!(
+                        fake_scid = requested_next_hop_scid,
+                        paid_fee_msat,
+                        required_fee_msat,
+                        "Could not forward the intercepted HTLC because the fee was underpaid"
+                    );
+
+                    self.fail_intercepted_htlc(intercept_id);
+
+                    return Ok(());
+                }
+
+                // The fake scid can only be used for a single payment, but that payment may
+                // consist of several parts and may be retried. We keep the fake scid until it
+                // expires, so that all of them can be forwarded.
+                if !self
+                    .intercept_scids
+                    .bind_to_payment(requested_next_hop_scid, payment_hash)?
+                {
+                    tracing::warn!(
+                        fake_scid = requested_next_hop_scid,
+                        "Could not forward the intercepted HTLC because the fake scid was already used for another payment"
+                    );
+
+                    self.fail_intercepted_htlc(intercept_id);
+
+                    return Ok(());
+                }
+
+                // If we are already collecting the parts of a payment to the target or opening a
+                // just-in-time channel to them, the HTLC is forwarded once that channel is ready
+                if let Some(pending_intercepted_htlcs) = self
+                    .pending_intercepted_htlcs
+                    .lock()
+                    .unwrap()
+                    .get_mut(&target_node_id)
+                {
+                    tracing::info!(
+                        intercepted_id,
+                        peer = %target_node_id,
+                        "Waiting for pending just in time channel to forward HTLC"
+                    );
+
+                    pending_intercepted_htlcs.push(PendingInterceptedHtlc {
+                        intercept_id,
+                        expected_outbound_amount_msat,
+                    });
+
+                    return Ok(());
+                }
 
                 // if we have already a channel with them, we try to forward the payment.
                 if let Some(channel) = self
                     .channel_manager
                     .list_channels()
                     .iter()
-                    // The coordinator can only have one channel with each app. Hence, if we find a
-                    // channel with the target of the intercepted HTLC, we know
-                    // that it is the only channel between coordinator and
-                    // target app and we can forward the intercepted HTLC through it.
-                    .find(|channel_details| channel_details.counterparty.node_id == target_node_id)
+                    .filter(|channel_details| {
+                        channel_details.counterparty.node_id == target_node_id
+                    })
+                    // The coordinator usually has only one channel with each app. While a channel
+                    // upgrade is in progress there can temporarily be two, in which case we
+                    // forward through the one with the most outbound liquidity.
+                    .max_by_key(|channel_details| {
+                        (
+                            channel_details.is_usable,
+                            channel_details.next_outbound_htlc_limit_msat,
+                        )
+                    })
                 {
                     // Note, the forward intercepted htlc might fail due to insufficient balance,
                     // since we do not check yet if the channel outbound capacity is sufficient.
@@ -479,66 +623,27 @@ impl EventHandler {
                     return Ok(());
                 }
 
-                // FIXME: This will set the channel capacity to twice the amount that is
-                // transferred or the `JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT` ensuring there
-                // is enough liquidity in the channel. This is arbitrary and needs
-                // to be computed, but good enough for our test cases to support any
-                // amount to be sent.
-                let channel_value = cmp::max(
-                    JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT,
-                    expected_outbound_amount_msat * 2 / 1000,
-                );
-
-                let mut user_config = coordinator_config();
-                // We are overwriting the coordinators channel handshake configuration to prevent
-                // the just-in-time-channel from being announced (private). This is required as both
-                // parties need to agree on this configuration. For other channels, like with the
-                // channel to an external node we want this channel to be announced (public).
-                // NOTE: we want private channels with the mobile app, as this will allow us to make
-                // use of 0-conf channels.
-                user_config.channel_handshake_config.announced_channel = false;
-
-                // NOTE: We actually might want to override the `UserConfig`
-                // for this just-in-time channel so that the
-                // intercepted HTLC is allowed to be added to the
-                // channel according to its
-                // `max_inbound_htlc_value_in_flight_percent_of_channel`
-                // configuration value
-                let temp_channel_id = match self.channel_manager.create_channel(
+                // The payment may consist of several parts, which all have to be forwarded through
+                // the just-in-time channel. We wait for the parts to arrive before opening the
+                // channel, so that it is big enough for all of them.
+                self.pending_intercepted_htlcs.lock().unwrap().insert(
                     target_node_id,
-                    channel_value,
-                    0,
-                    0,
-                    Some(user_config),
-                ) {
-                    Ok(temp_channel_id) => temp_channel_id,
-                    Err(err) => {
-                        tracing::warn!(?err, "Failed to open just in time channel");
-
-                        if let Err(err) = self
-                            .channel_manager
-                            .fail_intercepted_htlc(intercept_id)
-                            .map_err(|e| anyhow!("{e:?}"))
-                        {
-                            tracing::error!("Could not fail intercepted htlc {err:?}");
-                        };
-
-                        return Ok(());
-                    }
-                };
-
-                tracing::info!(
-                    peer = %target_node_id,
-                    temp_channel_id = %hex::encode(temp_channel_id),
-                    "Started channel creation for in-flight payment"
+                    vec![PendingInterceptedHtlc {
+                        intercept_id,
+                        expected_outbound_amount_msat,
+                    }],
                 );
 
+                let channel_manager = self.channel_manager.clone();
                 let pending_intercepted_htlcs = self.pending_intercepted_htlcs.clone();
-                let mut pending_intercepted_htlcs = pending_intercepted_htlcs.lock().unwrap();
-                pending_intercepted_htlcs.insert(
-                    target_node_id,
-                    (intercept_id, expected_outbound_amount_msat),
-                );
+                tokio::spawn(async move {
+                    tokio::time::sleep(MULTI_PART_PAYMENT_COLLECTION_DELAY).await;
+                    open_just_in_time_channel(
+                        &channel_manager,
+                        &pending_intercepted_htlcs,
+                        target_node_id,
+                    );
+                });
             }
         };
 
@@ -546,6 +651,82 @@ impl EventHandler {
     }
 }
 
+impl EventHandler {
+    fn fail_intercepted_htlc(&self, intercept_id: InterceptId) {
+        if let Err(err) = self.channel_manager.fail_intercepted_htlc(intercept_id) {
+            tracing::error!("Could not fail intercepted htlc {err:?}")
+        }
+    }
+}
+
+/// Opens a just-in-time channel to `target_node_id` which is big enough to forward all the
+/// intercepted HTLCs pending for them, failing those HTLCs if the channel cannot be opened.
+fn open_just_in_time_channel(
+    channel_manager: &ChannelManager,
+    pending_intercepted_htlcs: &PendingInterceptedHtlcs,
+    target_node_id: PublicKey,
+) {
+    let mut pending_intercepted_htlcs = pending_intercepted_htlcs.lock().unwrap();
+    let htlcs = match pending_intercepted_htlcs.get(&target_node_id) {
+        Some(htlcs) => htlcs,
+        None => return,
+    };
+
+    let total_outbound_amount_msat = htlcs
+        .iter()
+        .map(|htlc| htlc.expected_outbound_amount_msat)
+        .sum::<u64>();
+
+    // FIXME: This will set the channel capacity to twice the amount that is
+    // transferred or the `JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT` ensuring there
+    // is enough liquidity in the channel. This is arbitrary and needs
+    // to be computed, but good enough for our test cases to support any
+    // amount to be sent.
+    let channel_value = cmp::max(
+        JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT,
+        total_outbound_amount_msat * 2 / 1000,
+    );
+
+    let mut user_config = coordinator_config();
+    // We are overwriting the coordinators channel handshake configuration to prevent
+    // the just-in-time-channel from being announced (private). This is required as both
+    // parties need to agree on this configuration. For other channels, like with the
+    // channel to an external node we want this channel to be announced (public).
+    // NOTE: we want private channels with the mobile app, as this will allow us to make
+    // use of 0-conf channels.
+    user_config.channel_handshake_config.announced_channel = false;
+
+    // NOTE: We actually might want to override the `UserConfig`
+    // for this just-in-time channel so that the
+    // intercepted HTLC is allowed to be added to the
+    // channel according to its
+    // `max_inbound_htlc_value_in_flight_percent_of_channel`
+    // configuration value
+    match channel_manager.create_channel(target_node_id, channel_value, 0, 0, Some(user_config)) {
+        Ok(temp_channel_id) => {
+            tracing::info!(
+                peer = %target_node_id,
+                temp_channel_id = %hex::encode(temp_channel_id),
+                total_outbound_amount_msat,
+                htlcs = htlcs.len(),
+                "Started channel creation for in-flight payment"
+            );
+        }
+        Err(err) => {
+            tracing::warn!(?err, "Failed to open just in time channel");
+
+            for htlc in pending_intercepted_htlcs
+                .remove(&target_node_id)
+                .unwrap_or_default()
+            {
+                if let Err(err) = channel_manager.fail_intercepted_htlc(htlc.intercept_id) {
+                    tracing::error!("Could not fail intercepted htlc {err:?}");
+                }
+            }
+        }
+    }
+}
+
 impl lightning::util::events::EventHandler for EventHandler {
     fn handle_event(&self, event: Event) {
         tracing::info!(?event, "Received event");
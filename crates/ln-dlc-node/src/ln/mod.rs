@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 mod channel_details;
 mod config;
 mod dlc_channel_details;
@@ -8,6 +10,7 @@ pub use channel_details::ChannelDetails;
 pub(crate) use config::app_config;
 pub(crate) use config::coordinator_config;
 pub use dlc_channel_details::DlcChannelDetails;
+pub use dlc_channel_details::SubChannelState;
 pub(crate) use event_handler::EventHandler;
 pub(crate) use logger::TracingLogger;
 
@@ -16,9 +19,16 @@ pub(crate) use logger::TracingLogger;
 /// payment is small enough (< 1000 sats), opening the channel will
 /// fail unless we provide more outbound liquidity.
 ///
-/// This value is completely arbitrary at this stage. Eventually, we
-/// should, for example, let the payee decide how much inbound
-/// liquidity they desire, and charge them for it.
+/// This value is completely arbitrary at this stage. Payees who want
+/// more inbound liquidity can buy it with a liquidity lease, see
+/// [`crate::node::LiquidityLease`].
 ///
 /// This constant only applies to the coordinator.
 pub(crate) const JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT: u64 = 10_000;
+
+/// For how long we collect the parts of a payment intercepted for a just-in-time channel, before
+/// opening the channel for all of them.
+///
+/// The parts of a multi-path payment are sent at the same time, so they usually arrive well
+/// within this delay.
+pub(crate) const MULTI_PART_PAYMENT_COLLECTION_DELAY: Duration = Duration::from_secs(2);
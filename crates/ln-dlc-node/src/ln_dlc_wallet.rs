@@ -1,7 +1,10 @@
+use crate::dlc_custom_signer::CustomKeysManager;
+use crate::storage::SqliteStorage;
+use crate::storage::WalletDatabase;
+use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
-use bdk::blockchain::ElectrumBlockchain;
-use bdk::sled;
+use bdk::blockchain::AnyBlockchain;
 use bdk::wallet::AddressIndex;
 use bdk::TransactionDetails;
 use bitcoin::secp256k1::All;
@@ -11,20 +14,20 @@ use bitcoin::secp256k1::SecretKey;
 use bitcoin::Address;
 use bitcoin::Block;
 use bitcoin::BlockHeader;
-use bitcoin::KeyPair;
 use bitcoin::Network;
 use bitcoin::Script;
 use bitcoin::Transaction;
 use bitcoin::TxOut;
 use bitcoin::Txid;
+use dlc_manager::contract::Contract;
 use dlc_manager::error::Error;
 use dlc_manager::error::Error::WalletError;
 use dlc_manager::Signer;
+use dlc_manager::Storage;
 use dlc_manager::Utxo;
-use dlc_sled_storage_provider::SledStorageProvider;
+use lightning::chain::channelmonitor::ANTI_REORG_DELAY;
 use lightning::chain::Filter;
 use lightning::chain::WatchedOutput;
-use simple_wallet::WalletStorage;
 use std::sync::Arc;
 
 /// This is a wrapper type introduced to be able to implement traits from `rust-dlc` on the
@@ -32,33 +35,36 @@ use std::sync::Arc;
 ///
 /// We want to eventually get rid of the dependency on `bdk-ldk`, because it's a dead project.
 pub struct LnDlcWallet {
-    ln_wallet: bdk_ldk::LightningWallet<ElectrumBlockchain, sled::Tree>,
-    electrum: Arc<ElectrumBlockchain>,
-    storage: Arc<SledStorageProvider>,
+    ln_wallet: bdk_ldk::LightningWallet<AnyBlockchain, WalletDatabase>,
+    blockchain: Arc<AnyBlockchain>,
+    storage: Arc<SqliteStorage>,
+    keys_manager: Arc<CustomKeysManager>,
     secp: Secp256k1<All>,
 }
 
 impl LnDlcWallet {
     pub fn new(
-        blockchain_client: Arc<ElectrumBlockchain>,
-        wallet: bdk::Wallet<bdk::sled::Tree>,
-        storage: Arc<SledStorageProvider>,
+        blockchain_client: Arc<AnyBlockchain>,
+        wallet: bdk::Wallet<WalletDatabase>,
+        storage: Arc<SqliteStorage>,
+        keys_manager: Arc<CustomKeysManager>,
     ) -> Self {
         Self {
             ln_wallet: bdk_ldk::LightningWallet::new(blockchain_client.clone(), wallet),
-            electrum: blockchain_client,
+            blockchain: blockchain_client,
             storage,
+            keys_manager,
             secp: Secp256k1::new(),
         }
     }
 
     // TODO: Better to keep this private and expose the necessary APIs instead.
-    pub(crate) fn inner(&self) -> &bdk_ldk::LightningWallet<ElectrumBlockchain, sled::Tree> {
+    pub(crate) fn inner(&self) -> &bdk_ldk::LightningWallet<AnyBlockchain, WalletDatabase> {
         &self.ln_wallet
     }
 
-    pub fn electrum(&self) -> Arc<ElectrumBlockchain> {
-        self.electrum.clone()
+    pub fn blockchain(&self) -> Arc<AnyBlockchain> {
+        self.blockchain.clone()
     }
 
     pub(crate) fn tip(&self) -> Result<(u32, BlockHeader)> {
@@ -88,6 +94,122 @@ impl LnDlcWallet {
 
         Ok(txs)
     }
+
+    /// Watches the transactions and outputs registered before the last shutdown again, as well as
+    /// the funding outputs of our DLCs.
+    ///
+    /// LDK re-registers what its channel monitors need when they are loaded, but the filter of the
+    /// wallet is otherwise only kept in memory. Without this, we would miss the counterparty
+    /// spending a DLC output (e.g. with a CET) while we were offline.
+    pub(crate) fn rewatch(&self) -> Result<()> {
+        // Pruned transactions and outputs may be registered again below or by LDK
+        let (pruned_txids, pruned_outpoints) = self
+            .storage
+            .get_pruned()
+            .map_err(|e| anyhow!("Failed to load pruned filter: {e:#}"))?;
+        self.ln_wallet
+            .restore_pruned(pruned_txids, pruned_outpoints);
+
+        let watched_transactions = self
+            .storage
+            .get_watched_transactions()
+            .map_err(|e| anyhow!("Failed to load watched transactions: {e:#}"))?;
+        for (txid, script_pubkey) in watched_transactions.iter() {
+            self.ln_wallet.register_tx(txid, script_pubkey);
+        }
+
+        let watched_outputs = self
+            .storage
+            .get_watched_outputs()
+            .map_err(|e| anyhow!("Failed to load watched outputs: {e:#}"))?;
+        let n_watched_outputs = watched_outputs.len();
+        for output in watched_outputs {
+            self.ln_wallet.register_output(output);
+        }
+
+        // Also covers DLCs which were set up before we persisted the filter
+        let contracts = self
+            .storage
+            .get_contracts()
+            .map_err(|e| anyhow!("Failed to load contracts: {e:#}"))?;
+        for contract in contracts.iter() {
+            let dlc_transactions = match contract {
+                Contract::Signed(contract) | Contract::Confirmed(contract) => {
+                    &contract.accepted_contract.dlc_transactions
+                }
+                Contract::PreClosed(contract) => {
+                    &contract.signed_contract.accepted_contract.dlc_transactions
+                }
+                _ => continue,
+            };
+
+            self.register_output(WatchedOutput {
+                block_hash: None,
+                outpoint: dlc_transactions.get_fund_outpoint(),
+                script_pubkey: dlc_transactions.funding_script_pubkey.to_v0_p2wsh(),
+            });
+        }
+
+        tracing::info!(
+            transactions = watched_transactions.len(),
+            outputs = n_watched_outputs,
+            "Watching transactions and outputs from before the restart"
+        );
+
+        Ok(())
+    }
+
+    /// Continues tracking the blocks the wallet was synced to before the last shutdown, so that a
+    /// reorg which happened while we were offline is detected with the next sync.
+    pub(crate) fn restore_tracked_blocks(&self) -> Result<()> {
+        let blocks = self
+            .storage
+            .get_tracked_blocks()
+            .map_err(|e| anyhow!("Failed to load tracked blocks: {e:#}"))?;
+
+        tracing::debug!(best_block = ?blocks.last(), "Tracking blocks from before the restart");
+
+        self.ln_wallet.restore_tracked_blocks(blocks);
+
+        Ok(())
+    }
+
+    /// Persists the blocks the wallet was synced to, see [`Self::restore_tracked_blocks`].
+    pub(crate) fn persist_tracked_blocks(&self) -> Result<()> {
+        self.storage
+            .replace_tracked_blocks(&self.ln_wallet.tracked_blocks())
+            .map_err(|e| anyhow!("Failed to persist tracked blocks: {e:#}"))
+    }
+
+    /// Stops watching the transactions and outputs whose confirmation or spend is buried deep
+    /// enough that a reorg is not expected to undo it, so that the filter does not keep growing
+    /// with every closed channel and DLC.
+    pub(crate) fn prune_watched(&self) -> Result<()> {
+        let (transactions, outputs) = self.ln_wallet.prune(ANTI_REORG_DELAY);
+        if transactions.is_empty() && outputs.is_empty() {
+            return Ok(());
+        }
+
+        let txids = transactions
+            .iter()
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+        let outpoints = outputs
+            .iter()
+            .map(|output| output.outpoint)
+            .collect::<Vec<_>>();
+        self.storage
+            .prune_watched(&txids, &outpoints)
+            .map_err(|e| anyhow!("Failed to persist pruned filter: {e:#}"))?;
+
+        tracing::debug!(
+            transactions = txids.len(),
+            outputs = outpoints.len(),
+            "Stopped watching confirmed transactions and spent outputs"
+        );
+
+        Ok(())
+    }
 }
 
 impl dlc_manager::Blockchain for LnDlcWallet {
@@ -129,10 +251,18 @@ impl dlc_manager::Blockchain for LnDlcWallet {
 
 impl Filter for LnDlcWallet {
     fn register_tx(&self, txid: &Txid, script_pubkey: &Script) {
+        if let Err(e) = self.storage.insert_watched_transaction(txid, script_pubkey) {
+            tracing::error!(%txid, "Failed to persist watched transaction: {e:#}");
+        }
+
         self.inner().register_tx(txid, script_pubkey)
     }
 
     fn register_output(&self, output: WatchedOutput) {
+        if let Err(e) = self.storage.insert_watched_output(&output) {
+            tracing::error!(outpoint = ?output.outpoint, "Failed to persist watched output: {e:#}");
+        }
+
         self.inner().register_output(output);
     }
 }
@@ -166,13 +296,24 @@ impl dlc_manager::Wallet for LnDlcWallet {
         Ok(address_info.address)
     }
 
+    /// Derives the next unused DLC key from the seed, so that it can be re-derived after
+    /// restoring the node.
     fn get_new_secret_key(&self) -> Result<SecretKey, Error> {
-        let kp = KeyPair::new(&self.secp, &mut rand::thread_rng());
-        let sk = kp.secret_key();
+        loop {
+            let index = self.storage.next_dlc_key_index()?;
+            let sk = self
+                .keys_manager
+                .derive_dlc_secret_key(index)
+                .map_err(|e| WalletError(e.into()))?;
+            let pk = PublicKey::from_secret_key(&self.secp, &sk);
 
-        self.storage.upsert_key_pair(&kp.public_key(), &sk)?;
+            // Skips the keys handed out before we persisted the index
+            if self.storage.get_priv_key_for_pubkey(&pk)?.is_none() {
+                self.storage.upsert_key_pair(&pk, &sk)?;
 
-        Ok(sk)
+                return Ok(sk);
+            }
+        }
     }
 
     fn get_utxos_for_amount(
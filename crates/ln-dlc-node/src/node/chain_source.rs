@@ -0,0 +1,82 @@
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(feature = "rpc")]
+use bdk::blockchain::rpc::Auth;
+#[cfg(feature = "rpc")]
+use bdk::blockchain::rpc::RpcConfig;
+use bdk::blockchain::AnyBlockchain;
+#[cfg(feature = "rpc")]
+use bdk::blockchain::ConfigurableBlockchain;
+use bdk::blockchain::ElectrumBlockchain;
+#[cfg(feature = "esplora")]
+use bdk::blockchain::EsploraBlockchain;
+#[cfg(feature = "rpc")]
+use bdk::blockchain::RpcBlockchain;
+use bitcoin::Network;
+
+/// How many unused addresses in a row we look at before we stop syncing the wallet with Esplora.
+#[cfg(feature = "esplora")]
+const ESPLORA_STOP_GAP: usize = 20;
+
+/// The backend the node gets its chain data from and broadcasts transactions to.
+#[derive(Clone)]
+pub enum ChainSource {
+    /// An Electrum server, e.g. `tcp://localhost:50000`.
+    Electrum { url: String },
+    /// An Esplora HTTP API, e.g. `https://blockstream.info/api`.
+    #[cfg(feature = "esplora")]
+    Esplora { url: String },
+    /// A bitcoind node, accessed via RPC.
+    ///
+    /// Since bitcoind has no address index, the node tracks its wallet and channel outputs in a
+    /// watch-only wallet it creates in bitcoind. bitcoind must run with `txindex=1`.
+    #[cfg(feature = "rpc")]
+    Bitcoind { url: String, auth: Auth },
+}
+
+impl ChainSource {
+    #[cfg_attr(not(feature = "rpc"), allow(unused_variables))]
+    pub(crate) fn build(&self, network: Network, wallet_name: &str) -> Result<AnyBlockchain> {
+        let blockchain = match self {
+            ChainSource::Electrum { url } => {
+                let client = bdk::electrum_client::Client::new(url)
+                    .with_context(|| format!("Failed to connect to Electrum server at {url}"))?;
+
+                AnyBlockchain::from(ElectrumBlockchain::from(client))
+            }
+            #[cfg(feature = "esplora")]
+            ChainSource::Esplora { url } => {
+                AnyBlockchain::from(EsploraBlockchain::new(url, ESPLORA_STOP_GAP))
+            }
+            #[cfg(feature = "rpc")]
+            ChainSource::Bitcoind { url, auth } => {
+                let config = RpcConfig {
+                    url: url.clone(),
+                    auth: auth.clone(),
+                    network,
+                    wallet_name: wallet_name.to_string(),
+                    sync_params: None,
+                };
+                let blockchain = RpcBlockchain::from_config(&config)
+                    .with_context(|| format!("Failed to connect to bitcoind at {url}"))?;
+
+                AnyBlockchain::from(blockchain)
+            }
+        };
+
+        Ok(blockchain)
+    }
+}
+
+impl std::fmt::Display for ChainSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the bitcoind credentials
+        match self {
+            ChainSource::Electrum { url } => write!(f, "Electrum ({url})"),
+            #[cfg(feature = "esplora")]
+            ChainSource::Esplora { url } => write!(f, "Esplora ({url})"),
+            #[cfg(feature = "rpc")]
+            ChainSource::Bitcoind { url, .. } => write!(f, "bitcoind ({url})"),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::backup::BackupPersister;
 use crate::dlc_custom_signer::CustomKeysManager;
 use crate::ln::TracingLogger;
 use crate::ln_dlc_wallet::LnDlcWallet;
@@ -13,7 +14,6 @@ use lightning::ln::channelmanager::ChainParameters;
 use lightning::ln::channelmanager::ChannelManagerReadArgs;
 use lightning::util::config::UserConfig;
 use lightning::util::ser::ReadableArgs;
-use lightning_persister::FilesystemPersister;
 use std::sync::Arc;
 
 pub type ChannelManager = lightning::ln::channelmanager::ChannelManager<
@@ -33,7 +33,7 @@ pub(crate) fn build(
     chain_monitor: Arc<ChainMonitor>,
     ldk_user_config: UserConfig,
     network: bitcoin::Network,
-    persister: Arc<FilesystemPersister>,
+    persister: Arc<BackupPersister>,
 ) -> Result<ChannelManager> {
     let file = std::fs::File::open(format!("{ldk_data_dir}/manager")).ok();
 
@@ -63,7 +63,9 @@ pub(crate) fn build(
         }
     };
 
-    let mut channelmonitors = persister.read_channelmonitors(keys_manager.clone())?;
+    let mut channelmonitors = persister
+        .inner()
+        .read_channelmonitors(keys_manager.clone())?;
 
     let mut channel_monitor_mut_references = Vec::new();
     for (_, channel_monitor) in channelmonitors.iter_mut() {
@@ -0,0 +1,340 @@
+use crate::node::HTLCStatus;
+use crate::node::Node;
+use crate::MillisatAmount;
+use crate::PaymentInfo;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::channelmanager::provided_channel_features;
+use lightning::ln::channelmanager::provided_node_features;
+use lightning::ln::channelmanager::ChannelDetails;
+use lightning::ln::channelmanager::PaymentId;
+use lightning::ln::channelmanager::MIN_FINAL_CLTV_EXPIRY;
+use lightning::ln::PaymentHash;
+use lightning::routing::router::Route;
+use lightning::routing::router::RouteHop;
+use lightning::util::config::ChannelConfig;
+use rand::thread_rng;
+use rand::Rng;
+use time::OffsetDateTime;
+
+/// The expiry of the invoice-less inbound payment used to move the balance between our own
+/// channels.
+const CHANNEL_UPGRADE_PAYMENT_EXPIRY_SECS: u32 = 60;
+
+impl Node {
+    /// Opens a private replacement channel with `counterparty` which is bigger than the channel we
+    /// already have with them.
+    ///
+    /// This is the first step of upgrading an app channel without splicing: once the new channel
+    /// is usable, the app moves its balance over with
+    /// [`Node::move_balance_to_replacement_channel`] and cooperatively closes the old channel.
+    ///
+    /// The replacement channel is fully funded by us. Until the balance has been moved over, it
+    /// charges the [`upgrade_fee_msat`] on the added capacity as a fixed forwarding fee instead of
+    /// our usual forwarding fees, so that the app does not pay for moving its own balance.
+    ///
+    /// The added capacity has to include the liquidity the counterparty has leased from us, but
+    /// which we have not provided yet. Those leases become active once the replacement channel is
+    /// ready and are not charged the upgrade fee.
+    ///
+    /// Returns a temporary channel ID as a 32-byte long array.
+    pub fn open_replacement_channel(
+        &self,
+        counterparty: PublicKey,
+        channel_capacity_sat: u64,
+    ) -> Result<[u8; 32]> {
+        let channels = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .filter(|channel| channel.counterparty.node_id == counterparty)
+            .collect::<Vec<_>>();
+
+        let old_channel = match channels.as_slice() {
+            [] => bail!("No channel with {counterparty} to upgrade"),
+            [channel] => channel,
+            _ => bail!("Channel upgrade with {counterparty} already in progress"),
+        };
+
+        ensure!(
+            channel_capacity_sat > old_channel.channel_value_satoshis,
+            "Replacement channel of {channel_capacity_sat} sats must be bigger than the current \
+             channel of {} sats",
+            old_channel.channel_value_satoshis
+        );
+
+        self.ensure_no_dlc_channel(&counterparty)?;
+
+        let added_capacity_sat = channel_capacity_sat - old_channel.channel_value_satoshis;
+
+        let leases = self.liquidity_leases.paid_leases(&counterparty)?;
+        let leased_inbound_liquidity_sat = leases
+            .iter()
+            .map(|lease| lease.inbound_liquidity_sat)
+            .sum::<u64>();
+        ensure!(
+            added_capacity_sat >= leased_inbound_liquidity_sat,
+            "Replacement channel has to add at least the {leased_inbound_liquidity_sat} sats of \
+             inbound liquidity leased by {counterparty}"
+        );
+
+        let upgrade_fee_msat = upgrade_fee_msat(
+            &self.user_config.channel_config,
+            added_capacity_sat - leased_inbound_liquidity_sat,
+        );
+
+        let mut user_config = self.user_config;
+        // The app only accepts private channels
+        user_config.channel_handshake_config.announced_channel = false;
+        user_config.channel_config.forwarding_fee_base_msat = u32::try_from(upgrade_fee_msat)
+            .context("Upgrade fee does not fit into a forwarding fee")?;
+        user_config
+            .channel_config
+            .forwarding_fee_proportional_millionths = 0;
+
+        // Identifies the replacement channel once it is ready, so that we can activate the leases
+        // it provides
+        let user_channel_id = thread_rng().gen::<u128>();
+
+        let temp_channel_id = self
+            .channel_manager
+            .create_channel(
+                counterparty,
+                channel_capacity_sat,
+                0,
+                user_channel_id,
+                Some(user_config),
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .with_context(|| format!("Could not create replacement channel with {counterparty}"))?;
+
+        let lease_payment_hashes = leases
+            .iter()
+            .map(|lease| lease.payment_hash)
+            .collect::<Vec<_>>();
+        self.liquidity_leases
+            .provide(&lease_payment_hashes, user_channel_id)?;
+
+        tracing::info!(
+            %counterparty,
+            old_channel_id = %hex::encode(old_channel.channel_id),
+            temp_channel_id = %hex::encode(temp_channel_id),
+            channel_capacity_sat,
+            leased_inbound_liquidity_sat,
+            upgrade_fee_msat,
+            "Started replacement channel creation"
+        );
+
+        Ok(temp_channel_id)
+    }
+
+    /// Moves as much of our balance as possible from the channel `from_channel_id` to the channel
+    /// `to_channel_id` with a circular payment.
+    ///
+    /// Both channels must be with the same counterparty, which will forward the payment back to
+    /// us. Returns the payment hash of the circular payment, which can be used to wait for it to
+    /// be claimed.
+    pub fn move_balance_to_replacement_channel(
+        &self,
+        from_channel_id: [u8; 32],
+        to_channel_id: [u8; 32],
+    ) -> Result<PaymentHash> {
+        let channels = self.channel_manager.list_usable_channels();
+        let from_channel = find_channel(&channels, from_channel_id)?;
+        let to_channel = find_channel(&channels, to_channel_id)?;
+
+        let counterparty = from_channel.counterparty.node_id;
+        ensure!(
+            counterparty == to_channel.counterparty.node_id,
+            "Cannot move balance between channels with different counterparties"
+        );
+
+        self.ensure_no_dlc_channel(&counterparty)?;
+
+        let from_scid = from_channel
+            .get_outbound_payment_scid()
+            .context("Old channel has no short channel ID")?;
+        let to_scid = to_channel
+            .get_inbound_payment_scid()
+            .context("Replacement channel has no short channel ID")?;
+
+        // The counterparty charges us their forwarding fee for using the replacement channel, which
+        // is the upgrade fee on the added capacity. If we did not learn their channel update yet we
+        // assume the default channel config.
+        let (fee_base_msat, fee_proportional_millionths, cltv_expiry_delta) =
+            match &to_channel.counterparty.forwarding_info {
+                Some(info) => (
+                    info.fee_base_msat,
+                    info.fee_proportional_millionths,
+                    info.cltv_expiry_delta,
+                ),
+                None => {
+                    let config = self.user_config.channel_config;
+                    (
+                        config.forwarding_fee_base_msat,
+                        config.forwarding_fee_proportional_millionths,
+                        config.cltv_expiry_delta,
+                    )
+                }
+            };
+
+        let spendable_msat = from_channel.next_outbound_htlc_limit_msat;
+        let fee_msat =
+            fee_base_msat as u64 + spendable_msat * fee_proportional_millionths as u64 / 1_000_000;
+        let amount_msat = spendable_msat
+            .checked_sub(fee_msat)
+            .filter(|amount_msat| *amount_msat > 0)
+            .context("Not enough balance in old channel to pay for the circular payment")?;
+
+        let (payment_hash, payment_secret) = self
+            .channel_manager
+            .create_inbound_payment(Some(amount_msat), CHANNEL_UPGRADE_PAYMENT_EXPIRY_SECS)
+            .map_err(|_| anyhow!("Failed to create inbound payment"))?;
+
+        let node_features = provided_node_features(&self.user_config);
+        let channel_features = provided_channel_features(&self.user_config);
+        let route = Route {
+            paths: vec![vec![
+                RouteHop {
+                    pubkey: counterparty,
+                    node_features: node_features.clone(),
+                    short_channel_id: from_scid,
+                    channel_features: channel_features.clone(),
+                    fee_msat,
+                    cltv_expiry_delta: cltv_expiry_delta as u32,
+                },
+                RouteHop {
+                    pubkey: self.info.pubkey,
+                    node_features,
+                    short_channel_id: to_scid,
+                    channel_features,
+                    fee_msat: amount_msat,
+                    cltv_expiry_delta: MIN_FINAL_CLTV_EXPIRY,
+                },
+            ]],
+            payment_params: None,
+        };
+
+        self.channel_manager
+            .send_payment(
+                &route,
+                payment_hash,
+                &Some(payment_secret),
+                PaymentId(payment_hash.0),
+            )
+            .map_err(|e| anyhow!("{e:?}"))
+            .context("Failed to send circular payment")?;
+
+        tracing::info!(
+            from_channel_id = %hex::encode(from_channel_id),
+            to_channel_id = %hex::encode(to_channel_id),
+            amount_msat,
+            fee_msat,
+            payment_hash = %hex::encode(payment_hash.0),
+            "Sent circular payment to move balance to replacement channel"
+        );
+
+        self.outbound_payments.lock().unwrap().insert(
+            payment_hash,
+            PaymentInfo {
+                preimage: None,
+                secret: Some(payment_secret),
+                status: HTLCStatus::Pending,
+                amt_msat: MillisatAmount(Some(amount_msat + fee_msat)),
+                timestamp: OffsetDateTime::now_utc(),
+                description: "Move balance to replacement channel".to_string(),
+            },
+        );
+
+        Ok(payment_hash)
+    }
+
+    /// Cooperatively closes the channel with ID `channel_id`.
+    ///
+    /// Fails if the counterparty has an active liquidity lease with us, unless the channel has
+    /// been replaced by another usable channel with them which provides all the leased liquidity.
+    pub fn close_channel(&self, channel_id: [u8; 32]) -> Result<()> {
+        let channels = self.channel_manager.list_channels();
+        let channel = channels
+            .iter()
+            .find(|channel| channel.channel_id == channel_id)
+            .with_context(|| format!("Unknown channel {}", hex::encode(channel_id)))?;
+
+        let leased_inbound_liquidity_sat = self
+            .liquidity_leases
+            .active_inbound_liquidity_sat(&channel.counterparty.node_id)?;
+        let is_replaced = channels.iter().any(|other| {
+            other.channel_id != channel_id
+                && other.counterparty.node_id == channel.counterparty.node_id
+                && other.is_usable
+                && other.outbound_capacity_msat / 1000 >= leased_inbound_liquidity_sat
+        });
+        ensure!(
+            leased_inbound_liquidity_sat == 0 || is_replaced,
+            "Cannot close channel {} before the liquidity lease of {} expires",
+            hex::encode(channel_id),
+            channel.counterparty.node_id
+        );
+
+        self.channel_manager
+            .close_channel(&channel_id, &channel.counterparty.node_id)
+            .map_err(|e| anyhow!("{e:?}"))
+            .with_context(|| format!("Failed to close channel {}", hex::encode(channel_id)))?;
+
+        tracing::info!(
+            channel_id = %hex::encode(channel_id),
+            counterparty = %channel.counterparty.node_id,
+            "Started cooperative channel close"
+        );
+
+        Ok(())
+    }
+
+    /// Upgrading a channel with a DLC on top of it would force-close the DLC.
+    fn ensure_no_dlc_channel(&self, counterparty: &PublicKey) -> Result<()> {
+        ensure!(
+            self.get_dlc_channel_offer(counterparty)?.is_none()
+                && self.get_dlc_channel_signed(counterparty)?.is_none()
+                && self.get_dlc_channel_close_offer(counterparty)?.is_none(),
+            "Cannot upgrade channel with {counterparty} while a DLC channel is open"
+        );
+
+        Ok(())
+    }
+}
+
+/// The fee for upgrading a channel, i.e. our usual forwarding fee on the capacity we add.
+pub(crate) fn upgrade_fee_msat(channel_config: &ChannelConfig, added_capacity_sat: u64) -> u64 {
+    channel_config.forwarding_fee_base_msat as u64
+        + added_capacity_sat * 1000 * channel_config.forwarding_fee_proportional_millionths as u64
+            / 1_000_000
+}
+
+fn find_channel(channels: &[ChannelDetails], channel_id: [u8; 32]) -> Result<&ChannelDetails> {
+    channels
+        .iter()
+        .find(|channel| channel.channel_id == channel_id)
+        .with_context(|| format!("No usable channel {}", hex::encode(channel_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrade_fee_is_charged_on_the_added_capacity() {
+        let channel_config = ChannelConfig {
+            forwarding_fee_base_msat: 1_000,
+            forwarding_fee_proportional_millionths: 100,
+            ..Default::default()
+        };
+
+        // 100 ppm of 80_000 sats
+        assert_eq!(upgrade_fee_msat(&channel_config, 80_000), 1_000 + 8_000);
+        assert_eq!(upgrade_fee_msat(&channel_config, 0), 1_000);
+    }
+}
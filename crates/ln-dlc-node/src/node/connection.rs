@@ -1,5 +1,6 @@
 use crate::node::Node;
 use crate::node::NodeInfo;
+use crate::node::PeerAddress;
 use crate::PeerManager;
 use anyhow::anyhow;
 use anyhow::bail;
@@ -7,30 +8,35 @@ use anyhow::Context;
 use anyhow::Result;
 use bitcoin::secp256k1::PublicKey;
 use futures::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+
+/// How long we wait for the TCP connection (through the proxy, if any) to be set up.
+const CONNECTION_SETUP_TIMEOUT: Duration = Duration::from_secs(10);
 
 impl Node {
     pub async fn connect(&self, peer: NodeInfo) -> Result<Pin<Box<impl Future<Output = ()>>>> {
         #[allow(clippy::async_yields_async)] // We want to poll this future in a loop elsewhere
-        let connection_closed_future = tokio::time::timeout(Duration::from_secs(30), async move {
+        let connection_closed_future = tokio::time::timeout(Duration::from_secs(30), async {
             loop {
                 tracing::debug!(%peer, "Setting up connection");
 
-                if let Some(fut) = lightning_net_tokio::connect_outbound(
-                    self.peer_manager.clone(),
-                    peer.pubkey,
-                    peer.address,
-                )
-                .await
-                {
-                    return fut;
-                };
-
-                let retry_interval = Duration::from_secs(1);
-                tracing::debug!(%peer, ?retry_interval, "Connection setup failed; retrying");
-                tokio::time::sleep(retry_interval).await;
+                match connect_outbound(&self.peer_manager, &peer, self.socks5_proxy).await {
+                    Ok(fut) => return fut,
+                    Err(e) => {
+                        let retry_interval = Duration::from_secs(1);
+                        tracing::debug!(
+                            %peer,
+                            ?retry_interval,
+                            "Connection setup failed: {e:#}; retrying"
+                        );
+                        tokio::time::sleep(retry_interval).await;
+                    }
+                }
             }
         })
         .await
@@ -39,30 +45,154 @@ impl Node {
         tracing::debug!(%peer, "Connection setup completed");
 
         let mut connection_closed_future = Box::pin(connection_closed_future);
+        await_handshake(&self.peer_manager, &peer, &mut connection_closed_future).await?;
 
-        tokio::time::timeout(Duration::from_secs(30), async {
-            while !Self::is_connected(&self.peer_manager, peer.pubkey) {
-                if futures::poll!(&mut connection_closed_future).is_ready() {
-                    bail!("Peer disconnected before we finished the handshake");
-                }
+        tracing::info!(%peer, "Connection established");
+        Ok(connection_closed_future)
+    }
+}
 
-                tracing::debug!(%peer, "Waiting to confirm established connection");
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
+/// Makes a single attempt to connect to `peer`, through `socks5_proxy` if set.
+///
+/// Returns a future which resolves once the connection is closed.
+pub(crate) async fn connect_once(
+    peer_manager: &Arc<PeerManager>,
+    peer: &NodeInfo,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<Pin<Box<impl Future<Output = ()>>>> {
+    tracing::debug!(%peer, "Setting up connection");
 
-            Ok(())
-        })
+    let connection_closed_future = connect_outbound(peer_manager, peer, socks5_proxy)
         .await
-        .map_err(|e| anyhow!(e.to_string()))??;
+        .with_context(|| format!("Failed to connect to peer: {peer}"))?;
 
-        tracing::info!(%peer, "Connection established");
-        Ok(connection_closed_future)
-    }
+    let mut connection_closed_future = Box::pin(connection_closed_future);
+    await_handshake(peer_manager, peer, &mut connection_closed_future).await?;
+
+    tracing::info!(%peer, "Connection established");
+    Ok(connection_closed_future)
+}
+
+/// Sets up a TCP connection to `peer` and hands it to the peer manager.
+///
+/// Unlike [`lightning_net_tokio::connect_outbound`], this can connect to hostnames and, through a
+/// SOCKS5 proxy, to onion services.
+async fn connect_outbound(
+    peer_manager: &Arc<PeerManager>,
+    peer: &NodeInfo,
+    socks5_proxy: Option<SocketAddr>,
+) -> Result<impl Future<Output = ()>> {
+    let stream = tokio::time::timeout(
+        CONNECTION_SETUP_TIMEOUT,
+        tcp_connect(&peer.address, socks5_proxy),
+    )
+    .await
+    .context("Timed out setting up connection")??;
+
+    // The peer manager takes over the connection as a non-blocking std stream, which is what
+    // converting the tokio stream gives us
+    let stream = stream.into_std()?;
+
+    Ok(lightning_net_tokio::setup_outbound(
+        peer_manager.clone(),
+        peer.pubkey,
+        stream,
+    ))
+}
+
+async fn tcp_connect(address: &PeerAddress, socks5_proxy: Option<SocketAddr>) -> Result<TcpStream> {
+    let stream = match (address, socks5_proxy) {
+        // Hostnames and onion addresses are resolved by the proxy
+        (address, Some(proxy)) => Socks5Stream::connect(proxy, (address.host(), address.port()))
+            .await
+            .with_context(|| format!("Failed to connect to {address} through SOCKS5 proxy"))?
+            .into_inner(),
+        (PeerAddress::Ip(address), None) => TcpStream::connect(*address).await?,
+        (PeerAddress::Hostname { host, port }, None) => {
+            TcpStream::connect((host.as_str(), *port)).await?
+        }
+        (PeerAddress::OnionV3 { .. }, None) => {
+            bail!("Cannot connect to an onion address without a SOCKS5 proxy")
+        }
+    };
+
+    Ok(stream)
+}
+
+async fn await_handshake<F>(
+    peer_manager: &Arc<PeerManager>,
+    peer: &NodeInfo,
+    connection_closed_future: &mut F,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Unpin,
+{
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while !is_connected(peer_manager, peer.pubkey) {
+            if futures::poll!(&mut *connection_closed_future).is_ready() {
+                bail!("Peer disconnected before we finished the handshake");
+            }
+
+            tracing::debug!(%peer, "Waiting to confirm established connection");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow!(e.to_string()))?
+}
+
+pub(crate) fn is_connected(peer_manager: &Arc<PeerManager>, pubkey: PublicKey) -> bool {
+    peer_manager
+        .get_peer_node_ids()
+        .iter()
+        .any(|id| *id == pubkey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn connects_to_hostname_through_socks5_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+
+        let proxy_task = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            // Offers no authentication, which we accept
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(&[5, 0]).await.unwrap();
+
+            // Lets the proxy resolve the hostname
+            let host = b"coordinator.10101.finance";
+            let mut request = vec![0; 5 + host.len() + 2];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[..5], [5, 1, 0, 3, host.len() as u8]);
+            assert_eq!(&request[5..5 + host.len()], host);
+            assert_eq!(request[5 + host.len()..], 9045u16.to_be_bytes());
+
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+            stream.write_all(b"hello").await.unwrap();
+        });
+
+        let target = "coordinator.10101.finance:9045".parse().unwrap();
+        let mut stream = tcp_connect(&target, Some(proxy)).await.unwrap();
+
+        let mut hello = [0; 5];
+        stream.read_exact(&mut hello).await.unwrap();
+        assert_eq!(&hello, b"hello");
 
-    fn is_connected(peer_manager: &Arc<PeerManager>, pubkey: PublicKey) -> bool {
-        peer_manager
-            .get_peer_node_ids()
-            .iter()
-            .any(|id| *id == pubkey)
+        proxy_task.await.unwrap();
     }
 }
@@ -1,11 +1,16 @@
 use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::DlcChannelEventKind;
 use crate::node::Node;
 use anyhow::anyhow;
+use anyhow::Context;
 use anyhow::Result;
 use bitcoin::secp256k1::PublicKey;
+use dlc_manager::channel::Channel;
 use dlc_manager::contract::contract_input::ContractInput;
+use dlc_manager::contract::Contract;
 use dlc_manager::subchannel::SubChannel;
 use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::ChannelId;
 use dlc_manager::Oracle;
 use dlc_manager::Storage;
 use dlc_messages::Message;
@@ -20,7 +25,52 @@ pub struct Dlc {
     pub accept_pk: PublicKey,
 }
 
+/// How we choose the fee rate of the DLC transactions of the DLC channels we propose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DlcFeeRatePolicy {
+    /// Within how many blocks the DLC transactions should be confirmed.
+    pub confirmation_target_blocks: usize,
+    pub min_sats_per_vbyte: u64,
+    pub max_sats_per_vbyte: u64,
+}
+
+impl DlcFeeRatePolicy {
+    /// Applies the caps of the policy to the estimated fee rate, rounding up so that we do not
+    /// undershoot the estimate.
+    pub fn fee_rate(&self, estimated_sats_per_vbyte: f32) -> u64 {
+        let fee_rate = estimated_sats_per_vbyte.max(0.0).ceil() as u64;
+
+        fee_rate.clamp(self.min_sats_per_vbyte, self.max_sats_per_vbyte)
+    }
+}
+
+impl Default for DlcFeeRatePolicy {
+    fn default() -> Self {
+        Self {
+            confirmation_target_blocks: 6,
+            min_sats_per_vbyte: 1,
+            max_sats_per_vbyte: 100,
+        }
+    }
+}
+
 impl Node {
+    /// The fee rate in sats per vbyte to use for the DLC transactions of a new DLC channel.
+    pub async fn dlc_fee_rate(&self, policy: DlcFeeRatePolicy) -> Result<u64> {
+        let estimate = tokio::task::spawn_blocking({
+            let wallet = self.wallet.clone();
+            move || {
+                wallet
+                    .inner()
+                    .estimate_fee_rate(policy.confirmation_target_blocks)
+            }
+        })
+        .await?
+        .with_context(|| format!("Failed to estimate DLC fee rate with {policy:?}"))?;
+
+        Ok(policy.fee_rate(estimate.as_sat_per_vb()))
+    }
+
     pub async fn propose_dlc_channel(
         &self,
         channel_details: &ChannelDetails,
@@ -61,6 +111,8 @@ impl Node {
 
         tracing::info!(channel_id = %channel_id_hex, "Accepting DLC channel offer");
 
+        let dlc = self.dlc_of_dlc_channel(channel_id);
+
         let (node_id, accept_sub_channel) = self
             .sub_channel_manager
             .accept_sub_channel(channel_id)
@@ -71,6 +123,36 @@ impl Node {
             Message::SubChannel(SubChannelMessage::Accept(accept_sub_channel)),
         );
 
+        self.emit_dlc_channel_event(*channel_id, node_id, DlcChannelEventKind::Accepted, dlc);
+
+        Ok(())
+    }
+
+    pub fn reject_dlc_channel_offer(&self, channel_id: &[u8; 32]) -> Result<()> {
+        let channel_id_hex = hex::encode(channel_id);
+
+        tracing::info!(channel_id = %channel_id_hex, "Rejecting DLC channel offer");
+
+        let counterparty_pk = self.get_dlc_channel_counterparty(channel_id)?;
+        let dlc = self.dlc_of_dlc_channel(channel_id);
+
+        let reject = self
+            .sub_channel_manager
+            .reject_sub_channel_offer(*channel_id)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        self.dlc_message_handler.send_message(
+            counterparty_pk,
+            Message::SubChannel(SubChannelMessage::Reject(reject)),
+        );
+
+        self.emit_dlc_channel_event(
+            *channel_id,
+            counterparty_pk,
+            DlcChannelEventKind::Rejected,
+            dlc,
+        );
+
         Ok(())
     }
 
@@ -118,6 +200,34 @@ impl Node {
         Ok(())
     }
 
+    pub fn reject_dlc_channel_collaborative_settlement(&self, channel_id: &[u8; 32]) -> Result<()> {
+        let channel_id_hex = hex::encode(channel_id);
+
+        tracing::info!(channel_id = %channel_id_hex, "Rejecting DLC channel collaborative settlement");
+
+        let counterparty_pk = self.get_dlc_channel_counterparty(channel_id)?;
+        let dlc = self.dlc_of_dlc_channel(channel_id);
+
+        let reject = self
+            .sub_channel_manager
+            .reject_sub_channel_close_offer(*channel_id)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        self.dlc_message_handler.send_message(
+            counterparty_pk,
+            Message::SubChannel(SubChannelMessage::Reject(reject)),
+        );
+
+        self.emit_dlc_channel_event(
+            *channel_id,
+            counterparty_pk,
+            DlcChannelEventKind::CloseRejected,
+            dlc,
+        );
+
+        Ok(())
+    }
+
     pub fn get_dlc_channel_offer(&self, pubkey: &PublicKey) -> Result<Option<SubChannel>> {
         let dlc_channel = self
             .dlc_manager
@@ -149,6 +259,35 @@ impl Node {
         Ok(dlc_channel)
     }
 
+    /// The usable LN channels with `pubkey` which do not hold a DLC, so that a new DLC can be
+    /// added to them.
+    ///
+    /// Each LN channel can only hold a single DLC, so a counterparty needs several LN channels with
+    /// us to have several DLCs at once.
+    pub fn list_usable_channels_without_dlc(
+        &self,
+        pubkey: &PublicKey,
+    ) -> Result<Vec<ChannelDetails>> {
+        let dlc_channels = self.list_dlc_channels()?;
+
+        let channels = self
+            .list_usable_channels()
+            .into_iter()
+            .filter(|channel| channel.counterparty.node_id == *pubkey)
+            .filter(|channel| {
+                !dlc_channels.iter().any(|dlc_channel| {
+                    dlc_channel.channel_id == channel.channel_id
+                        && !matches!(
+                            dlc_channel.state,
+                            SubChannelState::OffChainClosed | SubChannelState::Rejected
+                        )
+                })
+            })
+            .collect();
+
+        Ok(channels)
+    }
+
     pub fn list_dlc_channels(&self) -> Result<Vec<SubChannel>> {
         let dlc_channels = self
             .dlc_manager
@@ -159,6 +298,51 @@ impl Node {
         Ok(dlc_channels)
     }
 
+    /// The contract of the DLC in the DLC channel of the LN channel `channel_id`, if any.
+    ///
+    /// Unlike going through the contracts with the counterparty, this finds the exact contract
+    /// even if we have several DLC channels with the same counterparty.
+    pub fn get_dlc_channel_contract(&self, channel_id: &[u8; 32]) -> Result<Option<Contract>> {
+        let store = self.dlc_manager.get_store();
+
+        let dlc_channel = store
+            .get_sub_channel(*channel_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .with_context(|| format!("Unknown DLC channel {}", hex::encode(channel_id)))?;
+
+        let dlc_channel_id = match dlc_channel.get_dlc_channel_id(0) {
+            Some(dlc_channel_id) => dlc_channel_id,
+            None => return Ok(None),
+        };
+
+        let contract_id = match store
+            .get_channel(&dlc_channel_id)
+            .map_err(|e| anyhow!(e.to_string()))?
+        {
+            Some(Channel::Offered(channel)) => Some(channel.offered_contract_id),
+            Some(Channel::Accepted(channel)) => Some(channel.accepted_contract_id),
+            Some(Channel::Signed(channel)) => channel.get_contract_id(),
+            _ => None,
+        };
+
+        let contract = match contract_id {
+            Some(contract_id) => store
+                .get_contract(&contract_id)
+                .map_err(|e| anyhow!(e.to_string()))?,
+            None => None,
+        };
+
+        Ok(contract)
+    }
+
+    fn get_dlc_channel_counterparty(&self, channel_id: &[u8; 32]) -> Result<PublicKey> {
+        let dlc_channel = self
+            .get_dlc_channel(|dlc_channel| dlc_channel.channel_id == *channel_id)?
+            .with_context(|| format!("Unknown DLC channel {}", hex::encode(channel_id)))?;
+
+        Ok(dlc_channel.counter_party)
+    }
+
     fn get_dlc_channel(
         &self,
         matcher: impl FnMut(&&SubChannel) -> bool,
@@ -169,8 +353,40 @@ impl Node {
         Ok(dlc_channel.cloned())
     }
 
-    #[cfg(test)]
+    /// Processes DLC messages as soon as we receive them, forever.
+    ///
+    /// `on_sub_channel_message` is called for every DLC channel message we process, together with
+    /// our reply to it, once the reply has been sent.
+    pub async fn process_incoming_messages_continuously(
+        &self,
+        mut on_sub_channel_message: impl FnMut(
+            PublicKey,
+            &SubChannelMessage,
+            Option<&SubChannelMessage>,
+        ),
+    ) {
+        loop {
+            self.dlc_message_handler.message_received().await;
+
+            if let Err(e) = self.process_incoming_messages_with(&mut on_sub_channel_message) {
+                tracing::error!("Unable to process incoming messages: {e:#}");
+            }
+        }
+    }
+
+    /// Processes all the DLC messages we have received since the last call.
     pub fn process_incoming_messages(&self) -> Result<()> {
+        self.process_incoming_messages_with(|_, _, _| {})
+    }
+
+    fn process_incoming_messages_with(
+        &self,
+        mut on_sub_channel_message: impl FnMut(
+            PublicKey,
+            &SubChannelMessage,
+            Option<&SubChannelMessage>,
+        ),
+    ) -> Result<()> {
         let dlc_message_handler = &self.dlc_message_handler;
         let dlc_manager = &self.dlc_manager;
         let sub_channel_manager = &self.sub_channel_manager;
@@ -196,18 +412,25 @@ impl Node {
                         msg = %sub_channel_message_as_str(&msg),
                         "Processing DLC channel message"
                     );
+                    let dlc = self.dlc_of_dlc_channel(&sub_channel_message_channel_id(&msg));
+
                     let resp = sub_channel_manager
                         .on_sub_channel_message(&msg, &node_id)
                         .map_err(|e| anyhow!(e.to_string()))?;
 
-                    if let Some(msg) = resp {
+                    if let Some(resp) = &resp {
                         tracing::debug!(
                             to = %node_id,
-                            msg = %sub_channel_message_as_str(&msg),
+                            msg = %sub_channel_message_as_str(resp),
                             "Sending DLC channel message"
                         );
-                        dlc_message_handler.send_message(node_id, Message::SubChannel(msg));
+                        dlc_message_handler
+                            .send_message(node_id, Message::SubChannel(resp.clone()));
                     }
+
+                    self.emit_dlc_channel_message_event(node_id, &msg, resp.as_ref(), dlc);
+
+                    on_sub_channel_message(node_id, &msg, resp.as_ref());
                 }
             }
         }
@@ -223,6 +446,22 @@ impl Node {
     }
 }
 
+fn sub_channel_message_channel_id(msg: &SubChannelMessage) -> ChannelId {
+    use SubChannelMessage::*;
+
+    match msg {
+        Offer(msg) => msg.channel_id,
+        Accept(msg) => msg.channel_id,
+        Confirm(msg) => msg.channel_id,
+        Finalize(msg) => msg.channel_id,
+        CloseOffer(msg) => msg.channel_id,
+        CloseAccept(msg) => msg.channel_id,
+        CloseConfirm(msg) => msg.channel_id,
+        CloseFinalize(msg) => msg.channel_id,
+        Reject(msg) => msg.channel_id,
+    }
+}
+
 pub fn sub_channel_message_as_str(msg: &SubChannelMessage) -> &str {
     use SubChannelMessage::*;
 
@@ -238,3 +477,21 @@ pub fn sub_channel_message_as_str(msg: &SubChannelMessage) -> &str {
         Reject(_) => "Reject",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dlc_fee_rate_is_rounded_up_and_capped() {
+        let policy = DlcFeeRatePolicy {
+            confirmation_target_blocks: 6,
+            min_sats_per_vbyte: 2,
+            max_sats_per_vbyte: 50,
+        };
+
+        assert_eq!(policy.fee_rate(12.3), 13);
+        assert_eq!(policy.fee_rate(0.5), 2);
+        assert_eq!(policy.fee_rate(120.0), 50);
+    }
+}
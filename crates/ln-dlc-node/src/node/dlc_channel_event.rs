@@ -0,0 +1,263 @@
+use crate::disk::FileStore;
+use crate::node::Node;
+use crate::Dlc;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::ChannelId;
+use dlc_manager::Storage;
+use dlc_messages::SubChannelMessage;
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// How many DLC channel events a subscriber can fall behind before it starts missing events.
+pub(crate) const DLC_CHANNEL_EVENTS_CAPACITY: usize = 100;
+
+/// A step in the lifecycle of a DLC channel with `counterparty`.
+#[derive(Debug, Clone)]
+pub struct DlcChannelEvent {
+    pub channel_id: ChannelId,
+    pub counterparty: PublicKey,
+    /// The contract of the DLC channel, if it could be identified.
+    pub dlc: Option<Dlc>,
+    pub kind: DlcChannelEventKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DlcChannelEventKind {
+    /// The counterparty offered us a DLC channel.
+    OfferReceived,
+    /// The DLC channel offer was accepted, by us or by the counterparty.
+    Accepted,
+    /// Both parties signed the DLC, i.e. the DLC channel is established.
+    Signed,
+    /// The counterparty offered to collaboratively close the DLC channel.
+    CloseOffered,
+    /// The DLC channel was collaboratively closed.
+    Closed,
+    /// The DLC channel offer was rejected, by us or by the counterparty.
+    Rejected,
+    /// The offer to close the DLC channel was rejected, by us or by the counterparty. The DLC
+    /// channel stays established.
+    CloseRejected,
+    /// The channel the DLC lives in was closed on-chain.
+    ForceClosed,
+}
+
+/// Persists the DLC channels we already emitted a [`DlcChannelEventKind::ForceClosed`] event for,
+/// one file per channel, so that the event is not emitted again after a restart.
+pub(crate) struct ForceClosedDlcChannelStore {
+    store: FileStore,
+    /// Serialises checking for and recording a channel.
+    lock: Mutex<()>,
+}
+
+impl ForceClosedDlcChannelStore {
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            store: FileStore::new(dir)?,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Records that the force-close of the DLC channel `channel_id` has been reported. Returns
+    /// false if it had been reported before.
+    fn insert(&self, channel_id: ChannelId) -> Result<bool> {
+        let _guard = self.lock.lock().unwrap();
+
+        let key = hex::encode(channel_id);
+        if self.store.get::<ChannelId>(&key)?.is_some() {
+            return Ok(false);
+        }
+
+        self.store.insert(&key, &channel_id)?;
+
+        Ok(true)
+    }
+}
+
+impl Node {
+    pub fn subscribe_to_dlc_channel_events(&self) -> broadcast::Receiver<DlcChannelEvent> {
+        self.dlc_channel_events.subscribe()
+    }
+
+    /// The DLC in the DLC channel of the LN channel `channel_id`, if there is one.
+    pub fn dlc_of_dlc_channel(&self, channel_id: &ChannelId) -> Option<Dlc> {
+        match self.get_dlc_channel_contract(channel_id) {
+            Ok(contract) => contract.as_ref().and_then(dlc_from_contract),
+            Err(e) => {
+                tracing::debug!(
+                    channel_id = %hex::encode(channel_id),
+                    "Could not get DLC of DLC channel: {e:#}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Emits the event caused by the DLC channel message `incoming_msg` from `counterparty` and our
+    /// `reply_msg` to it.
+    ///
+    /// `before` is the DLC of the DLC channel before the message was processed.
+    pub(crate) fn emit_dlc_channel_message_event(
+        &self,
+        counterparty: PublicKey,
+        incoming_msg: &SubChannelMessage,
+        reply_msg: Option<&SubChannelMessage>,
+        before: Option<Dlc>,
+    ) {
+        use SubChannelMessage::*;
+
+        let (channel_id, kind) = match (incoming_msg, reply_msg) {
+            (Offer(msg), _) => (msg.channel_id, DlcChannelEventKind::OfferReceived),
+            (Accept(msg), _) => (msg.channel_id, DlcChannelEventKind::Accepted),
+            (Confirm(msg), Some(Finalize(_))) => (msg.channel_id, DlcChannelEventKind::Signed),
+            (Finalize(msg), _) => (msg.channel_id, DlcChannelEventKind::Signed),
+            (CloseOffer(msg), _) => (msg.channel_id, DlcChannelEventKind::CloseOffered),
+            (CloseConfirm(msg), Some(CloseFinalize(_))) => {
+                (msg.channel_id, DlcChannelEventKind::Closed)
+            }
+            (CloseFinalize(msg), _) => (msg.channel_id, DlcChannelEventKind::Closed),
+            // A rejected close offer leaves the DLC channel established
+            (Reject(msg), _) if self.is_dlc_channel_signed(&msg.channel_id) => {
+                (msg.channel_id, DlcChannelEventKind::CloseRejected)
+            }
+            (Reject(msg), _) => (msg.channel_id, DlcChannelEventKind::Rejected),
+            _ => return,
+        };
+
+        self.emit_dlc_channel_event(channel_id, counterparty, kind, before);
+    }
+
+    /// Emits an event of `kind` about the DLC channel of the LN channel `channel_id`.
+    ///
+    /// `before` is the DLC of the DLC channel before the event happened. It is reported if the DLC
+    /// channel does not hold a DLC anymore, e.g. because it was closed.
+    pub(crate) fn emit_dlc_channel_event(
+        &self,
+        channel_id: ChannelId,
+        counterparty: PublicKey,
+        kind: DlcChannelEventKind,
+        before: Option<Dlc>,
+    ) {
+        // The contract ID changes when the contract is accepted, so we prefer the current DLC
+        let dlc = self.dlc_of_dlc_channel(&channel_id).or(before);
+
+        let event = DlcChannelEvent {
+            channel_id,
+            counterparty,
+            dlc,
+            kind,
+        };
+
+        tracing::info!(
+            channel_id = %hex::encode(channel_id),
+            %counterparty,
+            ?kind,
+            ?dlc,
+            "DLC channel event"
+        );
+
+        // Sending only fails if nobody is subscribed, in which case nobody cares about the event
+        let _ = self.dlc_channel_events.send(event);
+
+        self.backup_needed.send_replace(());
+    }
+
+    /// Emits a [`DlcChannelEventKind::ForceClosed`] event for every DLC channel whose LN channel
+    /// was closed on-chain since the last call.
+    ///
+    /// Channels are only reported once, also across restarts of the node.
+    pub(crate) fn detect_force_closed_dlc_channels(&self) -> Result<()> {
+        for dlc_channel in self.list_dlc_channels()? {
+            let is_force_closed = matches!(
+                dlc_channel.state,
+                SubChannelState::Closing(_)
+                    | SubChannelState::OnChainClosed
+                    | SubChannelState::CounterOnChainClosed
+                    | SubChannelState::ClosedPunished(_)
+            );
+
+            if !is_force_closed
+                || !self
+                    .force_closed_dlc_channels
+                    .insert(dlc_channel.channel_id)?
+            {
+                continue;
+            }
+
+            let dlc = self.dlc_of_dlc_channel(&dlc_channel.channel_id);
+            self.emit_dlc_channel_event(
+                dlc_channel.channel_id,
+                dlc_channel.counter_party,
+                DlcChannelEventKind::ForceClosed,
+                dlc,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn is_dlc_channel_signed(&self, channel_id: &ChannelId) -> bool {
+        match self.dlc_manager.get_store().get_sub_channel(*channel_id) {
+            Ok(Some(dlc_channel)) => matches!(dlc_channel.state, SubChannelState::Signed(_)),
+            Ok(None) => false,
+            Err(e) => {
+                tracing::warn!(
+                    channel_id = %hex::encode(channel_id),
+                    "Failed to get DLC channel: {e}"
+                );
+                false
+            }
+        }
+    }
+}
+
+fn offered_contract(contract: &Contract) -> Option<&OfferedContract> {
+    match contract {
+        Contract::Offered(offered) | Contract::Rejected(offered) => Some(offered),
+        Contract::Accepted(accepted) => Some(&accepted.offered_contract),
+        Contract::Signed(signed) | Contract::Confirmed(signed) | Contract::Refunded(signed) => {
+            Some(&signed.accepted_contract.offered_contract)
+        }
+        Contract::FailedAccept(failed) => Some(&failed.offered_contract),
+        Contract::FailedSign(failed) => Some(&failed.accepted_contract.offered_contract),
+        // Closed contracts do not keep the offer around
+        _ => None,
+    }
+}
+
+fn dlc_from_contract(contract: &Contract) -> Option<Dlc> {
+    let offered_contract = offered_contract(contract)?;
+    let offer_collateral = offered_contract.offer_params.collateral;
+
+    Some(Dlc {
+        id: contract.get_id(),
+        offer_collateral,
+        accept_collateral: offered_contract.total_collateral - offer_collateral,
+        accept_pk: offered_contract.counter_party,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn force_closed_dlc_channel_is_only_reported_once_across_restarts() {
+        let dir = TempDir::new();
+        let store = ForceClosedDlcChannelStore::new(dir.path()).unwrap();
+
+        assert!(store.insert([1; 32]).unwrap());
+        assert!(!store.insert([1; 32]).unwrap());
+
+        let store = ForceClosedDlcChannelStore::new(dir.path()).unwrap();
+
+        assert!(!store.insert([1; 32]).unwrap());
+        assert!(store.insert([2; 32]).unwrap());
+    }
+}
@@ -1,8 +1,8 @@
 use crate::ln_dlc_wallet::LnDlcWallet;
+use crate::storage::SqliteStorage;
 use anyhow::Result;
 use dlc_manager::Oracle;
 use dlc_manager::SystemTimeProvider;
-use dlc_sled_storage_provider::SledStorageProvider;
 use p2pd_oracle_client::P2PDOracleClient;
 use std::collections::HashMap;
 use std::fs;
@@ -12,7 +12,7 @@ use std::sync::Arc;
 pub type DlcManager = dlc_manager::manager::Manager<
     Arc<LnDlcWallet>,
     Arc<LnDlcWallet>,
-    Arc<SledStorageProvider>,
+    Arc<SqliteStorage>,
     Arc<P2PDOracleClient>,
     Arc<SystemTimeProvider>,
     Arc<LnDlcWallet>,
@@ -21,7 +21,7 @@ pub type DlcManager = dlc_manager::manager::Manager<
 pub fn build(
     data_dir: &Path,
     ln_dlc_wallet: Arc<LnDlcWallet>,
-    storage: Arc<SledStorageProvider>,
+    storage: Arc<SqliteStorage>,
     p2pdoracle: Arc<P2PDOracleClient>,
 ) -> Result<DlcManager> {
     let offers_path = data_dir.join("offers");
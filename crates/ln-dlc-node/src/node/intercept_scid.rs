@@ -0,0 +1,329 @@
+use crate::disk::FileStore;
+use crate::node::Node;
+use crate::node::LIQUIDITY_ROUTING_FEE_MILLIONTHS;
+use crate::util::unix_timestamp;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::PaymentHash;
+use lightning::util::ser::Readable;
+use lightning::util::ser::Writeable;
+use lightning::util::ser::Writer;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// For how long an intercept SCID can be used to pay the target node.
+///
+/// This should be at least as long as the expiry of the invoices using the intercept SCID.
+pub const INTERCEPT_SCID_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The fees we charge for forwarding a payment through an intercept SCID, i.e. potentially through
+/// a just-in-time channel.
+///
+/// The fees are advertised in the route hint of the invoice and enforced when the intercepted HTLC
+/// is forwarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JitFeePolicy {
+    pub base_msat: u32,
+    /// In millionths of the forwarded amount. In other words, 10000 is 1%.
+    pub proportional_millionths: u32,
+}
+
+impl JitFeePolicy {
+    /// The fee to be paid to forward `amount_msat`, rounded down just like the sender does.
+    pub fn fee_msat(&self, amount_msat: u64) -> u64 {
+        self.base_msat as u64 + amount_msat * self.proportional_millionths as u64 / 1_000_000
+    }
+}
+
+impl Default for JitFeePolicy {
+    fn default() -> Self {
+        Self {
+            base_msat: 1000,
+            proportional_millionths: LIQUIDITY_ROUTING_FEE_MILLIONTHS,
+        }
+    }
+}
+
+/// A fake short channel ID which the payee puts in the route hint of their invoice, so that we
+/// intercept the payment and forward it to them, opening a channel if needed.
+///
+/// An intercept SCID can only be used for a single payment and only until `expiry_timestamp`.
+/// The SCID is bound to the payment hash of the first HTLC using it, so that further parts of a
+/// multi-path payment and retries of the same payment are still forwarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterceptScid {
+    pub scid: u64,
+    pub target_node: PublicKey,
+    pub fee_policy: JitFeePolicy,
+    /// Unix timestamp after which payments through this SCID are failed.
+    pub expiry_timestamp: u64,
+    /// The payment this SCID is used for, once the first HTLC has been intercepted.
+    #[serde(skip)]
+    pub payment_hash: Option<PaymentHash>,
+}
+
+impl InterceptScid {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expiry_timestamp <= now
+    }
+}
+
+impl Writeable for InterceptScid {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        self.scid.write(writer)?;
+        self.target_node.write(writer)?;
+        self.fee_policy.base_msat.write(writer)?;
+        self.fee_policy.proportional_millionths.write(writer)?;
+        self.expiry_timestamp.write(writer)?;
+        self.payment_hash.write(writer)?;
+
+        Ok(())
+    }
+}
+
+impl Readable for InterceptScid {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let scid = Readable::read(reader)?;
+        let target_node = Readable::read(reader)?;
+        let base_msat = Readable::read(reader)?;
+        let proportional_millionths = Readable::read(reader)?;
+        let expiry_timestamp = Readable::read(reader)?;
+        let payment_hash = Readable::read(reader)?;
+
+        Ok(Self {
+            scid,
+            target_node,
+            fee_policy: JitFeePolicy {
+                base_msat,
+                proportional_millionths,
+            },
+            expiry_timestamp,
+            payment_hash,
+        })
+    }
+}
+
+/// Persists the [`InterceptScid`]s we handed out, one file per SCID, so that invoices remain
+/// payable across restarts.
+pub(crate) struct InterceptScidStore {
+    store: FileStore,
+    /// Serialises read-modify-write cycles on the SCIDs.
+    lock: Mutex<()>,
+}
+
+impl InterceptScidStore {
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            store: FileStore::new(dir)?,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn insert(&self, intercept_scid: &InterceptScid) -> Result<()> {
+        self.store
+            .insert(&intercept_scid.scid.to_string(), intercept_scid)
+    }
+
+    pub(crate) fn get(&self, scid: u64) -> Result<Option<InterceptScid>> {
+        self.store.get(&scid.to_string())
+    }
+
+    /// Forgets about `scid`, so that it cannot be used again.
+    pub(crate) fn remove(&self, scid: u64) -> Result<()> {
+        self.store.remove(&scid.to_string())
+    }
+
+    /// Binds the intercept SCID `scid` to `payment_hash` if it has not been used yet.
+    ///
+    /// Returns whether an HTLC of the payment with `payment_hash` may be forwarded through the
+    /// SCID, i.e. whether the SCID is unused or already bound to the same payment.
+    pub(crate) fn bind_to_payment(&self, scid: u64, payment_hash: PaymentHash) -> Result<bool> {
+        let _guard = self.lock.lock().unwrap();
+
+        let intercept_scid = match self.get(scid)? {
+            Some(intercept_scid) => intercept_scid,
+            None => return Ok(false),
+        };
+
+        match intercept_scid.payment_hash {
+            Some(bound_payment_hash) => Ok(bound_payment_hash == payment_hash),
+            None => {
+                self.insert(&InterceptScid {
+                    payment_hash: Some(payment_hash),
+                    ..intercept_scid
+                })?;
+
+                Ok(true)
+            }
+        }
+    }
+
+    fn remove_expired(&self, now: u64) -> Result<()> {
+        for intercept_scid in self.store.all::<InterceptScid>()? {
+            if intercept_scid.is_expired(now) {
+                self.remove(intercept_scid.scid)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Node {
+    /// Creates a fake channel id needed to intercept payments to the provided `target_node`
+    ///
+    /// This is mainly used for instant payments where the receiver does not have a lightning
+    /// channel yet, e.g. Alice does not have a channel with Bob yet but wants to
+    /// receive a LN payment. Clair pays to Bob who opens a channel to Alice and pays her.
+    ///
+    /// The payment has to pay the fees of `fee_policy` and has to arrive within
+    /// [`INTERCEPT_SCID_EXPIRY`].
+    pub fn create_intercept_scid(
+        &self,
+        target_node: PublicKey,
+        fee_policy: JitFeePolicy,
+    ) -> Result<InterceptScid> {
+        let now = unix_timestamp();
+        if let Err(e) = self.intercept_scids.remove_expired(now) {
+            tracing::warn!("Failed to remove expired intercept SCIDs: {e:#}");
+        }
+
+        let intercept_scid = InterceptScid {
+            scid: self.channel_manager.get_intercept_scid(),
+            target_node,
+            fee_policy,
+            expiry_timestamp: now + INTERCEPT_SCID_EXPIRY.as_secs(),
+            payment_hash: None,
+        };
+
+        self.intercept_scids.insert(&intercept_scid)?;
+
+        Ok(intercept_scid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::secp256k1::SecretKey;
+    use std::sync::Arc;
+
+    #[test]
+    fn fee_is_rounded_down() {
+        let fee_policy = JitFeePolicy {
+            base_msat: 1_000,
+            proportional_millionths: 20_000,
+        };
+
+        assert_eq!(fee_policy.fee_msat(1_000_000), 21_000);
+        assert_eq!(fee_policy.fee_msat(49), 1_000);
+    }
+
+    #[test]
+    fn expired_intercept_scids_are_removed() {
+        let dir = TempDir::new();
+        let store = InterceptScidStore::new(dir.path()).unwrap();
+
+        let secp = Secp256k1::new();
+        let target_node =
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let expired = InterceptScid {
+            scid: 1,
+            target_node,
+            fee_policy: JitFeePolicy::default(),
+            expiry_timestamp: 100,
+            payment_hash: None,
+        };
+        let valid = InterceptScid {
+            scid: 2,
+            expiry_timestamp: 200,
+            ..expired
+        };
+        store.insert(&expired).unwrap();
+        store.insert(&valid).unwrap();
+
+        assert_eq!(store.get(1).unwrap(), Some(expired));
+
+        store.remove_expired(150).unwrap();
+
+        assert_eq!(store.get(1).unwrap(), None);
+        assert_eq!(store.get(2).unwrap(), Some(valid));
+
+        store.remove(2).unwrap();
+        assert_eq!(store.get(2).unwrap(), None);
+    }
+
+    #[test]
+    fn intercept_scid_is_bound_to_the_first_payment() {
+        let dir = TempDir::new();
+        let store = InterceptScidStore::new(dir.path()).unwrap();
+
+        let secp = Secp256k1::new();
+        let target_node =
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let intercept_scid = InterceptScid {
+            scid: 1,
+            target_node,
+            fee_policy: JitFeePolicy::default(),
+            expiry_timestamp: 100,
+            payment_hash: None,
+        };
+        store.insert(&intercept_scid).unwrap();
+
+        assert!(store.bind_to_payment(1, PaymentHash([1; 32])).unwrap());
+
+        // Further parts and retries of the same payment are accepted after a restart
+        let store = InterceptScidStore::new(dir.path()).unwrap();
+        assert_eq!(
+            store.get(1).unwrap().unwrap().payment_hash,
+            Some(PaymentHash([1; 32]))
+        );
+        assert!(store.bind_to_payment(1, PaymentHash([1; 32])).unwrap());
+
+        // Other payments are not
+        assert!(!store.bind_to_payment(1, PaymentHash([2; 32])).unwrap());
+
+        // Neither are unknown SCIDs
+        assert!(!store.bind_to_payment(2, PaymentHash([1; 32])).unwrap());
+    }
+
+    #[test]
+    fn intercept_scid_is_bound_to_a_single_payment_when_used_concurrently() {
+        let dir = TempDir::new();
+        let store = Arc::new(InterceptScidStore::new(dir.path()).unwrap());
+
+        let secp = Secp256k1::new();
+        let target_node =
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        store
+            .insert(&InterceptScid {
+                scid: 1,
+                target_node,
+                fee_policy: JitFeePolicy::default(),
+                expiry_timestamp: 100,
+                payment_hash: None,
+            })
+            .unwrap();
+
+        let handles = (0..8u8)
+            .map(|i| {
+                let store = store.clone();
+                std::thread::spawn(move || store.bind_to_payment(1, PaymentHash([i; 32])).unwrap())
+            })
+            .collect::<Vec<_>>();
+        let bound = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|bound| *bound)
+            .count();
+
+        assert_eq!(bound, 1);
+    }
+}
@@ -1,8 +1,9 @@
+use crate::node::InterceptScid;
 use crate::node::Node;
-use crate::node::LIQUIDITY_ROUTING_FEE_MILLIONTHS;
 use crate::MillisatAmount;
 use crate::PaymentInfo;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use bitcoin::hashes::sha256;
@@ -22,13 +23,14 @@ use lightning_invoice::payment::PaymentError;
 use lightning_invoice::Currency;
 use lightning_invoice::Invoice;
 use lightning_invoice::InvoiceBuilder;
+use lightning_invoice::InvoiceDescription;
 use std::time::Duration;
 use std::time::SystemTime;
 use time::OffsetDateTime;
 
 impl Node {
     pub fn create_invoice(&self, amount_in_sats: u64) -> Result<Invoice> {
-        lightning_invoice::utils::create_invoice_from_channelmanager(
+        let invoice = lightning_invoice::utils::create_invoice_from_channelmanager(
             &self.channel_manager,
             self.keys_manager.clone(),
             self.logger.clone(),
@@ -37,19 +39,23 @@ impl Node {
             "".to_string(),
             180,
         )
-        .map_err(|e| anyhow!(e))
+        .map_err(|e| anyhow!(e))?;
+
+        self.track_inbound_payment(&invoice);
+
+        Ok(invoice)
     }
 
     /// Creates an invoice which is meant to be intercepted
     ///
-    /// Doing so we need to pass in `intercepted_channel_id` which needs to be generated by the
+    /// Doing so we need to pass in `intercept_scid` which needs to be generated by the
     /// intercepting node. This information, in combination with `hop_before_me` is used to add a
     /// routing hint to the invoice. Otherwise the sending node does not know how to pay the
     /// invoice
     pub fn create_interceptable_invoice(
         &self,
         amount_in_sats: Option<u64>,
-        intercepted_channel_id: u64,
+        intercept_scid: InterceptScid,
         hop_before_me: PublicKey,
         invoice_expiry: u32,
         description: String,
@@ -72,12 +78,13 @@ impl Node {
             .min_final_cltv_expiry(MIN_FINAL_CLTV_EXPIRY as u64)
             .private_route(RouteHint(vec![RouteHintHop {
                 src_node_id: hop_before_me,
-                short_channel_id: intercepted_channel_id,
-                // QUESTION: What happens if these differ with the actual values
-                // in the `ChannelConfig` for the private channel?
+                short_channel_id: intercept_scid.scid,
+                // The intercepting node fails the payment if these fees are not paid, regardless
+                // of the `ChannelConfig` of the channel the payment is eventually forwarded
+                // through.
                 fees: RoutingFees {
-                    base_msat: 1000,
-                    proportional_millionths: LIQUIDITY_ROUTING_FEE_MILLIONTHS,
+                    base_msat: intercept_scid.fee_policy.base_msat,
+                    proportional_millionths: intercept_scid.fee_policy.proportional_millionths,
                 },
                 cltv_expiry_delta: MIN_CLTV_EXPIRY_DELTA,
                 htlc_minimum_msat: None,
@@ -98,9 +105,28 @@ impl Node {
             })
             .unwrap();
         let invoice = Invoice::from_signed(signed_invoice).unwrap();
+
+        self.track_inbound_payment(&invoice);
+
         Ok(invoice)
     }
 
+    /// Records the payment we expect for `invoice`, so that we know its amount and description
+    /// once it is claimed.
+    fn track_inbound_payment(&self, invoice: &Invoice) {
+        self.inbound_payments.lock().unwrap().insert(
+            PaymentHash(invoice.payment_hash().into_inner()),
+            PaymentInfo {
+                preimage: None,
+                secret: Some(*invoice.payment_secret()),
+                status: HTLCStatus::Pending,
+                amt_msat: MillisatAmount(invoice.amount_milli_satoshis()),
+                timestamp: OffsetDateTime::now_utc(),
+                description: invoice_description(invoice),
+            },
+        );
+    }
+
     fn get_currency(&self) -> Currency {
         match self.network {
             Network::Bitcoin => Currency::Bitcoin,
@@ -110,20 +136,6 @@ impl Node {
         }
     }
 
-    /// Creates a fake channel id needed to intercept payments to the provided `target_node`
-    ///
-    /// This is mainly used for instant payments where the receiver does not have a lightning
-    /// channel yet, e.g. Alice does not have a channel with Bob yet but wants to
-    /// receive a LN payment. Clair pays to Bob who opens a channel to Alice and pays her.
-    pub fn create_intercept_scid(&self, target_node: PublicKey) -> u64 {
-        let intercept_scid = self.channel_manager.get_intercept_scid();
-        self.fake_channel_payments
-            .lock()
-            .unwrap()
-            .insert(intercept_scid, target_node);
-        intercept_scid
-    }
-
     pub fn send_payment(&self, invoice: &Invoice) -> Result<()> {
         let status = match self.invoice_payer.pay_invoice(invoice) {
             Ok(_) => {
@@ -156,6 +168,7 @@ impl Node {
                 status,
                 amt_msat: MillisatAmount(invoice.amount_milli_satoshis()),
                 timestamp: OffsetDateTime::now_utc(),
+                description: invoice_description(invoice),
             },
         );
 
@@ -196,6 +209,45 @@ impl Node {
         })
         .await
     }
+
+    /// Waits until our payment with `hash` has been sent, failing if the payment failed.
+    pub async fn wait_for_payment_sent(&self, hash: &sha256::Hash) -> Result<()> {
+        let payment_hash = PaymentHash(hash.into_inner());
+
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+
+                match self.outbound_payments.lock().unwrap().get(&payment_hash) {
+                    Some(PaymentInfo {
+                        status: HTLCStatus::Succeeded,
+                        ..
+                    }) => return Ok(()),
+                    Some(PaymentInfo {
+                        status: HTLCStatus::Failed,
+                        ..
+                    }) => bail!("Payment {} failed", hex::encode(hash)),
+                    _ => {
+                        tracing::debug!(
+                            payment_hash = %hex::encode(hash),
+                            "Checking if payment has been sent"
+                        );
+                    }
+                }
+            }
+        })
+        .await
+        .with_context(|| format!("Payment {} was not sent in time", hex::encode(hash)))?
+    }
+}
+
+/// The description of `invoice`, which is empty if the invoice only commits to the hash of its
+/// description.
+fn invoice_description(invoice: &Invoice) -> String {
+    match invoice.description() {
+        InvoiceDescription::Direct(description) => description.clone().into_inner(),
+        InvoiceDescription::Hash(_) => String::new(),
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
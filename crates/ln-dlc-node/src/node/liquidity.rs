@@ -0,0 +1,380 @@
+use crate::disk::FileStore;
+use crate::node::Node;
+use crate::util::unix_timestamp;
+use anyhow::Result;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::PublicKey;
+use lightning::ln::msgs::DecodeError;
+use lightning::ln::PaymentHash;
+use lightning::util::ser::Readable;
+use lightning::util::ser::Writeable;
+use lightning::util::ser::Writer;
+use lightning_invoice::Invoice;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Inbound liquidity bought by a peer, i.e. liquidity that we commit to our side of a channel with
+/// them for the duration of the lease.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiquidityLease {
+    /// The hash of the invoice the peer has to pay for the lease.
+    pub payment_hash: PaymentHash,
+    pub counterparty: PublicKey,
+    pub inbound_liquidity_sat: u64,
+    pub fee_sat: u64,
+    pub lease_duration_secs: u64,
+    pub status: LeaseStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseStatus {
+    /// Waiting for the peer to pay the lease invoice.
+    Unpaid,
+    /// The lease has been paid for, but the liquidity has not yet been provided. The peer gets it
+    /// by upgrading its channel with us, see [`Node::open_replacement_channel`].
+    Paid,
+    /// We are opening the replacement channel with our `user_channel_id` which includes the
+    /// leased liquidity.
+    Providing { user_channel_id: u128 },
+    /// We opened a channel with the leased liquidity. We must not close the channel before the
+    /// unix timestamp `expiry_timestamp`.
+    Active { expiry_timestamp: u64 },
+}
+
+impl LiquidityLease {
+    pub fn is_active(&self, now: u64) -> bool {
+        matches!(self.status, LeaseStatus::Active { expiry_timestamp } if expiry_timestamp > now)
+    }
+}
+
+impl Writeable for LiquidityLease {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        self.payment_hash.write(writer)?;
+        self.counterparty.write(writer)?;
+        self.inbound_liquidity_sat.write(writer)?;
+        self.fee_sat.write(writer)?;
+        self.lease_duration_secs.write(writer)?;
+        match self.status {
+            LeaseStatus::Unpaid => 0u8.write(writer)?,
+            LeaseStatus::Paid => 1u8.write(writer)?,
+            LeaseStatus::Active { expiry_timestamp } => {
+                2u8.write(writer)?;
+                expiry_timestamp.write(writer)?;
+            }
+            LeaseStatus::Providing { user_channel_id } => {
+                3u8.write(writer)?;
+                user_channel_id.write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Readable for LiquidityLease {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let payment_hash = Readable::read(reader)?;
+        let counterparty = Readable::read(reader)?;
+        let inbound_liquidity_sat = Readable::read(reader)?;
+        let fee_sat = Readable::read(reader)?;
+        let lease_duration_secs = Readable::read(reader)?;
+        let status = match <u8 as Readable>::read(reader)? {
+            0 => LeaseStatus::Unpaid,
+            1 => LeaseStatus::Paid,
+            2 => LeaseStatus::Active {
+                expiry_timestamp: Readable::read(reader)?,
+            },
+            3 => LeaseStatus::Providing {
+                user_channel_id: Readable::read(reader)?,
+            },
+            _ => return Err(DecodeError::InvalidValue),
+        };
+
+        Ok(Self {
+            payment_hash,
+            counterparty,
+            inbound_liquidity_sat,
+            fee_sat,
+            lease_duration_secs,
+            status,
+        })
+    }
+}
+
+/// Persists [`LiquidityLease`]s, one file per lease payment hash.
+pub(crate) struct LiquidityLeaseStore {
+    store: FileStore,
+    /// Serialises read-modify-write cycles on the leases.
+    lock: Mutex<()>,
+}
+
+impl LiquidityLeaseStore {
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            store: FileStore::new(dir)?,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn insert(&self, lease: &LiquidityLease) -> Result<()> {
+        self.store.insert(&hex::encode(lease.payment_hash.0), lease)
+    }
+
+    pub(crate) fn all(&self) -> Result<Vec<LiquidityLease>> {
+        self.store.all()
+    }
+
+    /// Marks the lease paid with `payment_hash` as paid. Returns false if the payment does not
+    /// belong to a lease.
+    pub(crate) fn mark_paid(&self, payment_hash: &PaymentHash) -> Result<bool> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut lease = match self
+            .all()?
+            .into_iter()
+            .find(|lease| lease.payment_hash == *payment_hash)
+        {
+            Some(lease) => lease,
+            None => return Ok(false),
+        };
+
+        if lease.status == LeaseStatus::Unpaid {
+            lease.status = LeaseStatus::Paid;
+            self.insert(&lease)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the leases of `counterparty` which have been paid for, but whose liquidity we have
+    /// not provided yet.
+    pub(crate) fn paid_leases(&self, counterparty: &PublicKey) -> Result<Vec<LiquidityLease>> {
+        let leases = self
+            .all()?
+            .into_iter()
+            .filter(|lease| lease.counterparty == *counterparty)
+            .filter(|lease| lease.status == LeaseStatus::Paid)
+            .collect();
+
+        Ok(leases)
+    }
+
+    /// Records that the paid leases with `payment_hashes` are provided by the channel we are
+    /// opening with `user_channel_id`.
+    pub(crate) fn provide(
+        &self,
+        payment_hashes: &[PaymentHash],
+        user_channel_id: u128,
+    ) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        for mut lease in self
+            .all()?
+            .into_iter()
+            .filter(|lease| payment_hashes.contains(&lease.payment_hash))
+            .filter(|lease| lease.status == LeaseStatus::Paid)
+        {
+            lease.status = LeaseStatus::Providing { user_channel_id };
+            self.insert(&lease)?;
+        }
+
+        Ok(())
+    }
+
+    /// Activates the leases provided by the channel with `user_channel_id`, starting their lease
+    /// period now.
+    ///
+    /// Only to be called once that channel is ready.
+    pub(crate) fn activate(&self, user_channel_id: u128) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        let now = unix_timestamp();
+        for mut lease in self
+            .all()?
+            .into_iter()
+            .filter(|lease| lease.status == LeaseStatus::Providing { user_channel_id })
+        {
+            lease.status = LeaseStatus::Active {
+                expiry_timestamp: now + lease.lease_duration_secs,
+            };
+            self.insert(&lease)?;
+
+            tracing::info!(
+                counterparty = %lease.counterparty,
+                payment_hash = %hex::encode(lease.payment_hash.0),
+                inbound_liquidity_sat = lease.inbound_liquidity_sat,
+                "Activated liquidity lease"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the inbound liquidity that we have to keep providing to `counterparty` because of
+    /// their active leases.
+    pub(crate) fn active_inbound_liquidity_sat(&self, counterparty: &PublicKey) -> Result<u64> {
+        let now = unix_timestamp();
+        let amount = self
+            .all()?
+            .iter()
+            .filter(|lease| lease.counterparty == *counterparty && lease.is_active(now))
+            .map(|lease| lease.inbound_liquidity_sat)
+            .sum();
+
+        Ok(amount)
+    }
+}
+
+impl Node {
+    /// Creates a liquidity lease for `counterparty` and the invoice they have to pay for it.
+    ///
+    /// Once the invoice is paid, `counterparty` upgrades its channel with us to one which includes
+    /// `inbound_liquidity_sat` on our side, see [`Node::open_replacement_channel`]. We will not
+    /// close channels with `counterparty` for `lease_duration` after that.
+    pub fn create_liquidity_lease(
+        &self,
+        counterparty: PublicKey,
+        inbound_liquidity_sat: u64,
+        fee_sat: u64,
+        lease_duration: Duration,
+    ) -> Result<(Invoice, LiquidityLease)> {
+        let invoice = self.create_invoice(fee_sat)?;
+
+        let lease = LiquidityLease {
+            payment_hash: PaymentHash(invoice.payment_hash().into_inner()),
+            counterparty,
+            inbound_liquidity_sat,
+            fee_sat,
+            lease_duration_secs: lease_duration.as_secs(),
+            status: LeaseStatus::Unpaid,
+        };
+
+        self.liquidity_leases.insert(&lease)?;
+
+        tracing::info!(
+            %counterparty,
+            inbound_liquidity_sat,
+            fee_sat,
+            payment_hash = %hex::encode(lease.payment_hash.0),
+            "Created liquidity lease"
+        );
+
+        Ok((invoice, lease))
+    }
+
+    pub fn list_liquidity_leases(&self) -> Result<Vec<LiquidityLease>> {
+        self.liquidity_leases.all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::secp256k1::SecretKey;
+    use std::io::Cursor;
+
+    fn dummy_lease(payment_hash: [u8; 32], status: LeaseStatus) -> LiquidityLease {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+
+        LiquidityLease {
+            payment_hash: PaymentHash(payment_hash),
+            counterparty: PublicKey::from_secret_key(&secp, &secret_key),
+            inbound_liquidity_sat: 100_000,
+            fee_sat: 1_000,
+            lease_duration_secs: 3_600,
+            status,
+        }
+    }
+
+    #[test]
+    fn lease_round_trip() {
+        let lease = dummy_lease(
+            [2; 32],
+            LeaseStatus::Active {
+                expiry_timestamp: 1_000,
+            },
+        );
+
+        let decoded = LiquidityLease::read(&mut Cursor::new(lease.encode())).unwrap();
+
+        assert_eq!(lease, decoded);
+    }
+
+    #[test]
+    fn paid_lease_is_activated() {
+        let dir = TempDir::new();
+        let store = LiquidityLeaseStore::new(dir.path()).unwrap();
+        let lease = dummy_lease([3; 32], LeaseStatus::Unpaid);
+        store.insert(&lease).unwrap();
+
+        assert!(store.paid_leases(&lease.counterparty).unwrap().is_empty());
+
+        assert!(store.mark_paid(&lease.payment_hash).unwrap());
+        assert!(!store.mark_paid(&PaymentHash([4; 32])).unwrap());
+        assert_eq!(
+            store.paid_leases(&lease.counterparty).unwrap(),
+            vec![LiquidityLease {
+                status: LeaseStatus::Paid,
+                ..lease.clone()
+            }]
+        );
+        assert_eq!(
+            store
+                .active_inbound_liquidity_sat(&lease.counterparty)
+                .unwrap(),
+            0
+        );
+
+        store.activate(&[lease.payment_hash]).unwrap();
+
+        store.provide(&[lease.payment_hash], 7).unwrap();
+
+        assert!(store.paid_leases(&lease.counterparty).unwrap().is_empty());
+        assert_eq!(
+            store
+                .active_inbound_liquidity_sat(&lease.counterparty)
+                .unwrap(),
+            0
+        );
+
+        store.activate(7).unwrap();
+
+        assert_eq!(
+            store
+                .active_inbound_liquidity_sat(&lease.counterparty)
+                .unwrap(),
+            100_000
+        );
+    }
+
+    #[test]
+    fn only_leases_provided_by_the_ready_channel_are_activated() {
+        let dir = TempDir::new();
+        let store = LiquidityLeaseStore::new(dir.path()).unwrap();
+        let provided = dummy_lease([5; 32], LeaseStatus::Paid);
+        let paid_later = dummy_lease([6; 32], LeaseStatus::Paid);
+        let other_channel = dummy_lease([7; 32], LeaseStatus::Providing { user_channel_id: 2 });
+        store.insert(&provided).unwrap();
+        store.insert(&paid_later).unwrap();
+        store.insert(&other_channel).unwrap();
+
+        store.provide(&[provided.payment_hash], 1).unwrap();
+        store.activate(1).unwrap();
+
+        assert_eq!(
+            store.paid_leases(&provided.counterparty).unwrap(),
+            vec![paid_later]
+        );
+        assert_eq!(
+            store
+                .active_inbound_liquidity_sat(&provided.counterparty)
+                .unwrap(),
+            100_000
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use crate::await_with_timeout::AwaitWithTimeout;
+use crate::backup;
+use crate::backup::BackupPersister;
 use crate::disk;
 use crate::dlc_custom_signer::CustomKeysManager;
 use crate::ln::app_config;
@@ -6,29 +8,38 @@ use crate::ln::coordinator_config;
 use crate::ln::EventHandler;
 use crate::ln::TracingLogger;
 use crate::ln_dlc_wallet::LnDlcWallet;
+use crate::node::dlc_channel_event::ForceClosedDlcChannelStore;
+use crate::node::dlc_channel_event::DLC_CHANNEL_EVENTS_CAPACITY;
+use crate::node::intercept_scid::InterceptScidStore;
+use crate::node::liquidity::LiquidityLeaseStore;
+use crate::node::persistent_peers::PersistentPeers;
+use crate::node::sweeper::SpendableOutputStore;
+use crate::node::sync_status::SYNC_EVENTS_CAPACITY;
 use crate::on_chain_wallet::OnChainWallet;
 use crate::seed::Bip39Seed;
+use crate::storage::migration;
+use crate::storage::SqliteStorage;
+use crate::storage::STORAGE_FILE;
 use crate::util;
 use crate::ChainMonitor;
-use crate::FakeChannelPaymentRequests;
+use crate::DlcMessageHandler;
 use crate::InvoicePayer;
+use crate::NetworkGraph;
 use crate::PaymentInfoStorage;
 use crate::PeerManager;
+use crate::TrustedPeers;
+use anyhow::anyhow;
 use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
-use bdk::blockchain::ElectrumBlockchain;
 use bitcoin::blockdata::constants::genesis_block;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::Network;
-use dlc_messages::message_handler::MessageHandler as DlcMessageHandler;
-use dlc_sled_storage_provider::SledStorageProvider;
 use futures::future::RemoteHandle;
 use futures::FutureExt;
 use lightning::chain;
 use lightning::chain::chainmonitor;
 use lightning::chain::keysinterface::KeysInterface;
-use lightning::chain::keysinterface::KeysManager;
 use lightning::chain::keysinterface::Recipient;
 use lightning::ln::msgs::NetAddress;
 use lightning::ln::peer_handler::IgnoringMessageHandler;
@@ -49,32 +60,69 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::net::SocketAddr;
 use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tokio::sync::watch;
 
+mod chain_source;
 mod channel_manager;
+pub(crate) mod channel_upgrade;
 mod connection;
 pub(crate) mod dlc_channel;
+mod dlc_channel_event;
 mod dlc_manager;
+pub(crate) mod intercept_scid;
 pub(crate) mod invoice;
+pub(crate) mod liquidity;
 mod ln_channel;
 mod oracle_client;
+mod peer_address;
+mod persistent_peers;
 mod sub_channel_manager;
+pub(crate) mod sweeper;
+mod sync_status;
 mod wallet;
 
 pub use self::dlc_manager::DlcManager;
 pub use ::dlc_manager as rust_dlc_manager;
+pub use chain_source::ChainSource;
 pub use channel_manager::ChannelManager;
 pub use dlc_channel::sub_channel_message_as_str;
+pub use dlc_channel::DlcFeeRatePolicy;
+pub use dlc_channel_event::DlcChannelEvent;
+pub use dlc_channel_event::DlcChannelEventKind;
+pub use intercept_scid::InterceptScid;
+pub use intercept_scid::JitFeePolicy;
+pub use intercept_scid::INTERCEPT_SCID_EXPIRY;
 pub use invoice::HTLCStatus;
+pub use liquidity::LeaseStatus;
+pub use liquidity::LiquidityLease;
+pub use peer_address::PeerAddress;
+pub use persistent_peers::ConnectionState;
+pub use persistent_peers::PeerEvent;
 pub use sub_channel_manager::SubChannelManager;
+pub use sweeper::PendingSweep;
+pub use sync_status::SyncEvent;
+pub use sync_status::SyncStatus;
+pub use sync_status::MAX_BLOCKS_BEHIND;
+pub use sync_status::MAX_CONSECUTIVE_SYNC_FAILURES;
+pub use wallet::Balance;
+pub use wallet::Fee;
 pub use wallet::PaymentDetails;
 pub use wallet::PaymentFlow;
+pub use wallet::SendAmount;
+pub use wallet::Utxo;
+pub use wallet::MAX_ON_CHAIN_FEE_RATE_SATS_PER_VBYTE;
 
 // TODO: These intervals are quite arbitrary at the moment, come up with more sensible values
 const BROADCAST_NODE_ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(60);
+const SWEEP_SPENDABLE_OUTPUTS_INTERVAL: Duration = Duration::from_secs(300);
+const CLOSE_RESTORED_DLC_CHANNELS_INTERVAL: Duration = Duration::from_secs(10);
 
 /// An LN-DLC node.
 pub struct Node {
@@ -85,38 +133,61 @@ pub struct Node {
     invoice_payer: Arc<InvoicePayer<EventHandler>>,
     pub(crate) channel_manager: Arc<ChannelManager>,
     chain_monitor: Arc<ChainMonitor>,
+    network_graph: Arc<NetworkGraph>,
     keys_manager: Arc<CustomKeysManager>,
     _background_processor: BackgroundProcessor,
     _connection_manager_handle: RemoteHandle<()>,
     _broadcast_node_announcement_handle: RemoteHandle<()>,
+    _sweep_spendable_outputs_handle: RemoteHandle<()>,
+    _close_restored_dlc_channels_handle: RemoteHandle<()>,
 
     logger: Arc<TracingLogger>,
 
     pub info: NodeInfo,
-    fake_channel_payments: FakeChannelPaymentRequests,
+    intercept_scids: Arc<InterceptScidStore>,
 
     pub dlc_manager: Arc<DlcManager>,
     pub sub_channel_manager: Arc<SubChannelManager>,
     oracle: Arc<P2PDOracleClient>,
     pub dlc_message_handler: Arc<DlcMessageHandler>,
+    dlc_channel_events: broadcast::Sender<DlcChannelEvent>,
+    /// The DLC channels we already emitted a [`DlcChannelEventKind::ForceClosed`] event for.
+    force_closed_dlc_channels: ForceClosedDlcChannelStore,
     inbound_payments: PaymentInfoStorage,
     outbound_payments: PaymentInfoStorage,
+    spendable_outputs: Arc<SpendableOutputStore>,
+    liquidity_leases: Arc<LiquidityLeaseStore>,
+    trusted_peers: TrustedPeers,
+    persistent_peers: PersistentPeers,
+    /// The SOCKS5 proxy (e.g. Tor) through which we connect to peers, if any.
+    socks5_proxy: Option<SocketAddr>,
+    sync_status: Mutex<SyncStatus>,
+    sync_events: broadcast::Sender<SyncEvent>,
 
     pub(crate) user_config: UserConfig,
+
+    pub(crate) data_dir: PathBuf,
+    pub(crate) backup_encryption_key: [u8; 32],
+    pub(crate) backup_needed: Arc<watch::Sender<()>>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct NodeInfo {
     pub pubkey: PublicKey,
-    pub address: SocketAddr,
+    pub address: PeerAddress,
 }
 
-/// Liquidity-based routing fee in millionths of a routed amount. In
+/// Default liquidity-based routing fee of a [`JitFeePolicy`] in millionths of a routed amount. In
 /// other words, 10000 is 1%.
 pub(crate) const LIQUIDITY_ROUTING_FEE_MILLIONTHS: u32 = 20_000;
 
 impl Node {
     /// Constructs a new node to be run as the app
+    ///
+    /// Only channels opened by one of the `trusted_peers` (usually the coordinator) are accepted
+    /// without confirmations.
+    ///
+    /// If a `socks5_proxy` is set, we connect to peers through it, e.g. to reach onion services.
     #[allow(clippy::too_many_arguments)]
     pub async fn new_app(
         alias: &str,
@@ -124,9 +195,11 @@ impl Node {
         data_dir: &Path,
         announcement_address: SocketAddr,
         listen_address: SocketAddr,
-        electrs_origin: String,
+        chain_source: ChainSource,
         seed: Bip39Seed,
         ephemeral_randomness: [u8; 32],
+        trusted_peers: Vec<PublicKey>,
+        socks5_proxy: Option<SocketAddr>,
     ) -> Result<Self> {
         let user_config = app_config();
         Node::new(
@@ -139,10 +212,12 @@ impl Node {
                 announcement_address.ip(),
                 announcement_address.port(),
             )],
-            electrs_origin,
+            chain_source,
             seed,
             ephemeral_randomness,
             user_config,
+            trusted_peers,
+            socks5_proxy,
         )
         .await
     }
@@ -159,9 +234,10 @@ impl Node {
         announcement_address: SocketAddr,
         listen_address: SocketAddr,
         announcements: Vec<NetAddress>,
-        electrs_origin: String,
+        chain_source: ChainSource,
         seed: Bip39Seed,
         ephemeral_randomness: [u8; 32],
+        socks5_proxy: Option<SocketAddr>,
     ) -> Result<Self> {
         let mut user_config = coordinator_config();
 
@@ -178,10 +254,12 @@ impl Node {
             announcement_address,
             listen_address,
             announcements,
-            electrs_origin,
+            chain_source,
             seed,
             ephemeral_randomness,
             user_config,
+            vec![],
+            socks5_proxy,
         )
         .await
     }
@@ -194,10 +272,12 @@ impl Node {
         announcement_address: SocketAddr,
         listen_address: SocketAddr,
         announcements: Vec<NetAddress>,
-        electrs_origin: String,
+        chain_source: ChainSource,
         seed: Bip39Seed,
         ephemeral_randomness: [u8; 32],
         ldk_user_config: UserConfig,
+        trusted_peers: Vec<PublicKey>,
+        socks5_proxy: Option<SocketAddr>,
     ) -> Result<Self> {
         let time_since_unix_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
 
@@ -209,25 +289,38 @@ impl Node {
         }
 
         let ldk_data_dir = data_dir.to_string_lossy().to_string();
-        let persister = Arc::new(FilesystemPersister::new(ldk_data_dir.clone()));
+        let backup_needed = Arc::new(watch::channel(()).0);
+        let persister = Arc::new(BackupPersister::new(
+            FilesystemPersister::new(ldk_data_dir.clone()),
+            backup_needed.clone(),
+        ));
+        let backup_encryption_key = seed.backup_encryption_key();
 
-        let storage = Arc::new(SledStorageProvider::new(
-            data_dir.to_str().expect("data_dir"),
-        )?);
+        let wallet_seed = seed.wallet_seed();
+        migration::migrate_from_sled(data_dir, network, &wallet_seed)?;
 
-        let on_chain_dir = data_dir.join("on_chain");
-        let on_chain_wallet =
-            OnChainWallet::new(on_chain_dir.as_path(), network, seed.wallet_seed())?;
+        let storage_path = data_dir.join(STORAGE_FILE);
+        let storage = Arc::new(SqliteStorage::open(&storage_path)?);
+
+        let on_chain_wallet = OnChainWallet::new(&storage_path, network, &wallet_seed)?;
+
+        let keys_manager = Arc::new(CustomKeysManager::new(
+            seed.lightning_seed(),
+            time_since_unix_epoch,
+        )?);
 
         let ln_dlc_wallet = {
-            let blockchain_client =
-                ElectrumBlockchain::from(bdk::electrum_client::Client::new(&electrs_origin)?);
+            tracing::info!(%chain_source, "Connecting to chain source");
+            let blockchain_client = chain_source.build(network, &on_chain_wallet.name)?;
             Arc::new(LnDlcWallet::new(
                 Arc::new(blockchain_client),
                 on_chain_wallet.inner,
                 storage.clone(),
+                keys_manager.clone(),
             ))
         };
+        ln_dlc_wallet.rewatch()?;
+        ln_dlc_wallet.restore_tracked_blocks()?;
 
         let chain_monitor: Arc<ChainMonitor> = Arc::new(chainmonitor::ChainMonitor::new(
             Some(ln_dlc_wallet.clone()),
@@ -237,14 +330,6 @@ impl Node {
             persister.clone(),
         ));
 
-        let keys_manager = {
-            Arc::new(CustomKeysManager::new(KeysManager::new(
-                &seed.lightning_seed(),
-                time_since_unix_epoch.as_secs(),
-                time_since_unix_epoch.subsec_nanos(),
-            )))
-        };
-
         let channel_manager = channel_manager::build(
             &ldk_data_dir,
             keys_manager.clone(),
@@ -304,14 +389,28 @@ impl Node {
             scorer.clone(),
         );
 
-        let fake_channel_payments: FakeChannelPaymentRequests =
-            Arc::new(Mutex::new(HashMap::new()));
+        let trusted_peers: TrustedPeers = Arc::new(Mutex::new(trusted_peers.into_iter().collect()));
+
+        let intercept_scids = Arc::new(InterceptScidStore::new(
+            data_dir.join("intercept_scids").as_path(),
+        )?);
 
         // TODO: Persist inbound payment info to disk
         let inbound_payments: PaymentInfoStorage = Arc::new(Mutex::new(HashMap::new()));
         // TODO: Persist outbound payment info to disk
         let outbound_payments: PaymentInfoStorage = Arc::new(Mutex::new(HashMap::new()));
 
+        let spendable_outputs = Arc::new(SpendableOutputStore::new(
+            data_dir.join("spendable_outputs").as_path(),
+        )?);
+
+        let liquidity_leases = Arc::new(LiquidityLeaseStore::new(
+            data_dir.join("liquidity_leases").as_path(),
+        )?);
+
+        let force_closed_dlc_channels =
+            ForceClosedDlcChannelStore::new(data_dir.join("force_closed_dlc_channels").as_path())?;
+
         let event_handler = {
             let runtime_handle = tokio::runtime::Handle::current();
 
@@ -319,12 +418,15 @@ impl Node {
                 runtime_handle,
                 channel_manager.clone(),
                 ln_dlc_wallet.clone(),
-                network_graph,
+                network_graph.clone(),
                 keys_manager.clone(),
                 inbound_payments.clone(),
                 outbound_payments.clone(),
-                fake_channel_payments.clone(),
+                intercept_scids.clone(),
                 Arc::new(Mutex::new(HashMap::new())),
+                spendable_outputs.clone(),
+                liquidity_leases.clone(),
+                trusted_peers.clone(),
             )
         };
 
@@ -429,35 +531,117 @@ impl Node {
             remote_handle
         };
 
+        let sweep_spendable_outputs_handle = {
+            let spendable_outputs = spendable_outputs.clone();
+            let keys_manager = keys_manager.clone();
+            let wallet = ln_dlc_wallet.clone();
+            let (fut, remote_handle) = async move {
+                let mut interval = tokio::time::interval(SWEEP_SPENDABLE_OUTPUTS_INTERVAL);
+
+                loop {
+                    interval.tick().await;
+
+                    if let Err(e) =
+                        sweeper::sweep_spendable_outputs(&spendable_outputs, &keys_manager, &wallet)
+                    {
+                        tracing::error!("Failed to sweep spendable outputs: {e:#}");
+                    }
+                }
+            }
+            .remote_handle();
+
+            tokio::spawn(fut);
+
+            remote_handle
+        };
+
+        let close_restored_dlc_channels_handle = {
+            let channel_manager = channel_manager.clone();
+            let sub_channel_manager = sub_channel_manager.clone();
+            let data_dir = data_dir.to_path_buf();
+            let (fut, remote_handle) = async move {
+                let mut interval = tokio::time::interval(CLOSE_RESTORED_DLC_CHANNELS_INTERVAL);
+
+                loop {
+                    interval.tick().await;
+
+                    match backup::close_dlc_channels_restored_from_backup(
+                        &data_dir,
+                        &channel_manager,
+                        &sub_channel_manager,
+                    ) {
+                        Ok(true) => return,
+                        Ok(false) => {}
+                        Err(e) => {
+                            tracing::error!(
+                                "Failed to close DLC channels restored from backup: {e:#}"
+                            )
+                        }
+                    }
+                }
+            }
+            .remote_handle();
+
+            tokio::spawn(fut);
+
+            remote_handle
+        };
+
+        let persistent_peers = PersistentPeers::new(
+            peer_manager.clone(),
+            socks5_proxy,
+            &data_dir.join("persistent_peers"),
+        )?;
+
         let node_info = NodeInfo {
             pubkey: channel_manager.get_our_node_id(),
-            address: announcement_address,
+            address: announcement_address.into(),
         };
 
         tracing::info!("Lightning node started with node ID {}", node_info);
 
-        Ok(Self {
+        let node = Self {
             network,
             wallet: ln_dlc_wallet,
             peer_manager,
             invoice_payer,
             keys_manager,
             chain_monitor,
+            network_graph,
             logger,
             channel_manager: channel_manager.clone(),
             info: node_info,
-            fake_channel_payments,
+            intercept_scids,
             sub_channel_manager,
             oracle: oracle_client,
             dlc_message_handler,
+            dlc_channel_events: broadcast::channel(DLC_CHANNEL_EVENTS_CAPACITY).0,
+            force_closed_dlc_channels,
             dlc_manager,
             inbound_payments,
             outbound_payments,
+            spendable_outputs,
+            liquidity_leases,
+            trusted_peers,
+            persistent_peers,
+            socks5_proxy,
+            sync_status: Mutex::new(SyncStatus::default()),
+            sync_events: broadcast::channel(SYNC_EVENTS_CAPACITY).0,
             user_config: ldk_user_config,
             _background_processor: background_processor,
             _connection_manager_handle: connection_manager_handle,
             _broadcast_node_announcement_handle: broadcast_node_announcement_handle,
-        })
+            _sweep_spendable_outputs_handle: sweep_spendable_outputs_handle,
+            _close_restored_dlc_channels_handle: close_restored_dlc_channels_handle,
+            data_dir: data_dir.to_path_buf(),
+            backup_encryption_key,
+            backup_needed,
+        };
+
+        node.close_channels_restored_from_backup()
+            .context("Failed to close channels restored from backup")?;
+
+        Ok(node)
     }
 }
 
@@ -467,6 +651,22 @@ impl Display for NodeInfo {
     }
 }
 
+impl FromStr for NodeInfo {
+    type Err = anyhow::Error;
+
+    /// Parses a node in the `pubkey@address` format it is displayed in.
+    fn from_str(s: &str) -> Result<Self> {
+        let (pubkey, address) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("Expected pubkey@address, got {s}"))?;
+
+        Ok(Self {
+            pubkey: pubkey.parse().context("Invalid pubkey")?,
+            address: address.parse().context("Invalid address")?,
+        })
+    }
+}
+
 fn alias_as_bytes(alias: &str) -> Result<[u8; 32]> {
     ensure!(
         alias.len() <= 32,
@@ -478,3 +678,14 @@ fn alias_as_bytes(alias: &str) -> Result<[u8; 32]> {
 
     Ok(bytes)
 }
+
+impl Node {
+    /// Accept channels opened by `peer` without waiting for confirmations.
+    pub fn add_trusted_peer(&self, peer: PublicKey) {
+        self.trusted_peers.lock().unwrap().insert(peer);
+    }
+
+    pub fn is_trusted_peer(&self, peer: &PublicKey) -> bool {
+        self.trusted_peers.lock().unwrap().contains(peer)
+    }
+}
@@ -0,0 +1,304 @@
+use crate::util;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use base32::Alphabet;
+use lightning::ln::msgs::NetAddress;
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use sha3::Digest;
+use sha3::Sha3_256;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+/// The version byte of a v3 onion service address.
+const ONION_V3_VERSION: u8 = 3;
+
+/// Onion service addresses are encoded in RFC 4648 base32 without padding, in lowercase.
+const ONION_BASE32: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// The address at which we can reach a peer.
+///
+/// Addresses are displayed and parsed as `host:port`, where the host is an IP address, a DNS
+/// hostname or a v3 onion service address ending in `.onion`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    Ip(SocketAddr),
+    /// A DNS hostname, which is resolved when connecting. If we connect through a SOCKS5 proxy,
+    /// the proxy resolves it.
+    Hostname {
+        host: String,
+        port: u16,
+    },
+    /// A Tor v3 onion service, which we can only reach through a SOCKS5 proxy such as Tor.
+    OnionV3 {
+        ed25519_pubkey: [u8; 32],
+        checksum: u16,
+        port: u16,
+    },
+}
+
+impl PeerAddress {
+    pub fn port(&self) -> u16 {
+        match self {
+            PeerAddress::Ip(address) => address.port(),
+            PeerAddress::Hostname { port, .. } | PeerAddress::OnionV3 { port, .. } => *port,
+        }
+    }
+
+    /// The host part of the address, e.g. to let a SOCKS5 proxy resolve it.
+    pub fn host(&self) -> String {
+        match self {
+            PeerAddress::Ip(address) => address.ip().to_string(),
+            PeerAddress::Hostname { host, .. } => host.clone(),
+            PeerAddress::OnionV3 {
+                ed25519_pubkey,
+                checksum,
+                ..
+            } => {
+                let mut bytes = ed25519_pubkey.to_vec();
+                bytes.extend_from_slice(&checksum.to_be_bytes());
+                bytes.push(ONION_V3_VERSION);
+
+                let onion = base32::encode(ONION_BASE32, &bytes).to_lowercase();
+
+                format!("{onion}.onion")
+            }
+        }
+    }
+
+    /// The address to announce to the network, unless it cannot be announced.
+    ///
+    /// LDK does not support announcing DNS hostnames.
+    pub fn to_net_address(&self) -> Option<NetAddress> {
+        match self {
+            PeerAddress::Ip(address) => Some(util::build_net_address(address.ip(), address.port())),
+            PeerAddress::Hostname { .. } => None,
+            PeerAddress::OnionV3 {
+                ed25519_pubkey,
+                checksum,
+                port,
+            } => Some(NetAddress::OnionV3 {
+                ed25519_pubkey: *ed25519_pubkey,
+                checksum: *checksum,
+                version: ONION_V3_VERSION,
+                port: *port,
+            }),
+        }
+    }
+
+    /// The address a peer announced, unless it is of a kind we cannot connect to.
+    pub fn from_net_address(address: &NetAddress) -> Option<Self> {
+        match address {
+            NetAddress::IPv4 { addr, port } => {
+                Some(PeerAddress::Ip(SocketAddr::new(IpAddr::from(*addr), *port)))
+            }
+            NetAddress::IPv6 { addr, port } => {
+                Some(PeerAddress::Ip(SocketAddr::new(IpAddr::from(*addr), *port)))
+            }
+            NetAddress::OnionV3 {
+                ed25519_pubkey,
+                checksum,
+                version: ONION_V3_VERSION,
+                port,
+            } => Some(PeerAddress::OnionV3 {
+                ed25519_pubkey: *ed25519_pubkey,
+                checksum: *checksum,
+                port: *port,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        PeerAddress::Ip(address)
+    }
+}
+
+impl Display for PeerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddress::Ip(address) => address.fmt(f),
+            _ => write!(f, "{}:{}", self.host(), self.port()),
+        }
+    }
+}
+
+impl FromStr for PeerAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(address) = s.parse::<SocketAddr>() {
+            return Ok(PeerAddress::Ip(address));
+        }
+
+        let (host, port) = s
+            .rsplit_once(':')
+            .with_context(|| format!("Expected host:port, got {s}"))?;
+        let port = port
+            .parse()
+            .with_context(|| format!("Invalid port: {port}"))?;
+
+        if let Some(onion) = host.strip_suffix(".onion") {
+            return parse_onion_v3(onion, port);
+        }
+
+        ensure!(
+            !host.is_empty()
+                && host.len() <= 255
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'),
+            "Invalid hostname: {host}"
+        );
+
+        Ok(PeerAddress::Hostname {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl Serialize for PeerAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PeerAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+
+        address.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Parses the part of a v3 onion service address before `.onion`, i.e. the base32 encoding of
+/// the public key, checksum and version.
+fn parse_onion_v3(onion: &str, port: u16) -> Result<PeerAddress> {
+    let bytes = base32::decode(ONION_BASE32, onion)
+        .with_context(|| format!("Invalid onion address: {onion}"))?;
+    if bytes.len() != 35 {
+        bail!("Invalid onion address length: {onion}");
+    }
+    ensure!(
+        bytes[34] == ONION_V3_VERSION,
+        "Unsupported onion address version: {onion}"
+    );
+
+    let mut ed25519_pubkey = [0; 32];
+    ed25519_pubkey.copy_from_slice(&bytes[..32]);
+
+    let checksum = u16::from_be_bytes([bytes[32], bytes[33]]);
+    ensure!(
+        checksum == onion_v3_checksum(&ed25519_pubkey),
+        "Invalid onion address checksum: {onion}"
+    );
+
+    Ok(PeerAddress::OnionV3 {
+        ed25519_pubkey,
+        checksum,
+        port,
+    })
+}
+
+/// The checksum of a v3 onion service address: the first two bytes of
+/// `SHA3-256(".onion checksum" || pubkey || version)`.
+fn onion_v3_checksum(ed25519_pubkey: &[u8; 32]) -> u16 {
+    let hash = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(ed25519_pubkey)
+        .chain_update([ONION_V3_VERSION])
+        .finalize();
+
+    u16::from_be_bytes([hash[0], hash[1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The onion service of the Tor Project's website
+    const ONION: &str = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion";
+
+    #[test]
+    fn ip_address_round_trip() {
+        for address in ["127.0.0.1:9045", "[::1]:9735"] {
+            let parsed = address.parse::<PeerAddress>().unwrap();
+
+            assert!(matches!(parsed, PeerAddress::Ip(_)));
+            assert_eq!(parsed.to_string(), address);
+        }
+    }
+
+    #[test]
+    fn hostname_round_trip() {
+        let parsed = "coordinator.10101.finance:9045"
+            .parse::<PeerAddress>()
+            .unwrap();
+
+        assert_eq!(
+            parsed,
+            PeerAddress::Hostname {
+                host: "coordinator.10101.finance".to_string(),
+                port: 9045
+            }
+        );
+        assert_eq!(parsed.to_string(), "coordinator.10101.finance:9045");
+        assert!(parsed.to_net_address().is_none());
+    }
+
+    #[test]
+    fn onion_v3_round_trip() {
+        let address = format!("{ONION}:9735");
+
+        let parsed = address.parse::<PeerAddress>().unwrap();
+
+        assert!(matches!(parsed, PeerAddress::OnionV3 { port: 9735, .. }));
+        assert_eq!(parsed.to_string(), address);
+
+        let net_address = parsed.to_net_address().unwrap();
+        assert!(matches!(
+            net_address,
+            NetAddress::OnionV3 {
+                version: 3,
+                port: 9735,
+                ..
+            }
+        ));
+        assert_eq!(PeerAddress::from_net_address(&net_address), Some(parsed));
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        for address in [
+            "coordinator.10101.finance",
+            "coordinator.10101.finance:port",
+            ":9045",
+            "coordinator 10101:9045",
+            "tooshort.onion:9735",
+        ] {
+            assert!(address.parse::<PeerAddress>().is_err(), "{address}");
+        }
+    }
+
+    #[test]
+    fn onion_address_with_invalid_checksum_is_rejected() {
+        // Same public key, but the first character of the checksum is changed
+        let onion = ONION.replacen("53wid", "63wid", 1);
+
+        let error = format!("{onion}:9735").parse::<PeerAddress>().unwrap_err();
+
+        assert!(format!("{error:#}").contains("checksum"), "{error:#}");
+    }
+}
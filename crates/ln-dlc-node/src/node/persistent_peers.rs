@@ -0,0 +1,411 @@
+use crate::node::connection::connect_once;
+use crate::node::connection::is_connected;
+use crate::node::Node;
+use crate::node::NodeInfo;
+use crate::node::PeerAddress;
+use crate::PeerManager;
+use anyhow::Context;
+use anyhow::Result;
+use bitcoin::secp256k1::PublicKey;
+use futures::future::RemoteHandle;
+use futures::FutureExt;
+use lightning::routing::gossip::NodeId;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// How many peer events a subscriber can fall behind before it starts missing events.
+pub(crate) const PEER_EVENTS_CAPACITY: usize = 100;
+
+/// How long we wait before reconnecting after the first failed attempt. The delay doubles with
+/// every further failed attempt, up to [`MAX_RECONNECT_DELAY`].
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// How often we check whether a connection we did not set up ourselves is still alive.
+const CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// We are trying to (re)connect to the peer, having failed `failed_attempts` times in a row.
+    Connecting {
+        failed_attempts: u32,
+    },
+    Connected,
+}
+
+#[derive(Debug, Clone)]
+pub enum PeerEvent {
+    /// We connected to a persistent peer.
+    Connected(NodeInfo),
+    /// We lost the connection to a persistent peer. We keep trying to reconnect.
+    Disconnected(NodeInfo),
+}
+
+/// Keeps us connected to a set of peers, reconnecting with exponential backoff whenever a
+/// connection attempt fails or an established connection is lost.
+///
+/// The set of peers is persisted, so that we reconnect to them after a restart.
+pub(crate) struct PersistentPeers {
+    peer_manager: Arc<PeerManager>,
+    socks5_proxy: Option<SocketAddr>,
+    path: PathBuf,
+    /// The peers we keep connected to, with the handles of the tasks doing so. Dropping a handle
+    /// stops the task.
+    peers: Mutex<HashMap<PublicKey, (NodeInfo, RemoteHandle<()>)>>,
+    states: Arc<Mutex<HashMap<PublicKey, ConnectionState>>>,
+    events: broadcast::Sender<PeerEvent>,
+}
+
+impl PersistentPeers {
+    /// Loads the persistent peers stored at `path` and starts connecting to them.
+    pub(crate) fn new(
+        peer_manager: Arc<PeerManager>,
+        socks5_proxy: Option<SocketAddr>,
+        path: &Path,
+    ) -> Result<Self> {
+        let persistent_peers = Self {
+            peer_manager,
+            socks5_proxy,
+            path: path.to_path_buf(),
+            peers: Mutex::new(HashMap::new()),
+            states: Arc::new(Mutex::new(HashMap::new())),
+            events: broadcast::channel(PEER_EVENTS_CAPACITY).0,
+        };
+
+        for peer in read_peers(path)? {
+            persistent_peers.start(peer);
+        }
+
+        Ok(persistent_peers)
+    }
+
+    pub(crate) fn add(&self, peer: NodeInfo) -> Result<()> {
+        if let Some((known, _)) = self.peers.lock().unwrap().get(&peer.pubkey) {
+            if known.address == peer.address {
+                return Ok(());
+            }
+        }
+
+        self.start(peer);
+        self.persist()
+    }
+
+    pub(crate) fn remove(&self, pubkey: &PublicKey) -> Result<()> {
+        // Removing the state first makes sure the task does not re-insert it before it is stopped
+        self.states.lock().unwrap().remove(pubkey);
+        if self.peers.lock().unwrap().remove(pubkey).is_none() {
+            return Ok(());
+        }
+
+        tracing::info!(%pubkey, "Stopped keeping connected to peer");
+
+        self.persist()
+    }
+
+    pub(crate) fn contains(&self, pubkey: &PublicKey) -> bool {
+        self.peers.lock().unwrap().contains_key(pubkey)
+    }
+
+    pub(crate) fn list(&self) -> Vec<(NodeInfo, ConnectionState)> {
+        let peers = self.peers.lock().unwrap();
+        let states = self.states.lock().unwrap();
+
+        peers
+            .values()
+            .filter_map(|(peer, _)| Some((peer.clone(), *states.get(&peer.pubkey)?)))
+            .collect()
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Spawns the task keeping us connected to `peer`, replacing the one for a previous address of
+    /// the peer.
+    fn start(&self, peer: NodeInfo) {
+        tracing::info!(%peer, "Keeping connected to peer");
+
+        self.states.lock().unwrap().insert(
+            peer.pubkey,
+            ConnectionState::Connecting { failed_attempts: 0 },
+        );
+
+        let (fut, remote_handle) = keep_connected(
+            self.peer_manager.clone(),
+            self.socks5_proxy,
+            peer.clone(),
+            self.states.clone(),
+            self.events.clone(),
+        )
+        .remote_handle();
+        tokio::spawn(fut);
+
+        self.peers
+            .lock()
+            .unwrap()
+            .insert(peer.pubkey, (peer, remote_handle));
+    }
+
+    fn persist(&self) -> Result<()> {
+        let peers = self
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(peer, _)| peer.clone())
+            .collect::<Vec<_>>();
+
+        write_peers(&self.path, &peers)
+    }
+}
+
+async fn keep_connected(
+    peer_manager: Arc<PeerManager>,
+    socks5_proxy: Option<SocketAddr>,
+    peer: NodeInfo,
+    states: Arc<Mutex<HashMap<PublicKey, ConnectionState>>>,
+    events: broadcast::Sender<PeerEvent>,
+) {
+    let set_state = |state| {
+        if let Some(current) = states.lock().unwrap().get_mut(&peer.pubkey) {
+            *current = state;
+        }
+    };
+    // Sending only fails if nobody is subscribed, in which case nobody cares about the event
+    let emit = |event| {
+        let _ = events.send(event);
+    };
+
+    let mut failed_attempts = 0;
+    loop {
+        // The peer may have connected to us or we may have connected to it elsewhere, in which
+        // case we don't get to know when the connection is closed
+        if is_connected(&peer_manager, peer.pubkey) {
+            failed_attempts = 0;
+            set_state(ConnectionState::Connected);
+            emit(PeerEvent::Connected(peer.clone()));
+
+            while is_connected(&peer_manager, peer.pubkey) {
+                tokio::time::sleep(CONNECTION_CHECK_INTERVAL).await;
+            }
+
+            tracing::info!(%peer, "Lost connection to peer; reconnecting");
+            set_state(ConnectionState::Connecting { failed_attempts: 0 });
+            emit(PeerEvent::Disconnected(peer.clone()));
+            continue;
+        }
+
+        match connect_once(&peer_manager, &peer, socks5_proxy).await {
+            Ok(connection_closed_future) => {
+                failed_attempts = 0;
+                set_state(ConnectionState::Connected);
+                emit(PeerEvent::Connected(peer.clone()));
+
+                connection_closed_future.await;
+
+                tracing::info!(%peer, "Lost connection to peer; reconnecting");
+                set_state(ConnectionState::Connecting { failed_attempts: 0 });
+                emit(PeerEvent::Disconnected(peer.clone()));
+
+                // Don't hammer a peer which keeps dropping the connection right away
+                tokio::time::sleep(INITIAL_RECONNECT_DELAY).await;
+            }
+            Err(e) => {
+                failed_attempts += 1;
+                set_state(ConnectionState::Connecting { failed_attempts });
+
+                let delay = reconnect_delay(failed_attempts);
+                tracing::warn!(%peer, ?delay, "Failed to connect to peer: {e:#}; retrying");
+
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+fn reconnect_delay(failed_attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+
+    INITIAL_RECONNECT_DELAY
+        .saturating_mul(factor)
+        .min(MAX_RECONNECT_DELAY)
+}
+
+/// Reads the peers stored at `path`, one `pubkey@address` per line.
+fn read_peers(path: &Path) -> Result<Vec<NodeInfo>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let peers = fs::read_to_string(path)
+        .with_context(|| format!("Could not read persistent peers from {path:?}"))?;
+
+    peers
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .parse()
+                .with_context(|| format!("Invalid persistent peer: {line}"))
+        })
+        .collect()
+}
+
+fn write_peers(path: &Path, peers: &[NodeInfo]) -> Result<()> {
+    let peers = peers
+        .iter()
+        .map(|peer| format!("{peer}\n"))
+        .collect::<String>();
+
+    fs::write(path, peers).with_context(|| format!("Could not write persistent peers to {path:?}"))
+}
+
+impl Node {
+    /// Keep connected to `peer`, also across restarts, until told otherwise.
+    pub fn keep_connected(&self, peer: NodeInfo) -> Result<()> {
+        self.persistent_peers.add(peer)
+    }
+
+    pub fn stop_keeping_connected(&self, pubkey: &PublicKey) -> Result<()> {
+        self.persistent_peers.remove(pubkey)
+    }
+
+    /// The peers we keep connected to and the state of our connection to them.
+    pub fn persistent_peers(&self) -> Vec<(NodeInfo, ConnectionState)> {
+        self.persistent_peers.list()
+    }
+
+    pub fn subscribe_to_peer_events(&self) -> broadcast::Receiver<PeerEvent> {
+        self.persistent_peers.subscribe()
+    }
+
+    /// Keep connected to the peers we have channels with, as long as they announced an address we
+    /// can connect to.
+    ///
+    /// Peers we already keep connected to are left alone, even if they announced a different
+    /// address.
+    pub fn keep_connected_to_channel_peers(&self) -> Result<()> {
+        let peers = self
+            .announced_channel_peers()
+            .into_iter()
+            .filter(|peer| !self.persistent_peers.contains(&peer.pubkey))
+            .collect::<Vec<_>>();
+
+        for peer in peers {
+            self.keep_connected(peer)?;
+        }
+
+        Ok(())
+    }
+
+    /// The peers we have channels with, at the first address they announced that we can connect
+    /// to.
+    pub(crate) fn announced_channel_peers(&self) -> Vec<NodeInfo> {
+        let counterparties = self
+            .channel_manager
+            .list_channels()
+            .into_iter()
+            .map(|channel| channel.counterparty.node_id)
+            .collect::<HashSet<_>>();
+
+        let network_graph = self.network_graph.read_only();
+
+        counterparties
+            .into_iter()
+            .filter_map(|counterparty| {
+                let address = network_graph
+                    .node(&NodeId::from_pubkey(&counterparty))
+                    .and_then(|node| node.announcement_info.as_ref())
+                    .and_then(|info| {
+                        // Onion services are only reachable through a proxy
+                        info.addresses
+                            .iter()
+                            .filter_map(PeerAddress::from_net_address)
+                            .find(|address| {
+                                self.socks5_proxy.is_some()
+                                    || !matches!(address, PeerAddress::OnionV3 { .. })
+                            })
+                    });
+
+                if address.is_none() {
+                    tracing::debug!(
+                        %counterparty,
+                        "Channel peer did not announce an address we can connect to"
+                    );
+                }
+
+                address.map(|address| NodeInfo {
+                    pubkey: counterparty,
+                    address,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::secp256k1::SecretKey;
+
+    #[test]
+    fn reconnect_delay_doubles_up_to_the_maximum() {
+        assert_eq!(reconnect_delay(1), INITIAL_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(2), INITIAL_RECONNECT_DELAY * 2);
+        assert_eq!(reconnect_delay(4), INITIAL_RECONNECT_DELAY * 8);
+        assert_eq!(reconnect_delay(20), MAX_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(u32::MAX), MAX_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn persistent_peers_round_trip() {
+        let dir = TempDir::new();
+        let path = dir.path().join("persistent_peers");
+        let secp = Secp256k1::new();
+        let peers = [
+            NodeInfo {
+                pubkey: PublicKey::from_secret_key(
+                    &secp,
+                    &SecretKey::from_slice(&[1; 32]).unwrap(),
+                ),
+                address: "127.0.0.1:9045".parse().unwrap(),
+            },
+            NodeInfo {
+                pubkey: PublicKey::from_secret_key(
+                    &secp,
+                    &SecretKey::from_slice(&[2; 32]).unwrap(),
+                ),
+                address: "[::1]:9735".parse().unwrap(),
+            },
+            NodeInfo {
+                pubkey: PublicKey::from_secret_key(
+                    &secp,
+                    &SecretKey::from_slice(&[3; 32]).unwrap(),
+                ),
+                address: "coordinator.10101.finance:9045".parse().unwrap(),
+            },
+        ];
+
+        assert!(read_peers(&path).unwrap().is_empty());
+
+        write_peers(&path, &peers).unwrap();
+        let read = read_peers(&path).unwrap();
+
+        assert_eq!(read.len(), 3);
+        for (read, peer) in read.iter().zip(peers.iter()) {
+            assert_eq!(read.pubkey, peer.pubkey);
+            assert_eq!(read.address, peer.address);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::disk::FileStore;
+use crate::dlc_custom_signer::CustomKeysManager;
+use crate::ln_dlc_wallet::LnDlcWallet;
+use crate::node::Node;
+use anyhow::Result;
+use bdk_ldk::ScriptStatus;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Script;
+use bitcoin::Txid;
+use lightning::chain::chaininterface::ConfirmationTarget;
+use lightning::chain::chaininterface::FeeEstimator;
+use lightning::chain::keysinterface::SpendableOutputDescriptor;
+use lightning::chain::transaction::OutPoint;
+use lightning::ln::msgs::DecodeError;
+use lightning::util::ser::Readable;
+use lightning::util::ser::Writeable;
+use lightning::util::ser::Writer;
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The number of confirmations after which we consider a sweep transaction final and stop
+/// tracking the spendable output.
+const SWEEP_REQUIRED_CONFIRMATIONS: u32 = 6;
+
+/// If a sweep transaction has not confirmed this many blocks after we broadcast it, we replace it
+/// with one paying a higher fee rate.
+const SWEEP_CONFIRMATION_TARGET_BLOCKS: u32 = 6;
+
+/// Every time we replace a sweep transaction we increase the fee rate by this percentage, which is
+/// enough to satisfy the replacement rules of BIP125.
+const SWEEP_FEE_BUMP_PERCENT: u32 = 25;
+
+/// Upper bound for the fee rate we are willing to pay to sweep a spendable output.
+///
+/// Corresponds to 200 sat/vbyte.
+const MAX_SWEEP_FEE_RATE_SAT_PER_1000_WEIGHT: u32 = 200 * 250;
+
+/// A spendable output which LDK handed over to us and which has not yet been swept into the
+/// on-chain wallet with enough confirmations.
+#[derive(Debug, Clone)]
+pub struct PendingSweep {
+    pub outpoint: OutPoint,
+    pub value_sat: u64,
+    /// The sweep transactions that we have broadcast so far, the last one being the most recent
+    /// replacement.
+    pub spending_txids: Vec<Txid>,
+    /// The fee rate of the most recent sweep transaction that we have broadcast.
+    pub fee_rate_sat_per_1000_weight: u32,
+    pub attempts: u32,
+    /// Unix timestamp of the last broadcast attempt.
+    pub last_attempt_timestamp: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct SpendableOutputInfo {
+    descriptor: SpendableOutputDescriptor,
+    destination_script: Script,
+    spending_txids: Vec<Txid>,
+    fee_rate_sat_per_1000_weight: u32,
+    /// The block height at which we broadcast the most recent sweep transaction.
+    broadcast_height: u32,
+    attempts: u32,
+    last_attempt_timestamp: u64,
+}
+
+impl SpendableOutputInfo {
+    fn new(descriptor: SpendableOutputDescriptor, destination_script: Script) -> Self {
+        Self {
+            descriptor,
+            destination_script,
+            spending_txids: Vec::new(),
+            fee_rate_sat_per_1000_weight: 0,
+            broadcast_height: 0,
+            attempts: 0,
+            last_attempt_timestamp: 0,
+        }
+    }
+
+    fn outpoint(&self) -> OutPoint {
+        outpoint(&self.descriptor)
+    }
+
+    /// Whether we have to broadcast a (replacement) sweep transaction, because we have not
+    /// managed to broadcast one yet or the last one did not confirm in time.
+    fn needs_new_sweep_tx(&self, tip_height: u32) -> bool {
+        self.spending_txids.is_empty()
+            || tip_height >= self.broadcast_height + SWEEP_CONFIRMATION_TARGET_BLOCKS
+    }
+}
+
+impl From<&SpendableOutputInfo> for PendingSweep {
+    fn from(info: &SpendableOutputInfo) -> Self {
+        let value_sat = match &info.descriptor {
+            SpendableOutputDescriptor::StaticOutput { output, .. } => output.value,
+            SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.output.value,
+            SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.output.value,
+        };
+
+        PendingSweep {
+            outpoint: info.outpoint(),
+            value_sat,
+            spending_txids: info.spending_txids.clone(),
+            fee_rate_sat_per_1000_weight: info.fee_rate_sat_per_1000_weight,
+            attempts: info.attempts,
+            last_attempt_timestamp: info.last_attempt_timestamp,
+        }
+    }
+}
+
+impl Writeable for SpendableOutputInfo {
+    fn write<W: Writer>(&self, writer: &mut W) -> Result<(), std::io::Error> {
+        self.descriptor.write(writer)?;
+        self.destination_script.write(writer)?;
+        (self.spending_txids.len() as u16).write(writer)?;
+        for txid in self.spending_txids.iter() {
+            txid.write(writer)?;
+        }
+        self.fee_rate_sat_per_1000_weight.write(writer)?;
+        self.broadcast_height.write(writer)?;
+        self.attempts.write(writer)?;
+        self.last_attempt_timestamp.write(writer)?;
+
+        Ok(())
+    }
+}
+
+impl Readable for SpendableOutputInfo {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let descriptor = Readable::read(reader)?;
+        let destination_script = Readable::read(reader)?;
+        let n_spending_txids: u16 = Readable::read(reader)?;
+        let mut spending_txids = Vec::with_capacity(n_spending_txids as usize);
+        for _ in 0..n_spending_txids {
+            spending_txids.push(Readable::read(reader)?);
+        }
+        let fee_rate_sat_per_1000_weight = Readable::read(reader)?;
+        let broadcast_height = Readable::read(reader)?;
+        let attempts = Readable::read(reader)?;
+        let last_attempt_timestamp = Readable::read(reader)?;
+
+        Ok(Self {
+            descriptor,
+            destination_script,
+            spending_txids,
+            fee_rate_sat_per_1000_weight,
+            broadcast_height,
+            attempts,
+            last_attempt_timestamp,
+        })
+    }
+}
+
+/// Persists the [`SpendableOutputDescriptor`]s handed to us by LDK, one file per outpoint, so that
+/// they survive restarts until they have been swept successfully.
+pub(crate) struct SpendableOutputStore(FileStore);
+
+impl SpendableOutputStore {
+    pub(crate) fn new(dir: &Path) -> Result<Self> {
+        Ok(Self(FileStore::new(dir)?))
+    }
+
+    fn insert(&self, info: &SpendableOutputInfo) -> Result<()> {
+        self.0.insert(&key(&info.outpoint()), info)
+    }
+
+    fn remove(&self, outpoint: &OutPoint) -> Result<()> {
+        self.0.remove(&key(outpoint))
+    }
+
+    fn all(&self) -> Result<Vec<SpendableOutputInfo>> {
+        self.0.all()
+    }
+}
+
+fn key(outpoint: &OutPoint) -> String {
+    format!("{}_{}", outpoint.txid, outpoint.index)
+}
+
+/// Persists new spendable outputs and immediately tries to sweep them into the on-chain wallet.
+///
+/// Outputs that cannot be swept right away will be retried by [`sweep_spendable_outputs`].
+pub(crate) fn track_spendable_outputs(
+    store: &SpendableOutputStore,
+    keys_manager: &CustomKeysManager,
+    wallet: &LnDlcWallet,
+    descriptors: Vec<SpendableOutputDescriptor>,
+) -> Result<()> {
+    let destination_script = wallet.inner().get_unused_address()?.script_pubkey();
+
+    for descriptor in descriptors {
+        let info = SpendableOutputInfo::new(descriptor, destination_script.clone());
+        store.insert(&info)?;
+
+        tracing::info!(outpoint = %fmt_outpoint(&info.outpoint()), "Tracking spendable output");
+    }
+
+    sweep_spendable_outputs(store, keys_manager, wallet)
+}
+
+/// Goes through all the pending spendable outputs and
+///
+/// - forgets about the ones whose sweep transaction is sufficiently confirmed;
+/// - broadcasts a sweep transaction for the ones we have not managed to broadcast one for yet;
+/// - replaces the sweep transaction with one paying a higher fee rate if it has not confirmed
+///   within [`SWEEP_CONFIRMATION_TARGET_BLOCKS`].
+///
+/// A failure to sweep one output does not keep us from sweeping the others.
+pub(crate) fn sweep_spendable_outputs(
+    store: &SpendableOutputStore,
+    keys_manager: &CustomKeysManager,
+    wallet: &LnDlcWallet,
+) -> Result<()> {
+    let (tip_height, _) = wallet.tip()?;
+
+    for info in store.all()? {
+        let outpoint = fmt_outpoint(&info.outpoint());
+
+        if let Err(e) = sweep_spendable_output(store, keys_manager, wallet, info, tip_height) {
+            tracing::error!(%outpoint, "Failed to sweep spendable output: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+fn sweep_spendable_output(
+    store: &SpendableOutputStore,
+    keys_manager: &CustomKeysManager,
+    wallet: &LnDlcWallet,
+    mut info: SpendableOutputInfo,
+    tip_height: u32,
+) -> Result<()> {
+    let outpoint = fmt_outpoint(&info.outpoint());
+
+    match confirmed_spending_tx(wallet, &info, tip_height)? {
+        Some((txid, confirmations)) if confirmations >= SWEEP_REQUIRED_CONFIRMATIONS => {
+            tracing::info!(%outpoint, %txid, "Spendable output swept successfully");
+            store.remove(&info.outpoint())?;
+            return Ok(());
+        }
+        Some((txid, confirmations)) => {
+            tracing::debug!(
+                %outpoint,
+                %txid,
+                %confirmations,
+                "Waiting for sweep transaction to be buried deeper"
+            );
+            return Ok(());
+        }
+        None => {}
+    }
+
+    if !info.needs_new_sweep_tx(tip_height) {
+        tracing::debug!(%outpoint, "Waiting for sweep transaction to confirm");
+        return Ok(());
+    }
+
+    // We only bump the fee rate of a sweep transaction which made it into the mempool, but did
+    // not confirm in time. If we failed to broadcast, the fee rate may not have been the problem.
+    let previous_fee_rate = if info.spending_txids.is_empty() {
+        0
+    } else {
+        info.fee_rate_sat_per_1000_weight
+    };
+    let fee_rate = next_fee_rate(
+        previous_fee_rate,
+        wallet.get_est_sat_per_1000_weight(ConfirmationTarget::Normal),
+    );
+
+    let spending_tx = keys_manager.spend_spendable_outputs(
+        &[&info.descriptor],
+        Vec::new(),
+        info.destination_script.clone(),
+        fee_rate,
+        &Secp256k1::new(),
+    )?;
+    let txid = spending_tx.txid();
+
+    info.attempts += 1;
+    info.last_attempt_timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+
+    match wallet.inner().broadcast(&spending_tx) {
+        Ok(()) => {
+            tracing::info!(%outpoint, %txid, %fee_rate, "Broadcast sweep transaction");
+            info.fee_rate_sat_per_1000_weight = fee_rate;
+            info.broadcast_height = tip_height;
+            if !info.spending_txids.contains(&txid) {
+                info.spending_txids.push(txid);
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                %outpoint,
+                %txid,
+                %fee_rate,
+                attempts = %info.attempts,
+                "Failed to broadcast sweep transaction, will retry: {e:#}"
+            );
+        }
+    }
+
+    store.insert(&info)
+}
+
+/// Returns the ID and the number of confirmations of a confirmed transaction which sweeps the
+/// spendable output, if any.
+fn confirmed_spending_tx(
+    wallet: &LnDlcWallet,
+    info: &SpendableOutputInfo,
+    tip_height: u32,
+) -> Result<Option<(Txid, u32)>> {
+    for txid in info.spending_txids.iter() {
+        let status = wallet
+            .inner()
+            .get_tx_status_for_script(info.destination_script.clone(), *txid)?;
+
+        if let ScriptStatus::Confirmed {
+            block_height: Some(block_height),
+        } = status
+        {
+            let confirmations = tip_height.saturating_sub(block_height) + 1;
+            return Ok(Some((*txid, confirmations)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The fee rate to use for the next sweep transaction.
+///
+/// Without a previous sweep transaction we use the current estimate. A replacement uses whatever is
+/// higher: the current estimate or the previous fee rate bumped by [`SWEEP_FEE_BUMP_PERCENT`].
+fn next_fee_rate(previous_fee_rate: u32, estimated_fee_rate: u32) -> u32 {
+    let bumped_fee_rate = previous_fee_rate * (100 + SWEEP_FEE_BUMP_PERCENT) / 100;
+
+    estimated_fee_rate
+        .max(bumped_fee_rate)
+        .min(MAX_SWEEP_FEE_RATE_SAT_PER_1000_WEIGHT)
+}
+
+fn outpoint(descriptor: &SpendableOutputDescriptor) -> OutPoint {
+    match descriptor {
+        SpendableOutputDescriptor::StaticOutput { outpoint, .. } => *outpoint,
+        SpendableOutputDescriptor::DelayedPaymentOutput(descriptor) => descriptor.outpoint,
+        SpendableOutputDescriptor::StaticPaymentOutput(descriptor) => descriptor.outpoint,
+    }
+}
+
+fn fmt_outpoint(outpoint: &OutPoint) -> String {
+    format!("{}:{}", outpoint.txid, outpoint.index)
+}
+
+impl Node {
+    /// Lists the spendable outputs from closed channels which have not been swept into the
+    /// on-chain wallet yet.
+    pub fn list_pending_sweeps(&self) -> Result<Vec<PendingSweep>> {
+        let pending_sweeps = self
+            .spendable_outputs
+            .all()?
+            .iter()
+            .map(PendingSweep::from)
+            .collect();
+
+        Ok(pending_sweeps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bitcoin::hashes::Hash;
+    use bitcoin::TxOut;
+
+    #[test]
+    fn spendable_output_store_round_trip() {
+        let dir = TempDir::new();
+        let store = SpendableOutputStore::new(dir.path()).unwrap();
+
+        let descriptor = SpendableOutputDescriptor::StaticOutput {
+            outpoint: OutPoint {
+                txid: Txid::all_zeros(),
+                index: 1,
+            },
+            output: TxOut {
+                value: 10_000,
+                script_pubkey: Script::new(),
+            },
+        };
+        let mut info = SpendableOutputInfo::new(descriptor, Script::new());
+        info.spending_txids.push(Txid::all_zeros());
+        info.attempts = 2;
+        info.fee_rate_sat_per_1000_weight = 1_250;
+        info.broadcast_height = 100;
+
+        store.insert(&info).unwrap();
+
+        let pending_sweeps = store.all().unwrap();
+        assert_eq!(pending_sweeps.len(), 1);
+
+        let pending_sweep = PendingSweep::from(&pending_sweeps[0]);
+        assert_eq!(pending_sweep.outpoint, info.outpoint());
+        assert_eq!(pending_sweep.value_sat, 10_000);
+        assert_eq!(pending_sweep.spending_txids, vec![Txid::all_zeros()]);
+        assert_eq!(pending_sweep.attempts, 2);
+        assert_eq!(pending_sweep.fee_rate_sat_per_1000_weight, 1_250);
+        assert_eq!(pending_sweeps[0].broadcast_height, 100);
+
+        store.remove(&info.outpoint()).unwrap();
+        assert!(store.all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sweep_tx_is_only_replaced_if_it_does_not_confirm_in_time() {
+        let descriptor = SpendableOutputDescriptor::StaticOutput {
+            outpoint: OutPoint {
+                txid: Txid::all_zeros(),
+                index: 0,
+            },
+            output: TxOut {
+                value: 10_000,
+                script_pubkey: Script::new(),
+            },
+        };
+        let mut info = SpendableOutputInfo::new(descriptor, Script::new());
+
+        // Failed broadcasts are retried right away
+        info.attempts = 3;
+        assert!(info.needs_new_sweep_tx(100));
+
+        info.spending_txids.push(Txid::all_zeros());
+        info.broadcast_height = 100;
+        assert!(!info.needs_new_sweep_tx(100));
+        assert!(!info.needs_new_sweep_tx(100 + SWEEP_CONFIRMATION_TARGET_BLOCKS - 1));
+        assert!(info.needs_new_sweep_tx(100 + SWEEP_CONFIRMATION_TARGET_BLOCKS));
+    }
+
+    #[test]
+    fn fee_rate_is_bumped_on_every_replacement() {
+        assert_eq!(next_fee_rate(0, 253), 253);
+        assert_eq!(next_fee_rate(1_000, 253), 1_250);
+        assert_eq!(next_fee_rate(1_000, 5_000), 5_000);
+        assert_eq!(
+            next_fee_rate(MAX_SWEEP_FEE_RATE_SAT_PER_1000_WEIGHT, 253),
+            MAX_SWEEP_FEE_RATE_SAT_PER_1000_WEIGHT
+        );
+    }
+}
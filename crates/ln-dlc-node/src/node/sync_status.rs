@@ -0,0 +1,232 @@
+use crate::node::Node;
+use bitcoin::BlockHash;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+
+/// How many sync events a subscriber can fall behind before it starts missing events.
+pub(crate) const SYNC_EVENTS_CAPACITY: usize = 10;
+
+/// After how many failed syncs in a row the node is considered out of sync.
+pub const MAX_CONSECUTIVE_SYNC_FAILURES: u32 = 3;
+
+/// How many blocks the node may lag behind the chain tip before it is considered out of sync.
+pub const MAX_BLOCKS_BEHIND: u32 = 2;
+
+/// The node's view of the blockchain, as of the last sync.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    /// The height of the block we last synced to.
+    pub synced_height: Option<u32>,
+    /// The hash of the block we last synced to.
+    pub synced_block_hash: Option<BlockHash>,
+    /// When the last successful sync finished.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_successful_sync: Option<OffsetDateTime>,
+    /// Whether the chain source answered when we last asked it for the chain tip.
+    pub chain_source_reachable: bool,
+    /// The height of the chain tip according to the chain source.
+    pub tip_height: Option<u32>,
+    /// How many blocks the synced height lags behind the chain tip.
+    pub blocks_behind: Option<u32>,
+    /// How many syncs failed since the last successful one.
+    pub consecutive_failures: u32,
+    /// Why the last sync failed, unless it succeeded.
+    pub last_error: Option<String>,
+}
+
+/// A change of whether the node is in sync.
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    /// The last [`MAX_CONSECUTIVE_SYNC_FAILURES`] syncs failed.
+    OutOfSync { error: String },
+    /// A sync succeeded after the node was out of sync.
+    BackInSync,
+}
+
+impl SyncStatus {
+    /// Whether the node's view of the blockchain is recent enough to be relied upon.
+    pub fn is_in_sync(&self) -> bool {
+        self.last_successful_sync.is_some()
+            && self.consecutive_failures < MAX_CONSECUTIVE_SYNC_FAILURES
+            && self
+                .blocks_behind
+                .map_or(false, |blocks_behind| blocks_behind <= MAX_BLOCKS_BEHIND)
+    }
+
+    /// Records the outcome of a sync.
+    ///
+    /// `synced_block` is the block the node is synced to after the attempt and `tip_height` the
+    /// height of the chain tip, if the chain source could be reached. Returns the event to emit if
+    /// the node went out of sync or recovered.
+    fn record(
+        &mut self,
+        outcome: Result<(), String>,
+        synced_block: Option<(u32, BlockHash)>,
+        tip_height: Option<u32>,
+        now: OffsetDateTime,
+    ) -> Option<SyncEvent> {
+        self.synced_height = synced_block.map(|(height, _)| height);
+        self.synced_block_hash = synced_block.map(|(_, hash)| hash);
+        self.chain_source_reachable = tip_height.is_some();
+        self.tip_height = tip_height;
+        self.blocks_behind = match (tip_height, self.synced_height) {
+            (Some(tip_height), Some(synced_height)) => {
+                Some(tip_height.saturating_sub(synced_height))
+            }
+            _ => None,
+        };
+
+        match outcome {
+            Ok(()) => {
+                let was_out_of_sync = self.consecutive_failures >= MAX_CONSECUTIVE_SYNC_FAILURES;
+
+                self.last_successful_sync = Some(now);
+                self.consecutive_failures = 0;
+                self.last_error = None;
+
+                was_out_of_sync.then_some(SyncEvent::BackInSync)
+            }
+            Err(error) => {
+                self.consecutive_failures += 1;
+                self.last_error = Some(error.clone());
+
+                // Only emitted once, when the node goes out of sync
+                (self.consecutive_failures == MAX_CONSECUTIVE_SYNC_FAILURES)
+                    .then_some(SyncEvent::OutOfSync { error })
+            }
+        }
+    }
+}
+
+impl Node {
+    pub fn sync_status(&self) -> SyncStatus {
+        self.sync_status.lock().unwrap().clone()
+    }
+
+    /// Subscribes to the node going out of sync after [`MAX_CONSECUTIVE_SYNC_FAILURES`] failed
+    /// syncs in a row and to it getting back in sync.
+    pub fn subscribe_to_sync_events(&self) -> broadcast::Receiver<SyncEvent> {
+        self.sync_events.subscribe()
+    }
+
+    pub(crate) fn update_sync_status(&self, outcome: Result<(), String>) {
+        let synced_block = self.wallet.inner().best_block();
+        let tip_height = match self.wallet.tip() {
+            Ok((tip_height, _)) => Some(tip_height),
+            Err(e) => {
+                tracing::warn!("Chain source unreachable: {e:#}");
+                None
+            }
+        };
+
+        let event = self.sync_status.lock().unwrap().record(
+            outcome,
+            synced_block,
+            tip_height,
+            OffsetDateTime::now_utc(),
+        );
+
+        if let Some(event) = event {
+            match &event {
+                SyncEvent::OutOfSync { error } => {
+                    tracing::error!(%error, "Node is out of sync");
+                }
+                SyncEvent::BackInSync => tracing::info!("Node is back in sync"),
+            }
+
+            // Sending only fails if nobody is subscribed, in which case nobody cares about the
+            // event
+            let _ = self.sync_events.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_681_000_000).unwrap()
+    }
+
+    fn block(height: u32) -> Option<(u32, BlockHash)> {
+        Some((height, BlockHash::all_zeros()))
+    }
+
+    #[test]
+    fn successful_sync_is_in_sync() {
+        let mut status = SyncStatus::default();
+
+        let event = status.record(Ok(()), block(100), Some(101), now());
+
+        assert!(event.is_none());
+        assert!(status.is_in_sync());
+        assert!(status.chain_source_reachable);
+        assert_eq!(status.blocks_behind, Some(1));
+        assert_eq!(status.last_successful_sync, Some(now()));
+    }
+
+    #[test]
+    fn node_is_out_of_sync_before_first_sync() {
+        assert!(!SyncStatus::default().is_in_sync());
+    }
+
+    #[test]
+    fn lagging_behind_the_tip_is_out_of_sync() {
+        let mut status = SyncStatus::default();
+
+        status.record(Ok(()), block(100), Some(100 + MAX_BLOCKS_BEHIND + 1), now());
+
+        assert!(!status.is_in_sync());
+    }
+
+    #[test]
+    fn unreachable_chain_source_is_out_of_sync() {
+        let mut status = SyncStatus::default();
+
+        status.record(Ok(()), block(100), None, now());
+
+        assert!(!status.chain_source_reachable);
+        assert!(!status.is_in_sync());
+    }
+
+    #[test]
+    fn repeated_failures_emit_out_of_sync_once_and_recovery_emits_back_in_sync() {
+        let mut status = SyncStatus::default();
+        status.record(Ok(()), block(100), Some(100), now());
+
+        let mut events = Vec::new();
+        for _ in 0..MAX_CONSECUTIVE_SYNC_FAILURES + 2 {
+            events.extend(status.record(Err("boom".to_string()), block(100), Some(100), now()));
+        }
+
+        assert!(matches!(
+            events.as_slice(),
+            [SyncEvent::OutOfSync { error }] if error == "boom"
+        ));
+        assert!(!status.is_in_sync());
+        assert_eq!(status.last_error.as_deref(), Some("boom"));
+
+        let event = status.record(Ok(()), block(101), Some(101), now());
+
+        assert!(matches!(event, Some(SyncEvent::BackInSync)));
+        assert!(status.is_in_sync());
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.last_error, None);
+    }
+
+    #[test]
+    fn few_failures_do_not_emit_events() {
+        let mut status = SyncStatus::default();
+        status.record(Ok(()), block(100), Some(100), now());
+
+        let event = status.record(Err("boom".to_string()), block(100), Some(100), now());
+        assert!(event.is_none());
+        assert!(status.is_in_sync());
+
+        let event = status.record(Ok(()), block(100), Some(100), now());
+        assert!(event.is_none());
+    }
+}
@@ -2,34 +2,108 @@ use crate::node::HTLCStatus;
 use crate::node::Node;
 use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use bdk::wallet::AddressIndex;
+use bdk::FeeRate;
 use bitcoin::secp256k1::SecretKey;
 use bitcoin::Address;
+use bitcoin::OutPoint;
+use bitcoin::Transaction;
+use bitcoin::Txid;
+use dlc_manager::contract::Contract;
+use dlc_manager::subchannel::SubChannelState;
 use lightning::chain::keysinterface::KeysInterface;
 use lightning::chain::keysinterface::Recipient;
 use lightning::chain::Confirm;
 use lightning::ln::PaymentHash;
+use serde::Deserialize;
+use serde::Serialize;
 use time::OffsetDateTime;
 
+/// Upper bound for the fee rate of on-chain transactions we create, to protect against
+/// fat-fingered fee rates and broken fee estimates.
+pub const MAX_ON_CHAIN_FEE_RATE_SATS_PER_VBYTE: f32 = 1_000.0;
+
 #[derive(Debug, Clone)]
 pub struct OffChainBalance {
+    /// The sum of our balances in all open channels, including the collateral locked in DLCs.
     pub available: u64,
     pub pending_close: u64,
 }
 
+/// A breakdown of all the funds controlled by the node, in sats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Balance {
+    /// What we can spend over Lightning, i.e. our channel balances minus the DLC collateral.
+    pub lightning_spendable: u64,
+    /// Our collateral locked in DLCs.
+    pub dlc_collateral: u64,
+    /// Funds from closed channels which do not yet pay to our on-chain wallet.
+    pub pending_close: u64,
+    /// On-chain funds in transactions which are not yet confirmed, including immature coinbase
+    /// outputs.
+    pub on_chain_unconfirmed: u64,
+    pub on_chain_confirmed: u64,
+}
+
+/// How much to send in an on-chain transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendAmount {
+    Sats(u64),
+    /// Send everything we are spending (the selected UTXOs or the whole wallet), minus fees.
+    All,
+}
+
+/// The fee to pay for an on-chain transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Fee {
+    /// Estimate the fee rate needed to confirm within this number of blocks.
+    ConfirmationTarget(usize),
+    SatsPerVbyte(f32),
+}
+
+/// An unspent output of the on-chain wallet.
+#[derive(Debug, Clone, Serialize)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount_sats: u64,
+    pub is_confirmed: bool,
+}
+
 impl Node {
+    /// Syncs the on-chain wallet and the Lightning channels to the chain tip.
+    ///
+    /// The outcome is recorded in the [`SyncStatus`](crate::node::SyncStatus) of the node.
     pub fn sync(&self) -> Result<()> {
         let confirmables = vec![
             &*self.channel_manager as &dyn Confirm,
             &*self.chain_monitor as &dyn Confirm,
         ];
 
-        self.wallet
-            .inner()
-            .sync(confirmables)
-            .map_err(|e| anyhow!("{e:#}"))
+        if let Err(e) = self.wallet.inner().sync(confirmables) {
+            let error = format!("{e:#}");
+            self.update_sync_status(Err(error.clone()));
+
+            bail!(error);
+        }
+
+        self.update_sync_status(Ok(()));
+
+        if let Err(e) = self.wallet.persist_tracked_blocks() {
+            tracing::error!("Failed to persist tracked blocks: {e:#}");
+        }
+
+        if let Err(e) = self.detect_force_closed_dlc_channels() {
+            tracing::error!("Failed to detect force-closed DLC channels: {e:#}");
+        }
+
+        if let Err(e) = self.wallet.prune_watched() {
+            tracing::error!("Failed to prune watched transactions and outputs: {e:#}");
+        }
+
+        Ok(())
     }
 
     pub fn get_new_address(&self) -> Result<Address> {
@@ -47,6 +121,214 @@ impl Node {
         self.wallet.inner().get_balance().map_err(|e| anyhow!(e))
     }
 
+    /// List the unspent outputs of the on-chain wallet, which can be used for coin control in
+    /// [`Node::send_to_address`].
+    pub fn list_utxos(&self) -> Result<Vec<Utxo>> {
+        let wallet = self.wallet.inner();
+
+        let utxos = wallet.list_unspent().map_err(|e| anyhow!("{e:#}"))?;
+        let wallet = wallet.get_wallet().map_err(|e| anyhow!("{e:#}"))?;
+
+        utxos
+            .into_iter()
+            .filter(|utxo| !utxo.is_spent)
+            .map(|utxo| {
+                let is_confirmed = wallet
+                    .get_tx(&utxo.outpoint.txid, false)?
+                    .and_then(|tx| tx.confirmation_time)
+                    .is_some();
+
+                Ok(Utxo {
+                    outpoint: utxo.outpoint,
+                    amount_sats: utxo.txout.value,
+                    is_confirmed,
+                })
+            })
+            .collect()
+    }
+
+    /// Send on-chain funds to `address`.
+    ///
+    /// If `utxos` is not empty, only those outputs of the wallet are spent. The transaction
+    /// signals RBF, so that it can be bumped with [`Node::bump_fee`] if it gets stuck.
+    pub fn send_to_address(
+        &self,
+        address: &Address,
+        amount: SendAmount,
+        fee: Fee,
+        utxos: &[OutPoint],
+    ) -> Result<Txid> {
+        ensure!(
+            address.is_valid_for_network(self.network),
+            "Address {address} is not valid for {}",
+            self.network
+        );
+
+        let amount = match amount {
+            SendAmount::Sats(0) => bail!("Cannot send 0 sats"),
+            SendAmount::Sats(sats) => Some(sats),
+            SendAmount::All => None,
+        };
+        let fee_rate = self.on_chain_fee_rate(fee)?;
+
+        let (tx, details) = self
+            .wallet
+            .inner()
+            .construct_spend_transaction(&address.script_pubkey(), amount, fee_rate, utxos)
+            .map_err(|e| anyhow!("{e:#}"))
+            .context("Failed to build transaction")?;
+
+        tracing::info!(
+            %address,
+            sent = details.sent.saturating_sub(details.received),
+            fee = ?details.fee,
+            fee_rate = fee_rate.as_sat_per_vb(),
+            "Sending on-chain funds"
+        );
+
+        self.broadcast_wallet_transaction(&tx)
+    }
+
+    /// Replace the unconfirmed wallet transaction `txid` with one paying a higher fee (RBF).
+    pub fn bump_fee(&self, txid: Txid, fee: Fee) -> Result<Txid> {
+        let fee_rate = self.on_chain_fee_rate(fee)?;
+
+        let (tx, details) = self
+            .wallet
+            .inner()
+            .construct_fee_bump_transaction(txid, fee_rate)
+            .map_err(|e| anyhow!("{e:#}"))
+            .with_context(|| format!("Failed to bump fee of transaction {txid}"))?;
+
+        tracing::info!(
+            replaced_txid = %txid,
+            fee = ?details.fee,
+            fee_rate = fee_rate.as_sat_per_vb(),
+            "Bumping fee via RBF"
+        );
+
+        self.broadcast_wallet_transaction(&tx)
+    }
+
+    /// Speed up the unconfirmed transaction which created our output `outpoint` by spending it
+    /// back to the wallet with a high enough fee for both transactions to pay the given fee rate
+    /// (CPFP).
+    ///
+    /// Unlike [`Node::bump_fee`], this also works for transactions we did not create ourselves,
+    /// e.g. a channel close paying to our wallet.
+    pub fn cpfp(&self, outpoint: OutPoint, fee: Fee) -> Result<Txid> {
+        let fee_rate = self.on_chain_fee_rate(fee)?;
+
+        let (tx, details) = self
+            .wallet
+            .inner()
+            .construct_cpfp_transaction(outpoint, fee_rate)
+            .map_err(|e| anyhow!("{e:#}"))
+            .with_context(|| format!("Failed to build CPFP transaction for {outpoint}"))?;
+
+        tracing::info!(
+            parent_txid = %outpoint.txid,
+            fee = ?details.fee,
+            fee_rate = fee_rate.as_sat_per_vb(),
+            "Bumping fee via CPFP"
+        );
+
+        self.broadcast_wallet_transaction(&tx)
+    }
+
+    fn on_chain_fee_rate(&self, fee: Fee) -> Result<FeeRate> {
+        let fee_rate = match fee {
+            Fee::ConfirmationTarget(target_blocks) => self
+                .wallet
+                .inner()
+                .estimate_fee_rate(target_blocks)
+                .map_err(|e| anyhow!("{e:#}"))
+                .context("Failed to estimate fee rate")?,
+            Fee::SatsPerVbyte(sats_per_vbyte) => FeeRate::from_sat_per_vb(sats_per_vbyte),
+        };
+
+        ensure!(
+            fee_rate.as_sat_per_vb() <= MAX_ON_CHAIN_FEE_RATE_SATS_PER_VBYTE,
+            "Fee rate of {} sats/vbyte exceeds maximum of {MAX_ON_CHAIN_FEE_RATE_SATS_PER_VBYTE}",
+            fee_rate.as_sat_per_vb()
+        );
+
+        if fee_rate < FeeRate::default_min_relay_fee() {
+            return Ok(FeeRate::default_min_relay_fee());
+        }
+
+        Ok(fee_rate)
+    }
+
+    fn broadcast_wallet_transaction(&self, tx: &Transaction) -> Result<Txid> {
+        let txid = tx.txid();
+
+        self.wallet
+            .inner()
+            .broadcast(tx)
+            .map_err(|e| anyhow!("{e:#}"))?;
+
+        // So that the wallet does not try to spend the same outputs again and knows about the
+        // transaction in case we want to bump its fee.
+        if let Err(e) = self.wallet.inner().sync_onchain_wallet() {
+            tracing::warn!(%txid, "Failed to sync on-chain wallet after broadcast: {e:#}");
+        }
+
+        Ok(txid)
+    }
+
+    /// A breakdown of all our funds, on-chain, in channels and in DLCs.
+    pub fn get_balance(&self) -> Result<Balance> {
+        let on_chain = self.get_on_chain_balance()?;
+        let off_chain = self.get_ldk_balance();
+        let dlc_collateral = self.get_dlc_collateral()?;
+
+        Ok(Balance {
+            lightning_spendable: off_chain.available.saturating_sub(dlc_collateral),
+            dlc_collateral,
+            pending_close: off_chain.pending_close,
+            on_chain_unconfirmed: on_chain.immature
+                + on_chain.trusted_pending
+                + on_chain.untrusted_pending,
+            on_chain_confirmed: on_chain.confirmed,
+        })
+    }
+
+    /// The sum of our collateral in the DLCs of all DLC channels whose DLC is still part of the
+    /// LN channel, and is thus included in the channel balance.
+    fn get_dlc_collateral(&self) -> Result<u64> {
+        let mut collateral = 0;
+
+        for dlc_channel in self.list_dlc_channels()? {
+            let is_dlc_in_channel = matches!(
+                dlc_channel.state,
+                SubChannelState::Confirmed(_)
+                    | SubChannelState::Signed(_)
+                    | SubChannelState::CloseOffered(_)
+                    | SubChannelState::CloseAccepted(_)
+                    | SubChannelState::CloseConfirmed(_)
+            );
+            if !is_dlc_in_channel {
+                continue;
+            }
+
+            if let Some(Contract::Signed(contract) | Contract::Confirmed(contract)) =
+                self.get_dlc_channel_contract(&dlc_channel.channel_id)?
+            {
+                let offered_contract = &contract.accepted_contract.offered_contract;
+                let offer_collateral = offered_contract.offer_params.collateral;
+
+                collateral += if offered_contract.is_offer_party {
+                    offer_collateral
+                } else {
+                    offered_contract.total_collateral - offer_collateral
+                };
+            }
+        }
+
+        Ok(collateral)
+    }
+
     pub fn node_key(&self) -> Result<SecretKey> {
         match self.keys_manager.get_node_secret(Recipient::Node) {
             Ok(key) => Ok(key),
@@ -118,6 +400,13 @@ impl Node {
             .context("Failed to retrieve on-chain transaction history")
     }
 
+    /// The height of the tip of the blockchain, according to our chain source.
+    pub fn get_blockchain_height(&self) -> Result<u32> {
+        let (height, _) = self.wallet.tip()?;
+
+        Ok(height)
+    }
+
     pub fn get_off_chain_history(&self) -> Vec<PaymentDetails> {
         let inbound_payments = self
             .inbound_payments
@@ -129,6 +418,7 @@ impl Node {
             flow: PaymentFlow::Inbound,
             amount_msat: info.amt_msat.0,
             timestamp: info.timestamp,
+            description: info.description.clone(),
         });
 
         let outbound_payments = self
@@ -141,6 +431,7 @@ impl Node {
             flow: PaymentFlow::Outbound,
             amount_msat: info.amt_msat.0,
             timestamp: info.timestamp,
+            description: info.description.clone(),
         });
 
         let mut payments = inbound_payments
@@ -159,6 +450,8 @@ pub struct PaymentDetails {
     pub flow: PaymentFlow,
     pub amount_msat: Option<u64>,
     pub timestamp: OffsetDateTime,
+    /// The description of the invoice which was paid, empty if unknown.
+    pub description: String,
 }
 
 pub enum PaymentFlow {
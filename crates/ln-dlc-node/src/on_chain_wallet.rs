@@ -1,42 +1,31 @@
 use crate::seed::WalletSeed;
-use anyhow::Context;
+use crate::storage;
+use crate::storage::WalletDatabase;
+use anyhow::Result;
 use bdk::bitcoin::secp256k1::Secp256k1;
-use bdk::sled;
 use bdk::wallet::wallet_name_from_descriptor;
 use bdk::KeychainKind;
 use std::path::Path;
 
 pub struct OnChainWallet {
-    pub inner: bdk::Wallet<sled::Tree>,
+    pub inner: bdk::Wallet<WalletDatabase>,
+    /// Unique name of the wallet, derived from its descriptors.
+    pub name: String,
 }
 
 impl OnChainWallet {
+    /// Opens the wallet in the SQLite storage at `storage_path`.
     pub fn new(
-        data_dir: &Path,
+        storage_path: &Path,
         network: bitcoin::Network,
-        seed: WalletSeed,
+        seed: &WalletSeed,
     ) -> Result<OnChainWallet, anyhow::Error> {
         tracing::info!(?network, "Creating the wallet");
 
-        let data_dir = data_dir.join(network.to_string());
-        if !data_dir.exists() {
-            std::fs::create_dir_all(&data_dir).context(format!(
-                "Could not create data dir ({data_dir:?}) for {network}"
-            ))?;
-        }
-
         let ext_priv_key = seed.derive_extended_priv_key(network)?;
+        let wallet_name = wallet_name(network, seed)?;
 
-        let wallet_name = wallet_name_from_descriptor(
-            bdk::template::Bip84(ext_priv_key, KeychainKind::External),
-            Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
-            ext_priv_key.network,
-            &Secp256k1::new(),
-        )?;
-
-        // Create a database (using default sled type) to store wallet data
-        let db = bdk::sled::open(data_dir.join("wallet"))?;
-        let db = db.open_tree(wallet_name)?;
+        let db = storage::open_wallet_database(storage_path)?;
 
         let bdk_wallet = bdk::Wallet::new(
             bdk::template::Bip84(ext_priv_key, KeychainKind::External),
@@ -45,6 +34,23 @@ impl OnChainWallet {
             db,
         )?;
 
-        Ok(OnChainWallet { inner: bdk_wallet })
+        Ok(OnChainWallet {
+            inner: bdk_wallet,
+            name: wallet_name,
+        })
     }
 }
+
+/// Derives the unique name of the wallet from its descriptors.
+pub(crate) fn wallet_name(network: bitcoin::Network, seed: &WalletSeed) -> Result<String> {
+    let ext_priv_key = seed.derive_extended_priv_key(network)?;
+
+    let wallet_name = wallet_name_from_descriptor(
+        bdk::template::Bip84(ext_priv_key, KeychainKind::External),
+        Some(bdk::template::Bip84(ext_priv_key, KeychainKind::Internal)),
+        ext_priv_key.network,
+        &Secp256k1::new(),
+    )?;
+
+    Ok(wallet_name)
+}
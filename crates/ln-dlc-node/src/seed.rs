@@ -1,28 +1,84 @@
-use std::path::Path;
-
+use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
 use anyhow::Result;
+use argon2::Argon2;
 use bdk::bitcoin;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
 use bip39::Language;
 use bip39::Mnemonic;
 use bitcoin::Network;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::Key;
+use chacha20poly1305::KeyInit;
+use chacha20poly1305::Nonce;
 use hkdf::Hkdf;
+use rand::RngCore;
 use sha2::Sha256;
+use std::path::Path;
+
+/// Prefix of an encrypted seed file.
+///
+/// Unencrypted seed files only contain the entropy of the mnemonic.
+const ENCRYPTED_SEED_MAGIC: &[u8] = b"10101-encrypted-seed-v2";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
 
 #[derive(Clone)]
 pub struct Bip39Seed {
     mnemonic: Mnemonic,
+    /// The BIP39 passphrase, which is empty if the seed is not additionally password protected.
+    passphrase: String,
+}
+
+/// The number of words of a newly generated mnemonic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordCount {
+    Twelve,
+    TwentyFour,
+}
+
+impl WordCount {
+    fn as_usize(&self) -> usize {
+        match self {
+            WordCount::Twelve => 12,
+            WordCount::TwentyFour => 24,
+        }
+    }
 }
 
 impl Bip39Seed {
     pub fn new() -> Result<Self> {
+        Self::generate(WordCount::Twelve, None)
+    }
+
+    /// Generates a new seed with a mnemonic of `word_count` words and an optional BIP39
+    /// `passphrase`.
+    pub fn generate(word_count: WordCount, passphrase: Option<String>) -> Result<Self> {
         let mut rng = rand::thread_rng();
 
-        let word_count = 12;
-        let mnemonic = Mnemonic::generate_in_with(&mut rng, Language::English, word_count)?;
+        let mnemonic =
+            Mnemonic::generate_in_with(&mut rng, Language::English, word_count.as_usize())?;
 
-        Ok(Self { mnemonic })
+        Ok(Self {
+            mnemonic,
+            passphrase: passphrase.unwrap_or_default(),
+        })
+    }
+
+    /// Restores a seed from the words of its mnemonic, separated by whitespace, and the BIP39
+    /// `passphrase` it was created with, if any.
+    pub fn restore_from_mnemonic(seed_words: &str, passphrase: Option<String>) -> Result<Self> {
+        let mnemonic =
+            Mnemonic::parse_in(Language::English, seed_words).context("Invalid seed phrase")?;
+
+        Ok(Self {
+            mnemonic,
+            passphrase: passphrase.unwrap_or_default(),
+        })
     }
 
     /// Initialise a [`Seed`] from a path.
@@ -31,18 +87,40 @@ impl Bip39Seed {
         let seed = if !seed_file.exists() {
             tracing::info!("No seed found. Generating new seed");
             let seed = Self::new()?;
-            seed.write_to(seed_file)?;
+            seed.write_to(seed_file, None)?;
             seed
         } else {
-            Bip39Seed::read_from(seed_file)?
+            Bip39Seed::read_from(seed_file, None)?
         };
         Ok(seed)
     }
 
+    /// Like [`Bip39Seed::initialize`], but keeps the seed file encrypted with the user-provided
+    /// `encryption_key`.
+    ///
+    /// An existing unencrypted seed file is encrypted in place.
+    pub fn initialize_encrypted(seed_file: &Path, encryption_key: &[u8]) -> Result<Self> {
+        if !seed_file.exists() {
+            tracing::info!("No seed found. Generating new seed");
+            let seed = Self::new()?;
+            seed.write_to(seed_file, Some(encryption_key))?;
+            return Ok(seed);
+        }
+
+        let seed = Bip39Seed::read_from(seed_file, Some(encryption_key))?;
+
+        if !is_encrypted(&std::fs::read(seed_file)?) {
+            tracing::info!("Encrypting seed file");
+            seed.write_file(seed_file, Some(encryption_key))?;
+        }
+
+        Ok(seed)
+    }
+
     fn seed(&self) -> [u8; 64] {
-        // passing an empty string here is the expected argument if the seed should not be
-        // additionally password protected (according to https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki#from-mnemonic-to-seed)
-        self.mnemonic.to_seed_normalized("")
+        // an empty passphrase is the expected argument if the seed should not be additionally
+        // password protected (according to https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki#from-mnemonic-to-seed)
+        self.mnemonic.to_seed_normalized(&self.passphrase)
     }
 
     pub fn lightning_seed(&self) -> LightningSeed {
@@ -66,29 +144,131 @@ impl Bip39Seed {
         }
     }
 
+    /// The key with which the backups of the node are encrypted, so that they can be restored
+    /// with the seed alone.
+    pub(crate) fn backup_encryption_key(&self) -> [u8; 32] {
+        let mut key = [0u8; 32];
+
+        Hkdf::<Sha256>::new(None, &self.seed())
+            .expand(b"BACKUP_ENCRYPTION_KEY", &mut key)
+            .expect("array is of correct length");
+        key
+    }
+
     pub fn get_seed_phrase(&self) -> Vec<String> {
         self.mnemonic.word_iter().map(|word| word.into()).collect()
     }
 
+    /// Stores the seed at `path`, encrypted with the `encryption_key` if provided.
+    ///
+    /// A BIP39 passphrase is only ever stored in an encrypted seed file.
+    pub fn write_to(&self, path: &Path, encryption_key: Option<&[u8]>) -> Result<()> {
+        if path.exists() {
+            let path = path.display();
+            bail!("Refusing to overwrite file at {path}")
+        }
+
+        self.write_file(path, encryption_key)
+    }
+
     // Read the entropy used to generate Mnemonic from disk
-    fn read_from(path: &Path) -> Result<Self> {
+    fn read_from(path: &Path, encryption_key: Option<&[u8]>) -> Result<Self> {
         let bytes = std::fs::read(path)?;
 
-        let seed: Bip39Seed = TryInto::try_into(bytes)
-            .map_err(|_| anyhow::anyhow!("Cannot read the stored entropy"))?;
-        Ok(seed)
+        match (bytes.strip_prefix(ENCRYPTED_SEED_MAGIC), encryption_key) {
+            (Some(encrypted), Some(encryption_key)) => Self::decrypt(encrypted, encryption_key),
+            (Some(_), None) => bail!("Seed file is encrypted, but no encryption key was provided"),
+            (None, _) => {
+                let seed: Bip39Seed = TryInto::try_into(bytes)
+                    .map_err(|_| anyhow!("Cannot read the stored entropy"))?;
+                Ok(seed)
+            }
+        }
     }
 
-    // Store the entropy used to generate Mnemonic on disk
-    fn write_to(&self, path: &Path) -> Result<()> {
-        if path.exists() {
-            let path = path.display();
-            bail!("Refusing to overwrite file at {path}")
-        }
-        std::fs::write(path, self.mnemonic.to_entropy())?;
+    /// Writes the seed to a temporary file first, so that we never end up with a partially
+    /// written seed file.
+    fn write_file(&self, path: &Path, encryption_key: Option<&[u8]>) -> Result<()> {
+        let bytes = match encryption_key {
+            Some(encryption_key) => self.encrypt(encryption_key)?,
+            None => {
+                ensure!(
+                    self.passphrase.is_empty(),
+                    "Refusing to store a seed with passphrase without encryption"
+                );
+                self.mnemonic.to_entropy()
+            }
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, bytes)?;
+        std::fs::rename(&tmp_path, path)?;
 
         Ok(())
     }
+
+    fn encrypt(&self, encryption_key: &[u8]) -> Result<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let entropy = self.mnemonic.to_entropy();
+        let mut plaintext = vec![entropy.len() as u8];
+        plaintext.extend_from_slice(&entropy);
+        plaintext.extend_from_slice(self.passphrase.as_bytes());
+
+        let ciphertext = cipher(encryption_key, &salt)?
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow!("Failed to encrypt seed"))?;
+
+        Ok([ENCRYPTED_SEED_MAGIC, &salt, &nonce, &ciphertext].concat())
+    }
+
+    fn decrypt(encrypted: &[u8], encryption_key: &[u8]) -> Result<Self> {
+        ensure!(
+            encrypted.len() > SALT_LEN + NONCE_LEN,
+            "Encrypted seed file is too short"
+        );
+        let (salt, encrypted) = encrypted.split_at(SALT_LEN);
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+
+        let plaintext = cipher(encryption_key, salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt seed file, is the encryption key correct?"))?;
+
+        let (entropy_len, plaintext) = plaintext.split_first().context("Empty seed file")?;
+        ensure!(
+            plaintext.len() >= *entropy_len as usize,
+            "Seed file is missing entropy"
+        );
+        let (entropy, passphrase) = plaintext.split_at(*entropy_len as usize);
+
+        Ok(Self {
+            mnemonic: Mnemonic::from_entropy(entropy)?,
+            passphrase: String::from_utf8(passphrase.to_vec()).context("Invalid passphrase")?,
+        })
+    }
+}
+
+fn is_encrypted(seed_file: &[u8]) -> bool {
+    seed_file.starts_with(ENCRYPTED_SEED_MAGIC)
+}
+
+/// Derives the cipher for the seed file from the user-provided `encryption_key` and the `salt`
+/// stored in the seed file.
+///
+/// The `encryption_key` may be a low-entropy password, hence we stretch it with Argon2id.
+fn cipher(encryption_key: &[u8], salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+
+    Argon2::default()
+        .hash_password_into(encryption_key, salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive seed file encryption key: {e}"))?;
+
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
 }
 
 pub struct WalletSeed {
@@ -106,13 +286,19 @@ impl TryFrom<Vec<u8>> for Bip39Seed {
     type Error = anyhow::Error;
     fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
         let mnemonic = Mnemonic::from_entropy(&bytes)?;
-        Ok(Bip39Seed { mnemonic })
+        Ok(Bip39Seed {
+            mnemonic,
+            passphrase: String::new(),
+        })
     }
 }
 
 impl From<Mnemonic> for Bip39Seed {
     fn from(mnemonic: Mnemonic) -> Self {
-        Bip39Seed { mnemonic }
+        Bip39Seed {
+            mnemonic,
+            passphrase: String::new(),
+        }
     }
 }
 
@@ -121,9 +307,10 @@ pub type LightningSeed = [u8; 32];
 #[cfg(test)]
 mod tests {
     use bip39::Mnemonic;
-    use std::env::temp_dir;
 
     use crate::seed::Bip39Seed;
+    use crate::seed::WordCount;
+    use crate::tests::TempDir;
 
     #[test]
     fn create_bip39_seed() {
@@ -134,8 +321,8 @@ mod tests {
 
     #[test]
     fn reinitialised_seed_is_the_same() {
-        let mut path = temp_dir();
-        path.push("seed");
+        let dir = TempDir::new();
+        let path = dir.path().join("seed");
         let seed_1 = Bip39Seed::initialize(&path).unwrap();
         let seed_2 = Bip39Seed::initialize(&path).unwrap();
         assert_eq!(
@@ -165,4 +352,76 @@ mod tests {
             "1cf21ab62bf5a5ee40896158cbbc18b9ad75805e1824a252d8060c6c075b228f"
         );
     }
+
+    #[test]
+    fn create_24_word_seed() {
+        let seed = Bip39Seed::generate(WordCount::TwentyFour, None).unwrap();
+        assert_eq!(24, seed.get_seed_phrase().len());
+    }
+
+    #[test]
+    fn restored_seed_is_the_same() {
+        let seed = Bip39Seed::generate(WordCount::TwentyFour, Some("secret".to_string())).unwrap();
+
+        let restored = Bip39Seed::restore_from_mnemonic(
+            &seed.get_seed_phrase().join(" "),
+            Some("secret".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(seed.seed(), restored.seed());
+        assert_eq!(seed.lightning_seed(), restored.lightning_seed());
+    }
+
+    #[test]
+    fn passphrase_changes_seed() {
+        let seed = Bip39Seed::new().unwrap();
+        let words = seed.get_seed_phrase().join(" ");
+
+        let with_passphrase =
+            Bip39Seed::restore_from_mnemonic(&words, Some("secret".to_string())).unwrap();
+
+        assert_ne!(seed.seed(), with_passphrase.seed());
+    }
+
+    #[test]
+    fn invalid_seed_phrase_is_rejected() {
+        assert!(Bip39Seed::restore_from_mnemonic("not a seed phrase", None).is_err());
+    }
+
+    #[test]
+    fn encrypted_seed_can_only_be_read_with_encryption_key() {
+        let dir = TempDir::new();
+        let path = dir.path().join("seed");
+        let seed = Bip39Seed::generate(WordCount::Twelve, Some("secret".to_string())).unwrap();
+        seed.write_to(&path, Some(b"key")).unwrap();
+
+        let read = Bip39Seed::initialize_encrypted(&path, b"key").unwrap();
+        assert_eq!(seed.seed(), read.seed());
+
+        assert!(Bip39Seed::initialize_encrypted(&path, b"wrong key").is_err());
+        assert!(Bip39Seed::initialize(&path).is_err());
+    }
+
+    #[test]
+    fn unencrypted_seed_file_gets_encrypted() {
+        let dir = TempDir::new();
+        let path = dir.path().join("seed");
+        let seed = Bip39Seed::initialize(&path).unwrap();
+
+        let read = Bip39Seed::initialize_encrypted(&path, b"key").unwrap();
+        assert_eq!(seed.mnemonic, read.mnemonic);
+
+        assert!(Bip39Seed::initialize(&path).is_err());
+        let read = Bip39Seed::initialize_encrypted(&path, b"key").unwrap();
+        assert_eq!(seed.mnemonic, read.mnemonic);
+    }
+
+    #[test]
+    fn passphrase_is_not_stored_unencrypted() {
+        let seed = Bip39Seed::generate(WordCount::Twelve, Some("secret".to_string())).unwrap();
+
+        let dir = TempDir::new();
+        assert!(seed.write_to(&dir.path().join("seed"), None).is_err());
+    }
 }
@@ -0,0 +1,354 @@
+//! One-time migration of the on-chain wallet and the DLC state from the sled databases used by
+//! earlier versions of the node.
+//!
+//! The migration writes to a temporary file which is only moved into place once all data has been
+//! copied, so an interrupted migration is simply started over. The sled databases are left in
+//! place.
+
+use crate::on_chain_wallet;
+use crate::seed::WalletSeed;
+use crate::storage;
+use crate::storage::SqliteStorage;
+use crate::storage::STORAGE_FILE;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::database::BatchDatabase;
+use bdk::database::BatchOperations;
+use bdk::database::Database;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::Network;
+use dlc_manager::ChannelId;
+use dlc_manager::Storage;
+use dlc_sled_storage_provider::SledStorageProvider;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// The sled tree in which `dlc-sled-storage-provider` stores the DLC channels, keyed by their ID.
+const SLED_CHANNEL_TREE: u8 = 2;
+
+/// The sled tree in which `dlc-sled-storage-provider` stores the DLC key pairs, keyed by their
+/// public key.
+const SLED_KEY_PAIR_TREE: u8 = 7;
+
+/// Migrates the sled databases in `data_dir` to the SQLite storage, unless the SQLite storage
+/// already exists.
+pub(crate) fn migrate_from_sled(
+    data_dir: &Path,
+    network: Network,
+    seed: &WalletSeed,
+) -> Result<()> {
+    let path = data_dir.join(STORAGE_FILE);
+    if path.exists() {
+        return Ok(());
+    }
+
+    let sled_wallet_dir = data_dir
+        .join("on_chain")
+        .join(network.to_string())
+        .join("wallet");
+    let has_sled_wallet = sled_wallet_dir.exists();
+    let has_sled_dlc_storage = data_dir.join("conf").exists() && data_dir.join("db").exists();
+
+    if !has_sled_wallet && !has_sled_dlc_storage {
+        return Ok(());
+    }
+
+    tracing::info!(?data_dir, "Migrating node storage from sled to SQLite");
+
+    let tmp_path = data_dir.join(format!("{STORAGE_FILE}.tmp"));
+    for leftover in [
+        tmp_path.clone(),
+        data_dir.join(format!("{STORAGE_FILE}.tmp-wal")),
+        data_dir.join(format!("{STORAGE_FILE}.tmp-shm")),
+    ] {
+        if leftover.exists() {
+            fs::remove_file(&leftover).with_context(|| format!("Could not remove {leftover:?}"))?;
+        }
+    }
+
+    if has_sled_wallet {
+        let wallet_name = on_chain_wallet::wallet_name(network, seed)?;
+        migrate_wallet(&sled_wallet_dir, &wallet_name, &tmp_path)?;
+    }
+
+    if has_sled_dlc_storage {
+        migrate_dlc_storage(data_dir, &tmp_path)?;
+    }
+
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("Could not move migrated storage to {path:?}"))?;
+
+    tracing::info!(?path, "Migrated node storage to SQLite");
+
+    Ok(())
+}
+
+fn migrate_wallet(sled_dir: &Path, wallet_name: &str, path: &Path) -> Result<()> {
+    let sled = bdk::sled::open(sled_dir)
+        .with_context(|| format!("Could not open sled wallet at {sled_dir:?}"))?;
+    let old = sled.open_tree(wallet_name)?;
+
+    let mut new = storage::open_wallet_database(path)?;
+    let mut batch = new.begin_batch();
+
+    for script_pubkey in old.iter_script_pubkeys(None)? {
+        if let Some((keychain, child)) = old.get_path_from_script_pubkey(&script_pubkey)? {
+            batch.set_script_pubkey(&script_pubkey, keychain, child)?;
+        }
+    }
+
+    for keychain in [bdk::KeychainKind::External, bdk::KeychainKind::Internal] {
+        if let Some(index) = old.get_last_index(keychain)? {
+            batch.set_last_index(keychain, index)?;
+        }
+    }
+
+    let utxos = old.iter_utxos()?;
+    for utxo in utxos.iter() {
+        batch.set_utxo(utxo)?;
+    }
+
+    let raw_txs = old.iter_raw_txs()?;
+    for tx in raw_txs.iter() {
+        batch.set_raw_tx(tx)?;
+    }
+
+    let txs = old.iter_txs(false)?;
+    for details in txs.iter() {
+        batch.set_tx(details)?;
+    }
+
+    if let Some(sync_time) = old.get_sync_time()? {
+        batch.set_sync_time(sync_time)?;
+    }
+
+    new.commit_batch(batch)?;
+
+    tracing::info!(
+        utxos = utxos.len(),
+        transactions = txs.len(),
+        "Migrated on-chain wallet"
+    );
+
+    Ok(())
+}
+
+fn migrate_dlc_storage(data_dir: &Path, path: &Path) -> Result<()> {
+    // Sled locks its directory, so we have to be done with the raw database before opening the
+    // storage provider on top of it
+    let key_pairs = read_sled_key_pairs(data_dir)?;
+    let ids = read_sled_ids(data_dir)?;
+
+    let old = SledStorageProvider::new(data_dir.to_str().context("Invalid data dir")?)?;
+    let new = SqliteStorage::open(path)?;
+
+    for (public_key, secret_key) in key_pairs.iter() {
+        new.upsert_key_pair(public_key, secret_key)
+            .map_err(|e| anyhow!("Failed to migrate DLC key: {e:#}"))?;
+    }
+
+    let contracts = old
+        .get_contracts()
+        .map_err(|e| anyhow!("Failed to load contracts: {e:#}"))?;
+    for contract in contracts.iter() {
+        new.update_contract(contract)
+            .map_err(|e| anyhow!("Failed to migrate contract: {e:#}"))?;
+    }
+
+    // The storage provider only lists offered and signed channels, so we look up every ID in the
+    // database to also find the accepted and failed ones
+    let mut channels = 0;
+    for id in ids.iter() {
+        let channel = old
+            .get_channel(id)
+            .map_err(|e| anyhow!("Failed to load DLC channel: {e:#}"))?;
+
+        if let Some(channel) = channel {
+            new.upsert_channel(channel, None)
+                .map_err(|e| anyhow!("Failed to migrate DLC channel: {e:#}"))?;
+            channels += 1;
+        }
+    }
+
+    let sub_channels = old
+        .get_sub_channels()
+        .map_err(|e| anyhow!("Failed to load sub-channels: {e:#}"))?;
+    for sub_channel in sub_channels.iter() {
+        new.upsert_sub_channel(sub_channel)
+            .map_err(|e| anyhow!("Failed to migrate sub-channel: {e:#}"))?;
+    }
+
+    if let Some(chain_monitor) = old
+        .get_chain_monitor()
+        .map_err(|e| anyhow!("Failed to load chain monitor: {e:#}"))?
+    {
+        new.persist_chain_monitor(&chain_monitor)
+            .map_err(|e| anyhow!("Failed to migrate chain monitor: {e:#}"))?;
+    }
+
+    tracing::info!(
+        key_pairs = key_pairs.len(),
+        contracts = contracts.len(),
+        channels,
+        sub_channels = sub_channels.len(),
+        "Migrated DLC storage"
+    );
+
+    Ok(())
+}
+
+/// Reads the DLC key pairs from the sled database in `data_dir`.
+///
+/// The storage provider does not let us list its key pairs, so we read its key pair tree
+/// ourselves, skipping entries which do not map a public key to its secret key.
+fn read_sled_key_pairs(data_dir: &Path) -> Result<Vec<(PublicKey, SecretKey)>> {
+    let db = bdk::sled::open(data_dir)
+        .with_context(|| format!("Could not open sled DLC storage at {data_dir:?}"))?;
+    let tree = db.open_tree([SLED_KEY_PAIR_TREE])?;
+    let secp = Secp256k1::new();
+
+    let mut key_pairs = Vec::new();
+    for entry in tree.iter() {
+        let (key, value) = entry?;
+
+        let key_pair = PublicKey::from_slice(&key)
+            .ok()
+            .zip(SecretKey::from_slice(&value).ok());
+        match key_pair {
+            Some((public_key, secret_key))
+                if PublicKey::from_secret_key(&secp, &secret_key) == public_key =>
+            {
+                key_pairs.push((public_key, secret_key));
+            }
+            _ => tracing::warn!(key = %hex::encode(&key), "Skipping invalid DLC key pair"),
+        }
+    }
+
+    Ok(key_pairs)
+}
+
+/// Reads the IDs of the DLC channels in the sled database in `data_dir`.
+fn read_sled_ids(data_dir: &Path) -> Result<BTreeSet<ChannelId>> {
+    let db = bdk::sled::open(data_dir)
+        .with_context(|| format!("Could not open sled DLC storage at {data_dir:?}"))?;
+    let tree = db.open_tree([SLED_CHANNEL_TREE])?;
+
+    let mut ids = BTreeSet::new();
+    for entry in tree.iter() {
+        let (key, _) = entry?;
+
+        match ChannelId::try_from(&key[..]) {
+            Ok(id) => {
+                ids.insert(id);
+            }
+            Err(_) => tracing::warn!(key = %hex::encode(&key), "Skipping invalid DLC channel ID"),
+        }
+    }
+
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bdk::KeychainKind;
+    use bitcoin::Script;
+
+    #[test]
+    fn wallet_is_migrated_from_sled() {
+        let dir = TempDir::new();
+        let sled_dir = dir.path().join("wallet");
+        let script_pubkey = Script::from(vec![0x00, 0x14, 0x01]);
+
+        {
+            let sled = bdk::sled::open(&sled_dir).unwrap();
+            let mut tree = sled.open_tree("wallet").unwrap();
+            tree.set_script_pubkey(&script_pubkey, KeychainKind::External, 7)
+                .unwrap();
+            tree.set_last_index(KeychainKind::External, 7).unwrap();
+        }
+
+        let path = dir.path().join(STORAGE_FILE);
+        migrate_wallet(&sled_dir, "wallet", &path).unwrap();
+
+        let db = storage::open_wallet_database(&path).unwrap();
+        assert_eq!(
+            db.get_path_from_script_pubkey(&script_pubkey).unwrap(),
+            Some((KeychainKind::External, 7))
+        );
+        assert_eq!(db.get_last_index(KeychainKind::External).unwrap(), Some(7));
+        assert_eq!(db.get_last_index(KeychainKind::Internal).unwrap(), None);
+    }
+
+    #[test]
+    fn only_valid_key_pairs_are_read_from_sled() {
+        let dir = TempDir::new();
+        let secp = Secp256k1::new();
+
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let other_secret_key = SecretKey::from_slice(&[2; 32]).unwrap();
+
+        {
+            let sled = bdk::sled::open(dir.path()).unwrap();
+            let tree = sled.open_tree([SLED_KEY_PAIR_TREE]).unwrap();
+            tree.insert(public_key.serialize(), secret_key.secret_bytes().to_vec())
+                .unwrap();
+
+            // Looks like a key pair, but the keys don't belong together
+            tree.insert(
+                PublicKey::from_secret_key(&secp, &other_secret_key).serialize(),
+                secret_key.secret_bytes().to_vec(),
+            )
+            .unwrap();
+
+            // A key pair in another tree is not a DLC key pair
+            let other_tree = sled.open_tree([1u8]).unwrap();
+            other_tree
+                .insert(
+                    PublicKey::from_secret_key(&secp, &other_secret_key).serialize(),
+                    other_secret_key.secret_bytes().to_vec(),
+                )
+                .unwrap();
+        }
+
+        let key_pairs = read_sled_key_pairs(dir.path()).unwrap();
+
+        assert_eq!(key_pairs, vec![(public_key, secret_key)]);
+    }
+
+    #[test]
+    fn only_channel_ids_are_read_from_sled() {
+        let dir = TempDir::new();
+
+        {
+            let sled = bdk::sled::open(dir.path()).unwrap();
+            let tree = sled.open_tree([SLED_CHANNEL_TREE]).unwrap();
+            tree.insert([1; 32], vec![1]).unwrap();
+            tree.insert([2; 33], vec![2]).unwrap();
+
+            // The ID of e.g. a contract is not the ID of a DLC channel
+            let other_tree = sled.open_tree([1u8]).unwrap();
+            other_tree.insert([3; 32], vec![3]).unwrap();
+        }
+
+        let ids = read_sled_ids(dir.path()).unwrap();
+
+        assert_eq!(ids.into_iter().collect::<Vec<_>>(), vec![[1; 32]]);
+    }
+
+    #[test]
+    fn migration_is_skipped_for_a_new_node() {
+        let dir = TempDir::new();
+        let seed = crate::seed::Bip39Seed::new().unwrap().wallet_seed();
+
+        migrate_from_sled(dir.path(), Network::Regtest, &seed).unwrap();
+
+        assert!(!dir.path().join(STORAGE_FILE).exists());
+    }
+}
@@ -0,0 +1,1044 @@
+//! SQLite storage for the state of a node which is not persisted by LDK.
+//!
+//! The on-chain wallet and the DLC state share a single database file, so that the data of a node
+//! lives in one place and can be inspected with standard SQLite tooling. The on-chain wallet uses
+//! BDK's SQLite database, see [`WalletDatabase`], the DLC state lives in the `dlc_*` tables
+//! managed by [`SqliteStorage`]. The latter also keeps track of the transactions and outputs
+//! watched on-chain, so that we keep watching them after a restart.
+//!
+//! Every write is committed in a transaction: BDK commits the changes of a wallet sync as one
+//! batch, and every update of the DLC state which touches several rows (e.g. a DLC channel
+//! together with its contract) is committed as a whole. The on-chain wallet only holds what BDK
+//! learns from the chain and the DLC state does not refer to it, so there is no write which has
+//! to span both.
+
+use anyhow::Context;
+use bdk::database::any::SqliteDbConfiguration;
+use bdk::database::ConfigurableDatabase;
+use bdk::database::SqliteDatabase;
+use bitcoin::consensus::encode;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::BlockHash;
+use bitcoin::OutPoint;
+use bitcoin::Script;
+use bitcoin::Txid;
+use dlc_manager::chain_monitor::ChainMonitor;
+use dlc_manager::channel::accepted_channel::AcceptedChannel;
+use dlc_manager::channel::offered_channel::OfferedChannel;
+use dlc_manager::channel::signed_channel::SignedChannel;
+use dlc_manager::channel::signed_channel::SignedChannelStateType;
+use dlc_manager::channel::Channel;
+use dlc_manager::channel::FailedAccept;
+use dlc_manager::channel::FailedSign;
+use dlc_manager::contract::accepted_contract::AcceptedContract;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::ClosedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::contract::FailedAcceptContract;
+use dlc_manager::contract::FailedSignContract;
+use dlc_manager::contract::PreClosedContract;
+use dlc_manager::error::Error;
+use dlc_manager::subchannel::SubChannel;
+use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::ChannelId;
+use dlc_manager::ContractId;
+use lightning::chain::WatchedOutput;
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+use std::fmt::Display;
+use std::io::Cursor;
+use std::mem::discriminant;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub(crate) mod migration;
+
+/// The database of the on-chain wallet, stored in the same file as the DLC state.
+pub type WalletDatabase = SqliteDatabase;
+
+/// The name of the database file in the data dir of a node.
+pub const STORAGE_FILE: &str = "node.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS dlc_contracts (
+        id BLOB PRIMARY KEY NOT NULL,
+        state TEXT NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dlc_channels (
+        id BLOB PRIMARY KEY NOT NULL,
+        state TEXT NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dlc_sub_channels (
+        id BLOB PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dlc_chain_monitor (
+        id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dlc_key_pairs (
+        public_key BLOB PRIMARY KEY NOT NULL,
+        secret_key BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dlc_key_index (
+        id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+        next_index INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS backup_sequence (
+        id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+        next_sequence INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS watched_transactions (
+        txid BLOB NOT NULL,
+        script_pubkey BLOB NOT NULL,
+        pruned INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (txid, script_pubkey)
+    );
+    CREATE TABLE IF NOT EXISTS watched_outputs (
+        txid BLOB NOT NULL,
+        vout INTEGER NOT NULL,
+        script_pubkey BLOB NOT NULL,
+        block_hash BLOB,
+        pruned INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (txid, vout, script_pubkey)
+    );
+    CREATE TABLE IF NOT EXISTS tracked_blocks (
+        height INTEGER PRIMARY KEY NOT NULL,
+        hash BLOB NOT NULL
+    );
+";
+
+/// Opens the database of the on-chain wallet in the storage file at `path`.
+pub fn open_wallet_database(path: &Path) -> anyhow::Result<WalletDatabase> {
+    let config = SqliteDbConfiguration {
+        path: path
+            .to_str()
+            .with_context(|| format!("Invalid storage path {path:?}"))?
+            .to_string(),
+    };
+
+    WalletDatabase::from_config(&config)
+        .with_context(|| format!("Could not open wallet database at {path:?}"))
+}
+
+/// Implements the storage of `rust-dlc` on top of SQLite.
+///
+/// Every contract, channel and sub-channel is stored in the encoding provided by `rust-dlc`,
+/// together with its state so that we know how to decode it.
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Could not open storage at {path:?}"))?;
+        // BDK keeps its own connection to the same file for the on-chain wallet, which the
+        // write-ahead log allows to read while we write
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .context("Could not enable write-ahead log")?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("Could not create storage tables")?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub fn upsert_key_pair(
+        &self,
+        public_key: &PublicKey,
+        secret_key: &SecretKey,
+    ) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO dlc_key_pairs (public_key, secret_key) VALUES (?1, ?2)",
+                params![
+                    public_key.serialize().to_vec(),
+                    secret_key.secret_bytes().to_vec()
+                ],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    pub fn get_priv_key_for_pubkey(
+        &self,
+        public_key: &PublicKey,
+    ) -> Result<Option<SecretKey>, Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        let secret_key = connection
+            .query_row(
+                "SELECT secret_key FROM dlc_key_pairs WHERE public_key = ?1",
+                params![public_key.serialize().to_vec()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+
+        secret_key
+            .map(|secret_key| SecretKey::from_slice(&secret_key).map_err(storage_error))
+            .transpose()
+    }
+
+    /// Returns the index of the next DLC key to derive from the seed and increments it, so that
+    /// no index is handed out twice, even across restarts.
+    pub fn next_dlc_key_index(&self) -> Result<u32, Error> {
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        let index = transaction
+            .query_row(
+                "SELECT next_index FROM dlc_key_index WHERE id = 0",
+                [],
+                |row| row.get::<_, u32>(0),
+            )
+            .optional()
+            .map_err(storage_error)?
+            .unwrap_or(0);
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO dlc_key_index (id, next_index) VALUES (0, ?1)",
+                params![index + 1],
+            )
+            .map_err(storage_error)?;
+
+        transaction.commit().map_err(storage_error)?;
+
+        Ok(index)
+    }
+
+    /// Returns the sequence number of the next backup and increments it, so that every backup
+    /// gets a higher sequence number than the previous one.
+    pub fn next_backup_sequence(&self) -> Result<u64, Error> {
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        let sequence = transaction
+            .query_row(
+                "SELECT next_sequence FROM backup_sequence WHERE id = 0",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .map_err(storage_error)?
+            .unwrap_or(0);
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO backup_sequence (id, next_sequence) VALUES (0, ?1)",
+                params![sequence + 1],
+            )
+            .map_err(storage_error)?;
+
+        transaction.commit().map_err(storage_error)?;
+
+        Ok(sequence as u64)
+    }
+
+    /// Continues the sequence numbers of backups at `next_sequence`, e.g. after restoring a
+    /// backup.
+    pub fn set_next_backup_sequence(&self, next_sequence: u64) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO backup_sequence (id, next_sequence) VALUES (0, ?1)",
+                params![next_sequence as i64],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    pub fn insert_watched_transaction(
+        &self,
+        txid: &Txid,
+        script_pubkey: &Script,
+    ) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR IGNORE INTO watched_transactions (txid, script_pubkey) VALUES (?1, ?2)",
+                params![encode::serialize(txid), script_pubkey.to_bytes()],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    pub fn get_watched_transactions(&self) -> Result<Vec<(Txid, Script)>, Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        let mut statement = connection
+            .prepare("SELECT txid, script_pubkey FROM watched_transactions WHERE pruned = 0")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?;
+
+        rows.into_iter()
+            .map(|(txid, script_pubkey)| {
+                let txid = encode::deserialize(&txid).map_err(storage_error)?;
+
+                Ok((txid, Script::from(script_pubkey)))
+            })
+            .collect()
+    }
+
+    pub fn insert_watched_output(&self, output: &WatchedOutput) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR IGNORE INTO watched_outputs (txid, vout, script_pubkey, block_hash) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    encode::serialize(&output.outpoint.txid),
+                    output.outpoint.vout,
+                    output.script_pubkey.to_bytes(),
+                    output.block_hash.as_ref().map(encode::serialize),
+                ],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    /// Marks the given watched transactions and outputs as pruned, so that they are neither
+    /// returned as watched nor watched again if they are registered again.
+    pub fn prune_watched(&self, txids: &[Txid], outpoints: &[OutPoint]) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        for txid in txids {
+            transaction
+                .execute(
+                    "UPDATE watched_transactions SET pruned = 1 WHERE txid = ?1",
+                    params![encode::serialize(txid)],
+                )
+                .map_err(storage_error)?;
+        }
+
+        for outpoint in outpoints {
+            transaction
+                .execute(
+                    "UPDATE watched_outputs SET pruned = 1 WHERE txid = ?1 AND vout = ?2",
+                    params![encode::serialize(&outpoint.txid), outpoint.vout],
+                )
+                .map_err(storage_error)?;
+        }
+
+        transaction.commit().map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    /// The transactions and outputs passed to [`Self::prune_watched`].
+    pub fn get_pruned(&self) -> Result<(Vec<Txid>, Vec<OutPoint>), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+
+        let txids = connection
+            .prepare("SELECT DISTINCT txid FROM watched_transactions WHERE pruned = 1")
+            .map_err(storage_error)?
+            .query_map([], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?
+            .into_iter()
+            .map(|txid| encode::deserialize(&txid).map_err(storage_error))
+            .collect::<Result<Vec<Txid>, Error>>()?;
+
+        let outpoints = connection
+            .prepare("SELECT DISTINCT txid, vout FROM watched_outputs WHERE pruned = 1")
+            .map_err(storage_error)?
+            .query_map([], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, u32>(1)?))
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?
+            .into_iter()
+            .map(|(txid, vout)| {
+                let txid = encode::deserialize(&txid).map_err(storage_error)?;
+
+                Ok(OutPoint { txid, vout })
+            })
+            .collect::<Result<Vec<OutPoint>, Error>>()?;
+
+        Ok((txids, outpoints))
+    }
+
+    pub fn get_watched_outputs(&self) -> Result<Vec<WatchedOutput>, Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        let mut statement = connection
+            .prepare(
+                "SELECT txid, vout, script_pubkey, block_hash FROM watched_outputs \
+                 WHERE pruned = 0",
+            )
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, Option<Vec<u8>>>(3)?,
+                ))
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?;
+
+        rows.into_iter()
+            .map(|(txid, vout, script_pubkey, block_hash)| {
+                let txid = encode::deserialize(&txid).map_err(storage_error)?;
+                let block_hash = block_hash
+                    .map(|block_hash| encode::deserialize(&block_hash))
+                    .transpose()
+                    .map_err(storage_error)?;
+
+                Ok(WatchedOutput {
+                    block_hash,
+                    outpoint: OutPoint { txid, vout },
+                    script_pubkey: Script::from(script_pubkey),
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces the blocks tracked by the on-chain wallet to detect reorgs with `blocks`.
+    pub fn replace_tracked_blocks(&self, blocks: &[(u32, BlockHash)]) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        transaction
+            .execute("DELETE FROM tracked_blocks", [])
+            .map_err(storage_error)?;
+        for (height, hash) in blocks {
+            transaction
+                .execute(
+                    "INSERT INTO tracked_blocks (height, hash) VALUES (?1, ?2)",
+                    params![height, encode::serialize(hash)],
+                )
+                .map_err(storage_error)?;
+        }
+
+        transaction.commit().map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    pub fn get_tracked_blocks(&self) -> Result<Vec<(u32, BlockHash)>, Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        let mut statement = connection
+            .prepare("SELECT height, hash FROM tracked_blocks ORDER BY height")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?;
+
+        rows.into_iter()
+            .map(|(height, hash)| {
+                let hash = encode::deserialize(&hash).map_err(storage_error)?;
+
+                Ok((height, hash))
+            })
+            .collect()
+    }
+
+    fn select<T>(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+        decode: impl Fn(&str, &[u8]) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        let mut statement = connection.prepare(sql).map_err(storage_error)?;
+        let rows = statement
+            .query_map(params, |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(storage_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_error)?;
+
+        rows.iter()
+            .map(|(state, data)| decode(state, data))
+            .collect()
+    }
+
+    fn get_contracts_in_state(&self, state: &str) -> Result<Vec<Contract>, Error> {
+        self.select(
+            "SELECT state, data FROM dlc_contracts WHERE state = ?1",
+            params![state],
+            deserialize_contract,
+        )
+    }
+
+    fn get_channels_in_state(&self, state: &str) -> Result<Vec<Channel>, Error> {
+        self.select(
+            "SELECT state, data FROM dlc_channels WHERE state = ?1",
+            params![state],
+            deserialize_channel,
+        )
+    }
+}
+
+impl dlc_manager::Storage for SqliteStorage {
+    fn get_contract(&self, id: &ContractId) -> Result<Option<Contract>, Error> {
+        let contracts = self.select(
+            "SELECT state, data FROM dlc_contracts WHERE id = ?1",
+            params![id.to_vec()],
+            deserialize_contract,
+        )?;
+
+        Ok(contracts.into_iter().next())
+    }
+
+    fn get_contracts(&self) -> Result<Vec<Contract>, Error> {
+        self.select(
+            "SELECT state, data FROM dlc_contracts",
+            [],
+            deserialize_contract,
+        )
+    }
+
+    fn create_contract(&self, contract: &OfferedContract) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT INTO dlc_contracts (id, state, data) VALUES (?1, ?2, ?3)",
+                params![contract.id.to_vec(), OFFERED, serialize(contract)?],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn delete_contract(&self, id: &ContractId) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "DELETE FROM dlc_contracts WHERE id = ?1",
+                params![id.to_vec()],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn update_contract(&self, contract: &Contract) -> Result<(), Error> {
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        upsert_contract(&transaction, contract)?;
+
+        transaction.commit().map_err(storage_error)
+    }
+
+    fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error> {
+        let contracts = self.get_contracts_in_state(OFFERED)?;
+
+        Ok(contracts
+            .into_iter()
+            .filter_map(|contract| match contract {
+                Contract::Offered(contract) => Some(contract),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        let contracts = self.get_contracts_in_state(SIGNED)?;
+
+        Ok(contracts
+            .into_iter()
+            .filter_map(|contract| match contract {
+                Contract::Signed(contract) => Some(contract),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn get_confirmed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        let contracts = self.get_contracts_in_state(CONFIRMED)?;
+
+        Ok(contracts
+            .into_iter()
+            .filter_map(|contract| match contract {
+                Contract::Confirmed(contract) => Some(contract),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error> {
+        let contracts = self.get_contracts_in_state(PRE_CLOSED)?;
+
+        Ok(contracts
+            .into_iter()
+            .filter_map(|contract| match contract {
+                Contract::PreClosed(contract) => Some(contract),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn upsert_channel(&self, channel: Channel, contract: Option<Contract>) -> Result<(), Error> {
+        let (state, data) = serialize_channel(&channel)?;
+
+        let mut connection = self.connection.lock().expect("to get lock on connection");
+        let transaction = connection.transaction().map_err(storage_error)?;
+
+        // The channel is stored under its temporary ID until it is accepted
+        if matches!(channel, Channel::Accepted(_) | Channel::Signed(_)) {
+            transaction
+                .execute(
+                    "DELETE FROM dlc_channels WHERE id = ?1",
+                    params![channel.get_temporary_id().to_vec()],
+                )
+                .map_err(storage_error)?;
+        }
+
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO dlc_channels (id, state, data) VALUES (?1, ?2, ?3)",
+                params![channel.get_id().to_vec(), state, data],
+            )
+            .map_err(storage_error)?;
+
+        if let Some(contract) = contract {
+            upsert_contract(&transaction, &contract)?;
+        }
+
+        transaction.commit().map_err(storage_error)
+    }
+
+    fn delete_channel(&self, channel_id: &ChannelId) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "DELETE FROM dlc_channels WHERE id = ?1",
+                params![channel_id.to_vec()],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn get_channel(&self, channel_id: &ChannelId) -> Result<Option<Channel>, Error> {
+        let channels = self.select(
+            "SELECT state, data FROM dlc_channels WHERE id = ?1",
+            params![channel_id.to_vec()],
+            deserialize_channel,
+        )?;
+
+        Ok(channels.into_iter().next())
+    }
+
+    fn get_signed_channels(
+        &self,
+        channel_state: Option<SignedChannelStateType>,
+    ) -> Result<Vec<SignedChannel>, Error> {
+        let channels = self.get_channels_in_state(SIGNED)?;
+
+        Ok(channels
+            .into_iter()
+            .filter_map(|channel| match channel {
+                Channel::Signed(channel) => Some(channel),
+                _ => None,
+            })
+            .filter(|channel| match &channel_state {
+                Some(state) => discriminant(&channel.state.get_type()) == discriminant(state),
+                None => true,
+            })
+            .collect())
+    }
+
+    fn get_offered_channels(&self) -> Result<Vec<OfferedChannel>, Error> {
+        let channels = self.get_channels_in_state(OFFERED)?;
+
+        Ok(channels
+            .into_iter()
+            .filter_map(|channel| match channel {
+                Channel::Offered(channel) => Some(channel),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn persist_chain_monitor(&self, monitor: &ChainMonitor) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO dlc_chain_monitor (id, data) VALUES (0, ?1)",
+                params![serialize(monitor)?],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn get_chain_monitor(&self) -> Result<Option<ChainMonitor>, Error> {
+        let monitors = self.select(
+            "SELECT 'chain_monitor', data FROM dlc_chain_monitor WHERE id = 0",
+            [],
+            |_, data| deserialize(data),
+        )?;
+
+        Ok(monitors.into_iter().next())
+    }
+
+    fn upsert_sub_channel(&self, subchannel: &SubChannel) -> Result<(), Error> {
+        let connection = self.connection.lock().expect("to get lock on connection");
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO dlc_sub_channels (id, data) VALUES (?1, ?2)",
+                params![subchannel.channel_id.to_vec(), serialize(subchannel)?],
+            )
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn get_sub_channel(&self, channel_id: ChannelId) -> Result<Option<SubChannel>, Error> {
+        let sub_channels = self.select(
+            "SELECT 'sub_channel', data FROM dlc_sub_channels WHERE id = ?1",
+            params![channel_id.to_vec()],
+            |_, data| deserialize(data),
+        )?;
+
+        Ok(sub_channels.into_iter().next())
+    }
+
+    fn get_sub_channels(&self) -> Result<Vec<SubChannel>, Error> {
+        self.select(
+            "SELECT 'sub_channel', data FROM dlc_sub_channels",
+            [],
+            |_, data| deserialize(data),
+        )
+    }
+
+    fn get_offered_sub_channels(&self) -> Result<Vec<SubChannel>, Error> {
+        let sub_channels = self.get_sub_channels()?;
+
+        Ok(sub_channels
+            .into_iter()
+            .filter(|sub_channel| matches!(sub_channel.state, SubChannelState::Offered(_)))
+            .collect())
+    }
+}
+
+const OFFERED: &str = "offered";
+const ACCEPTED: &str = "accepted";
+const SIGNED: &str = "signed";
+const CONFIRMED: &str = "confirmed";
+const PRE_CLOSED: &str = "pre_closed";
+const CLOSED: &str = "closed";
+const REFUNDED: &str = "refunded";
+const FAILED_ACCEPT: &str = "failed_accept";
+const FAILED_SIGN: &str = "failed_sign";
+const REJECTED: &str = "rejected";
+
+/// Stores `contract` under its ID, replacing the entry stored under its temporary ID once the
+/// contract has been accepted.
+fn upsert_contract(connection: &Connection, contract: &Contract) -> Result<(), Error> {
+    let (state, data) = serialize_contract(contract)?;
+
+    if matches!(contract, Contract::Accepted(_) | Contract::Signed(_)) {
+        connection
+            .execute(
+                "DELETE FROM dlc_contracts WHERE id = ?1",
+                params![contract.get_temporary_id().to_vec()],
+            )
+            .map_err(storage_error)?;
+    }
+
+    connection
+        .execute(
+            "INSERT OR REPLACE INTO dlc_contracts (id, state, data) VALUES (?1, ?2, ?3)",
+            params![contract.get_id().to_vec(), state, data],
+        )
+        .map_err(storage_error)?;
+
+    Ok(())
+}
+
+fn serialize_contract(contract: &Contract) -> Result<(&'static str, Vec<u8>), Error> {
+    let serialized = match contract {
+        Contract::Offered(contract) => (OFFERED, serialize(contract)?),
+        Contract::Rejected(contract) => (REJECTED, serialize(contract)?),
+        Contract::Accepted(contract) => (ACCEPTED, serialize(contract)?),
+        Contract::Signed(contract) => (SIGNED, serialize(contract)?),
+        Contract::Confirmed(contract) => (CONFIRMED, serialize(contract)?),
+        Contract::Refunded(contract) => (REFUNDED, serialize(contract)?),
+        Contract::PreClosed(contract) => (PRE_CLOSED, serialize(contract)?),
+        Contract::Closed(contract) => (CLOSED, serialize(contract)?),
+        Contract::FailedAccept(contract) => (FAILED_ACCEPT, serialize(contract)?),
+        Contract::FailedSign(contract) => (FAILED_SIGN, serialize(contract)?),
+    };
+
+    Ok(serialized)
+}
+
+fn deserialize_contract(state: &str, data: &[u8]) -> Result<Contract, Error> {
+    let contract = match state {
+        OFFERED => Contract::Offered(deserialize::<OfferedContract>(data)?),
+        REJECTED => Contract::Rejected(deserialize::<OfferedContract>(data)?),
+        ACCEPTED => Contract::Accepted(deserialize::<AcceptedContract>(data)?),
+        SIGNED => Contract::Signed(deserialize::<SignedContract>(data)?),
+        CONFIRMED => Contract::Confirmed(deserialize::<SignedContract>(data)?),
+        REFUNDED => Contract::Refunded(deserialize::<SignedContract>(data)?),
+        PRE_CLOSED => Contract::PreClosed(deserialize::<PreClosedContract>(data)?),
+        CLOSED => Contract::Closed(deserialize::<ClosedContract>(data)?),
+        FAILED_ACCEPT => Contract::FailedAccept(deserialize::<FailedAcceptContract>(data)?),
+        FAILED_SIGN => Contract::FailedSign(deserialize::<FailedSignContract>(data)?),
+        state => {
+            return Err(Error::StorageError(format!(
+                "Unknown contract state {state}"
+            )))
+        }
+    };
+
+    Ok(contract)
+}
+
+fn serialize_channel(channel: &Channel) -> Result<(&'static str, Vec<u8>), Error> {
+    let serialized = match channel {
+        Channel::Offered(channel) => (OFFERED, serialize(channel)?),
+        Channel::Accepted(channel) => (ACCEPTED, serialize(channel)?),
+        Channel::Signed(channel) => (SIGNED, serialize(channel)?),
+        Channel::FailedAccept(channel) => (FAILED_ACCEPT, serialize(channel)?),
+        Channel::FailedSign(channel) => (FAILED_SIGN, serialize(channel)?),
+    };
+
+    Ok(serialized)
+}
+
+fn deserialize_channel(state: &str, data: &[u8]) -> Result<Channel, Error> {
+    let channel = match state {
+        OFFERED => Channel::Offered(deserialize::<OfferedChannel>(data)?),
+        ACCEPTED => Channel::Accepted(deserialize::<AcceptedChannel>(data)?),
+        SIGNED => Channel::Signed(deserialize::<SignedChannel>(data)?),
+        FAILED_ACCEPT => Channel::FailedAccept(deserialize::<FailedAccept>(data)?),
+        FAILED_SIGN => Channel::FailedSign(deserialize::<FailedSign>(data)?),
+        state => {
+            return Err(Error::StorageError(format!(
+                "Unknown channel state {state}"
+            )))
+        }
+    };
+
+    Ok(channel)
+}
+
+fn serialize<T: Serializable>(value: &T) -> Result<Vec<u8>, Error> {
+    value.serialize().map_err(storage_error)
+}
+
+fn deserialize<T: Serializable>(data: &[u8]) -> Result<T, Error> {
+    T::deserialize(&mut Cursor::new(data))
+        .map_err(|e| Error::StorageError(format!("Failed to decode stored value: {e:?}")))
+}
+
+fn storage_error(e: impl Display) -> Error {
+    Error::StorageError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::Secp256k1;
+    use dlc_manager::Storage;
+
+    #[test]
+    fn key_pairs_survive_reopening_the_storage() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        let secret_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        storage.upsert_key_pair(&public_key, &secret_key).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(
+            storage.get_priv_key_for_pubkey(&public_key).unwrap(),
+            Some(secret_key)
+        );
+    }
+
+    #[test]
+    fn dlc_key_index_survives_reopening_the_storage() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(storage.next_dlc_key_index().unwrap(), 0);
+        assert_eq!(storage.next_dlc_key_index().unwrap(), 1);
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(storage.next_dlc_key_index().unwrap(), 2);
+    }
+
+    #[test]
+    fn backup_sequence_continues_after_restore() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(storage.next_backup_sequence().unwrap(), 0);
+        assert_eq!(storage.next_backup_sequence().unwrap(), 1);
+
+        storage.set_next_backup_sequence(42).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(storage.next_backup_sequence().unwrap(), 42);
+    }
+
+    #[test]
+    fn unknown_key_pair_is_none() {
+        let dir = TempDir::new();
+        let storage = SqliteStorage::open(&dir.path().join(STORAGE_FILE)).unwrap();
+
+        let secret_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+
+        assert_eq!(storage.get_priv_key_for_pubkey(&public_key).unwrap(), None);
+    }
+
+    #[test]
+    fn watched_transactions_and_outputs_survive_reopening_the_storage() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        let txid = Txid::from_slice(&[1; 32]).unwrap();
+        let script_pubkey = Script::from(vec![0x00, 0x20, 0x01]);
+        let output = WatchedOutput {
+            block_hash: Some(BlockHash::from_slice(&[2; 32]).unwrap()),
+            outpoint: OutPoint { txid, vout: 1 },
+            script_pubkey: script_pubkey.clone(),
+        };
+
+        storage
+            .insert_watched_transaction(&txid, &script_pubkey)
+            .unwrap();
+        storage.insert_watched_output(&output).unwrap();
+        // Registering the same output again is a no-op
+        storage.insert_watched_output(&output).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(
+            storage.get_watched_transactions().unwrap(),
+            vec![(txid, script_pubkey)]
+        );
+        assert_eq!(storage.get_watched_outputs().unwrap(), vec![output]);
+    }
+
+    #[test]
+    fn pruned_watched_transactions_and_outputs_are_not_watched_after_reopening_the_storage() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        let txid = Txid::from_slice(&[1; 32]).unwrap();
+        let other_txid = Txid::from_slice(&[2; 32]).unwrap();
+        let script_pubkey = Script::from(vec![0x00, 0x20, 0x01]);
+        let spent_output = WatchedOutput {
+            block_hash: None,
+            outpoint: OutPoint { txid, vout: 0 },
+            script_pubkey: script_pubkey.clone(),
+        };
+        let unspent_output = WatchedOutput {
+            block_hash: None,
+            outpoint: OutPoint { txid, vout: 1 },
+            script_pubkey: script_pubkey.clone(),
+        };
+
+        storage
+            .insert_watched_transaction(&txid, &script_pubkey)
+            .unwrap();
+        storage
+            .insert_watched_transaction(&other_txid, &script_pubkey)
+            .unwrap();
+        storage.insert_watched_output(&spent_output).unwrap();
+        storage.insert_watched_output(&unspent_output).unwrap();
+        storage
+            .prune_watched(&[txid], &[spent_output.outpoint])
+            .unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        // Registering a pruned output again does not watch it again
+        storage.insert_watched_output(&spent_output).unwrap();
+
+        assert_eq!(
+            storage.get_watched_transactions().unwrap(),
+            vec![(other_txid, script_pubkey)]
+        );
+        assert_eq!(storage.get_watched_outputs().unwrap(), vec![unspent_output]);
+        assert_eq!(
+            storage.get_pruned().unwrap(),
+            (vec![txid], vec![spent_output.outpoint])
+        );
+    }
+
+    #[test]
+    fn tracked_blocks_survive_reopening_the_storage() {
+        let dir = TempDir::new();
+        let path = dir.path().join(STORAGE_FILE);
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        let stale_block = (101, BlockHash::from_slice(&[1; 32]).unwrap());
+        storage.replace_tracked_blocks(&[stale_block]).unwrap();
+
+        let blocks = vec![
+            (100, BlockHash::from_slice(&[2; 32]).unwrap()),
+            (101, BlockHash::from_slice(&[3; 32]).unwrap()),
+        ];
+        storage.replace_tracked_blocks(&blocks).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+
+        assert_eq!(storage.get_tracked_blocks().unwrap(), blocks);
+    }
+
+    #[test]
+    fn empty_storage_has_no_dlc_state() {
+        let dir = TempDir::new();
+        let storage = SqliteStorage::open(&dir.path().join(STORAGE_FILE)).unwrap();
+
+        assert!(storage.get_contracts().unwrap().is_empty());
+        assert!(storage.get_sub_channels().unwrap().is_empty());
+        assert!(storage.get_signed_channels(None).unwrap().is_empty());
+        assert!(storage.get_chain_monitor().unwrap().is_none());
+    }
+}
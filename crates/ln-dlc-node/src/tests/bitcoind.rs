@@ -2,6 +2,7 @@ use crate::await_with_timeout::AwaitWithTimeout;
 use crate::tests;
 use anyhow::bail;
 use anyhow::Result;
+use bitcoin::BlockHash;
 use reqwest::Response;
 use serde::Deserialize;
 use std::time::Duration;
@@ -49,6 +50,24 @@ pub async fn mine(n: u16) -> Result<()> {
     Ok(())
 }
 
+/// Instructs `bitcoind` to mark `block_hash` as invalid, which reorgs it (and all its
+/// descendants) out of the best chain.
+pub async fn invalidate_block(block_hash: BlockHash) -> Result<()> {
+    query(format!(
+        r#"{{"jsonrpc": "1.0", "method": "invalidateblock", "params": ["{block_hash}"]}}"#
+    ))
+    .await_with_timeout()
+    .await
+    .unwrap()?;
+    // For the reorg to be picked up by the subsequent wallet syncs
+    tokio::time::sleep(Duration::from_secs(5))
+        .await_with_timeout()
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
 async fn query(query: String) -> Result<Response> {
     let client = reqwest::Client::new();
     let response = client
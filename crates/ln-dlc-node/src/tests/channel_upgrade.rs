@@ -0,0 +1,115 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::channel_upgrade::upgrade_fee_msat;
+use crate::node::Node;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use bitcoin::hashes::sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::Amount;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn upgrade_channel_by_closing_and_reopening() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    coordinator
+        .fund(Amount::from_sat(200_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let old_channel = coordinator
+        .open_channel(&app, 20_000, 10_000, true)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let old_channel = app
+        .list_usable_channels()
+        .into_iter()
+        .find(|channel| channel.channel_id == old_channel.channel_id)
+        .unwrap();
+    let app_spendable_before_msat = old_channel.next_outbound_htlc_limit_msat;
+
+    // Act
+
+    coordinator
+        .open_replacement_channel(app.info.pubkey, 100_000)
+        .unwrap();
+
+    let new_channel = wait_until(Duration::from_secs(30), || async {
+        Ok(app
+            .list_usable_channels()
+            .into_iter()
+            .find(|channel| channel.channel_id != old_channel.channel_id))
+    })
+    .await
+    .unwrap();
+
+    let payment_hash = app
+        .move_balance_to_replacement_channel(old_channel.channel_id, new_channel.channel_id)
+        .unwrap();
+
+    app.wait_for_payment_claimed(&sha256::Hash::from_inner(payment_hash.0))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.close_channel(old_channel.channel_id).unwrap();
+
+    wait_until(Duration::from_secs(30), || async {
+        let channels = app.list_channels();
+        Ok((channels.len() == 1).then_some(()))
+    })
+    .await
+    .unwrap();
+
+    // Assert
+
+    let channels = app.list_usable_channels();
+    assert_eq!(channels.len(), 1);
+
+    let new_channel = &channels[0];
+    assert_eq!(new_channel.channel_value_satoshis, 100_000);
+
+    // The app only pays the upgrade fee on the added capacity for the circular payment
+    let upgrade_fee_msat = upgrade_fee_msat(&coordinator.user_config.channel_config, 80_000);
+    assert_eq!(
+        app_spendable_before_msat - new_channel.balance_msat,
+        upgrade_fee_msat
+    );
+
+    // Afterwards the replacement channel charges the coordinator's usual forwarding fees
+    let replacement_channel = coordinator
+        .list_channels()
+        .into_iter()
+        .find(|channel| channel.channel_id == new_channel.channel_id)
+        .unwrap();
+    assert_eq!(
+        replacement_channel.config,
+        Some(coordinator.user_config.channel_config)
+    );
+}
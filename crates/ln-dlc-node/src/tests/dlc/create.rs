@@ -1,4 +1,5 @@
 use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::DlcChannelEventKind;
 use crate::node::Node;
 use crate::tests::dummy_contract_input;
 use crate::tests::init_tracing;
@@ -27,6 +28,44 @@ async fn given_lightning_channel_then_can_add_dlc_channel() {
         .unwrap();
 }
 
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_then_collateral_is_separate_from_spendable_lightning_balance() {
+    init_tracing();
+
+    let app_dlc_collateral = 50_000;
+    let coordinator_dlc_collateral = 25_000;
+
+    let DlcChannelCreated {
+        coordinator,
+        coordinator_balance_channel_creation,
+        app,
+        app_balance_channel_creation,
+        ..
+    } = create_dlc_channel(app_dlc_collateral, coordinator_dlc_collateral)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    for (node, balance_channel_creation, dlc_collateral) in [
+        (&app, app_balance_channel_creation, app_dlc_collateral),
+        (
+            &coordinator,
+            coordinator_balance_channel_creation,
+            coordinator_dlc_collateral,
+        ),
+    ] {
+        let balance = node.get_balance().unwrap();
+
+        assert_eq!(balance.dlc_collateral, dlc_collateral);
+        assert_eq!(
+            balance.lightning_spendable,
+            balance_channel_creation - dlc_collateral
+        );
+    }
+}
+
 pub struct DlcChannelCreated {
     pub coordinator: Node,
     /// Available balance for the coordinator after the LN channel was created. In sats.
@@ -57,7 +96,7 @@ pub async fn create_dlc_channel(
         .await
         .unwrap()?;
 
-    app.connect(coordinator.info)
+    app.connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()?;
@@ -69,7 +108,7 @@ pub async fn create_dlc_channel(
         .unwrap()?;
 
     coordinator
-        .open_channel(&app, coordinator_ln_balance, app_ln_balance)
+        .open_channel(&app, coordinator_ln_balance, app_ln_balance, true)
         .await_with_timeout()
         .await
         .unwrap()?;
@@ -85,6 +124,8 @@ pub async fn create_dlc_channel(
 
     // Act
 
+    let mut app_dlc_channel_events = app.subscribe_to_dlc_channel_events();
+
     let oracle_pk = app.oracle_pk();
     let contract_input =
         dummy_contract_input(app_dlc_collateral, coordinator_dlc_collateral, oracle_pk);
@@ -166,6 +207,19 @@ pub async fn create_dlc_channel(
 
     matches!(sub_channel_app.state, SubChannelState::Signed(_));
 
+    let signed_event = loop {
+        let event = app_dlc_channel_events.try_recv()?;
+        if event.kind == DlcChannelEventKind::Signed {
+            break event;
+        }
+    };
+    assert_eq!(signed_event.channel_id, sub_channel.channel_id);
+    assert_eq!(signed_event.counterparty, coordinator.info.pubkey);
+
+    let dlc = signed_event.dlc.context("No DLC in signed event")?;
+    assert_eq!(dlc.offer_collateral, app_dlc_collateral);
+    assert_eq!(dlc.accept_collateral, coordinator_dlc_collateral);
+
     Ok(DlcChannelCreated {
         coordinator,
         coordinator_balance_channel_creation,
@@ -27,7 +27,7 @@ async fn reconnecting_during_dlc_channel_setup_leads_to_ln_channel_closure() {
         .unwrap()
         .unwrap();
 
-    app.connect(coordinator.info)
+    app.connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -41,7 +41,7 @@ async fn reconnecting_during_dlc_channel_setup_leads_to_ln_channel_closure() {
         .unwrap();
 
     coordinator
-        .open_channel(&app, 50_000, 50_000)
+        .open_channel(&app, 50_000, 50_000, true)
         .await_with_timeout()
         .await
         .unwrap()
@@ -70,7 +70,7 @@ async fn reconnecting_during_dlc_channel_setup_leads_to_ln_channel_closure() {
         .unwrap();
     coordinator.process_incoming_messages().unwrap();
 
-    app.reconnect(coordinator.info)
+    app.reconnect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -106,7 +106,7 @@ async fn reconnecting_during_dlc_channel_setup_leads_to_ln_channel_closure() {
 
     // This reconnect leads to the channel being force-closed. This issue is tracked here:
     // https://github.com/get10101/10101/issues/352
-    app.reconnect(coordinator.info)
+    app.reconnect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -1,4 +1,6 @@
 mod collaborative_settlement;
-mod create;
+pub mod create;
 mod dlc_setup_with_reconnects;
 mod non_collaborative_settlement;
+mod restart;
+mod storage;
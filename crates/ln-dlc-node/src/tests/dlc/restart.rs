@@ -0,0 +1,371 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::Node;
+use crate::storage::SqliteStorage;
+use crate::storage::STORAGE_FILE;
+use crate::tests::bitcoind;
+use crate::tests::dlc::create::create_dlc_channel;
+use crate::tests::dlc::create::DlcChannelCreated;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use bitcoin::OutPoint;
+use dlc_manager::contract::Contract;
+use dlc_manager::manager::CET_NSEQUENCE;
+use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::Storage;
+use lightning::chain::channelmonitor::ANTI_REORG_DELAY;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_when_app_restarts_then_dlc_funding_output_is_still_watched() {
+    init_tracing();
+
+    // Arrange
+
+    let DlcChannelCreated {
+        coordinator, app, ..
+    } = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let fund_outpoint = signed_contract_fund_outpoint(&app).unwrap();
+
+    // Act
+
+    let app = app
+        .restart("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    let watched_outputs = app.wallet.inner().watched_outputs();
+    assert!(
+        watched_outputs
+            .iter()
+            .any(|output| output.outpoint == fund_outpoint),
+        "DLC funding output is not watched after restart"
+    );
+
+    // A spend of the DLC funding output, e.g. the counterparty publishing a CET, is now picked up
+    // by the sync
+    app.sync().unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_when_cet_is_published_while_app_is_stopped_then_app_detects_closed_dlc()
+{
+    init_tracing();
+
+    // Arrange
+
+    let DlcChannelCreated {
+        coordinator,
+        app,
+        channel_id,
+        ..
+    } = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let fund_outpoint = signed_contract_fund_outpoint(&app).unwrap();
+    let app_data_dir = app.data_dir.clone();
+    let app = app.stop().await;
+
+    // Act
+
+    // Publishes the commitment and the split transaction, whose DLC output funds the CETs
+    coordinator
+        .sub_channel_manager
+        .force_close_sub_channel(&channel_id)
+        .map_err(|e| anyhow!(e.to_string()))
+        .unwrap();
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    coordinator.sync().unwrap();
+
+    // The CETs can only be published once the relative timelock of the split output expired
+    bitcoind::mine(CET_NSEQUENCE as u16)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    coordinator.sync().unwrap();
+    coordinator
+        .sub_channel_manager
+        .finalize_force_close_sub_channels(&channel_id)
+        .map_err(|e| anyhow!(e.to_string()))
+        .unwrap();
+
+    // The event of the contract is already attested, so the coordinator publishes the CET
+    coordinator
+        .dlc_manager
+        .periodic_check()
+        .map_err(|e| anyhow!(e.to_string()))
+        .unwrap();
+
+    // Bury the CET deep enough for the spent DLC funding output to be pruned
+    bitcoind::mine(ANTI_REORG_DELAY as u16)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let app = app
+        .start("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    wait_until(Duration::from_secs(30), || async {
+        app.sync()?;
+        app.dlc_manager
+            .periodic_check()
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let contracts = app
+            .dlc_manager
+            .get_store()
+            .get_contracts()
+            .map_err(|e| anyhow!("{e:#}"))?;
+        let is_closed = contracts
+            .iter()
+            .any(|contract| matches!(contract, Contract::PreClosed(_) | Contract::Closed(_)));
+
+        Ok(is_closed.then_some(()))
+    })
+    .await_with_timeout()
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert!(
+        app.wallet
+            .inner()
+            .watched_outputs()
+            .iter()
+            .all(|output| output.outpoint != fund_outpoint),
+        "Spent DLC funding output is still watched"
+    );
+
+    let storage = SqliteStorage::open(&app_data_dir.join(STORAGE_FILE)).unwrap();
+    let stored_outputs = storage.get_watched_outputs().unwrap();
+    assert!(
+        stored_outputs
+            .iter()
+            .all(|output| output.outpoint != fund_outpoint),
+        "Spent DLC funding output is still stored as watched"
+    );
+}
+
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_when_app_is_restored_from_seed_then_sub_channel_can_be_settled() {
+    init_tracing();
+
+    // Arrange
+
+    let coordinator_dlc_collateral = 25_000;
+    let DlcChannelCreated {
+        coordinator,
+        app,
+        channel_id,
+        ..
+    } = create_dlc_channel(50_000, coordinator_dlc_collateral)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Act
+
+    // The keys manager of the restarted app is derived from the seed again, with a different
+    // starting time
+    let app = app
+        .restart("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Settling the DLC requires signing the transactions of the sub-channel with the keys of the
+    // LN channel and of the DLC
+    app.propose_dlc_channel_collaborative_settlement(&channel_id, coordinator_dlc_collateral / 2)
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(2))
+        .await_with_timeout()
+        .await
+        .unwrap();
+    coordinator.process_incoming_messages().unwrap();
+
+    let sub_channel = wait_until(Duration::from_secs(30), || async {
+        let sub_channel = coordinator
+            .dlc_manager
+            .get_store()
+            .get_sub_channel(channel_id)
+            .map_err(|e| anyhow!("{e:#}"))?;
+
+        Ok(sub_channel
+            .filter(|sub_channel| matches!(sub_channel.state, SubChannelState::CloseOffered(_))))
+    })
+    .await_with_timeout()
+    .await
+    .unwrap()
+    .unwrap();
+
+    coordinator
+        .accept_dlc_channel_collaborative_settlement(&sub_channel.channel_id)
+        .unwrap();
+
+    // Accept, confirm and finalize
+    for node in [&app, &coordinator, &app] {
+        tokio::time::sleep(Duration::from_secs(2))
+            .await_with_timeout()
+            .await
+            .unwrap();
+        node.process_incoming_messages().unwrap();
+    }
+
+    // Assert
+
+    for node in [&app, &coordinator] {
+        let sub_channel = node
+            .dlc_manager
+            .get_store()
+            .get_sub_channel(channel_id)
+            .map_err(|e| anyhow!("{e:#}"))
+            .unwrap()
+            .context("No sub-channel")
+            .unwrap();
+
+        assert!(
+            matches!(sub_channel.state, SubChannelState::OffChainClosed),
+            "Unexpected sub-channel state {:?}",
+            sub_channel.state
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_when_app_is_restored_from_backup_then_dlc_channel_is_force_closed() {
+    init_tracing();
+
+    // Arrange
+
+    let DlcChannelCreated {
+        coordinator,
+        app,
+        channel_id,
+        ..
+    } = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Act
+
+    let app = app
+        .restore_from_backup("restored-app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The restored DLC channel is only force-closed once the coordinator confirms its state
+    let sub_channel = app
+        .dlc_manager
+        .get_store()
+        .get_sub_channel(channel_id)
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .context("DLC channel was not restored")
+        .unwrap();
+    assert!(
+        matches!(sub_channel.state, SubChannelState::Signed(_)),
+        "Unexpected sub-channel state {:?}",
+        sub_channel.state
+    );
+
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    wait_until(Duration::from_secs(60), || async {
+        let sub_channel = app
+            .dlc_manager
+            .get_store()
+            .get_sub_channel(channel_id)
+            .map_err(|e| anyhow!("{e:#}"))?
+            .context("DLC channel was not restored")?;
+
+        Ok(matches!(sub_channel.state, SubChannelState::Closing(_)).then_some(()))
+    })
+    .await
+    .unwrap();
+
+    assert!(
+        app.channel_manager
+            .list_channels()
+            .iter()
+            .all(|channel| channel.channel_id != channel_id),
+        "LN channel restored from backup is still open"
+    );
+
+    // The restored node continues the backup sequence of the backup
+    assert!(app.next_backup_sequence().unwrap() > 0);
+}
+
+/// The outpoint of the DLC funding output of the signed contract of `node`.
+fn signed_contract_fund_outpoint(node: &Node) -> Result<OutPoint> {
+    let contracts = node
+        .dlc_manager
+        .get_store()
+        .get_contracts()
+        .map_err(|e| anyhow!("{e:#}"))?;
+
+    contracts
+        .iter()
+        .find_map(|contract| match contract {
+            Contract::Signed(contract) | Contract::Confirmed(contract) => Some(
+                contract
+                    .accepted_contract
+                    .dlc_transactions
+                    .get_fund_outpoint(),
+            ),
+            _ => None,
+        })
+        .context("No signed contract")
+}
@@ -0,0 +1,205 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::storage::SqliteStorage;
+use crate::storage::STORAGE_FILE;
+use crate::tests::dlc::create::create_dlc_channel;
+use crate::tests::dlc::create::DlcChannelCreated;
+use crate::tests::init_tracing;
+use crate::tests::TempDir;
+use anyhow::anyhow;
+use anyhow::Context;
+use dlc_manager::channel::signed_channel::SignedChannelStateType;
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::Contract;
+use dlc_manager::subchannel::SubChannelState;
+use dlc_manager::Storage;
+
+#[tokio::test]
+#[ignore]
+async fn given_dlc_channel_then_dlc_state_is_stored_under_final_ids() {
+    init_tracing();
+
+    let DlcChannelCreated { app, .. } = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let store = app.dlc_manager.get_store();
+
+    let contracts = store.get_contracts().map_err(|e| anyhow!("{e:#}")).unwrap();
+    assert_eq!(contracts.len(), 1);
+    let contract = &contracts[0];
+    assert!(store
+        .get_contract(&contract.get_temporary_id())
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_none());
+    assert!(store
+        .get_contract_offers()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_empty());
+
+    let channels = store
+        .get_signed_channels(Some(SignedChannelStateType::Established))
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap();
+    assert_eq!(channels.len(), 1);
+    assert!(store
+        .get_channel(&channels[0].temporary_channel_id)
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_none());
+    assert!(store
+        .get_signed_channels(Some(SignedChannelStateType::Settled))
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_empty());
+    assert!(store
+        .get_offered_channels()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_empty());
+
+    let sub_channels = store
+        .get_sub_channels()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap();
+    assert_eq!(sub_channels.len(), 1);
+    assert!(matches!(sub_channels[0].state, SubChannelState::Signed(_)));
+    assert!(store
+        .get_offered_sub_channels()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+#[ignore]
+async fn dlc_state_round_trips_through_sqlite_storage() {
+    init_tracing();
+
+    let DlcChannelCreated { app, .. } = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let store = app.dlc_manager.get_store();
+
+    let signed_contract = store
+        .get_contracts()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .into_iter()
+        .find_map(|contract| match contract {
+            Contract::Signed(contract) | Contract::Confirmed(contract) => Some(contract),
+            _ => None,
+        })
+        .context("No signed contract")
+        .unwrap();
+    let signed_channel = store
+        .get_signed_channels(None)
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .pop()
+        .context("No signed channel")
+        .unwrap();
+    let sub_channel = store
+        .get_sub_channels()
+        .map_err(|e| anyhow!("{e:#}"))
+        .unwrap()
+        .pop()
+        .context("No sub-channel")
+        .unwrap();
+
+    let offered_contract = signed_contract.accepted_contract.offered_contract.clone();
+    let contract_id = signed_contract.accepted_contract.get_contract_id();
+    let channel_id = signed_channel.channel_id;
+
+    let dir = TempDir::new();
+    let path = dir.path().join(STORAGE_FILE);
+    let storage = SqliteStorage::open(&path).unwrap();
+
+    // The offer is stored under its temporary ID until the contract is accepted
+    storage.create_contract(&offered_contract).unwrap();
+    assert_eq!(storage.get_contract_offers().unwrap().len(), 1);
+
+    storage
+        .upsert_channel(
+            Channel::Signed(signed_channel.clone()),
+            Some(Contract::Signed(signed_contract.clone())),
+        )
+        .unwrap();
+    storage.upsert_sub_channel(&sub_channel).unwrap();
+    drop(storage);
+
+    let storage = SqliteStorage::open(&path).unwrap();
+
+    assert!(storage
+        .get_contract(&offered_contract.id)
+        .unwrap()
+        .is_none());
+    assert!(storage.get_contract_offers().unwrap().is_empty());
+    match storage.get_contract(&contract_id).unwrap() {
+        Some(Contract::Signed(contract)) => assert_eq!(
+            contract.serialize().unwrap(),
+            signed_contract.serialize().unwrap()
+        ),
+        _ => panic!("Expected signed contract"),
+    }
+    assert_eq!(storage.get_signed_contracts().unwrap().len(), 1);
+
+    match storage.get_channel(&channel_id).unwrap() {
+        Some(Channel::Signed(channel)) => assert_eq!(
+            channel.serialize().unwrap(),
+            signed_channel.serialize().unwrap()
+        ),
+        _ => panic!("Expected signed channel"),
+    }
+    assert_eq!(
+        storage
+            .get_signed_channels(Some(signed_channel.state.get_type()))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(storage
+        .get_signed_channels(Some(SignedChannelStateType::Closing))
+        .unwrap()
+        .is_empty());
+
+    let stored_sub_channel = storage
+        .get_sub_channel(sub_channel.channel_id)
+        .unwrap()
+        .context("No stored sub-channel")
+        .unwrap();
+    assert_eq!(
+        stored_sub_channel.serialize().unwrap(),
+        sub_channel.serialize().unwrap()
+    );
+
+    // Contracts sharing an encoding are told apart by the state they are stored with
+    storage
+        .update_contract(&Contract::Confirmed(signed_contract.clone()))
+        .unwrap();
+    assert!(storage.get_signed_contracts().unwrap().is_empty());
+    assert_eq!(storage.get_confirmed_contracts().unwrap().len(), 1);
+
+    storage
+        .update_contract(&Contract::Refunded(signed_contract))
+        .unwrap();
+    assert!(storage.get_confirmed_contracts().unwrap().is_empty());
+    assert!(matches!(
+        storage.get_contract(&contract_id).unwrap(),
+        Some(Contract::Refunded(_))
+    ));
+
+    storage
+        .update_contract(&Contract::Rejected(offered_contract.clone()))
+        .unwrap();
+    assert!(storage.get_contract_offers().unwrap().is_empty());
+    assert!(matches!(
+        storage.get_contract(&offered_contract.id).unwrap(),
+        Some(Contract::Rejected(_))
+    ));
+}
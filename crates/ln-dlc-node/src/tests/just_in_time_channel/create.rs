@@ -1,7 +1,7 @@
 use crate::await_with_timeout::AwaitWithTimeout;
 use crate::ln::JUST_IN_TIME_CHANNEL_OUTBOUND_LIQUIDITY_SAT;
+use crate::node::JitFeePolicy;
 use crate::node::Node;
-use crate::node::LIQUIDITY_ROUTING_FEE_MILLIONTHS;
 use crate::tests::init_tracing;
 use crate::tests::min_outbound_liquidity_channel_creator;
 use anyhow::Context;
@@ -33,13 +33,13 @@ async fn just_in_time_channel() {
         .unwrap();
 
     payer
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
         .unwrap();
     payee
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -61,6 +61,7 @@ async fn just_in_time_channel() {
             &payer,
             coordinator_outbound_liquidity_sat,
             payer_outbound_liquidity_sat,
+            true,
         )
         .await_with_timeout()
         .await
@@ -120,13 +121,17 @@ pub(crate) async fn send_interceptable_payment(
     let coordinator_balance_before = coordinator.get_ldk_balance();
     let payee_balance_before = payee.get_ldk_balance();
 
+    // The payee accepts the just-in-time channel from the coordinator without confirmations
+    payee.add_trusted_peer(coordinator.info.pubkey);
+
     // Act
 
-    let intercept_scid = coordinator.create_intercept_scid(payee.info.pubkey);
+    let fee_policy = JitFeePolicy::default();
+    let intercept_scid = coordinator.create_intercept_scid(payee.info.pubkey, fee_policy)?;
 
-    let flat_routing_fee = 1; // according to the default `ChannelConfig`
+    let flat_routing_fee = fee_policy.base_msat as u64 / 1000;
     let liquidity_routing_fee =
-        (invoice_amount * LIQUIDITY_ROUTING_FEE_MILLIONTHS as u64) / 1_000_000;
+        (invoice_amount * fee_policy.proportional_millionths as u64) / 1_000_000;
 
     assert!(
         does_inbound_htlc_fit_as_percent_of_channel(
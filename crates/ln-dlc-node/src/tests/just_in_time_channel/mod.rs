@@ -1,2 +1,4 @@
 mod create;
+mod multi_part_payment;
 mod multiple_payments;
+mod underpaid_fee;
@@ -0,0 +1,110 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::JitFeePolicy;
+use crate::node::Node;
+use crate::tests::init_tracing;
+use crate::tests::min_outbound_liquidity_channel_creator;
+use crate::tests::wait_until;
+use bitcoin::Amount;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn just_in_time_channel_is_sized_for_all_parts_of_a_payment() {
+    init_tracing();
+
+    // Arrange
+
+    let payer = Node::start_test_app("payer")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let payee = Node::start_test_app("payee")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    payer
+        .connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    payee
+        .connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    coordinator
+        .fund(Amount::from_sat(200_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Neither of the payer's channels can carry the payment on its own, so it has to be split
+    let payer_outbound_liquidity_sat = 10_000;
+    for _ in 0..2 {
+        coordinator
+            .open_channel(
+                &payer,
+                min_outbound_liquidity_channel_creator(&payer, payer_outbound_liquidity_sat),
+                payer_outbound_liquidity_sat,
+                true,
+            )
+            .await_with_timeout()
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    wait_until(Duration::from_secs(30), || async {
+        Ok((payer.list_usable_channels().len() == 2).then_some(()))
+    })
+    .await
+    .unwrap();
+
+    payee.add_trusted_peer(coordinator.info.pubkey);
+
+    // Act
+
+    let invoice_amount = 15_000;
+    let intercept_scid = coordinator
+        .create_intercept_scid(payee.info.pubkey, JitFeePolicy::default())
+        .unwrap();
+    let invoice = payee
+        .create_interceptable_invoice(
+            Some(invoice_amount),
+            intercept_scid,
+            coordinator.info.pubkey,
+            0,
+            "interceptable-invoice".to_string(),
+        )
+        .unwrap();
+
+    payer.send_payment(&invoice).unwrap();
+
+    payee
+        .wait_for_payment_claimed(invoice.payment_hash())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    let channels = payee.list_usable_channels();
+    assert_eq!(channels.len(), 1);
+    assert!(
+        channels[0].channel_value_satoshis >= 2 * invoice_amount,
+        "Just-in-time channel of {} sats was not sized for the whole payment",
+        channels[0].channel_value_satoshis
+    );
+}
@@ -30,13 +30,13 @@ async fn just_in_time_channel_with_multiple_payments() {
         .unwrap();
 
     user_a
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
         .unwrap();
     user_b
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -58,6 +58,7 @@ async fn just_in_time_channel_with_multiple_payments() {
             &user_a,
             coordinator_outbound_liquidity_sat,
             payer_outbound_liquidity_sat,
+            true,
         )
         .await_with_timeout()
         .await
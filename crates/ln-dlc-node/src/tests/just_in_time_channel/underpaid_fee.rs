@@ -0,0 +1,103 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::InterceptScid;
+use crate::node::JitFeePolicy;
+use crate::node::Node;
+use crate::tests::init_tracing;
+use crate::tests::min_outbound_liquidity_channel_creator;
+use bitcoin::Amount;
+
+#[tokio::test]
+#[ignore]
+async fn just_in_time_channel_fails_if_fee_is_underpaid() {
+    init_tracing();
+
+    // Arrange
+
+    let payer = Node::start_test_app("payer")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let payee = Node::start_test_app("payee")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    payer
+        .connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    payee
+        .connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    coordinator
+        .fund(Amount::from_sat(100_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let payer_outbound_liquidity_sat = 25_000;
+    coordinator
+        .open_channel(
+            &payer,
+            min_outbound_liquidity_channel_creator(&payer, payer_outbound_liquidity_sat),
+            payer_outbound_liquidity_sat,
+            true,
+        )
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let intercept_scid = coordinator
+        .create_intercept_scid(payee.info.pubkey, JitFeePolicy::default())
+        .unwrap();
+
+    // Act
+
+    // The payee advertises lower fees than the coordinator demands
+    let invoice = payee
+        .create_interceptable_invoice(
+            Some(1_000),
+            InterceptScid {
+                fee_policy: JitFeePolicy {
+                    base_msat: 0,
+                    proportional_millionths: 0,
+                },
+                ..intercept_scid
+            },
+            coordinator.info.pubkey,
+            0,
+            "underpaid-invoice".to_string(),
+        )
+        .unwrap();
+
+    payer.send_payment(&invoice).unwrap();
+
+    // Assert
+
+    let claimed = payee
+        .wait_for_payment_claimed(invoice.payment_hash())
+        .await_with_timeout()
+        .await
+        .unwrap();
+
+    assert!(claimed.is_err(), "Underpaid payment should not be claimed");
+    assert!(
+        payee.list_channels().is_empty(),
+        "No just-in-time channel should be opened for an underpaid payment"
+    );
+}
@@ -0,0 +1,135 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::LeaseStatus;
+use crate::node::Node;
+use crate::tests::init_tracing;
+use crate::tests::min_outbound_liquidity_channel_creator;
+use crate::tests::wait_until;
+use bitcoin::hashes::sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::Amount;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn leased_liquidity_is_provided_by_upgrading_the_channel() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    coordinator
+        .fund(Amount::from_sat(200_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The app needs some outbound liquidity to pay for the lease
+    let app_outbound_liquidity_sat = 5_000;
+    let old_channel = coordinator
+        .open_channel(
+            &app,
+            min_outbound_liquidity_channel_creator(&app, app_outbound_liquidity_sat),
+            app_outbound_liquidity_sat,
+            true,
+        )
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let leased_inbound_liquidity_sat = 50_000;
+    let (lease_invoice, _) = coordinator
+        .create_liquidity_lease(
+            app.info.pubkey,
+            leased_inbound_liquidity_sat,
+            500,
+            Duration::from_secs(60 * 60),
+        )
+        .unwrap();
+
+    app.send_payment(&lease_invoice).unwrap();
+    app.wait_for_payment_sent(lease_invoice.payment_hash())
+        .await
+        .unwrap();
+
+    // Act
+
+    let old_channel = app
+        .list_usable_channels()
+        .into_iter()
+        .find(|channel| channel.channel_id == old_channel.channel_id)
+        .unwrap();
+
+    // The replacement channel has to include the leased liquidity
+    assert!(coordinator
+        .open_replacement_channel(
+            app.info.pubkey,
+            old_channel.channel_value_satoshis + leased_inbound_liquidity_sat - 1
+        )
+        .is_err());
+
+    coordinator
+        .open_replacement_channel(
+            app.info.pubkey,
+            old_channel.channel_value_satoshis + leased_inbound_liquidity_sat,
+        )
+        .unwrap();
+
+    let new_channel = wait_until(Duration::from_secs(30), || async {
+        Ok(app
+            .list_usable_channels()
+            .into_iter()
+            .find(|channel| channel.channel_id != old_channel.channel_id))
+    })
+    .await
+    .unwrap();
+
+    let payment_hash = app
+        .move_balance_to_replacement_channel(old_channel.channel_id, new_channel.channel_id)
+        .unwrap();
+
+    app.wait_for_payment_claimed(&sha256::Hash::from_inner(payment_hash.0))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.close_channel(old_channel.channel_id).unwrap();
+
+    wait_until(Duration::from_secs(30), || async {
+        let channels = app.list_channels();
+        Ok((channels.len() == 1).then_some(()))
+    })
+    .await
+    .unwrap();
+
+    // Assert
+
+    let channels = app.list_usable_channels();
+    assert_eq!(channels.len(), 1);
+    assert!(
+        channels[0].inbound_capacity_msat >= leased_inbound_liquidity_sat * 1000,
+        "Leased inbound liquidity was not provided"
+    );
+
+    let leases = coordinator.list_liquidity_leases().unwrap();
+    assert_eq!(leases.len(), 1);
+    assert!(matches!(leases[0].status, LeaseStatus::Active { .. }));
+}
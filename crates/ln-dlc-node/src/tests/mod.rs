@@ -1,11 +1,13 @@
 use crate::await_with_timeout::AwaitWithTimeout;
 use crate::ln::app_config;
 use crate::ln::coordinator_config;
+use crate::node::ChainSource;
 use crate::node::Node;
 use crate::node::NodeInfo;
 use crate::seed::Bip39Seed;
 use crate::util;
 use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Result;
 use bitcoin::Address;
 use bitcoin::Amount;
@@ -34,20 +36,28 @@ use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use std::env::temp_dir;
 use std::net::TcpListener;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Once;
 use std::time::Duration;
 
 mod bitcoind;
+mod channel_upgrade;
 mod dlc;
 mod just_in_time_channel;
+mod leased_liquidity;
 mod lnd;
 mod multi_hop_payment;
+mod on_chain_send;
 mod onboard_from_lnd;
+mod persistent_peers;
+mod reorg;
 mod single_hop_payment;
+mod zero_conf;
 
 const ELECTRS_ORIGIN: &str = "tcp://localhost:50000";
 const FAUCET_ORIGIN: &str = "http://localhost:8080";
+const SEED_FILE: &str = "seed";
 
 fn init_tracing() {
     static TRACING_TEST_SUBSCRIBER: Once = Once::new();
@@ -79,9 +89,65 @@ impl Node {
 
     async fn start_test(name: &str, user_config: UserConfig) -> Result<Self> {
         let data_dir = random_tmp_dir().join(name);
+        std::fs::create_dir_all(&data_dir)?;
 
-        let seed = Bip39Seed::new().expect("A valid bip39 seed");
+        // Keep the seed around, so that the node can be restarted
+        let seed = Bip39Seed::initialize(&data_dir.join(SEED_FILE))?;
 
+        Self::start_test_in(name, data_dir, seed, user_config).await
+    }
+
+    /// Stops the node and starts it again from its data dir.
+    async fn restart(self, name: &str) -> Result<Self> {
+        self.stop().await.start(name).await
+    }
+
+    /// Stops the node, keeping its data dir around so that it can be started again.
+    async fn stop(self) -> StoppedNode {
+        let data_dir = self.data_dir.clone();
+        let user_config = self.user_config;
+        drop(self);
+
+        // Give the background tasks of the stopped node a moment to wind down
+        tokio::time::sleep(Duration::from_secs(1))
+            .await_with_timeout()
+            .await
+            .unwrap();
+
+        StoppedNode {
+            data_dir,
+            user_config,
+        }
+    }
+
+    /// Backs up the node, stops it and restores the backup into a new node with the same seed, as
+    /// if on a new device.
+    async fn restore_from_backup(self, name: &str) -> Result<Self> {
+        let backup = self.create_backup()?;
+        let sequence = self.next_backup_sequence()?;
+        let seed = Bip39Seed::initialize(&self.data_dir.join(SEED_FILE))?;
+        let user_config = self.user_config;
+        drop(self);
+
+        // Give the background tasks of the stopped node a moment to wind down
+        tokio::time::sleep(Duration::from_secs(1))
+            .await_with_timeout()
+            .await
+            .unwrap();
+
+        let data_dir = random_tmp_dir().join(name);
+        std::fs::create_dir_all(&data_dir)?;
+        crate::backup::restore(&data_dir, &seed, &backup, sequence)?;
+
+        Self::start_test_in(name, data_dir, seed, user_config).await
+    }
+
+    async fn start_test_in(
+        name: &str,
+        data_dir: PathBuf,
+        seed: Bip39Seed,
+        user_config: UserConfig,
+    ) -> Result<Self> {
         let mut ephemeral_randomness = [0; 32];
         thread_rng().fill_bytes(&mut ephemeral_randomness);
 
@@ -97,10 +163,14 @@ impl Node {
             address,
             address,
             vec![util::build_net_address(address.ip(), address.port())],
-            ELECTRS_ORIGIN.to_string(),
+            ChainSource::Electrum {
+                url: ELECTRS_ORIGIN.to_string(),
+            },
             seed,
             ephemeral_randomness,
             user_config,
+            vec![],
+            None,
         )
         .await_with_timeout()
         .await
@@ -151,19 +221,30 @@ impl Node {
 
     /// Initiates the opening of a channel _and_ waits for the channel
     /// to be usable.
+    ///
+    /// If `zero_conf` is set, `peer` is made to trust us, so that it accepts the channel without
+    /// confirmations. Otherwise we mine as many blocks as `peer` requires.
     async fn open_channel(
         &self,
         peer: &Node,
         amount_us: u64,
         amount_them: u64,
+        zero_conf: bool,
     ) -> Result<ChannelDetails> {
+        if zero_conf {
+            // Only peers which manually accept inbound channels can accept 0-conf channels
+            ensure!(
+                peer.user_config.manually_accept_inbound_channels,
+                "Peer cannot accept 0-conf channels"
+            );
+
+            peer.add_trusted_peer(self.info.pubkey);
+        }
+
         let temp_channel_id =
-            self.initiate_open_channel(peer.info, amount_us + amount_them, amount_them)?;
+            self.initiate_open_channel(peer.info.clone(), amount_us + amount_them, amount_them)?;
 
-        // The config flag
-        // `user_config.manually_accept_inbound_channels` implies that
-        // the peer will accept 0-conf channels
-        if !peer.user_config.manually_accept_inbound_channels {
+        if !zero_conf {
             let required_confirmations = peer.user_config.channel_handshake_config.minimum_depth;
 
             bitcoind::mine(required_confirmations as u16)
@@ -183,8 +264,8 @@ impl Node {
                     break details.clone();
                 }
 
-                // Only sync if 0-conf channels are disabled
-                if !peer.user_config.manually_accept_inbound_channels {
+                // Only sync if the channel is not 0-conf
+                if !zero_conf {
                     // We need to sync both parties, even if
                     // `trust_own_funding_0conf` is true for the creator
                     // of the channel (`self`)
@@ -215,7 +296,7 @@ impl Node {
     }
 
     pub async fn reconnect(&self, peer: NodeInfo) -> Result<()> {
-        self.disconnect(peer);
+        self.disconnect(peer.clone());
         tokio::time::sleep(Duration::from_secs(1))
             .await_with_timeout()
             .await
@@ -225,6 +306,21 @@ impl Node {
     }
 }
 
+/// A test node which was stopped, e.g. to make something happen while it is offline.
+struct StoppedNode {
+    data_dir: PathBuf,
+    user_config: UserConfig,
+}
+
+impl StoppedNode {
+    /// Starts the node again from its data dir.
+    async fn start(self, name: &str) -> Result<Node> {
+        let seed = Bip39Seed::initialize(&self.data_dir.join(SEED_FILE))?;
+
+        Node::start_test_in(name, self.data_dir, seed, self.user_config).await
+    }
+}
+
 async fn fund_and_mine(address: Address, amount: Amount) -> Result<()> {
     bitcoind::fund(address.to_string(), amount)
         .await_with_timeout()
@@ -292,6 +388,30 @@ fn random_tmp_dir() -> PathBuf {
     tmp
 }
 
+/// A temporary directory for unit tests, which is removed again when dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        let dir = random_tmp_dir();
+        std::fs::create_dir_all(&dir).expect("to create temporary directory");
+
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!(path = ?self.0, "Failed to remove temporary directory: {e}");
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn log_channel_id(node: &Node, index: usize, pair: &str) {
     let details = match node.channel_manager.list_channels().get(index) {
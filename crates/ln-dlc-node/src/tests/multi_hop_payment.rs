@@ -28,13 +28,13 @@ async fn multi_hop_payment() {
         .unwrap();
 
     payer
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
         .unwrap();
     payee
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -55,6 +55,7 @@ async fn multi_hop_payment() {
             &payer,
             coordinator_outbound_liquidity_sat,
             payer_outbound_liquidity_sat,
+            true,
         )
         .await_with_timeout()
         .await
@@ -62,7 +63,7 @@ async fn multi_hop_payment() {
         .unwrap();
 
     coordinator
-        .open_channel(&payee, 20_000, 0)
+        .open_channel(&payee, 20_000, 0, true)
         .await_with_timeout()
         .await
         .unwrap()
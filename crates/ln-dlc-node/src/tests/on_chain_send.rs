@@ -0,0 +1,154 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::Fee;
+use crate::node::Node;
+use crate::node::SendAmount;
+use crate::tests::bitcoind;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use bitcoin::Amount;
+use bitcoin::OutPoint;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn can_send_and_bump_fee_of_on_chain_transaction() {
+    init_tracing();
+
+    // Arrange
+
+    let sender = Node::start_test_app("sender")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let receiver = Node::start_test_app("receiver")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    sender
+        .fund(Amount::from_sat(100_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let address = receiver.get_new_address().unwrap();
+
+    // Act
+
+    let txid = sender
+        .send_to_address(
+            &address,
+            SendAmount::Sats(50_000),
+            Fee::SatsPerVbyte(1.0),
+            &[],
+        )
+        .unwrap();
+
+    let replacement_txid = sender.bump_fee(txid, Fee::SatsPerVbyte(5.0)).unwrap();
+
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    assert_ne!(txid, replacement_txid);
+
+    wait_until(Duration::from_secs(30), || async {
+        receiver.sync()?;
+        let balance = receiver.get_on_chain_balance()?;
+
+        Ok((balance.confirmed == 50_000).then_some(()))
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn can_drain_selected_utxo_and_bump_it_via_cpfp() {
+    init_tracing();
+
+    // Arrange
+
+    let sender = Node::start_test_app("sender")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let receiver = Node::start_test_app("receiver")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    sender
+        .fund(Amount::from_sat(100_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    sender
+        .fund(Amount::from_sat(20_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let utxo = sender
+        .list_utxos()
+        .unwrap()
+        .into_iter()
+        .find(|utxo| utxo.amount_sats == 20_000)
+        .unwrap();
+
+    let address = receiver.get_new_address().unwrap();
+
+    // Act
+
+    let txid = sender
+        .send_to_address(
+            &address,
+            SendAmount::All,
+            Fee::SatsPerVbyte(1.0),
+            &[utxo.outpoint],
+        )
+        .unwrap();
+
+    receiver.sync().unwrap();
+    let child_txid = receiver
+        .cpfp(OutPoint { txid, vout: 0 }, Fee::SatsPerVbyte(10.0))
+        .unwrap();
+
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    assert_ne!(txid, child_txid);
+
+    // The other UTXO of the sender was not spent
+    sender.sync().unwrap();
+    assert!(sender
+        .list_utxos()
+        .unwrap()
+        .iter()
+        .any(|utxo| utxo.amount_sats == 100_000));
+
+    wait_until(Duration::from_secs(30), || async {
+        receiver.sync()?;
+        let balance = receiver.get_on_chain_balance()?;
+
+        Ok((balance.confirmed > 0 && balance.untrusted_pending == 0).then_some(()))
+    })
+    .await
+    .unwrap();
+}
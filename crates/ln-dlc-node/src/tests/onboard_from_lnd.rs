@@ -1,4 +1,5 @@
 use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::JitFeePolicy;
 use crate::node::Node;
 use crate::tests::init_tracing;
 use crate::tests::lnd::LndNode;
@@ -24,12 +25,15 @@ async fn onboard_from_lnd() {
     let payer = LndNode::new();
 
     payee
-        .connect(coordinator.info)
+        .connect(coordinator.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
         .unwrap();
 
+    // The payee accepts the just-in-time channel from the coordinator without confirmations
+    payee.add_trusted_peer(coordinator.info.pubkey);
+
     // Fund the on-chain wallets of the nodes who will open a channel
     coordinator
         .fund(Amount::from_sat(100_000))
@@ -58,7 +62,9 @@ async fn onboard_from_lnd() {
 
     let invoice_amount = 1000;
 
-    let fake_scid = coordinator.create_intercept_scid(payee.info.pubkey);
+    let fake_scid = coordinator
+        .create_intercept_scid(payee.info.pubkey, JitFeePolicy::default())
+        .unwrap();
     let invoice = payee
         .create_interceptable_invoice(
             Some(invoice_amount),
@@ -0,0 +1,217 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::ConnectionState;
+use crate::node::Node;
+use crate::node::NodeInfo;
+use crate::node::PeerEvent;
+use crate::tests::bitcoind;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use anyhow::anyhow;
+use bitcoin::Amount;
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn app_reconnects_to_persistent_peer_after_disconnect() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut peer_events = app.subscribe_to_peer_events();
+
+    // Act
+
+    app.keep_connected(coordinator.info.clone()).unwrap();
+
+    // Assert
+
+    let is_connected = || async {
+        let connected = app.persistent_peers().iter().any(|(peer, state)| {
+            peer.pubkey == coordinator.info.pubkey && *state == ConnectionState::Connected
+        });
+
+        Ok(connected.then_some(()))
+    };
+
+    wait_until(Duration::from_secs(30), is_connected)
+        .await
+        .unwrap();
+    assert!(matches!(
+        peer_events.recv().await_with_timeout().await.unwrap().unwrap(),
+        PeerEvent::Connected(peer) if peer.pubkey == coordinator.info.pubkey
+    ));
+
+    coordinator.disconnect(app.info.clone());
+
+    assert!(matches!(
+        peer_events.recv().await_with_timeout().await.unwrap().unwrap(),
+        PeerEvent::Disconnected(peer) if peer.pubkey == coordinator.info.pubkey
+    ));
+    assert!(matches!(
+        peer_events.recv().await_with_timeout().await.unwrap().unwrap(),
+        PeerEvent::Connected(peer) if peer.pubkey == coordinator.info.pubkey
+    ));
+}
+
+#[tokio::test]
+#[ignore]
+async fn app_backs_off_while_persistent_peer_is_unreachable() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Nobody listens on a port we just released
+    let unreachable_address = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let unreachable_coordinator = NodeInfo {
+        pubkey: coordinator.info.pubkey,
+        address: unreachable_address.to_string().parse().unwrap(),
+    };
+
+    // Act
+
+    app.keep_connected(unreachable_coordinator).unwrap();
+
+    // Assert
+
+    // With the delay doubling after every failed attempt, the third attempt fails after about 3
+    // seconds
+    wait_until(Duration::from_secs(20), || async {
+        let failed_attempts = app
+            .persistent_peers()
+            .iter()
+            .find_map(|(peer, state)| match state {
+                ConnectionState::Connecting { failed_attempts }
+                    if peer.pubkey == coordinator.info.pubkey =>
+                {
+                    Some(*failed_attempts)
+                }
+                _ => None,
+            });
+
+        Ok(failed_attempts
+            .filter(|failed_attempts| *failed_attempts >= 3)
+            .map(|_| ()))
+    })
+    .await
+    .unwrap();
+
+    // Learning about the right address restarts the attempts without waiting for the backoff
+    app.keep_connected(coordinator.info.clone()).unwrap();
+
+    wait_until(Duration::from_secs(10), || async {
+        let connected = app.persistent_peers().iter().any(|(peer, state)| {
+            peer.pubkey == coordinator.info.pubkey && *state == ConnectionState::Connected
+        });
+
+        Ok(connected.then_some(()))
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn coordinator_reconnects_to_channel_peer_after_restart() {
+    init_tracing();
+
+    // Arrange
+
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let peer = Node::start_test_coordinator("peer")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    coordinator
+        .fund(Amount::from_sat(200_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    coordinator
+        .connect(peer.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Only the peers of public channels are in the network graph, which takes 6 confirmations
+    coordinator
+        .channel_manager
+        .create_channel(peer.info.pubkey, 100_000, 0, 0, None)
+        .map_err(|e| anyhow!("{e:?}"))
+        .unwrap();
+    bitcoind::mine(6)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The peer announces its address periodically
+    wait_until(Duration::from_secs(120), || async {
+        coordinator.sync()?;
+        peer.sync()?;
+
+        let is_announced = coordinator
+            .announced_channel_peers()
+            .iter()
+            .any(|announced| announced.pubkey == peer.info.pubkey);
+
+        Ok(is_announced.then_some(()))
+    })
+    .await
+    .unwrap();
+
+    // Act
+
+    let coordinator = coordinator
+        .restart("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    coordinator.keep_connected_to_channel_peers().unwrap();
+
+    // Assert
+
+    wait_until(Duration::from_secs(30), || async {
+        let connected = coordinator.persistent_peers().iter().any(|(known, state)| {
+            known.pubkey == peer.info.pubkey && *state == ConnectionState::Connected
+        });
+
+        Ok(connected.then_some(()))
+    })
+    .await
+    .unwrap();
+}
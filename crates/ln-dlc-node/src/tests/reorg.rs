@@ -0,0 +1,183 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::Node;
+use crate::tests::bitcoind;
+use crate::tests::dlc::create::create_dlc_channel;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use bitcoin::Amount;
+use bitcoin::BlockHash;
+use bitcoin::Txid;
+use dlc_manager::subchannel::SubChannelState;
+use lightning::chain::Confirm;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn channel_follows_reorg_of_funding_transaction() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let coordinator = Node::start_test_coordinator("coordinator")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.connect(coordinator.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    app.fund(Amount::from_sat(100_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The coordinator does not accept 0-conf channels from the app, so the funding transaction
+    // gets confirmed
+    let channel = app
+        .open_channel(&coordinator, 50_000, 0, false)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let funding_txid = channel.funding_txo.unwrap().txid;
+
+    let funding_block = wait_until_funding_confirmed(&app, funding_txid, None).await;
+
+    // Act
+
+    bitcoind::invalidate_block(funding_block)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    wait_until(Duration::from_secs(30), || async {
+        app.sync()?;
+        Ok(confirmation_block(&app, funding_txid)
+            .is_none()
+            .then_some(()))
+    })
+    .await
+    .expect("funding transaction to be unconfirmed after reorg");
+
+    // The funding transaction is back in the mempool and gets confirmed again
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let new_funding_block =
+        wait_until_funding_confirmed(&app, funding_txid, Some(funding_block)).await;
+
+    assert_ne!(funding_block, new_funding_block);
+    assert!(app
+        .channel_manager
+        .list_channels()
+        .iter()
+        .any(|c| c.channel_id == channel.channel_id));
+}
+
+#[tokio::test]
+#[ignore]
+async fn dlc_channel_survives_reorg_of_funding_transaction() {
+    init_tracing();
+
+    // Arrange
+
+    let dlc_channel = create_dlc_channel(50_000, 25_000)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let app = dlc_channel.app;
+    let coordinator = dlc_channel.coordinator;
+
+    let funding_txid = app
+        .channel_manager
+        .list_channels()
+        .iter()
+        .find(|c| c.channel_id == dlc_channel.channel_id)
+        .and_then(|c| c.funding_txo)
+        .unwrap()
+        .txid;
+
+    // The LN channel was opened as a 0-conf channel
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let funding_block = wait_until_funding_confirmed(&app, funding_txid, None).await;
+
+    // Act
+
+    bitcoind::invalidate_block(funding_block)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    bitcoind::mine(2)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Assert
+
+    wait_until_funding_confirmed(&app, funding_txid, Some(funding_block)).await;
+    coordinator.sync().unwrap();
+
+    for node in [&app, &coordinator] {
+        let sub_channel = node
+            .list_dlc_channels()
+            .unwrap()
+            .into_iter()
+            .find(|sub_channel| sub_channel.channel_id == dlc_channel.channel_id)
+            .unwrap();
+
+        assert!(matches!(sub_channel.state, SubChannelState::Signed(_)));
+    }
+
+    assert!(app
+        .channel_manager
+        .list_usable_channels()
+        .iter()
+        .any(|c| c.channel_id == dlc_channel.channel_id));
+}
+
+/// The block in which `node` considers `txid` to be confirmed, if any.
+fn confirmation_block(node: &Node, txid: Txid) -> Option<BlockHash> {
+    node.channel_manager
+        .get_relevant_txids()
+        .into_iter()
+        .find(|(relevant_txid, _)| *relevant_txid == txid)
+        .and_then(|(_, block_hash)| block_hash)
+}
+
+/// Waits until `node` considers the funding transaction `txid` to be confirmed in a block other
+/// than `stale_block`.
+async fn wait_until_funding_confirmed(
+    node: &Node,
+    txid: Txid,
+    stale_block: Option<BlockHash>,
+) -> BlockHash {
+    wait_until(Duration::from_secs(30), || async {
+        node.sync()?;
+        Ok(confirmation_block(node, txid).filter(|block| Some(*block) != stale_block))
+    })
+    .await
+    .expect("funding transaction to be confirmed")
+}
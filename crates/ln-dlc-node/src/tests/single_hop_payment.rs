@@ -22,7 +22,7 @@ async fn single_hop_payment() {
         .unwrap();
 
     payer
-        .connect(payee.info)
+        .connect(payee.info.clone())
         .await_with_timeout()
         .await
         .unwrap()
@@ -36,7 +36,7 @@ async fn single_hop_payment() {
         .unwrap();
 
     payer
-        .open_channel(&payee, 30_000, 0)
+        .open_channel(&payee, 30_000, 0, true)
         .await_with_timeout()
         .await
         .unwrap()
@@ -0,0 +1,88 @@
+use crate::await_with_timeout::AwaitWithTimeout;
+use crate::node::Node;
+use crate::tests::bitcoind;
+use crate::tests::init_tracing;
+use crate::tests::wait_until;
+use bitcoin::Amount;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore]
+async fn untrusted_peer_cannot_open_zero_conf_channel() {
+    init_tracing();
+
+    // Arrange
+
+    let app = Node::start_test_app("app")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+    let untrusted = Node::start_test_coordinator("untrusted")
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    untrusted
+        .connect(app.info.clone())
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    untrusted
+        .fund(Amount::from_sat(100_000))
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(!app.is_trusted_peer(&untrusted.info.pubkey));
+
+    // Act
+
+    // The untrusted peer trusts its own funding, so it would consider the channel usable without
+    // confirmations if the app accepted it as a 0-conf channel
+    untrusted
+        .initiate_open_channel(app.info.clone(), 30_000, 0)
+        .unwrap();
+
+    // Assert
+
+    let channel = wait_until(Duration::from_secs(30), || async {
+        Ok(app
+            .list_channels()
+            .into_iter()
+            .find(|channel| channel.counterparty.node_id == untrusted.info.pubkey)
+            .filter(|channel| channel.funding_txo.is_some()))
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(channel.confirmations_required, Some(1));
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert!(
+        app.list_usable_channels().is_empty(),
+        "Channel from untrusted peer must not be usable without confirmations"
+    );
+
+    bitcoind::mine(1)
+        .await_with_timeout()
+        .await
+        .unwrap()
+        .unwrap();
+
+    wait_until(Duration::from_secs(30), || async {
+        app.sync()?;
+        untrusted.sync()?;
+
+        Ok(app
+            .list_usable_channels()
+            .into_iter()
+            .find(|channel| channel.counterparty.node_id == untrusted.info.pubkey))
+    })
+    .await
+    .unwrap();
+}
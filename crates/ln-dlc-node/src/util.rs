@@ -1,5 +1,6 @@
 use lightning::ln::msgs::NetAddress;
 use std::net::IpAddr;
+use std::time::SystemTime;
 
 #[inline]
 pub fn hex_str(value: &[u8]) -> String {
@@ -24,3 +25,10 @@ pub fn build_net_address(ip: IpAddr, port: u16) -> NetAddress {
         },
     }
 }
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("current time to be after unix epoch")
+        .as_secs()
+}
@@ -25,7 +25,7 @@ async fn main() -> Result<Never> {
 
     loop {
         let mut stream =
-            orderbook_client::subscribe_with_authentication(url.clone(), &authenticate);
+            orderbook_client::subscribe_with_authentication(url.clone(), &authenticate, None);
 
         loop {
             match stream.try_next().await {
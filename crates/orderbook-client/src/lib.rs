@@ -10,36 +10,49 @@ use orderbook_commons::Signature;
 use secp256k1::Message;
 use serde::Serialize;
 use serde_json::to_string;
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
 use tokio_tungstenite::tungstenite;
+use url::Url;
 
 /// Connects to the 10101 orderbook websocket API
 ///
 /// If the connection needs authentication please use `subscribe_with_authentication` instead.
 pub fn subscribe(url: String) -> impl Stream<Item = Result<String, Error>> + Unpin {
-    subscribe_impl(None, url)
+    subscribe_impl(None, url, None)
 }
 
 /// Connects to the orderbook websocket API with authentication
 ///
-/// It subscribes and yields all messages.
+/// It subscribes and yields all messages. If a `socks5_proxy` is set, we connect through it, e.g.
+/// to reach an orderbook at an onion address.
 pub fn subscribe_with_authentication(
     url: String,
     authenticate: impl Fn(Message) -> Signature,
+    socks5_proxy: Option<SocketAddr>,
 ) -> impl Stream<Item = Result<String, Error>> + Unpin {
     let signature = authenticate(create_sign_message());
-    subscribe_impl(Some(signature), url)
+    subscribe_impl(Some(signature), url, socks5_proxy)
 }
 
 /// Connects to the orderbook websocket API yields all messages.
 fn subscribe_impl(
     signature: Option<Signature>,
     url: String,
+    socks5_proxy: Option<SocketAddr>,
 ) -> impl Stream<Item = Result<String, Error>> + Unpin {
     let stream = stream! {
         tracing::debug!("Connecting to orderbook API");
 
-        let (mut connection, _) = tokio_tungstenite::connect_async(url.clone())
-            .await.context("Could not connect to websocket")?;
+        let (mut connection, _) = match socks5_proxy {
+            Some(proxy) => {
+                let stream = connect_through_proxy(proxy, &url).await?;
+                tokio_tungstenite::client_async_tls(url.clone(), stream).await
+            }
+            None => tokio_tungstenite::connect_async(url.clone()).await,
+        }
+        .context("Could not connect to websocket")?;
 
         tracing::info!("Connected to orderbook realtime API");
 
@@ -88,6 +101,22 @@ fn subscribe_impl(
     stream.boxed()
 }
 
+/// Opens a TCP connection to the host of `url` through the SOCKS5 proxy at `proxy`, which also
+/// resolves the hostname.
+async fn connect_through_proxy(proxy: SocketAddr, url: &str) -> Result<TcpStream> {
+    let url = Url::parse(url).with_context(|| format!("Invalid websocket URL: {url}"))?;
+    let host = url.host_str().context("Websocket URL without host")?;
+    let port = url
+        .port_or_known_default()
+        .context("Websocket URL without port")?;
+
+    let stream = Socks5Stream::connect(proxy, (host, port))
+        .await
+        .with_context(|| format!("Failed to connect to {host}:{port} through {proxy}"))?;
+
+    Ok(stream.into_inner())
+}
+
 #[derive(Debug, Serialize)]
 pub enum Command {
     Authenticate(Signature),
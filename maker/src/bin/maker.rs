@@ -7,6 +7,7 @@ use ln_dlc_node::node::Node;
 use ln_dlc_node::seed::Bip39Seed;
 use maker::cli::Opts;
 use maker::logger;
+use maker::routes::admin_router;
 use maker::routes::router;
 use maker::run_migration;
 use maker::trading;
@@ -37,6 +38,7 @@ async fn main() -> Result<()> {
     let data_dir = opts.data_dir()?;
     let address = opts.p2p_address;
     let http_address = opts.http_address;
+    let admin_address = opts.admin_address;
     let network = opts.network();
 
     logger::init_tracing(LevelFilter::DEBUG, false)?;
@@ -63,9 +65,11 @@ async fn main() -> Result<()> {
             data_dir.as_path(),
             address,
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), address.port()),
-            opts.electrum,
+            opts.chain_source()?,
             seed,
             ephemeral_randomness,
+            vec![],
+            opts.socks5_proxy,
         )
         .await?,
     );
@@ -105,6 +109,17 @@ async fn main() -> Result<()> {
     let mut conn = pool.get().unwrap();
     run_migration(&mut conn);
 
+    let admin_app = admin_router(node.clone());
+    tokio::spawn(async move {
+        tracing::debug!("Admin API listening on http://{}", admin_address);
+        if let Err(e) = axum::Server::bind(&admin_address)
+            .serve(admin_app.into_make_service())
+            .await
+        {
+            tracing::error!("Admin API stopped: {e:#}");
+        }
+    });
+
     let app = router(node, pool);
 
     let addr = SocketAddr::from((http_address.ip(), http_address.port()));
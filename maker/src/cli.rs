@@ -1,5 +1,8 @@
+use anyhow::Context;
 use anyhow::Result;
+use bdk::blockchain::rpc::Auth;
 use clap::Parser;
+use ln_dlc_node::node::ChainSource;
 use reqwest::Url;
 use std::env::current_dir;
 use std::net::SocketAddr;
@@ -15,6 +18,11 @@ pub struct Opts {
     #[clap(long, default_value = "0.0.0.0:18000")]
     pub http_address: SocketAddr,
 
+    /// The IP address to listen on for the admin HTTP API, which is not authenticated and must
+    /// only be reachable from trusted hosts.
+    #[clap(long, default_value = "127.0.0.1:18001")]
+    pub admin_address: SocketAddr,
+
     /// Where to permanently store data, defaults to the current working directory.
     #[clap(long)]
     data_dir: Option<PathBuf>,
@@ -22,6 +30,10 @@ pub struct Opts {
     #[clap(value_enum, default_value = "regtest")]
     pub network: Network,
 
+    /// The SOCKS5 proxy to connect to peers through, e.g. Tor at `127.0.0.1:9050`.
+    #[clap(long)]
+    pub socks5_proxy: Option<SocketAddr>,
+
     /// The HTTP address for the orderbook.
     #[clap(long, default_value = "http://localhost:8000")]
     pub orderbook: Url,
@@ -35,7 +47,27 @@ pub struct Opts {
 
     /// The address to connect electrum to
     #[clap(long, default_value = "tcp://localhost:50000")]
-    pub electrum: String,
+    electrum: String,
+
+    /// The URL of an Esplora API to use instead of electrum.
+    #[clap(long, conflicts_with = "bitcoind_rpc_url")]
+    esplora: Option<String>,
+
+    /// The RPC address of a bitcoind (with `txindex=1`) to use instead of electrum.
+    #[clap(long)]
+    bitcoind_rpc_url: Option<String>,
+
+    /// The RPC username for bitcoind. Unless set, the cookie file is used.
+    #[clap(long, requires = "bitcoind_rpc_password")]
+    bitcoind_rpc_user: Option<String>,
+
+    /// The RPC password for bitcoind.
+    #[clap(long, requires = "bitcoind_rpc_user")]
+    bitcoind_rpc_password: Option<String>,
+
+    /// The RPC cookie file of bitcoind.
+    #[clap(long, conflicts_with = "bitcoind_rpc_user")]
+    bitcoind_rpc_cookie: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -67,6 +99,36 @@ impl Opts {
         self.network.into()
     }
 
+    pub fn chain_source(&self) -> Result<ChainSource> {
+        if let Some(url) = &self.bitcoind_rpc_url {
+            let auth = match (&self.bitcoind_rpc_user, &self.bitcoind_rpc_password) {
+                (Some(username), Some(password)) => Auth::UserPass {
+                    username: username.clone(),
+                    password: password.clone(),
+                },
+                _ => Auth::Cookie {
+                    file: self
+                        .bitcoind_rpc_cookie
+                        .clone()
+                        .context("Either bitcoind RPC credentials or a cookie file are required")?,
+                },
+            };
+
+            return Ok(ChainSource::Bitcoind {
+                url: url.clone(),
+                auth,
+            });
+        }
+
+        if let Some(url) = &self.esplora {
+            return Ok(ChainSource::Esplora { url: url.clone() });
+        }
+
+        Ok(ChainSource::Electrum {
+            url: self.electrum.clone(),
+        })
+    }
+
     pub fn data_dir(&self) -> Result<PathBuf> {
         let data_dir = match self.data_dir.clone() {
             None => current_dir()?.join("data"),
@@ -1,3 +1,4 @@
+use anyhow::Result;
 use axum::extract::Path;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -8,11 +9,17 @@ use axum::routing::post;
 use axum::Json;
 use axum::Router;
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::Address;
+use bitcoin::OutPoint;
+use bitcoin::Txid;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use diesel::PgConnection;
+use ln_dlc_node::node::Fee;
 use ln_dlc_node::node::Node;
 use ln_dlc_node::node::NodeInfo;
+use ln_dlc_node::node::SendAmount;
+use ln_dlc_node::node::Utxo;
 use ln_dlc_node::ChannelDetails;
 use serde::Deserialize;
 use serde::Serialize;
@@ -38,6 +45,16 @@ pub fn router(node: Arc<Node>, pool: Pool<ConnectionManager<PgConnection>>) -> R
         .with_state(app_state)
 }
 
+/// The routes to manage the node, which must only be served on a private interface.
+pub fn admin_router(node: Arc<Node>) -> Router {
+    Router::new()
+        .route("/api/admin/wallet/utxos", get(list_utxos))
+        .route("/api/admin/wallet/send", post(post_send_on_chain))
+        .route("/api/admin/wallet/bump-fee", post(post_bump_fee))
+        .route("/api/admin/wallet/cpfp", post(post_cpfp))
+        .with_state(node)
+}
+
 #[derive(serde::Serialize)]
 pub struct Index {
     address: String,
@@ -171,10 +188,14 @@ pub async fn create_channel(
     let channel_amount = channel_params.local_balance;
     let initial_send_amount = channel_params.remote_balance.unwrap_or_default();
 
-    state.node.connect(peer).await.map_err(|e| {
+    state.node.connect(peer.clone()).await.map_err(|e| {
         AppError::InternalServerError(format!("Could not connect to target node {e:#}"))
     })?;
 
+    state.node.keep_connected(peer.clone()).map_err(|e| {
+        AppError::InternalServerError(format!("Could not persist target node {e:#}"))
+    })?;
+
     let channel_id = state
         .node
         .initiate_open_channel(peer, channel_amount, initial_send_amount)
@@ -207,3 +228,96 @@ pub async fn pay_invoice(
         .map_err(|e| AppError::InternalServerError(format!("Could not pay invoice {e:#}")))?;
     Ok(Json("bl".to_string()))
 }
+
+pub async fn list_utxos(State(node): State<Arc<Node>>) -> Result<Json<Vec<Utxo>>, AppError> {
+    let utxos = spawn_blocking(move || node.list_utxos())
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to list UTXOs: {e:#}")))?;
+
+    Ok(Json(utxos))
+}
+
+#[derive(Deserialize)]
+pub struct SendOnChainParams {
+    address: String,
+    amount: SendAmount,
+    fee: Fee,
+    /// If not empty, only these UTXOs (formatted as `txid:vout`) are spent.
+    #[serde(default)]
+    utxos: Vec<String>,
+}
+
+pub async fn post_send_on_chain(
+    State(node): State<Arc<Node>>,
+    params: Json<SendOnChainParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let address = Address::from_str(&params.address)
+        .map_err(|e| AppError::BadRequest(format!("Invalid address provided: {e:#}")))?;
+    let utxos = params
+        .utxos
+        .iter()
+        .map(|utxo| OutPoint::from_str(utxo))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::BadRequest(format!("Invalid UTXO provided: {e:#}")))?;
+
+    let txid =
+        spawn_blocking(move || node.send_to_address(&address, params.amount, params.fee, &utxos))
+            .await
+            .map_err(|e| {
+                AppError::InternalServerError(format!("Failed to send on-chain: {e:#}"))
+            })?;
+
+    Ok(Json(txid.to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct BumpFeeParams {
+    txid: String,
+    fee: Fee,
+}
+
+pub async fn post_bump_fee(
+    State(node): State<Arc<Node>>,
+    params: Json<BumpFeeParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let txid = Txid::from_str(&params.txid)
+        .map_err(|e| AppError::BadRequest(format!("Invalid txid provided: {e:#}")))?;
+
+    let txid = spawn_blocking(move || node.bump_fee(txid, params.fee))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to bump fee: {e:#}")))?;
+
+    Ok(Json(txid.to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct CpfpParams {
+    /// The output of the stuck transaction which pays to us, formatted as `txid:vout`.
+    outpoint: String,
+    fee: Fee,
+}
+
+pub async fn post_cpfp(
+    State(node): State<Arc<Node>>,
+    params: Json<CpfpParams>,
+) -> Result<Json<String>, AppError> {
+    let Json(params) = params;
+    let outpoint = OutPoint::from_str(&params.outpoint)
+        .map_err(|e| AppError::BadRequest(format!("Invalid outpoint provided: {e:#}")))?;
+
+    let txid = spawn_blocking(move || node.cpfp(outpoint, params.fee))
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to bump fee: {e:#}")))?;
+
+    Ok(Json(txid.to_string()))
+}
+
+/// Runs the blocking wallet operation `f` without blocking the executor.
+async fn spawn_blocking<T>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T>
+where
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
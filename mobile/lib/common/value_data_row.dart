@@ -3,7 +3,7 @@ import 'package:flutter/material.dart';
 import 'amount_text.dart';
 import 'fiat_text.dart';
 
-enum ValueType { amount, fiat, percentage, contracts, loading }
+enum ValueType { amount, fiat, percentage, contracts, feeRate, loading }
 
 class ValueDataRow extends StatelessWidget {
   final ValueType type;
@@ -40,6 +40,9 @@ class ValueDataRow extends StatelessWidget {
       case ValueType.contracts:
         widget = Text("$value contracts", style: valueTextStyle);
         break;
+      case ValueType.feeRate:
+        widget = Text("$value sats/vbyte", style: valueTextStyle);
+        break;
       case ValueType.loading:
         widget = const SizedBox(width: 20, height: 20, child: CircularProgressIndicator());
         break;
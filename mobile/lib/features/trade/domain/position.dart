@@ -21,6 +21,8 @@ enum PositionState {
 }
 
 class Position {
+  /// The hex encoded ID of the DLC of this position
+  final String contractId;
   final Leverage leverage;
   final double quantity;
   final ContractSymbol contractSymbol;
@@ -33,7 +35,8 @@ class Position {
   final Amount collateral;
 
   Position(
-      {required this.averageEntryPrice,
+      {required this.contractId,
+      required this.averageEntryPrice,
       required this.liquidationPrice,
       required this.leverage,
       required this.quantity,
@@ -45,6 +48,7 @@ class Position {
 
   static Position fromApi(bridge.Position position) {
     return Position(
+        contractId: position.contractId,
         leverage: Leverage(position.leverage),
         quantity: position.quantity,
         contractSymbol: ContractSymbol.fromApi(position.contractSymbol),
@@ -57,6 +61,7 @@ class Position {
 
   static bridge.Position apiDummy() {
     return const bridge.Position(
+      contractId: "",
       leverage: 0,
       quantity: 0,
       contractSymbol: bridge.ContractSymbol.BtcUsd,
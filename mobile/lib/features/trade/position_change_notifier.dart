@@ -5,7 +5,6 @@ import 'package:get_10101/common/domain/model.dart';
 import 'package:get_10101/common/dummy_values.dart';
 import 'package:get_10101/features/trade/application/order_service.dart';
 import 'package:get_10101/features/trade/application/position_service.dart';
-import 'package:get_10101/features/trade/domain/contract_symbol.dart';
 import 'package:get_10101/bridge_generated/bridge_definitions.dart' as bridge;
 
 import 'domain/position.dart';
@@ -15,14 +14,15 @@ class PositionChangeNotifier extends ChangeNotifier implements Subscriber {
   final PositionService _positionService;
   final OrderService _orderService;
 
-  Map<ContractSymbol, Position> positions = {};
+  /// The open positions, keyed by the contract ID of their DLC
+  Map<String, Position> positions = {};
 
   Price? _price;
 
   Future<void> initialize() async {
     List<Position> positions = await _positionService.fetchPositions();
     for (Position position in positions) {
-      this.positions[position.contractSymbol] = position;
+      this.positions[position.contractId] = position;
     }
     _price = Price(bid: dummyBidPrice, ask: dummyAskPrice);
 
@@ -43,18 +43,14 @@ class PositionChangeNotifier extends ChangeNotifier implements Subscriber {
       } else {
         position.unrealizedPnl = null;
       }
-      positions[position.contractSymbol] = position;
+      positions[position.contractId] = position;
     } else if (event is bridge.Event_PositionClosedNotification) {
-      ContractSymbol contractSymbol = ContractSymbol.fromApi(event.field0.contractSymbol);
-      positions.remove(contractSymbol);
+      positions.remove(event.field0.contractId);
     } else if (event is bridge.Event_PriceUpdateNotification) {
       _price = Price.fromApi(event.field0);
-      for (ContractSymbol symbol in positions.keys) {
+      for (Position position in positions.values) {
         if (_price != null) {
-          if (positions[symbol] != null) {
-            positions[symbol]!.unrealizedPnl =
-                Amount(_positionService.calculatePnl(positions[symbol]!, _price!));
-          }
+          position.unrealizedPnl = Amount(_positionService.calculatePnl(position, _price!));
         }
       }
     } else {
@@ -64,12 +60,12 @@ class PositionChangeNotifier extends ChangeNotifier implements Subscriber {
     notifyListeners();
   }
 
-  Future<void> closePosition(ContractSymbol contractSymbol) async {
-    if (positions[contractSymbol] == null) {
-      throw Exception("No position for contract symbol $contractSymbol");
+  Future<void> closePosition(String contractId) async {
+    if (positions[contractId] == null) {
+      throw Exception("No position with contract id $contractId");
     }
 
-    Position position = positions[contractSymbol]!;
+    Position position = positions[contractId]!;
     await _orderService.submitMarketOrder(position.leverage, position.quantity,
         position.contractSymbol, position.direction.opposite());
   }
@@ -8,6 +8,7 @@ import 'package:get_10101/features/trade/domain/trade_values.dart';
 import 'package:get_10101/features/trade/submit_order_change_notifier.dart';
 import 'package:get_10101/features/trade/trade_theme.dart';
 import 'package:get_10101/features/trade/trade_value_change_notifier.dart';
+import 'package:get_10101/ffi.dart' as rust;
 import 'package:get_10101/util/constants.dart';
 import 'package:go_router/go_router.dart';
 import 'package:provider/provider.dart';
@@ -47,7 +48,7 @@ tradeBottomSheetConfirmation({required BuildContext context, required Direction
               }
             },
             child: SizedBox(
-                height: 350,
+                height: 380,
                 child: TradeBottomSheetConfirmation(
                   direction: direction,
                   sliderButtonKey: sliderButtonKey,
@@ -60,7 +61,7 @@ tradeBottomSheetConfirmation({required BuildContext context, required Direction
   );
 }
 
-class TradeBottomSheetConfirmation extends StatelessWidget {
+class TradeBottomSheetConfirmation extends StatefulWidget {
   final Direction direction;
   final Key sliderKey;
   final Key sliderButtonKey;
@@ -68,8 +69,24 @@ class TradeBottomSheetConfirmation extends StatelessWidget {
   const TradeBottomSheetConfirmation(
       {required this.direction, super.key, required this.sliderButtonKey, required this.sliderKey});
 
+  @override
+  State<TradeBottomSheetConfirmation> createState() => _TradeBottomSheetConfirmationState();
+}
+
+class _TradeBottomSheetConfirmationState extends State<TradeBottomSheetConfirmation> {
+  // Estimated once, so that rebuilds do not query the fee rate again
+  late final Future<int> _dlcFeeRate;
+
+  @override
+  void initState() {
+    super.initState();
+    _dlcFeeRate = rust.api.getDlcFeeRate();
+  }
+
   @override
   Widget build(BuildContext context) {
+    final direction = widget.direction;
+
     TradeTheme tradeTheme = Theme.of(context).extension<TradeTheme>()!;
     Color color = direction == Direction.long ? tradeTheme.buy : tradeTheme.sell;
 
@@ -105,6 +122,19 @@ class TradeBottomSheetConfirmation extends StatelessWidget {
                             value: tradeValues.fundingRate,
                             label: "Funding Rate"),
                         ValueDataRow(type: ValueType.amount, value: tradeValues.fee, label: "Fee"),
+                        // The on-chain fees of the DLC are paid out of the collateral
+                        FutureBuilder<int>(
+                            future: _dlcFeeRate,
+                            builder: (BuildContext context, AsyncSnapshot<int> snapshot) {
+                              if (snapshot.hasError) {
+                                return const SizedBox.shrink();
+                              }
+
+                              return ValueDataRow(
+                                  type: snapshot.hasData ? ValueType.feeRate : ValueType.loading,
+                                  value: snapshot.data,
+                                  label: "On-chain Fee Rate");
+                            }),
                       ],
                     ),
                     const Divider(),
@@ -127,13 +157,13 @@ class TradeBottomSheetConfirmation extends StatelessWidget {
             ),
             const Spacer(),
             ConfirmationSlider(
-              key: sliderKey,
+              key: widget.sliderKey,
               text: "Swipe to confirm ${direction.nameU}",
               textStyle: TextStyle(color: color),
               height: 40,
               foregroundColor: color,
               sliderButtonContent: Container(
-                key: sliderButtonKey,
+                key: widget.sliderButtonKey,
                 child: const Icon(
                   Icons.chevron_right,
                   color: Colors.white,
@@ -147,7 +147,7 @@ class TradeScreen extends StatelessWidget {
                         return PositionListItem(
                           position: position,
                           onClose: () async {
-                            await positionChangeNotifier.closePosition(position.contractSymbol);
+                            await positionChangeNotifier.closePosition(position.contractId);
                           },
                         );
                       },
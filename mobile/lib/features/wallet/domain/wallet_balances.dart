@@ -1,8 +1,26 @@
 import 'package:get_10101/common/domain/model.dart';
 
 class WalletBalances {
+  /// Confirmed on-chain balance
   Amount onChain;
+  Amount onChainUnconfirmed;
+
+  /// What can be spent over Lightning, excluding the collateral locked in positions
   Amount lightning;
 
-  WalletBalances({required this.onChain, required this.lightning});
+  /// Collateral locked in open positions
+  Amount dlcCollateral;
+
+  /// Funds from closed channels which are not yet in the on-chain wallet
+  Amount pendingClose;
+
+  WalletBalances(
+      {required this.onChain,
+      required this.lightning,
+      Amount? onChainUnconfirmed,
+      Amount? dlcCollateral,
+      Amount? pendingClose})
+      : onChainUnconfirmed = onChainUnconfirmed ?? Amount(0),
+        dlcCollateral = dlcCollateral ?? Amount(0),
+        pendingClose = pendingClose ?? Amount(0);
 }
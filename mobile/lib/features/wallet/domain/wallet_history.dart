@@ -7,6 +7,7 @@ enum WalletHistoryItemDataType { lightning, onChain, trade }
 enum WalletHistoryStatus { pending, confirmed }
 
 class WalletHistoryItemData {
+  final String id;
   final PaymentFlow flow;
   final Amount amount;
   final WalletHistoryItemDataType type;
@@ -15,22 +16,31 @@ class WalletHistoryItemData {
 
   // on-chain
   final String? txid;
+  final Amount? fee;
+  final int? confirmations;
 
   // lightning
   final String? paymentHash;
+  final String? description;
 
   // trade
   final String? orderId;
+  final String? contractId;
 
   const WalletHistoryItemData(
-      {required this.flow,
+      {required this.id,
+      required this.flow,
       required this.amount,
       required this.type,
       required this.status,
       required this.timestamp,
       this.paymentHash,
+      this.description,
       this.orderId,
-      this.txid});
+      this.contractId,
+      this.txid,
+      this.fee,
+      this.confirmations});
 
   static WalletHistoryItemData fromApi(rust.WalletHistoryItem item) {
     PaymentFlow flow =
@@ -46,32 +56,41 @@ class WalletHistoryItemData {
       rust.WalletType_OnChain type = item.walletType as rust.WalletType_OnChain;
 
       return WalletHistoryItemData(
+          id: item.id,
           flow: flow,
           amount: amount,
           status: status,
           type: WalletHistoryItemDataType.onChain,
           timestamp: timestamp,
-          txid: type.txid);
+          txid: type.txid,
+          fee: type.feeSats != null ? Amount(type.feeSats!) : null,
+          confirmations: type.confirmations);
     }
 
     if (item.walletType is rust.WalletType_Trade) {
       rust.WalletType_Trade type = item.walletType as rust.WalletType_Trade;
 
       return WalletHistoryItemData(
+          id: item.id,
           flow: flow,
           amount: amount,
           status: status,
           type: WalletHistoryItemDataType.trade,
           timestamp: timestamp,
-          orderId: type.orderId);
+          orderId: type.orderId,
+          contractId: type.contractId);
     }
 
+    rust.WalletType_Lightning type = item.walletType as rust.WalletType_Lightning;
+
     return WalletHistoryItemData(
+        id: item.id,
         flow: flow,
         amount: amount,
         status: status,
         type: WalletHistoryItemDataType.lightning,
         timestamp: timestamp,
-        paymentHash: (item.walletType as rust.WalletType_Lightning).paymentHash);
+        paymentHash: type.paymentHash,
+        description: type.description);
   }
 }
@@ -11,14 +11,18 @@ class WalletInfo {
   WalletInfo.fromApi(rust.WalletInfo walletInfo)
       : balances = WalletBalances(
             onChain: Amount(walletInfo.balances.onChain),
-            lightning: Amount(walletInfo.balances.lightning)),
+            onChainUnconfirmed: Amount(walletInfo.balances.onChainUnconfirmed),
+            lightning: Amount(walletInfo.balances.lightning),
+            dlcCollateral: Amount(walletInfo.balances.dlcCollateral),
+            pendingClose: Amount(walletInfo.balances.pendingClose)),
         history = walletInfo.history.map((item) {
           return WalletHistoryItemData.fromApi(item);
         }).toList();
 
   static rust.WalletInfo apiDummy() {
     return rust.WalletInfo(
-      balances: const rust.Balances(onChain: -1, lightning: -1),
+      balances: const rust.Balances(
+          onChain: -1, onChainUnconfirmed: -1, lightning: -1, dlcCollateral: -1, pendingClose: -1),
       history: List.empty(growable: false),
     );
   }
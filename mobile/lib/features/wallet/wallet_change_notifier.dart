@@ -15,6 +15,9 @@ class WalletChangeNotifier extends ChangeNotifier implements Subscriber {
     history: List.empty(),
   );
 
+  /// Whether the node is in sync with the blockchain, i.e. the balances and history are current.
+  bool inSync = true;
+
   WalletChangeNotifier(this._service);
 
   void update(WalletInfo? walletInfo) {
@@ -43,6 +46,11 @@ class WalletChangeNotifier extends ChangeNotifier implements Subscriber {
 
     if (event is bridge.Event_WalletInfoUpdateNotification) {
       update(WalletInfo.fromApi(event.field0));
+    } else if (event is bridge.Event_SyncStatusUpdateNotification) {
+      if (inSync != event.field0.inSync) {
+        inSync = event.field0.inSync;
+        super.notifyListeners();
+      }
     } else {
       log("Received unexpected event: ${event.toString()}");
     }
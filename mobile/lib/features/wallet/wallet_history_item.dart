@@ -1,4 +1,5 @@
 import 'package:flutter/material.dart';
+import 'package:get_10101/common/domain/model.dart';
 import 'package:get_10101/features/wallet/domain/payment_flow.dart';
 import 'package:get_10101/features/wallet/domain/wallet_history.dart';
 import 'package:intl/intl.dart';
@@ -47,11 +48,24 @@ class WalletHistoryItem extends StatelessWidget {
     String title = () {
       switch (data.type) {
         case WalletHistoryItemDataType.lightning:
-          return data.paymentHash ?? "";
+          String description = data.description ?? "";
+          return description.isNotEmpty ? description : data.paymentHash ?? "";
         case WalletHistoryItemDataType.onChain:
           return data.txid ?? "";
         case WalletHistoryItemDataType.trade:
-          return data.orderId ?? "";
+          return data.flow == PaymentFlow.inbound ? "Closed position" : "Opened position";
+      }
+    }();
+
+    String details = () {
+      switch (data.type) {
+        case WalletHistoryItemDataType.onChain:
+          String confirmations = "${data.confirmations ?? 0} confirmations";
+          Amount? fee = data.fee;
+          return fee != null ? "$confirmations, fee ${fee.sats} sats" : confirmations;
+        case WalletHistoryItemDataType.lightning:
+        case WalletHistoryItemDataType.trade:
+          return "";
       }
     }();
 
@@ -111,6 +125,8 @@ class WalletHistoryItem extends StatelessWidget {
                 TextSpan(
                     text: timeago.format(data.timestamp),
                     style: const TextStyle(color: Colors.grey)),
+                if (details.isNotEmpty)
+                  TextSpan(text: " · $details", style: const TextStyle(color: Colors.grey)),
               ])),
           trailing: Padding(
             padding: const EdgeInsets.only(top: 11.0, bottom: 5.0),
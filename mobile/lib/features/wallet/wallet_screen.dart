@@ -85,6 +85,15 @@ class _WalletScreenState extends State<WalletScreen> {
                 expansionCallback: (i, isOpen) => setState(() => _isBalanceBreakdownOpen = !isOpen),
               ),
               Divider(color: theme.dividerColor),
+              if (!walletChangeNotifier.inSync)
+                const Padding(
+                  padding: EdgeInsets.only(bottom: 10),
+                  child: Text(
+                    "Out of sync: balances may be outdated",
+                    textAlign: TextAlign.center,
+                    style: TextStyle(color: Colors.orange, fontWeight: FontWeight.bold),
+                  ),
+                ),
               if (walletChangeNotifier.lightning().sats == 0)
                 ElevatedButton(
                   onPressed: () {
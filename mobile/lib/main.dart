@@ -208,12 +208,20 @@ class _TenTenOneAppState extends State<TenTenOneApp> {
 
       eventService.subscribe(
           positionChangeNotifier,
-          const bridge.Event.positionClosedNotification(
-              bridge.PositionClosed(contractSymbol: bridge.ContractSymbol.BtcUsd)));
+          const bridge.Event.positionClosedNotification(bridge.PositionClosed(contractId: "")));
 
       eventService.subscribe(
           walletChangeNotifier, bridge.Event.walletInfoUpdateNotification(WalletInfo.apiDummy()));
 
+      eventService.subscribe(
+          walletChangeNotifier,
+          const bridge.Event.syncStatusUpdateNotification(bridge.SyncStatus(
+              inSync: true,
+              syncedHeight: null,
+              tipHeight: null,
+              lastSuccessfulSync: null,
+              chainSourceReachable: true)));
+
       eventService.subscribe(
           tradeValuesChangeNotifier, bridge.Event.priceUpdateNotification(Price.apiDummy()));
 
@@ -223,7 +231,7 @@ class _TenTenOneAppState extends State<TenTenOneApp> {
       final appSupportDir = await getApplicationSupportDirectory();
       FLog.info(text: "App data will be stored in: $appSupportDir");
 
-      await rust.api.run(config: config, appDir: appSupportDir.path);
+      await rust.api.run(config: config, appDir: appSupportDir.path, seedEncryptionKey: null);
 
       await orderChangeNotifier.initialize();
       await positionChangeNotifier.initialize();
@@ -10,7 +10,11 @@ class Environment {
     int httpPort = const int.fromEnvironment("COORDINATOR_PORT_HTTP", defaultValue: 8000);
     String electrsEndpoint =
         const String.fromEnvironment("ELECTRS_ENDPOINT", defaultValue: "127.0.0.1:50000");
+    // if set, the app syncs with this Esplora API instead of electrs
+    String esploraEndpoint = const String.fromEnvironment("ESPLORA_ENDPOINT");
     String network = const String.fromEnvironment('NETWORK', defaultValue: "regtest");
+    // if set, the app connects to the coordinator through this SOCKS5 proxy, e.g. Tor
+    String socks5Proxy = const String.fromEnvironment("SOCKS5_PROXY");
 
     String p2pEndpoint = const String.fromEnvironment('COORDINATOR_P2P_ENDPOINT');
     if (p2pEndpoint.contains("@")) {
@@ -25,9 +29,11 @@ class Environment {
     return Config(
         host: host,
         electrsEndpoint: electrsEndpoint,
+        esploraEndpoint: esploraEndpoint.isEmpty ? null : esploraEndpoint,
         coordinatorPubkey: coordinatorPublicKey,
         p2PPort: lightningPort,
         httpPort: httpPort,
-        network: network);
+        network: network,
+        socks5Proxy: socks5Proxy.isEmpty ? null : socks5Proxy);
   }
 }
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE positions RENAME TO positions_by_contract_id;
+ALTER TABLE legacy_positions RENAME TO positions;
+-- Only one position per contract symbol can be kept
+INSERT OR IGNORE INTO positions
+SELECT
+    contract_symbol,
+    leverage,
+    quantity,
+    direction,
+    average_entry_price,
+    liquidation_price,
+    state,
+    collateral,
+    creation_timestamp
+FROM positions_by_contract_id;
+DROP TABLE positions_by_contract_id;
@@ -0,0 +1,19 @@
+-- A trader can have several positions at once, one per DLC, so positions are identified by the
+-- contract ID of their DLC instead of the contract symbol.
+--
+-- The contract ID of existing positions is only known to the DLC manager, so they are kept in
+-- `legacy_positions` until reconciliation on startup moves them over with the contract ID of their
+-- DLC.
+ALTER TABLE positions RENAME TO legacy_positions;
+CREATE TABLE IF NOT EXISTS positions (
+    contract_id TEXT PRIMARY KEY NOT NULL,
+    contract_symbol TEXT NOT NULL,
+    leverage NUMBER NOT NULL,
+    quantity NUMBER NOT NULL,
+    direction TEXT NOT NULL,
+    average_entry_price NUMBER NOT NULL,
+    liquidation_price NUMBER NOT NULL,
+    state TEXT NOT NULL,
+    collateral BIGINT NOT NULL,
+    creation_timestamp BIGINT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Funds which moved into or out of a DLC when a position was opened or closed, so that they can
+-- be shown in the wallet history after the position is gone
+CREATE TABLE IF NOT EXISTS trades (
+    order_id TEXT PRIMARY KEY NOT NULL,
+    contract_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount_sats BIGINT NOT NULL,
+    timestamp BIGINT NOT NULL
+)
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE legacy_positions DROP COLUMN opening_order_id;
@@ -0,0 +1,19 @@
+-- Links each legacy position to the filled order which opened it, so that the trade opening the
+-- position shows up in the wallet history once reconciliation knows the DLC of the position.
+--
+-- Until then a trader had at most one position per contract symbol, opened by the last filled
+-- order of its contract symbol, direction and quantity before the position was created. Filled
+-- orders which did not open a legacy position, e.g. the orders of positions closed before trades
+-- were stored, are not backfilled: neither their DLC nor what we got out of it is known.
+ALTER TABLE legacy_positions ADD COLUMN opening_order_id TEXT;
+UPDATE legacy_positions SET opening_order_id = (
+    SELECT orders.id
+    FROM orders
+    WHERE orders.state = 'filled'
+        AND orders.contract_symbol = legacy_positions.contract_symbol
+        AND orders.direction = legacy_positions.direction
+        AND orders.quantity = legacy_positions.quantity
+        AND orders.creation_timestamp <= legacy_positions.creation_timestamp
+    ORDER BY orders.creation_timestamp DESC, orders.rowid DESC
+    LIMIT 1
+);
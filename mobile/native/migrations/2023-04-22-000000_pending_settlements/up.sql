@@ -0,0 +1,6 @@
+-- What we get out of a DLC according to the DLC channel close offer we accepted, until the DLC
+-- channel is closed and the amount is recorded in the closing trade
+CREATE TABLE IF NOT EXISTS pending_settlements (
+    channel_id TEXT PRIMARY KEY NOT NULL,
+    amount_sats BIGINT NOT NULL
+);
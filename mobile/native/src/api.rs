@@ -14,11 +14,16 @@ use crate::trade::order::api::NewOrder;
 use crate::trade::order::api::Order;
 use crate::trade::position;
 use crate::trade::position::api::Position;
+use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin::OutPoint;
+use bdk::bitcoin::Txid;
 use flutter_rust_bridge::frb;
 use flutter_rust_bridge::StreamSink;
 use flutter_rust_bridge::SyncReturn;
 use std::backtrace::Backtrace;
+use std::path::Path;
+use std::str::FromStr;
 pub use trade::ContractSymbol;
 pub use trade::Direction;
 
@@ -33,10 +38,32 @@ pub struct WalletInfo {
     pub history: Vec<WalletHistoryItem>,
 }
 
+/// All balances are in sats.
 #[derive(Clone, Debug, Default)]
 pub struct Balances {
+    /// Confirmed on-chain balance.
     pub on_chain: u64,
+    pub on_chain_unconfirmed: u64,
+    /// What can be spent over Lightning, i.e. excluding the collateral locked in DLCs.
     pub lightning: u64,
+    /// Our collateral locked in open positions.
+    pub dlc_collateral: u64,
+    /// Funds from closed channels which are not yet in the on-chain wallet.
+    pub pending_close: u64,
+}
+
+/// Whether the node is in sync with the blockchain, as of the last sync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncStatus {
+    /// If false, balances and positions may be outdated.
+    pub in_sync: bool,
+    /// The height of the block the node last synced to.
+    pub synced_height: Option<u32>,
+    /// The height of the chain tip, unless the chain source is unreachable.
+    pub tip_height: Option<u32>,
+    /// When the node last synced successfully, as a unix timestamp.
+    pub last_successful_sync: Option<i64>,
+    pub chain_source_reachable: bool,
 }
 
 /// Assembles the wallet info and publishes wallet info update event
@@ -49,6 +76,8 @@ pub async fn refresh_wallet_info() -> Result<()> {
 
 #[derive(Clone, Debug)]
 pub struct WalletHistoryItem {
+    /// Identifies the entry across updates of the wallet history.
+    pub id: String,
     pub flow: PaymentFlow,
     pub amount_sats: u64,
     pub timestamp: u64,
@@ -58,9 +87,22 @@ pub struct WalletHistoryItem {
 
 #[derive(Clone, Debug)]
 pub enum WalletType {
-    OnChain { txid: String },
-    Lightning { payment_hash: String },
-    Trade { order_id: String },
+    OnChain {
+        txid: String,
+        /// The fee we paid, if we sent the transaction.
+        fee_sats: Option<u64>,
+        confirmations: u64,
+    },
+    Lightning {
+        payment_hash: String,
+        description: String,
+    },
+    /// Funds moving into our DLC when opening a position (outbound) or out of it when closing the
+    /// position (inbound).
+    Trade {
+        order_id: String,
+        contract_id: String,
+    },
 }
 
 #[derive(Clone, Debug, Default)]
@@ -164,7 +206,8 @@ pub fn subscribe(stream: StreamSink<event::api::Event>) {
     event::subscribe(FlutterSubscriber::new(stream))
 }
 
-pub fn run(config: Config, app_dir: String) -> Result<()> {
+/// Starts the app, keeping the seed file encrypted with the `seed_encryption_key` if provided.
+pub fn run(config: Config, app_dir: String, seed_encryption_key: Option<String>) -> Result<()> {
     std::panic::set_hook(
         #[allow(clippy::print_stderr)]
         Box::new(|info| {
@@ -177,12 +220,35 @@ pub fn run(config: Config, app_dir: String) -> Result<()> {
         }),
     );
 
-    config::set(config);
+    config::set(config)?;
     db::init_db(&app_dir, get_network())?;
-    ln_dlc::run(app_dir)?;
+    ln_dlc::run(app_dir, seed_encryption_key)?;
     orderbook::subscribe(ln_dlc::get_node_key()?)
 }
 
+pub fn get_seed_phrase() -> Result<Vec<String>> {
+    ln_dlc::get_seed_phrase()
+}
+
+/// Restores the seed from the `seed_phrase` and an optional BIP39 `passphrase`.
+///
+/// Must be called before [`run`], with the same `app_dir` and the `network` of the config.
+pub fn restore_from_seed_phrase(
+    seed_phrase: String,
+    passphrase: Option<String>,
+    app_dir: String,
+    network: String,
+    seed_encryption_key: Option<String>,
+) -> Result<()> {
+    ln_dlc::restore_from_seed_phrase(
+        &seed_phrase,
+        passphrase,
+        Path::new(&app_dir),
+        config::api::parse_network(&network),
+        seed_encryption_key.as_deref().map(str::as_bytes),
+    )
+}
+
 pub fn get_new_address() -> SyncReturn<String> {
     SyncReturn(ln_dlc::get_new_address().unwrap())
 }
@@ -191,6 +257,43 @@ pub fn open_channel() -> Result<()> {
     ln_dlc::open_channel()
 }
 
+/// Replaces the channel with the coordinator with a bigger one of `channel_capacity_sat`
+pub fn upgrade_channel(channel_capacity_sat: u64) -> Result<()> {
+    ln_dlc::upgrade_channel(channel_capacity_sat)
+}
+
+pub struct LiquidityOffer {
+    pub price_msat_per_sat: u64,
+    pub lease_duration_secs: u64,
+    pub min_inbound_liquidity_sat: u64,
+    pub max_inbound_liquidity_sat: u64,
+}
+
+impl From<coordinator_commons::LiquidityOffer> for LiquidityOffer {
+    fn from(value: coordinator_commons::LiquidityOffer) -> Self {
+        Self {
+            price_msat_per_sat: value.price_msat_per_sat,
+            lease_duration_secs: value.lease_duration_secs,
+            min_inbound_liquidity_sat: value.min_inbound_liquidity_sat,
+            max_inbound_liquidity_sat: value.max_inbound_liquidity_sat,
+        }
+    }
+}
+
+/// The fee rate in sats per vbyte the transactions of a new position will pay
+pub fn get_dlc_fee_rate() -> Result<u64> {
+    ln_dlc::get_dlc_fee_rate()
+}
+
+pub fn get_liquidity_offer() -> Result<LiquidityOffer> {
+    Ok(ln_dlc::get_liquidity_offer()?.into())
+}
+
+/// Buys inbound liquidity from the coordinator and returns the fee paid for it in sats
+pub fn buy_inbound_liquidity(inbound_liquidity_sat: u64) -> Result<u64> {
+    Ok(ln_dlc::buy_inbound_liquidity(inbound_liquidity_sat)?.fee_sat)
+}
+
 pub fn create_invoice_with_amount(amount_sats: u64) -> Result<String> {
     Ok(ln_dlc::create_invoice(Some(amount_sats))?.to_string())
 }
@@ -203,6 +306,90 @@ pub fn send_payment(invoice: String) -> Result<()> {
     ln_dlc::send_payment(&invoice)
 }
 
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    pub amount_sats: u64,
+    pub is_confirmed: bool,
+}
+
+impl From<ln_dlc_node::node::Utxo> for Utxo {
+    fn from(value: ln_dlc_node::node::Utxo) -> Self {
+        Self {
+            txid: value.outpoint.txid.to_string(),
+            vout: value.outpoint.vout,
+            amount_sats: value.amount_sats,
+            is_confirmed: value.is_confirmed,
+        }
+    }
+}
+
+/// The fee to pay for an on-chain transaction.
+pub enum OnChainFee {
+    /// Pay whatever is estimated to get the transaction confirmed within `blocks`.
+    ConfirmationTarget {
+        blocks: u32,
+    },
+    SatsPerVbyte {
+        sats_per_vbyte: f32,
+    },
+}
+
+impl From<OnChainFee> for ln_dlc_node::node::Fee {
+    fn from(value: OnChainFee) -> Self {
+        match value {
+            OnChainFee::ConfirmationTarget { blocks } => Self::ConfirmationTarget(blocks as usize),
+            OnChainFee::SatsPerVbyte { sats_per_vbyte } => Self::SatsPerVbyte(sats_per_vbyte),
+        }
+    }
+}
+
+pub fn list_utxos() -> Result<Vec<Utxo>> {
+    let utxos = ln_dlc::list_utxos()?;
+    Ok(utxos.into_iter().map(Utxo::from).collect())
+}
+
+/// Sends on-chain funds to `address` and returns the txid.
+///
+/// If `amount_sats` is `None`, the whole wallet (or all the selected `utxos`) is sent, minus fees.
+/// `utxos` are formatted as `txid:vout`.
+pub fn send_on_chain(
+    address: String,
+    amount_sats: Option<u64>,
+    fee: OnChainFee,
+    utxos: Vec<String>,
+) -> Result<String> {
+    let amount = match amount_sats {
+        Some(sats) => ln_dlc_node::node::SendAmount::Sats(sats),
+        None => ln_dlc_node::node::SendAmount::All,
+    };
+    let utxos = utxos
+        .iter()
+        .map(|utxo| OutPoint::from_str(utxo).context("Could not parse UTXO"))
+        .collect::<Result<Vec<_>>>()?;
+
+    let txid = ln_dlc::send_on_chain(&address, amount, fee.into(), &utxos)?;
+    Ok(txid.to_string())
+}
+
+/// Replaces the unconfirmed on-chain transaction `txid` with one paying a higher fee and returns
+/// the txid of the replacement.
+pub fn bump_fee(txid: String, fee: OnChainFee) -> Result<String> {
+    let txid = Txid::from_str(&txid).context("Could not parse txid")?;
+
+    let txid = ln_dlc::bump_fee(txid, fee.into())?;
+    Ok(txid.to_string())
+}
+
+/// Speeds up the unconfirmed transaction `txid` by spending its output `vout`, which must belong
+/// to our wallet, with a higher fee. Returns the txid of the child transaction.
+pub fn cpfp(txid: String, vout: u32, fee: OnChainFee) -> Result<String> {
+    let txid = Txid::from_str(&txid).context("Could not parse txid")?;
+
+    let txid = ln_dlc::cpfp(OutPoint { txid, vout }, fee.into())?;
+    Ok(txid.to_string())
+}
+
 pub struct LastLogin {
     pub id: i32,
     pub date: String,
@@ -1,4 +1,6 @@
 use crate::config::ConfigInternal;
+use anyhow::Context;
+use anyhow::Result;
 use bdk::bitcoin::Network;
 use flutter_rust_bridge::frb;
 
@@ -7,32 +9,48 @@ use flutter_rust_bridge::frb;
 pub struct Config {
     pub coordinator_pubkey: String,
     pub electrs_endpoint: String,
+    /// If set, the app uses this Esplora API instead of `electrs_endpoint`.
+    pub esplora_endpoint: Option<String>,
+    /// An IP address, DNS hostname or onion address. Onion addresses require a `socks5_proxy`.
     pub host: String,
     pub p2p_port: u16,
     pub http_port: u16,
     pub network: String,
+    /// If set, the app connects to the coordinator through this SOCKS5 proxy, e.g. Tor.
+    pub socks5_proxy: Option<String>,
 }
 
-impl From<Config> for ConfigInternal {
-    fn from(config: Config) -> Self {
-        Self {
+impl TryFrom<Config> for ConfigInternal {
+    type Error = anyhow::Error;
+
+    fn try_from(config: Config) -> Result<Self> {
+        let socks5_proxy = config
+            .socks5_proxy
+            .map(|proxy| {
+                proxy
+                    .parse()
+                    .with_context(|| format!("Invalid SOCKS5 proxy address: {proxy}"))
+            })
+            .transpose()?;
+
+        Ok(Self {
             coordinator_pubkey: config.coordinator_pubkey.parse().expect("PK to be valid"),
             electrs_endpoint: config
                 .electrs_endpoint
                 .parse()
                 .expect("electrs endpoint to be valid"),
-            http_endpoint: format!("{}:{}", config.host, config.http_port)
-                .parse()
-                .expect("host and http_port to be valid"),
+            esplora_endpoint: config.esplora_endpoint,
+            http_endpoint: format!("{}:{}", config.host, config.http_port),
             p2p_endpoint: format!("{}:{}", config.host, config.p2p_port)
                 .parse()
                 .expect("host and p2p_port to be valid"),
+            socks5_proxy,
             network: parse_network(&config.network),
-        }
+        })
     }
 }
 
-fn parse_network(network: &str) -> Network {
+pub(crate) fn parse_network(network: &str) -> Network {
     match network {
         "signet" => Network::Signet,
         "testnet" => Network::Testnet,
@@ -1,32 +1,40 @@
 pub mod api;
 
 use crate::config::api::Config;
+use anyhow::Result;
 use bdk::bitcoin;
 use bdk::bitcoin::secp256k1::PublicKey;
+use ln_dlc_node::node::ChainSource;
 use ln_dlc_node::node::NodeInfo;
+use ln_dlc_node::node::PeerAddress;
 use state::Storage;
 use std::net::SocketAddr;
 
 static CONFIG: Storage<ConfigInternal> = Storage::new();
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 struct ConfigInternal {
     coordinator_pubkey: PublicKey,
     electrs_endpoint: SocketAddr,
-    http_endpoint: SocketAddr,
-    p2p_endpoint: SocketAddr,
+    esplora_endpoint: Option<String>,
+    /// The coordinator's HTTP API as `host:port`, where the host may be a DNS hostname.
+    http_endpoint: String,
+    p2p_endpoint: PeerAddress,
+    socks5_proxy: Option<SocketAddr>,
     network: bitcoin::Network,
 }
 
-pub fn set(config: Config) {
-    CONFIG.set(config.into());
+pub fn set(config: Config) -> Result<()> {
+    CONFIG.set(config.try_into()?);
+
+    Ok(())
 }
 
 pub fn get_coordinator_info() -> NodeInfo {
     let config = CONFIG.get();
     NodeInfo {
         pubkey: config.coordinator_pubkey,
-        address: config.p2p_endpoint,
+        address: config.p2p_endpoint.clone(),
     }
 }
 
@@ -34,8 +42,36 @@ pub fn get_electrs_endpoint() -> SocketAddr {
     CONFIG.get().electrs_endpoint
 }
 
-pub fn get_http_endpoint() -> SocketAddr {
-    CONFIG.get().http_endpoint
+/// The app syncs with Esplora if an endpoint is configured and with electrs otherwise.
+pub fn get_chain_source() -> ChainSource {
+    let config = CONFIG.get();
+    match &config.esplora_endpoint {
+        Some(url) => ChainSource::Esplora { url: url.clone() },
+        None => ChainSource::Electrum {
+            url: config.electrs_endpoint.to_string(),
+        },
+    }
+}
+
+pub fn get_http_endpoint() -> String {
+    CONFIG.get().http_endpoint.clone()
+}
+
+/// The SOCKS5 proxy (e.g. Tor) through which the app connects to the coordinator, if any.
+pub fn get_socks5_proxy() -> Option<SocketAddr> {
+    CONFIG.get().socks5_proxy
+}
+
+/// A client for the coordinator's HTTP API, which goes through the SOCKS5 proxy if one is
+/// configured, so that the coordinator can be reached at an onion address.
+pub fn get_http_client() -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = get_socks5_proxy() {
+        // With `socks5h` the proxy resolves the hostname, which it has to for onion addresses
+        builder = builder.proxy(reqwest::Proxy::all(format!("socks5h://{proxy}"))?);
+    }
+
+    Ok(builder.build()?)
 }
 
 pub fn get_network() -> bitcoin::Network {
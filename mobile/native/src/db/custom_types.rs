@@ -4,6 +4,7 @@ use crate::db::models::FailureReason;
 use crate::db::models::OrderState;
 use crate::db::models::OrderType;
 use crate::db::models::PositionState;
+use crate::db::models::TradeKind;
 use diesel::backend;
 use diesel::deserialize::FromSql;
 use diesel::deserialize::{self};
@@ -122,6 +123,8 @@ impl ToSql<Text, Sqlite> for FailureReason {
             FailureReason::ProposeDlcChannel => "ProposeDlcChannel",
             FailureReason::FailedToSetToFilling => "FailedToSetToFilling",
             FailureReason::OrderNotAcceptable => "OrderNotAcceptable",
+            FailureReason::InvalidDlcOffer => "InvalidDlcOffer",
+            FailureReason::Interrupted => "Interrupted",
         };
         out.set_value(text);
         Ok(IsNull::No)
@@ -140,6 +143,8 @@ impl FromSql<Text, Sqlite> for FailureReason {
             "ProposeDlcChannel" => Ok(FailureReason::ProposeDlcChannel),
             "FailedToSetToFilling" => Ok(FailureReason::FailedToSetToFilling),
             "OrderNotAcceptable" => Ok(FailureReason::OrderNotAcceptable),
+            "InvalidDlcOffer" => Ok(FailureReason::InvalidDlcOffer),
+            "Interrupted" => Ok(FailureReason::Interrupted),
             _ => Err("Unrecognized enum variant".into()),
         };
     }
@@ -168,6 +173,29 @@ impl FromSql<Text, Sqlite> for PositionState {
     }
 }
 
+impl ToSql<Text, Sqlite> for TradeKind {
+    fn to_sql(&self, out: &mut Output<Sqlite>) -> serialize::Result {
+        let text = match *self {
+            TradeKind::Open => "Open",
+            TradeKind::Close => "Close",
+        };
+        out.set_value(text);
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for TradeKind {
+    fn from_sql(bytes: backend::RawValue<Sqlite>) -> deserialize::Result<Self> {
+        let string = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+
+        return match string.as_str() {
+            "Open" => Ok(TradeKind::Open),
+            "Close" => Ok(TradeKind::Close),
+            _ => Err("Unrecognized enum variant".into()),
+        };
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::db::custom_types::tests::customstruct::id;
@@ -1,7 +1,10 @@
 use crate::api;
+use crate::db::models::LegacyPosition;
 use crate::db::models::Order;
 use crate::db::models::OrderState;
+use crate::db::models::PendingSettlement;
 use crate::db::models::Position;
+use crate::db::models::Trade;
 use crate::trade;
 use anyhow::anyhow;
 use anyhow::bail;
@@ -13,10 +16,13 @@ use diesel::r2d2;
 use diesel::r2d2::ConnectionManager;
 use diesel::r2d2::Pool;
 use diesel::r2d2::PooledConnection;
+use diesel::Connection;
 use diesel::SqliteConnection;
 use diesel_migrations::embed_migrations;
 use diesel_migrations::EmbeddedMigrations;
 use diesel_migrations::MigrationHarness;
+use ln_dlc_node::node::rust_dlc_manager::ChannelId;
+use ln_dlc_node::node::rust_dlc_manager::ContractId;
 use state::Storage;
 use std::sync::Arc;
 use time::Duration;
@@ -151,6 +157,15 @@ pub fn get_filled_orders() -> Result<Vec<trade::order::Order>> {
     Ok(orders)
 }
 
+/// Returns the most recent order, leaving out rejected orders and orders which were never sent
+pub fn get_last_order() -> Result<Option<trade::order::Order>> {
+    let order = get_orders_for_ui()?
+        .into_iter()
+        .max_by_key(|order| order.creation_timestamp);
+
+    Ok(order)
+}
+
 /// Returns an order of there is currently an order that is being filled
 pub fn maybe_get_order_in_filling() -> Result<Option<trade::order::Order>> {
     let mut db = connection()?;
@@ -182,7 +197,24 @@ pub fn insert_position(position: trade::position::Position) -> Result<trade::pos
     let mut db = connection()?;
     let position = Position::insert(position.into(), &mut db)?;
 
-    Ok(position.into())
+    Ok(position.try_into()?)
+}
+
+/// Inserts the `position` together with the `trade` which opened it, so that we never have one
+/// without the other.
+pub fn insert_position_with_trade(
+    position: trade::position::Position,
+    trade: trade::position::Trade,
+) -> Result<trade::position::Position> {
+    let mut db = connection()?;
+    let position = db.transaction(|conn| -> Result<Position> {
+        let position = Position::insert(position.into(), conn)?;
+        Trade::insert(trade.into(), conn)?;
+
+        Ok(position)
+    })?;
+
+    Ok(position.try_into()?)
 }
 
 pub fn get_positions() -> Result<Vec<trade::position::Position>> {
@@ -190,26 +222,98 @@ pub fn get_positions() -> Result<Vec<trade::position::Position>> {
     let positions = Position::get_all(&mut db)?;
     let positions = positions
         .into_iter()
-        .map(|position| position.into())
-        .collect();
+        .map(|position| {
+            position
+                .try_into()
+                .context("Failed to convert to trade::position::Position")
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     Ok(positions)
 }
 
-pub fn delete_positions() -> Result<()> {
+pub fn delete_position(contract_id: ContractId) -> Result<()> {
     let mut db = connection()?;
-    Position::delete_all(&mut db)?;
+    Position::delete(hex::encode(contract_id), &mut db)?;
 
     Ok(())
 }
 
 pub fn update_position_state(
-    contract_symbol: ::trade::ContractSymbol,
+    contract_id: ContractId,
     position_state: trade::position::PositionState,
 ) -> Result<()> {
     let mut db = connection()?;
-    Position::update_state(contract_symbol.into(), position_state.into(), &mut db)
+    Position::update_state(hex::encode(contract_id), position_state.into(), &mut db)
         .context("Failed to update position state")?;
 
     Ok(())
 }
+
+pub fn get_legacy_positions() -> Result<Vec<trade::position::LegacyPosition>> {
+    let mut db = connection()?;
+    let positions = LegacyPosition::get_all(&mut db)?;
+    let positions = positions
+        .into_iter()
+        .map(|position| {
+            position
+                .try_into()
+                .context("Failed to convert to trade::position::LegacyPosition")
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(positions)
+}
+
+pub fn delete_legacy_position(contract_symbol: ::trade::ContractSymbol) -> Result<()> {
+    let mut db = connection()?;
+    LegacyPosition::delete(contract_symbol.into(), &mut db)?;
+
+    Ok(())
+}
+
+pub fn insert_trade(trade: trade::position::Trade) -> Result<()> {
+    let mut db = connection()?;
+    Trade::insert(trade.into(), &mut db)?;
+
+    Ok(())
+}
+
+/// Remembers what we get out of the DLC of the DLC channel `channel_id` according to the close
+/// offer we accepted, until the DLC channel is closed.
+pub fn insert_pending_settlement(channel_id: ChannelId, amount_sats: u64) -> Result<()> {
+    let mut db = connection()?;
+    PendingSettlement::insert(
+        PendingSettlement {
+            channel_id: hex::encode(channel_id),
+            amount_sats: amount_sats as i64,
+        },
+        &mut db,
+    )?;
+
+    Ok(())
+}
+
+/// Returns and forgets what we get out of the DLC of the DLC channel `channel_id`, if we accepted
+/// a close offer.
+pub fn take_pending_settlement(channel_id: ChannelId) -> Result<Option<u64>> {
+    let mut db = connection()?;
+    let pending_settlement = PendingSettlement::take(hex::encode(channel_id), &mut db)?;
+
+    Ok(pending_settlement.map(|pending_settlement| pending_settlement.amount_sats as u64))
+}
+
+pub fn get_trades() -> Result<Vec<trade::position::Trade>> {
+    let mut db = connection()?;
+    let trades = Trade::get_all(&mut db)?;
+    let trades = trades
+        .into_iter()
+        .map(|trade| {
+            trade
+                .try_into()
+                .context("Failed to convert to trade::position::Trade")
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(trades)
+}
@@ -1,8 +1,11 @@
 use crate::api;
 use crate::schema;
 use crate::schema::last_login;
+use crate::schema::legacy_positions;
 use crate::schema::orders;
+use crate::schema::pending_settlements;
 use crate::schema::positions;
+use crate::schema::trades;
 use anyhow::bail;
 use anyhow::Result;
 use diesel;
@@ -31,6 +34,8 @@ pub enum Error {
     MissingExecutionPrice,
     #[error("A failed order must have a reason")]
     MissingFailureReason,
+    #[error("Invalid contract id when converting hex string to bytes")]
+    InvalidContractId,
 }
 
 #[derive(Queryable, QueryableByName, Debug, Clone)]
@@ -246,6 +251,7 @@ impl TryFrom<Order> for crate::trade::order::Order {
 #[derive(Queryable, QueryableByName, Insertable, Debug, Clone, PartialEq)]
 #[diesel(table_name = positions)]
 pub(crate) struct Position {
+    pub contract_id: String,
     pub contract_symbol: ContractSymbol,
     pub leverage: f64,
     pub quantity: f64,
@@ -282,14 +288,14 @@ impl Position {
         positions::table.load(conn)
     }
 
-    /// updates the status of the given order in the db
+    /// updates the status of the position with the given contract id in the db
     pub fn update_state(
-        contract_symbol: ContractSymbol,
+        contract_id: String,
         state: PositionState,
         conn: &mut SqliteConnection,
     ) -> Result<()> {
         let effected_rows = diesel::update(positions::table)
-            .filter(schema::positions::contract_symbol.eq(contract_symbol))
+            .filter(schema::positions::contract_id.eq(contract_id))
             .set(schema::positions::state.eq(state))
             .execute(conn)?;
 
@@ -300,16 +306,24 @@ impl Position {
         Ok(())
     }
 
-    // TODO: This is obviously only for the MVP :)
-    /// deletes all positions in the database
-    pub fn delete_all(conn: &mut SqliteConnection) -> QueryResult<usize> {
-        diesel::delete(positions::table).execute(conn)
+    /// deletes the position with the given contract id from the db
+    pub fn delete(contract_id: String, conn: &mut SqliteConnection) -> QueryResult<usize> {
+        diesel::delete(positions::table)
+            .filter(schema::positions::contract_id.eq(contract_id))
+            .execute(conn)
     }
 }
 
-impl From<Position> for crate::trade::position::Position {
-    fn from(value: Position) -> Self {
-        Self {
+impl TryFrom<Position> for crate::trade::position::Position {
+    type Error = Error;
+
+    fn try_from(value: Position) -> std::result::Result<Self, Self::Error> {
+        let mut contract_id = [0; 32];
+        hex::decode_to_slice(&value.contract_id, &mut contract_id)
+            .map_err(|_| Error::InvalidContractId)?;
+
+        Ok(Self {
+            contract_id,
             leverage: value.leverage,
             quantity: value.quantity,
             contract_symbol: value.contract_symbol.into(),
@@ -318,13 +332,14 @@ impl From<Position> for crate::trade::position::Position {
             liquidation_price: value.liquidation_price,
             position_state: value.state.into(),
             collateral: value.collateral as u64,
-        }
+        })
     }
 }
 
 impl From<crate::trade::position::Position> for Position {
     fn from(value: crate::trade::position::Position) -> Self {
         Self {
+            contract_id: hex::encode(value.contract_id),
             contract_symbol: value.contract_symbol.into(),
             leverage: value.leverage,
             quantity: value.quantity,
@@ -338,6 +353,65 @@ impl From<crate::trade::position::Position> for Position {
     }
 }
 
+/// A position stored before positions were identified by the contract ID of their DLC.
+#[derive(Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = legacy_positions)]
+pub(crate) struct LegacyPosition {
+    pub contract_symbol: ContractSymbol,
+    pub leverage: f64,
+    pub quantity: f64,
+    pub direction: Direction,
+    pub average_entry_price: f64,
+    pub liquidation_price: f64,
+    pub state: PositionState,
+    pub collateral: i64,
+    pub creation_timestamp: i64,
+    /// The filled order which opened the position, if it could be told.
+    pub opening_order_id: Option<String>,
+}
+
+impl LegacyPosition {
+    pub fn get_all(conn: &mut SqliteConnection) -> QueryResult<Vec<LegacyPosition>> {
+        legacy_positions::table.load(conn)
+    }
+
+    /// deletes the legacy position with the given contract symbol from the db
+    pub fn delete(
+        contract_symbol: ContractSymbol,
+        conn: &mut SqliteConnection,
+    ) -> QueryResult<usize> {
+        diesel::delete(legacy_positions::table)
+            .filter(schema::legacy_positions::contract_symbol.eq(contract_symbol))
+            .execute(conn)
+    }
+}
+
+impl TryFrom<LegacyPosition> for crate::trade::position::LegacyPosition {
+    type Error = Error;
+
+    fn try_from(value: LegacyPosition) -> std::result::Result<Self, Self::Error> {
+        let opening_order_id = value
+            .opening_order_id
+            .map(|id| Uuid::parse_str(id.as_str()))
+            .transpose()
+            .map_err(Error::InvalidId)?;
+
+        Ok(Self {
+            leverage: value.leverage,
+            quantity: value.quantity,
+            contract_symbol: value.contract_symbol.into(),
+            direction: value.direction.into(),
+            average_entry_price: value.average_entry_price,
+            liquidation_price: value.liquidation_price,
+            position_state: value.state.into(),
+            collateral: value.collateral as u64,
+            creation_timestamp: OffsetDateTime::from_unix_timestamp(value.creation_timestamp)
+                .expect("unix timestamp to fit in itself"),
+            opening_order_id,
+        })
+    }
+}
+
 impl From<crate::trade::position::PositionState> for PositionState {
     fn from(value: crate::trade::position::PositionState) -> Self {
         match value {
@@ -356,6 +430,130 @@ impl From<PositionState> for crate::trade::position::PositionState {
     }
 }
 
+#[derive(Queryable, QueryableByName, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = trades)]
+pub(crate) struct Trade {
+    pub order_id: String,
+    pub contract_id: String,
+    pub kind: TradeKind,
+    pub amount_sats: i64,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
+#[diesel(sql_type = Text)]
+pub enum TradeKind {
+    Open,
+    Close,
+}
+
+impl Trade {
+    /// inserts the given trade into the db. Returns the trade if successful
+    pub fn insert(trade: Trade, conn: &mut SqliteConnection) -> Result<Trade> {
+        let effected_rows = diesel::insert_into(trades::table)
+            .values(&trade)
+            .execute(conn)?;
+
+        if effected_rows > 0 {
+            Ok(trade)
+        } else {
+            bail!("Could not insert trade")
+        }
+    }
+
+    pub fn get_all(conn: &mut SqliteConnection) -> QueryResult<Vec<Trade>> {
+        trades::table.load(conn)
+    }
+}
+
+impl TryFrom<Trade> for crate::trade::position::Trade {
+    type Error = Error;
+
+    fn try_from(value: Trade) -> std::result::Result<Self, Self::Error> {
+        let mut contract_id = [0; 32];
+        hex::decode_to_slice(&value.contract_id, &mut contract_id)
+            .map_err(|_| Error::InvalidContractId)?;
+
+        Ok(Self {
+            order_id: Uuid::parse_str(value.order_id.as_str()).map_err(Error::InvalidId)?,
+            contract_id,
+            kind: value.kind.into(),
+            amount_sats: value.amount_sats as u64,
+            timestamp: OffsetDateTime::from_unix_timestamp(value.timestamp)
+                .expect("unix timestamp to fit in itself"),
+        })
+    }
+}
+
+impl From<crate::trade::position::Trade> for Trade {
+    fn from(value: crate::trade::position::Trade) -> Self {
+        Self {
+            order_id: value.order_id.to_string(),
+            contract_id: hex::encode(value.contract_id),
+            kind: value.kind.into(),
+            amount_sats: value.amount_sats as i64,
+            timestamp: value.timestamp.unix_timestamp(),
+        }
+    }
+}
+
+/// What we get out of the DLC of a DLC channel according to the close offer we accepted.
+#[derive(Queryable, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = pending_settlements)]
+pub(crate) struct PendingSettlement {
+    pub channel_id: String,
+    pub amount_sats: i64,
+}
+
+impl PendingSettlement {
+    /// inserts the given pending settlement into the db, replacing the one of the same channel
+    pub fn insert(
+        pending_settlement: PendingSettlement,
+        conn: &mut SqliteConnection,
+    ) -> QueryResult<usize> {
+        diesel::replace_into(pending_settlements::table)
+            .values(&pending_settlement)
+            .execute(conn)
+    }
+
+    /// deletes the pending settlement of the given channel from the db and returns it
+    pub fn take(
+        channel_id: String,
+        conn: &mut SqliteConnection,
+    ) -> QueryResult<Option<PendingSettlement>> {
+        conn.transaction(|conn| {
+            let pending_settlement = pending_settlements::table
+                .filter(schema::pending_settlements::channel_id.eq(&channel_id))
+                .first(conn)
+                .optional()?;
+
+            diesel::delete(pending_settlements::table)
+                .filter(schema::pending_settlements::channel_id.eq(&channel_id))
+                .execute(conn)?;
+
+            Ok(pending_settlement)
+        })
+    }
+}
+
+impl From<crate::trade::position::TradeKind> for TradeKind {
+    fn from(value: crate::trade::position::TradeKind) -> Self {
+        match value {
+            crate::trade::position::TradeKind::Open => TradeKind::Open,
+            crate::trade::position::TradeKind::Close => TradeKind::Close,
+        }
+    }
+}
+
+impl From<TradeKind> for crate::trade::position::TradeKind {
+    fn from(value: TradeKind) -> Self {
+        match value {
+            TradeKind::Open => crate::trade::position::TradeKind::Open,
+            TradeKind::Close => crate::trade::position::TradeKind::Close,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression)]
 #[diesel(sql_type = Text)]
 pub enum ContractSymbol {
@@ -509,6 +707,8 @@ pub enum FailureReason {
     NoUsableChannel,
     ProposeDlcChannel,
     OrderNotAcceptable,
+    InvalidDlcOffer,
+    Interrupted,
 }
 
 impl From<FailureReason> for crate::trade::order::FailureReason {
@@ -527,6 +727,8 @@ impl From<FailureReason> for crate::trade::order::FailureReason {
             FailureReason::OrderNotAcceptable => {
                 crate::trade::order::FailureReason::OrderNotAcceptable
             }
+            FailureReason::InvalidDlcOffer => crate::trade::order::FailureReason::InvalidDlcOffer,
+            FailureReason::Interrupted => crate::trade::order::FailureReason::Interrupted,
         }
     }
 }
@@ -547,19 +749,30 @@ impl From<crate::trade::order::FailureReason> for FailureReason {
             crate::trade::order::FailureReason::OrderNotAcceptable => {
                 FailureReason::OrderNotAcceptable
             }
+            crate::trade::order::FailureReason::InvalidDlcOffer => FailureReason::InvalidDlcOffer,
+            crate::trade::order::FailureReason::Interrupted => FailureReason::Interrupted,
         }
     }
 }
 
 #[cfg(test)]
 pub mod test {
+    use crate::db::models::ContractSymbol;
     use crate::db::models::LastLogin;
+    use crate::db::models::LegacyPosition;
     use crate::db::models::Order;
     use crate::db::models::OrderState;
+    use crate::db::models::Position;
+    use crate::db::models::PositionState;
+    use crate::db::models::Trade;
     use crate::db::MIGRATIONS;
     use crate::trade::order::FailureReason;
+    use diesel::migration::MigrationSource;
     use diesel::result::Error;
+    use diesel::sql_query;
+    use diesel::sqlite::Sqlite;
     use diesel::Connection;
+    use diesel::RunQueryDsl;
     use diesel::SqliteConnection;
     use diesel_migrations::MigrationHarness;
     use time::OffsetDateTime;
@@ -780,4 +993,208 @@ pub mod test {
         let orders = Order::get_without_rejected_and_initial(&mut connection).unwrap();
         assert_eq!(orders.len(), 2);
     }
+
+    #[test]
+    pub fn several_positions_can_be_open_at_once() {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let position = crate::trade::position::Position {
+            contract_id: [1; 32],
+            leverage: 2.0,
+            quantity: 100.0,
+            contract_symbol: trade::ContractSymbol::BtcUsd,
+            direction: trade::Direction::Long,
+            average_entry_price: 30_000.0,
+            liquidation_price: 20_000.0,
+            position_state: crate::trade::position::PositionState::Open,
+            collateral: 10_000,
+        };
+        let other_position = crate::trade::position::Position {
+            contract_id: [2; 32],
+            ..position.clone()
+        };
+
+        Position::insert(position.into(), &mut connection).unwrap();
+        Position::insert(other_position.into(), &mut connection).unwrap();
+
+        let positions = Position::get_all(&mut connection).unwrap();
+        assert_eq!(positions.len(), 2);
+
+        Position::update_state(
+            hex::encode([2; 32]),
+            PositionState::Closing,
+            &mut connection,
+        )
+        .unwrap();
+
+        let positions = Position::get_all(&mut connection).unwrap();
+        let states = positions
+            .iter()
+            .map(|position| (position.contract_id.clone(), position.state))
+            .collect::<Vec<_>>();
+        assert!(states.contains(&(hex::encode([1; 32]), PositionState::Open)));
+        assert!(states.contains(&(hex::encode([2; 32]), PositionState::Closing)));
+
+        let deleted_rows = Position::delete(hex::encode([2; 32]), &mut connection).unwrap();
+        assert_eq!(deleted_rows, 1);
+
+        let positions = Position::get_all(&mut connection).unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].contract_id, hex::encode([1; 32]));
+    }
+
+    /// Runs the migrations up to and including the migration called `name`.
+    fn run_migrations_up_to(connection: &mut SqliteConnection, name: &str) {
+        let migrations = MigrationSource::<Sqlite>::migrations(&MIGRATIONS).unwrap();
+
+        for migration in migrations {
+            connection.run_migration(migration.as_ref()).unwrap();
+
+            if migration.name().to_string() == name {
+                return;
+            }
+        }
+
+        panic!("No migration called {name}");
+    }
+
+    #[test]
+    pub fn positions_by_contract_symbol_are_kept_as_legacy_positions() {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations_up_to(&mut connection, "2023-03-20-005447_positions");
+
+        sql_query(
+            "INSERT INTO positions (contract_symbol, leverage, quantity, direction, \
+             average_entry_price, liquidation_price, state, collateral, creation_timestamp) \
+             VALUES ('BtcUsd', 2, 100, 'Long', 30000, 20000, 'Open', 10000, 1681000000)",
+        )
+        .execute(&mut connection)
+        .unwrap();
+
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        assert!(Position::get_all(&mut connection).unwrap().is_empty());
+
+        let legacy_positions = LegacyPosition::get_all(&mut connection)
+            .unwrap()
+            .into_iter()
+            .map(|position| crate::trade::position::LegacyPosition::try_from(position).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(legacy_positions.len(), 1);
+        assert_eq!(legacy_positions[0].quantity, 100.0);
+        assert_eq!(legacy_positions[0].collateral, 10_000);
+        assert_eq!(legacy_positions[0].opening_order_id, None);
+
+        let deleted_rows = LegacyPosition::delete(ContractSymbol::BtcUsd, &mut connection).unwrap();
+        assert_eq!(deleted_rows, 1);
+        assert!(LegacyPosition::get_all(&mut connection).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn trades_outlive_their_position() {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let timestamp = OffsetDateTime::from_unix_timestamp(1_681_000_000).unwrap();
+        let open = crate::trade::position::Trade {
+            order_id: uuid::Uuid::new_v4(),
+            contract_id: [1; 32],
+            kind: crate::trade::position::TradeKind::Open,
+            amount_sats: 10_000,
+            timestamp,
+        };
+        let close = crate::trade::position::Trade {
+            order_id: uuid::Uuid::new_v4(),
+            kind: crate::trade::position::TradeKind::Close,
+            amount_sats: 12_500,
+            ..open
+        };
+
+        Trade::insert(open.into(), &mut connection).unwrap();
+        Trade::insert(close.into(), &mut connection).unwrap();
+        Position::delete(hex::encode([1; 32]), &mut connection).unwrap();
+
+        let trades = Trade::get_all(&mut connection)
+            .unwrap()
+            .into_iter()
+            .map(|trade| crate::trade::position::Trade::try_from(trade).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(trades.len(), 2);
+        assert!(trades.contains(&open));
+        assert!(trades.contains(&close));
+    }
+
+    #[test]
+    pub fn pending_settlement_is_taken_once() {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let channel_id = hex::encode([1; 32]);
+        for amount_sats in [10_000, 12_500] {
+            PendingSettlement::insert(
+                PendingSettlement {
+                    channel_id: channel_id.clone(),
+                    amount_sats,
+                },
+                &mut connection,
+            )
+            .unwrap();
+        }
+
+        let pending_settlement = PendingSettlement::take(channel_id.clone(), &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pending_settlement.amount_sats, 12_500);
+
+        assert_eq!(
+            PendingSettlement::take(channel_id, &mut connection).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn legacy_positions_are_linked_to_their_opening_order() {
+        let mut connection = SqliteConnection::establish(":memory:").unwrap();
+        run_migrations_up_to(&mut connection, "2023-04-20-000000_trades");
+
+        let earlier_open_id = uuid::Uuid::new_v4();
+        let earlier_close_id = uuid::Uuid::new_v4();
+        let open_id = uuid::Uuid::new_v4();
+        let failed_id = uuid::Uuid::new_v4();
+        sql_query(format!(
+            "INSERT INTO orders (id, leverage, quantity, contract_symbol, direction, order_type, \
+             state, creation_timestamp, limit_price, execution_price, failure_reason) VALUES \
+             ('{earlier_open_id}', 2, 100, 'BtcUsd', 'Long', 'market', 'filled', 1680000000, NULL, 25000, NULL), \
+             ('{earlier_close_id}', 2, 100, 'BtcUsd', 'Short', 'market', 'filled', 1680000100, NULL, 30000, NULL), \
+             ('{open_id}', 2, 100, 'BtcUsd', 'Long', 'market', 'filled', 1681000000, NULL, 28000, NULL), \
+             ('{failed_id}', 2, 100, 'BtcUsd', 'Long', 'market', 'failed', 1681000200, NULL, NULL, 'TradeResponse')"
+        ))
+        .execute(&mut connection)
+        .unwrap();
+        sql_query(
+            "INSERT INTO legacy_positions (contract_symbol, leverage, quantity, direction, \
+             average_entry_price, liquidation_price, state, collateral, creation_timestamp) \
+             VALUES ('BtcUsd', 2, 100, 'Long', 28000, 18000, 'Open', 178571, 1681000005)",
+        )
+        .execute(&mut connection)
+        .unwrap();
+
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let legacy_positions = LegacyPosition::get_all(&mut connection)
+            .unwrap()
+            .into_iter()
+            .map(|position| crate::trade::position::LegacyPosition::try_from(position).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(legacy_positions.len(), 1);
+        assert_eq!(legacy_positions[0].opening_order_id, Some(open_id));
+
+        // The trade is only recorded once reconciliation knows the DLC of the position, the
+        // orders of the earlier position are not backfilled
+        assert!(Trade::get_all(&mut connection).unwrap().is_empty());
+    }
 }
@@ -1,3 +1,4 @@
+use crate::api::SyncStatus;
 use crate::api::WalletInfo;
 use crate::event::subscriber::Subscriber;
 use crate::event::EventInternal;
@@ -20,6 +21,7 @@ pub enum Event {
     PositionUpdateNotification(Position),
     PositionClosedNotification(PositionClosed),
     PriceUpdateNotification(BestPrice),
+    SyncStatusUpdateNotification(SyncStatus),
 }
 
 impl From<EventInternal> for Event {
@@ -39,8 +41,10 @@ impl From<EventInternal> for Event {
             EventInternal::PositionUpdateNotification(position) => {
                 Event::PositionUpdateNotification(position.into())
             }
-            EventInternal::PositionCloseNotification(contract_symbol) => {
-                Event::PositionClosedNotification(PositionClosed { contract_symbol })
+            EventInternal::PositionCloseNotification(contract_id) => {
+                Event::PositionClosedNotification(PositionClosed {
+                    contract_id: hex::encode(contract_id),
+                })
             }
             EventInternal::PriceUpdateNotification(prices) => {
                 let best_price = prices
@@ -50,18 +54,18 @@ impl From<EventInternal> for Event {
                     .into();
                 Event::PriceUpdateNotification(best_price)
             }
+            EventInternal::SyncStatusUpdateNotification(value) => {
+                Event::SyncStatusUpdateNotification(value)
+            }
         }
     }
 }
 
-/// Wrapper struct needed by frb
-///
-/// The mirrored `ContractSymbol` does not get picked up correctly when using it directly as
-/// type in an enum variant, so we wrap it in a struct.
 #[frb]
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct PositionClosed {
-    pub contract_symbol: ContractSymbol,
+    /// The hex encoded ID of the DLC of the closed position.
+    pub contract_id: String,
 }
 
 #[derive(Clone)]
@@ -83,6 +87,7 @@ impl Subscriber for FlutterSubscriber {
             EventType::PositionUpdateNotification,
             EventType::PositionClosedNotification,
             EventType::PriceUpdateNotification,
+            EventType::SyncStatusUpdateNotification,
         ]
     }
 }
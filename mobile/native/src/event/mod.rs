@@ -2,11 +2,12 @@ pub mod api;
 mod event_hub;
 pub mod subscriber;
 
+use crate::api::SyncStatus;
 use crate::api::WalletInfo;
 use coordinator_commons::TradeParams;
+use ln_dlc_node::node::rust_dlc_manager::ContractId;
 use orderbook_commons::Prices;
 use std::hash::Hash;
-use trade::ContractSymbol;
 
 use crate::event::event_hub::get;
 use crate::event::subscriber::Subscriber;
@@ -29,8 +30,9 @@ pub enum EventInternal {
     WalletInfoUpdateNotification(WalletInfo),
     OrderFilledWith(Box<TradeParams>),
     PositionUpdateNotification(Position),
-    PositionCloseNotification(ContractSymbol),
+    PositionCloseNotification(ContractId),
     PriceUpdateNotification(Prices),
+    SyncStatusUpdateNotification(SyncStatus),
 }
 
 impl From<EventInternal> for EventType {
@@ -46,6 +48,9 @@ impl From<EventInternal> for EventType {
             EventInternal::PositionUpdateNotification(_) => EventType::PositionUpdateNotification,
             EventInternal::PositionCloseNotification(_) => EventType::PositionClosedNotification,
             EventInternal::PriceUpdateNotification(_) => EventType::PriceUpdateNotification,
+            EventInternal::SyncStatusUpdateNotification(_) => {
+                EventType::SyncStatusUpdateNotification
+            }
         }
     }
 }
@@ -60,4 +65,5 @@ pub enum EventType {
     PositionUpdateNotification,
     PositionClosedNotification,
     PriceUpdateNotification,
+    SyncStatusUpdateNotification,
 }
@@ -0,0 +1,116 @@
+use crate::config;
+use crate::ln_dlc::node::Node;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use coordinator_commons::Backup;
+use ln_dlc_node::seed::Bip39Seed;
+use reqwest::StatusCode;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long we wait for further changes before uploading a backup, so that a burst of updates
+/// results in a single upload.
+const BACKUP_DEBOUNCE: Duration = Duration::from_secs(1);
+
+impl Node {
+    /// Uploads an encrypted backup to the coordinator on startup and after every change to the
+    /// channels.
+    pub async fn upload_backups_continuously(&self) {
+        let mut backup_updates = self.inner.subscribe_to_backup_updates();
+
+        loop {
+            if let Err(e) = self.upload_backup().await {
+                tracing::error!("Failed to upload backup: {e:#}");
+            }
+
+            if backup_updates.changed().await.is_err() {
+                tracing::error!("Stopped uploading backups");
+                return;
+            }
+
+            tokio::time::sleep(BACKUP_DEBOUNCE).await;
+            backup_updates.borrow_and_update();
+        }
+    }
+
+    async fn upload_backup(&self) -> Result<()> {
+        let backup = self.inner.create_backup()?;
+        let sequence = self.inner.next_backup_sequence()?;
+        let backup = Backup::new(&backup, sequence, &self.inner.node_key()?);
+
+        let client = config::get_http_client()?;
+        let response = client
+            .put(format!(
+                "http://{}/api/backup/{}",
+                config::get_http_endpoint(),
+                self.inner.info.pubkey
+            ))
+            .json(&backup)
+            .send()
+            .await
+            .context("Failed to upload backup to coordinator")?;
+
+        if !response.status().is_success() {
+            let text = response.text().await?;
+            bail!("Coordinator rejected backup: {text}")
+        }
+
+        tracing::debug!("Uploaded backup");
+
+        Ok(())
+    }
+}
+
+/// Restores the latest backup of the node of the `seed` from the coordinator, if the `data_dir`
+/// has no channel state yet.
+///
+/// Fails unless the coordinator returns the backup or tells us that there is none, as starting
+/// without a backup which exists would lose the channels of the node. As long as there is no
+/// channel state in the `data_dir`, we ask the coordinator again on the next start.
+pub async fn restore_backup_if_available(data_dir: &Path, seed: &Bip39Seed) -> Result<()> {
+    if data_dir.join("manager").exists() {
+        return Ok(());
+    }
+
+    let node_id = ln_dlc_node::backup::node_id(seed)?;
+
+    let client = config::get_http_client()?;
+    let response = client
+        .get(format!(
+            "http://{}/api/backup/{node_id}",
+            config::get_http_endpoint()
+        ))
+        .send()
+        .await
+        .context("Failed to fetch backup from coordinator")?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        tracing::info!("No backup to restore");
+        return Ok(());
+    }
+
+    if !response.status().is_success() {
+        let text = response.text().await.unwrap_or_default();
+        bail!("Failed to fetch backup from coordinator: {text}");
+    }
+
+    let backup: Backup = response
+        .json()
+        .await
+        .context("Failed to read backup from coordinator")?;
+    let sequence = backup.sequence;
+    let backup = match backup.signature {
+        Some(_) => backup
+            .verify(&node_id)
+            .context("Backup from coordinator was not signed by our node")?,
+        None => {
+            tracing::warn!("Restoring backup stored before backups were signed");
+            backup.unverified_backup()?
+        }
+    };
+
+    tracing::info!(sequence, "Restoring backup");
+
+    ln_dlc_node::backup::restore(data_dir, seed, &backup, sequence)
+}
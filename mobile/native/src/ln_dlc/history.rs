@@ -0,0 +1,360 @@
+use crate::api;
+use crate::trade::position::Trade;
+use crate::trade::position::TradeKind;
+use bdk::BlockTime;
+use bdk::TransactionDetails;
+use ln_dlc_node::node::HTLCStatus;
+use ln_dlc_node::node::PaymentDetails;
+use ln_dlc_node::node::PaymentFlow;
+use std::cmp::Reverse;
+use time::OffsetDateTime;
+
+/// Assembles the wallet history, most recent entries first.
+///
+/// `tip_height` is the height of the blockchain, used to count the confirmations of on-chain
+/// transactions. Unconfirmed transactions are timestamped with `now`, so that they appear towards
+/// the top of the history.
+pub fn wallet_history(
+    on_chain: &[TransactionDetails],
+    off_chain: &[PaymentDetails],
+    trades: &[Trade],
+    tip_height: u32,
+    now: OffsetDateTime,
+) -> Vec<api::WalletHistoryItem> {
+    let on_chain = on_chain
+        .iter()
+        .map(|details| on_chain_item(details, tip_height, now));
+    let off_chain = off_chain.iter().filter_map(lightning_item);
+    let trades = trades.iter().map(trade_item);
+
+    let mut history = on_chain.chain(off_chain).chain(trades).collect::<Vec<_>>();
+
+    // Sorting by ID as well keeps the order of entries with the same timestamp stable
+    history.sort_by(|a, b| (Reverse(a.timestamp), &a.id).cmp(&(Reverse(b.timestamp), &b.id)));
+
+    history
+}
+
+fn on_chain_item(
+    details: &TransactionDetails,
+    tip_height: u32,
+    now: OffsetDateTime,
+) -> api::WalletHistoryItem {
+    let net_sats = details.received as i64 - details.sent as i64;
+
+    // We only pay the fee if we spend our own coins, in which case it is part of what we sent
+    let (flow, amount_sats, fee_sats) = if net_sats >= 0 {
+        (api::PaymentFlow::Inbound, net_sats as u64, None)
+    } else {
+        let fee_sats = details.fee.unwrap_or_default();
+        let amount_sats = net_sats.unsigned_abs().saturating_sub(fee_sats);
+
+        (api::PaymentFlow::Outbound, amount_sats, details.fee)
+    };
+
+    let (status, timestamp, confirmations) = match details.confirmation_time {
+        Some(BlockTime { height, timestamp }) => {
+            // The tip we know of can lag behind the wallet, but a confirmed transaction always
+            // has at least one confirmation
+            let confirmations = tip_height.saturating_sub(height) + 1;
+
+            (api::Status::Confirmed, timestamp, confirmations as u64)
+        }
+        None => (api::Status::Pending, now.unix_timestamp() as u64, 0),
+    };
+
+    api::WalletHistoryItem {
+        id: format!("on-chain-{}", details.txid),
+        flow,
+        amount_sats,
+        timestamp,
+        status,
+        wallet_type: api::WalletType::OnChain {
+            txid: details.txid.to_string(),
+            fee_sats,
+            confirmations,
+        },
+    }
+}
+
+/// Maps a Lightning payment to an entry of the wallet history, unless it did not move any funds
+/// (yet).
+fn lightning_item(details: &PaymentDetails) -> Option<api::WalletHistoryItem> {
+    // Skip payments that don't yet have an amount associated
+    let amount_msat = details.amount_msat?;
+
+    let (flow, flow_id) = match details.flow {
+        PaymentFlow::Inbound => (api::PaymentFlow::Inbound, "inbound"),
+        PaymentFlow::Outbound => (api::PaymentFlow::Outbound, "outbound"),
+    };
+
+    let status = match (&details.status, &details.flow) {
+        // An invoice we created which has not been paid
+        (HTLCStatus::Pending, PaymentFlow::Inbound) => return None,
+        (HTLCStatus::Pending, PaymentFlow::Outbound) => api::Status::Pending,
+        (HTLCStatus::Succeeded, _) => api::Status::Confirmed,
+        // TODO: Handle failed payments
+        (HTLCStatus::Failed, _) => return None,
+    };
+
+    let payment_hash = hex::encode(details.payment_hash.0);
+
+    // We pay ourselves when moving our balance between channels, so the payment hash alone does
+    // not identify the entry
+    Some(api::WalletHistoryItem {
+        id: format!("lightning-{flow_id}-{payment_hash}"),
+        flow,
+        amount_sats: amount_msat / 1_000,
+        timestamp: details.timestamp.unix_timestamp() as u64,
+        status,
+        wallet_type: api::WalletType::Lightning {
+            payment_hash,
+            description: details.description.clone(),
+        },
+    })
+}
+
+fn trade_item(trade: &Trade) -> api::WalletHistoryItem {
+    let flow = match trade.kind {
+        TradeKind::Open => api::PaymentFlow::Outbound,
+        TradeKind::Close => api::PaymentFlow::Inbound,
+    };
+
+    api::WalletHistoryItem {
+        id: format!("trade-{}", trade.order_id),
+        flow,
+        amount_sats: trade.amount_sats,
+        timestamp: trade.timestamp.unix_timestamp() as u64,
+        // Trades are only recorded once the DLC has been set up or settled
+        status: api::Status::Confirmed,
+        wallet_type: api::WalletType::Trade {
+            order_id: trade.order_id.to_string(),
+            contract_id: hex::encode(trade.contract_id),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+    use bdk::bitcoin::Txid;
+    use lightning::ln::PaymentHash;
+    use uuid::Uuid;
+
+    const NOW: i64 = 1_681_000_000;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(NOW).unwrap()
+    }
+
+    fn transaction(
+        received: u64,
+        sent: u64,
+        fee: Option<u64>,
+        confirmation_time: Option<BlockTime>,
+    ) -> TransactionDetails {
+        TransactionDetails {
+            transaction: None,
+            txid: Txid::all_zeros(),
+            received,
+            sent,
+            fee,
+            confirmation_time,
+        }
+    }
+
+    fn payment(flow: PaymentFlow, status: HTLCStatus, amount_msat: Option<u64>) -> PaymentDetails {
+        PaymentDetails {
+            payment_hash: PaymentHash([1; 32]),
+            status,
+            flow,
+            amount_msat,
+            timestamp: now(),
+            description: "coffee".to_string(),
+        }
+    }
+
+    fn trade(kind: TradeKind, amount_sats: u64, timestamp: i64) -> Trade {
+        Trade {
+            order_id: Uuid::new_v4(),
+            contract_id: [1; 32],
+            kind,
+            amount_sats,
+            timestamp: OffsetDateTime::from_unix_timestamp(timestamp).unwrap(),
+        }
+    }
+
+    fn block_time(height: u32) -> Option<BlockTime> {
+        Some(BlockTime {
+            height,
+            timestamp: 1_680_000_000,
+        })
+    }
+
+    #[test]
+    fn received_transaction_is_inbound() {
+        let details = transaction(50_000, 0, Some(200), block_time(100));
+
+        let item = on_chain_item(&details, 100, now());
+
+        assert!(matches!(item.flow, api::PaymentFlow::Inbound));
+        assert_eq!(item.amount_sats, 50_000);
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::OnChain { fee_sats: None, .. }
+        ));
+    }
+
+    #[test]
+    fn sent_transaction_is_outbound_and_shows_fee_separately() {
+        // We spent a 100_000 sats coin, paid 30_000 sats and got 69_800 sats of change back
+        let details = transaction(69_800, 100_000, Some(200), block_time(100));
+
+        let item = on_chain_item(&details, 100, now());
+
+        assert!(matches!(item.flow, api::PaymentFlow::Outbound));
+        assert_eq!(item.amount_sats, 30_000);
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::OnChain {
+                fee_sats: Some(200),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn confirmations_are_counted_from_the_tip() {
+        let details = transaction(50_000, 0, None, block_time(100));
+
+        let item = on_chain_item(&details, 105, now());
+
+        assert!(matches!(item.status, api::Status::Confirmed));
+        assert_eq!(item.timestamp, 1_680_000_000);
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::OnChain {
+                confirmations: 6,
+                ..
+            }
+        ));
+
+        // The tip may not have caught up with the wallet yet
+        let item = on_chain_item(&details, 99, now());
+
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::OnChain {
+                confirmations: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unconfirmed_transaction_is_pending_and_recent() {
+        let details = transaction(50_000, 0, None, None);
+
+        let item = on_chain_item(&details, 100, now());
+
+        assert!(matches!(item.status, api::Status::Pending));
+        assert_eq!(item.timestamp, NOW as u64);
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::OnChain {
+                confirmations: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn lightning_payment_keeps_description() {
+        let details = payment(PaymentFlow::Outbound, HTLCStatus::Pending, Some(21_000_500));
+
+        let item = lightning_item(&details).unwrap();
+
+        assert!(matches!(item.flow, api::PaymentFlow::Outbound));
+        assert!(matches!(item.status, api::Status::Pending));
+        assert_eq!(item.amount_sats, 21_000);
+        assert!(matches!(
+            item.wallet_type,
+            api::WalletType::Lightning { ref description, .. } if description == "coffee"
+        ));
+    }
+
+    #[test]
+    fn unpaid_invoice_and_failed_payment_are_not_shown() {
+        let unpaid = payment(PaymentFlow::Inbound, HTLCStatus::Pending, Some(1_000));
+        let failed = payment(PaymentFlow::Outbound, HTLCStatus::Failed, Some(1_000));
+        let without_amount = payment(PaymentFlow::Inbound, HTLCStatus::Succeeded, None);
+
+        assert!(lightning_item(&unpaid).is_none());
+        assert!(lightning_item(&failed).is_none());
+        assert!(lightning_item(&without_amount).is_none());
+    }
+
+    #[test]
+    fn payment_to_ourselves_yields_distinct_entries() {
+        let sent = payment(PaymentFlow::Outbound, HTLCStatus::Succeeded, Some(1_000));
+        let received = payment(PaymentFlow::Inbound, HTLCStatus::Succeeded, Some(1_000));
+
+        let sent = lightning_item(&sent).unwrap();
+        let received = lightning_item(&received).unwrap();
+
+        assert_ne!(sent.id, received.id);
+    }
+
+    #[test]
+    fn opening_trade_is_outbound_and_closing_trade_is_inbound() {
+        let open = trade(TradeKind::Open, 10_000, NOW);
+        let close = trade(TradeKind::Close, 12_500, NOW);
+
+        let open = trade_item(&open);
+        let close = trade_item(&close);
+
+        assert!(matches!(open.flow, api::PaymentFlow::Outbound));
+        assert_eq!(open.amount_sats, 10_000);
+        assert!(matches!(close.flow, api::PaymentFlow::Inbound));
+        assert_eq!(close.amount_sats, 12_500);
+    }
+
+    #[test]
+    fn history_is_sorted_by_most_recent_first_and_stable() {
+        let trades = [
+            trade(TradeKind::Open, 10_000, NOW - 100),
+            trade(TradeKind::Close, 12_500, NOW - 10),
+            trade(TradeKind::Open, 20_000, NOW - 10),
+        ];
+        let on_chain = [transaction(50_000, 0, None, None)];
+
+        let history = wallet_history(&on_chain, &[], &trades, 100, now());
+
+        let timestamps = history
+            .iter()
+            .map(|item| item.timestamp)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            timestamps,
+            vec![
+                NOW as u64,
+                NOW as u64 - 10,
+                NOW as u64 - 10,
+                NOW as u64 - 100
+            ]
+        );
+
+        let mut reversed_trades = trades;
+        reversed_trades.reverse();
+        let same_history = wallet_history(&on_chain, &[], &reversed_trades, 100, now());
+
+        let ids = |history: &[api::WalletHistoryItem]| {
+            history
+                .iter()
+                .map(|item| item.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&history), ids(&same_history));
+    }
+}
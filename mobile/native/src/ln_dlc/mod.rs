@@ -8,18 +8,31 @@ use crate::trade::order::FailureReason;
 use crate::trade::position;
 use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash;
 use bdk::bitcoin::secp256k1::rand::thread_rng;
 use bdk::bitcoin::secp256k1::rand::RngCore;
+use bdk::bitcoin::secp256k1::PublicKey;
 use bdk::bitcoin::secp256k1::SecretKey;
+use bdk::bitcoin::Address;
+use bdk::bitcoin::Network;
+use bdk::bitcoin::OutPoint;
+use bdk::bitcoin::Txid;
 use bdk::bitcoin::XOnlyPublicKey;
-use bdk::BlockTime;
+use coordinator_commons::LiquidityOffer;
+use coordinator_commons::LiquidityPurchase;
+use coordinator_commons::LiquidityPurchaseParams;
 use coordinator_commons::TradeParams;
-use itertools::chain;
-use itertools::Itertools;
+use coordinator_commons::UpgradeChannelParams;
 use lightning_invoice::Invoice;
+use ln_dlc_node::node::Fee;
+use ln_dlc_node::node::InterceptScid;
 use ln_dlc_node::node::NodeInfo;
+use ln_dlc_node::node::SendAmount;
+use ln_dlc_node::node::Utxo;
 use ln_dlc_node::seed::Bip39Seed;
 use state::Storage;
 use std::net::IpAddr;
@@ -27,16 +40,23 @@ use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::net::TcpListener;
 use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 use time::OffsetDateTime;
 use tokio::runtime::Runtime;
 
+mod backup;
+mod history;
 mod node;
+mod reconciliation;
+mod validation;
 
 static NODE: Storage<Arc<Node>> = Storage::new();
-const PROCESS_INCOMING_MESSAGES_INTERVAL: Duration = Duration::from_secs(5);
+static SEED: Storage<Bip39Seed> = Storage::new();
+const CHANNEL_UPGRADE_TIMEOUT: Duration = Duration::from_secs(60);
 
 pub async fn refresh_wallet_info() -> Result<()> {
     let node = NODE.try_get().context("failed to get ln dlc node")?;
@@ -46,6 +66,41 @@ pub async fn refresh_wallet_info() -> Result<()> {
     Ok(())
 }
 
+pub fn get_seed_phrase() -> Result<Vec<String>> {
+    Ok(SEED
+        .try_get()
+        .context("failed to get seed")?
+        .get_seed_phrase())
+}
+
+/// The seed file of the node on `network` in the `app_dir`.
+fn seed_file(app_dir: &Path, network: Network) -> PathBuf {
+    app_dir.join(network.to_string()).join("seed")
+}
+
+/// Stores the seed restored from the `seed_phrase` where the node on `network` picks it up on
+/// startup.
+///
+/// Refuses to overwrite an existing seed file.
+pub fn restore_from_seed_phrase(
+    seed_phrase: &str,
+    passphrase: Option<String>,
+    app_dir: &Path,
+    network: Network,
+    seed_encryption_key: Option<&[u8]>,
+) -> Result<()> {
+    tracing::info!(%network, "Restoring seed from seed phrase");
+
+    let seed_file = seed_file(app_dir, network);
+    if let Some(seed_dir) = seed_file.parent() {
+        std::fs::create_dir_all(seed_dir)
+            .context(format!("Could not create seed dir ({seed_dir:?})"))?;
+    }
+
+    Bip39Seed::restore_from_mnemonic(seed_phrase, passphrase)?
+        .write_to(&seed_file, seed_encryption_key)
+}
+
 pub fn get_node_key() -> Result<SecretKey> {
     NODE.try_get()
         .context("failed to get ln dlc node")?
@@ -58,7 +113,8 @@ pub fn get_node_info() -> Result<NodeInfo> {
         .try_get()
         .context("failed to get ln dlc node")?
         .inner
-        .info)
+        .info
+        .clone())
 }
 
 // TODO: should we also wrap the oracle as `NodeInfo`. It would fit the required attributes pubkey
@@ -84,7 +140,7 @@ fn runtime() -> Result<&'static Runtime> {
     Ok(RUNTIME.get())
 }
 
-pub fn run(data_dir: String) -> Result<()> {
+pub fn run(data_dir: String, seed_encryption_key: Option<String>) -> Result<()> {
     let network = config::get_network();
     let runtime = runtime()?;
 
@@ -94,6 +150,7 @@ pub fn run(data_dir: String) -> Result<()> {
         let mut ephemeral_randomness = [0; 32];
         thread_rng().fill_bytes(&mut ephemeral_randomness);
 
+        let seed_path = seed_file(Path::new(&data_dir), network);
         let data_dir = Path::new(&data_dir).join(network.to_string());
         if !data_dir.exists() {
             std::fs::create_dir_all(&data_dir)
@@ -108,8 +165,15 @@ pub fn run(data_dir: String) -> Result<()> {
             listener.local_addr().expect("To get a free local address")
         };
 
-        let seed_path = data_dir.join("seed");
-        let seed = Bip39Seed::initialize(&seed_path)?;
+        let seed = match seed_encryption_key {
+            Some(seed_encryption_key) => {
+                Bip39Seed::initialize_encrypted(&seed_path, seed_encryption_key.as_bytes())?
+            }
+            None => Bip39Seed::initialize(&seed_path)?,
+        };
+        SEED.set(seed.clone());
+
+        backup::restore_backup_if_available(&data_dir, &seed).await?;
 
         let node = Arc::new(
             ln_dlc_node::node::Node::new_app(
@@ -118,37 +182,58 @@ pub fn run(data_dir: String) -> Result<()> {
                 data_dir.as_path(),
                 address,
                 SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), address.port()),
-                config::get_electrs_endpoint().to_string(),
+                config::get_chain_source(),
                 seed,
                 ephemeral_randomness,
+                vec![config::get_coordinator_info().pubkey],
+                config::get_socks5_proxy(),
             )
             .await?,
         );
-        let node = Arc::new(Node { inner: node });
+        let node = Arc::new(Node {
+            inner: node,
+            pending_trade: Arc::new(Mutex::new(None)),
+        });
+
+        if let Err(e) = node.reconcile_orders_and_positions() {
+            tracing::error!("Failed to reconcile orders and positions with DLC channel: {e:#}");
+        }
+
+        // Without a connection to the coordinator we can't trade, but the wallet is still usable
+        if let Err(e) = node.inner.keep_connected(config::get_coordinator_info()) {
+            tracing::error!("Failed to keep connected to the coordinator: {e:#}");
+        }
 
         runtime.spawn({
             let node = node.clone();
-            async move { node.keep_connected(config::get_coordinator_info()).await }
+            async move {
+                node.inner
+                    .process_incoming_messages_continuously(|node_id, incoming_msg, _| {
+                        node.on_sub_channel_message(node_id, incoming_msg)
+                    })
+                    .await
+            }
         });
 
         runtime.spawn({
             let node = node.clone();
-            async move {
-                loop {
-                    if let Err(e) = node.process_incoming_messages() {
-                        tracing::error!("Unable to process incoming messages: {e:#}");
-                    }
+            async move { node.handle_dlc_channel_events().await }
+        });
 
-                    tokio::time::sleep(PROCESS_INCOMING_MESSAGES_INTERVAL).await;
-                }
-            }
+        runtime.spawn({
+            let node = node.clone();
+            async move { node.upload_backups_continuously().await }
         });
 
         runtime.spawn({
             let node = node.clone();
             async move {
+                let mut last_sync_status = None;
                 loop {
-                    if let Err(e) = keep_wallet_balance_and_history_up_to_date(&node).await {
+                    if let Err(e) =
+                        keep_wallet_balance_and_history_up_to_date(&node, &mut last_sync_status)
+                            .await
+                    {
                         tracing::error!("Failed to sync balance and wallet history: {e:#}");
                     }
 
@@ -163,8 +248,22 @@ pub fn run(data_dir: String) -> Result<()> {
     })
 }
 
-async fn keep_wallet_balance_and_history_up_to_date(node: &Node) -> Result<()> {
-    node.inner.sync()?;
+async fn keep_wallet_balance_and_history_up_to_date(
+    node: &Node,
+    last_sync_status: &mut Option<api::SyncStatus>,
+) -> Result<()> {
+    let sync_result = node.inner.sync();
+
+    // Also published if the sync failed, so that the UI can tell that we are out of sync
+    let sync_status = api::SyncStatus::from(node.inner.sync_status());
+    if is_sync_status_changed(last_sync_status.as_ref(), &sync_status) {
+        event::publish(&EventInternal::SyncStatusUpdateNotification(
+            sync_status.clone(),
+        ));
+        *last_sync_status = Some(sync_status);
+    }
+
+    sync_result?;
 
     let wallet_balances = node
         .get_wallet_balances()
@@ -177,108 +276,20 @@ async fn keep_wallet_balance_and_history_up_to_date(node: &Node) -> Result<()> {
         .get_wallet_histories()
         .context("Failed to get wallet histories")?;
 
-    let on_chain = on_chain.iter().map(|details| {
-        let net_sats = details.received as i64 - details.sent as i64;
-
-        let (flow, amount_sats) = if net_sats >= 0 {
-            (api::PaymentFlow::Outbound, net_sats as u64)
-        } else {
-            (api::PaymentFlow::Inbound, net_sats.unsigned_abs())
-        };
-
-        let (status, timestamp) = match details.confirmation_time {
-            Some(BlockTime { timestamp, .. }) => (api::Status::Confirmed, timestamp),
-
-            None => {
-                (
-                    api::Status::Pending,
-                    // Unconfirmed transactions should appear towards the top of the history
-                    OffsetDateTime::now_utc().unix_timestamp() as u64,
-                )
-            }
-        };
-
-        let wallet_type = api::WalletType::OnChain {
-            txid: details.txid.to_string(),
-        };
-
-        api::WalletHistoryItem {
-            flow,
-            amount_sats,
-            timestamp,
-            status,
-            wallet_type,
-        }
-    });
-
-    let off_chain = off_chain.iter().filter_map(|details| {
-        let amount_sats = match details.amount_msat {
-            Some(msat) => msat / 1_000,
-            // Skip payments that don't yet have an amount associated
-            None => return None,
-        };
-
-        let status = match details.status {
-            ln_dlc_node::node::HTLCStatus::Pending => api::Status::Pending,
-            ln_dlc_node::node::HTLCStatus::Succeeded => api::Status::Confirmed,
-            // TODO: Handle failed payments
-            ln_dlc_node::node::HTLCStatus::Failed => return None,
-        };
-
-        let flow = match details.flow {
-            ln_dlc_node::node::PaymentFlow::Inbound => api::PaymentFlow::Inbound,
-            ln_dlc_node::node::PaymentFlow::Outbound => api::PaymentFlow::Outbound,
-        };
-
-        let timestamp = details.timestamp.unix_timestamp() as u64;
-
-        let wallet_type = api::WalletType::Lightning {
-            payment_hash: hex::encode(details.payment_hash.0),
-        };
+    let tip_height = node
+        .inner
+        .get_blockchain_height()
+        .context("Failed to get blockchain height")?;
 
-        Some(api::WalletHistoryItem {
-            flow,
-            amount_sats,
-            timestamp,
-            status,
-            wallet_type,
-        })
-    });
-
-    let trades = {
-        let orders = crate::db::get_filled_orders()
-            .context("Failed to get filled orders; skipping update")?;
-
-        orders.into_iter().enumerate().map(|(i, order)| {
-            let flow = if i % 2 == 0 {
-                api::PaymentFlow::Outbound
-            } else {
-                api::PaymentFlow::Inbound
-            };
-
-            let amount_sats = order
-                .trader_margin()
-                .expect("Filled order to have a margin");
-
-            let timestamp = order.creation_timestamp.unix_timestamp() as u64;
-
-            let wallet_type = api::WalletType::Trade {
-                order_id: order.id.to_string(),
-            };
-
-            api::WalletHistoryItem {
-                flow,
-                amount_sats,
-                timestamp,
-                status: api::Status::Confirmed, // TODO: Support other order/trade statuses
-                wallet_type,
-            }
-        })
-    };
+    let trades = crate::db::get_trades().context("Failed to get trades; skipping update")?;
 
-    let history = chain![on_chain, off_chain, trades]
-        .sorted_by(|a, b| b.timestamp.cmp(&a.timestamp))
-        .collect();
+    let history = history::wallet_history(
+        &on_chain,
+        &off_chain,
+        &trades,
+        tip_height,
+        OffsetDateTime::now_utc(),
+    );
 
     let wallet_info = api::WalletInfo {
         balances: wallet_balances.into(),
@@ -290,6 +301,32 @@ async fn keep_wallet_balance_and_history_up_to_date(node: &Node) -> Result<()> {
     Ok(())
 }
 
+/// Whether the UI has to be told about `current`, given that it last learned about `previous`.
+///
+/// Another successful sync to the same block does not change what the UI shows.
+fn is_sync_status_changed(previous: Option<&api::SyncStatus>, current: &api::SyncStatus) -> bool {
+    let without_sync_time = |status: &api::SyncStatus| api::SyncStatus {
+        last_successful_sync: None,
+        ..status.clone()
+    };
+
+    previous.map(without_sync_time) != Some(without_sync_time(current))
+}
+
+impl From<ln_dlc_node::node::SyncStatus> for api::SyncStatus {
+    fn from(value: ln_dlc_node::node::SyncStatus) -> Self {
+        Self {
+            in_sync: value.is_in_sync(),
+            synced_height: value.synced_height,
+            tip_height: value.tip_height,
+            last_successful_sync: value
+                .last_successful_sync
+                .map(|timestamp| timestamp.unix_timestamp()),
+            chain_source_reachable: value.chain_source_reachable,
+        }
+    }
+}
+
 pub fn get_new_address() -> Result<String> {
     let node = NODE.try_get().context("failed to get ln dlc node")?;
     let address = node
@@ -299,6 +336,61 @@ pub fn get_new_address() -> Result<String> {
     Ok(address.to_string())
 }
 
+pub fn list_utxos() -> Result<Vec<Utxo>> {
+    let node = NODE.try_get().context("failed to get ln dlc node")?;
+    node.inner.list_utxos()
+}
+
+/// Sends on-chain funds to `address`, spending only `utxos` if any are given.
+pub fn send_on_chain(
+    address: &str,
+    amount: SendAmount,
+    fee: Fee,
+    utxos: &[OutPoint],
+) -> Result<Txid> {
+    let node = NODE.try_get().context("failed to get ln dlc node")?;
+    let address = Address::from_str(address).context("Could not parse address")?;
+
+    let txid = node.inner.send_to_address(&address, amount, fee, utxos)?;
+
+    refresh_wallet_info_in_background();
+
+    Ok(txid)
+}
+
+pub fn bump_fee(txid: Txid, fee: Fee) -> Result<Txid> {
+    let node = NODE.try_get().context("failed to get ln dlc node")?;
+
+    let txid = node.inner.bump_fee(txid, fee)?;
+
+    refresh_wallet_info_in_background();
+
+    Ok(txid)
+}
+
+pub fn cpfp(outpoint: OutPoint, fee: Fee) -> Result<Txid> {
+    let node = NODE.try_get().context("failed to get ln dlc node")?;
+
+    let txid = node.inner.cpfp(outpoint, fee)?;
+
+    refresh_wallet_info_in_background();
+
+    Ok(txid)
+}
+
+fn refresh_wallet_info_in_background() {
+    match runtime() {
+        Ok(runtime) => {
+            runtime.spawn(async {
+                if let Err(e) = refresh_wallet_info().await {
+                    tracing::error!("Failed to refresh wallet info: {e:#}");
+                }
+            });
+        }
+        Err(e) => tracing::error!("Failed to refresh wallet info: {e:#}"),
+    }
+}
+
 /// TODO: remove this function once the lightning faucet is more stable. This is only added for
 /// testing purposes - so that we can quickly get funds into the lightning wallet.
 pub fn open_channel() -> Result<()> {
@@ -310,12 +402,222 @@ pub fn open_channel() -> Result<()> {
     Ok(())
 }
 
+/// Replaces our channel with the coordinator with a bigger one of `channel_capacity_sat`.
+///
+/// The coordinator opens the replacement channel, we move our balance over to it with a circular
+/// payment and then cooperatively close the old channel.
+pub fn upgrade_channel(channel_capacity_sat: u64) -> Result<()> {
+    let runtime = runtime()?;
+
+    runtime.block_on(async {
+        let node = NODE.try_get().context("failed to get ln dlc node")?;
+        let coordinator = config::get_coordinator_info().pubkey;
+
+        let old_channel = node
+            .inner
+            .list_usable_channels()
+            .into_iter()
+            .find(|channel| channel.counterparty.node_id == coordinator)
+            .context("No usable channel with the coordinator to upgrade")?;
+
+        let client = config::get_http_client()?;
+        let response = client
+            .post(format!(
+                "http://{}/api/channels/upgrade",
+                config::get_http_endpoint()
+            ))
+            .json(&UpgradeChannelParams::new(
+                channel_capacity_sat,
+                &node.inner.node_key()?,
+            ))
+            .send()
+            .await
+            .context("Failed to request channel upgrade from coordinator")?;
+
+        if !response.status().is_success() {
+            let text = response.text().await?;
+            bail!("Coordinator rejected channel upgrade: {text}")
+        }
+
+        let new_channel = tokio::time::timeout(CHANNEL_UPGRADE_TIMEOUT, async {
+            loop {
+                if let Some(channel) = node.inner.list_usable_channels().into_iter().find(|c| {
+                    c.counterparty.node_id == coordinator && c.channel_id != old_channel.channel_id
+                }) {
+                    break channel;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .context("Replacement channel did not become usable in time")?;
+
+        let payment_hash = node
+            .inner
+            .move_balance_to_replacement_channel(old_channel.channel_id, new_channel.channel_id)?;
+
+        node.inner
+            .wait_for_payment_claimed(&sha256::Hash::from_inner(payment_hash.0))
+            .await
+            .context("Circular payment to replacement channel was not claimed in time")?;
+
+        node.inner.close_channel(old_channel.channel_id)?;
+
+        tracing::info!(
+            old_channel_id = %hex::encode(old_channel.channel_id),
+            new_channel_id = %hex::encode(new_channel.channel_id),
+            channel_capacity_sat,
+            "Upgraded channel with coordinator"
+        );
+
+        Ok(())
+    })
+}
+
+/// The fee rate in sats per vbyte the coordinator currently uses for the transactions of the DLC
+/// channels it proposes.
+pub fn get_dlc_fee_rate() -> Result<u64> {
+    let runtime = runtime()?;
+
+    runtime.block_on(async {
+        let client = config::get_http_client()?;
+        let response = client
+            .get(format!(
+                "http://{}/api/dlc_channels/fee_rate",
+                config::get_http_endpoint()
+            ))
+            .send()
+            .await
+            .context("Failed to fetch DLC fee rate from coordinator")?;
+
+        if !response.status().is_success() {
+            let text = response.text().await?;
+            bail!("Failed to fetch DLC fee rate from coordinator: {text}")
+        }
+
+        Ok(response.json().await?)
+    })
+}
+
+pub fn get_liquidity_offer() -> Result<LiquidityOffer> {
+    let runtime = runtime()?;
+
+    runtime.block_on(async {
+        let client = config::get_http_client()?;
+        let response = client
+            .get(format!(
+                "http://{}/api/liquidity/offer",
+                config::get_http_endpoint()
+            ))
+            .send()
+            .await
+            .context("Failed to fetch liquidity offer from coordinator")?;
+
+        if !response.status().is_success() {
+            let text = response.text().await?;
+            bail!("Failed to fetch liquidity offer from coordinator: {text}")
+        }
+
+        Ok(response.json().await?)
+    })
+}
+
+/// Buys `inbound_liquidity_sat` of inbound liquidity from the coordinator.
+///
+/// We pay the coordinator's invoice for the lease right away and then upgrade our channel with the
+/// coordinator to one which includes the purchased liquidity.
+pub fn buy_inbound_liquidity(inbound_liquidity_sat: u64) -> Result<LiquidityPurchase> {
+    let offer = get_liquidity_offer()?;
+    let runtime = runtime()?;
+
+    let purchase: LiquidityPurchase = runtime.block_on(async {
+        let node = NODE.try_get().context("failed to get ln dlc node")?;
+        let client = config::get_http_client()?;
+        let response = client
+            .post(format!(
+                "http://{}/api/liquidity/purchase",
+                config::get_http_endpoint()
+            ))
+            .json(&LiquidityPurchaseParams::new(
+                inbound_liquidity_sat,
+                &node.inner.node_key()?,
+            ))
+            .send()
+            .await
+            .context("Failed to request liquidity purchase from coordinator")?;
+
+        if !response.status().is_success() {
+            let text = response.text().await?;
+            bail!("Coordinator rejected liquidity purchase: {text}")
+        }
+
+        Ok(response.json().await?)
+    })?;
+
+    let invoice = Invoice::from_str(&purchase.invoice).context("Could not parse Invoice string")?;
+    check_liquidity_invoice(
+        &invoice,
+        &purchase,
+        offer.fee_sat(inbound_liquidity_sat),
+        config::get_coordinator_info().pubkey,
+    )?;
+
+    tracing::info!(
+        inbound_liquidity_sat,
+        fee_sat = purchase.fee_sat,
+        "Paying for inbound liquidity"
+    );
+
+    let node = NODE.try_get().context("failed to get ln dlc node")?;
+    node.inner.send_payment(&invoice)?;
+    runtime.block_on(node.inner.wait_for_payment_sent(invoice.payment_hash()))?;
+
+    let coordinator = config::get_coordinator_info().pubkey;
+    let channel = node
+        .inner
+        .list_usable_channels()
+        .into_iter()
+        .find(|channel| channel.counterparty.node_id == coordinator)
+        .context("No usable channel with the coordinator to add the liquidity to")?;
+
+    upgrade_channel(channel.channel_value_satoshis + inbound_liquidity_sat)?;
+
+    Ok(purchase)
+}
+
+/// Makes sure that the coordinator charges us what it offered for the liquidity, before we pay
+/// the `invoice` for it.
+fn check_liquidity_invoice(
+    invoice: &Invoice,
+    purchase: &LiquidityPurchase,
+    expected_fee_sat: u64,
+    coordinator: PublicKey,
+) -> Result<()> {
+    ensure!(
+        purchase.fee_sat == expected_fee_sat,
+        "Coordinator charges {} sats instead of the offered {expected_fee_sat} sats",
+        purchase.fee_sat
+    );
+    ensure!(
+        invoice.amount_milli_satoshis() == Some(expected_fee_sat * 1000),
+        "Invoice amount of {:?} msats does not match the fee of {expected_fee_sat} sats",
+        invoice.amount_milli_satoshis()
+    );
+    ensure!(
+        invoice.recover_payee_pub_key() == coordinator,
+        "Invoice is not payable to the coordinator"
+    );
+
+    Ok(())
+}
+
 pub fn create_invoice(amount_sats: Option<u64>) -> Result<Invoice> {
     let runtime = runtime()?;
 
     runtime.block_on(async {
         let node = NODE.try_get().context("failed to get ln dlc node")?;
-        let client = reqwest::Client::new();
+        let client = config::get_http_client()?;
         let response = client
             .post(format!(
                 "http://{}/api/fake_scid/{}",
@@ -330,14 +632,16 @@ pub fn create_invoice(amount_sats: Option<u64>) -> Result<Invoice> {
             bail!("Failed to fetch fake scid from coordinator: {text}")
         }
 
-        let text = response.text().await?;
-        tracing::info!("Fetch fake channel id: {}", text);
-
-        let fake_channel_id: u64 = text.parse()?;
+        let intercept_scid: InterceptScid = response.json().await?;
+        tracing::info!(
+            fake_scid = intercept_scid.scid,
+            fee_policy = ?intercept_scid.fee_policy,
+            "Fetched fake channel id"
+        );
 
         node.inner.create_interceptable_invoice(
             amount_sats,
-            fake_channel_id,
+            intercept_scid,
             config::get_coordinator_info().pubkey,
             0,
             "test".to_string(),
@@ -352,16 +656,33 @@ pub fn send_payment(invoice: &str) -> Result<()> {
 }
 
 pub async fn trade(trade_params: TradeParams) -> Result<(), (FailureReason, anyhow::Error)> {
-    let client = reqwest::Client::new();
+    let node = NODE
+        .try_get()
+        .context("failed to get ln dlc node")
+        .map_err(|e| (FailureReason::NodeAccess, e))?;
+
+    // The coordinator may offer the DLC channel before responding to our request, so we have to
+    // know what to expect beforehand
+    node.pending_trade
+        .lock()
+        .unwrap()
+        .replace(trade_params.clone());
+
+    let client = config::get_http_client()?;
     let response = client
         .post(format!("http://{}/api/trade", config::get_http_endpoint()))
         .json(&trade_params)
         .send()
         .await
         .context("Failed to request trade with coordinator")
-        .map_err(|e| (FailureReason::TradeRequest, e))?;
+        .map_err(|e| {
+            node.pending_trade.lock().unwrap().take();
+            (FailureReason::TradeRequest, e)
+        })?;
 
     if !response.status().is_success() {
+        node.pending_trade.lock().unwrap().take();
+
         let response_text = match response.text().await {
             Ok(text) => text,
             Err(err) => {
@@ -378,3 +699,103 @@ pub async fn trade(trade_params: TradeParams) -> Result<(), (FailureReason, anyh
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::secp256k1::Secp256k1;
+    use lightning::ln::PaymentSecret;
+    use lightning_invoice::Currency;
+    use lightning_invoice::InvoiceBuilder;
+
+    fn invoice(amount_msat: u64, payee_key: &SecretKey) -> Invoice {
+        InvoiceBuilder::new(Currency::Regtest)
+            .description("Inbound liquidity".to_string())
+            .payment_hash(sha256::Hash::hash(&[1; 32]))
+            .payment_secret(PaymentSecret([2; 32]))
+            .current_timestamp()
+            .min_final_cltv_expiry(144)
+            .amount_milli_satoshis(amount_msat)
+            .build_signed(|hash| Secp256k1::new().sign_ecdsa_recoverable(hash, payee_key))
+            .unwrap()
+    }
+
+    #[test]
+    fn liquidity_invoice_has_to_match_the_offered_fee() {
+        let coordinator_key = SecretKey::from_slice(&[1; 32]).unwrap();
+        let coordinator = PublicKey::from_secret_key(&Secp256k1::new(), &coordinator_key);
+        let purchase = |fee_sat| LiquidityPurchase {
+            invoice: String::new(),
+            fee_sat,
+        };
+
+        check_liquidity_invoice(
+            &invoice(1_000_000, &coordinator_key),
+            &purchase(1_000),
+            1_000,
+            coordinator,
+        )
+        .unwrap();
+
+        // Invoice amount differs from the quoted fee
+        assert!(check_liquidity_invoice(
+            &invoice(2_000_000, &coordinator_key),
+            &purchase(1_000),
+            1_000,
+            coordinator,
+        )
+        .is_err());
+
+        // Quoted fee differs from the offer
+        assert!(check_liquidity_invoice(
+            &invoice(2_000_000, &coordinator_key),
+            &purchase(2_000),
+            1_000,
+            coordinator,
+        )
+        .is_err());
+
+        // Invoice is not payable to the coordinator
+        let other_key = SecretKey::from_slice(&[2; 32]).unwrap();
+        assert!(check_liquidity_invoice(
+            &invoice(1_000_000, &other_key),
+            &purchase(1_000),
+            1_000,
+            coordinator,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sync_status_is_only_published_when_it_changed() {
+        let status = api::SyncStatus {
+            in_sync: true,
+            synced_height: Some(100),
+            tip_height: Some(100),
+            last_successful_sync: Some(1_681_000_000),
+            chain_source_reachable: true,
+        };
+
+        assert!(is_sync_status_changed(None, &status));
+
+        // Synced again to the same block
+        let synced_again = api::SyncStatus {
+            last_successful_sync: Some(1_681_000_010),
+            ..status.clone()
+        };
+        assert!(!is_sync_status_changed(Some(&status), &synced_again));
+
+        let new_block = api::SyncStatus {
+            synced_height: Some(101),
+            tip_height: Some(101),
+            ..synced_again.clone()
+        };
+        assert!(is_sync_status_changed(Some(&synced_again), &new_block));
+
+        let out_of_sync = api::SyncStatus {
+            in_sync: false,
+            ..new_block.clone()
+        };
+        assert!(is_sync_status_changed(Some(&new_block), &out_of_sync));
+    }
+}
@@ -1,36 +1,49 @@
+use crate::config;
+use crate::db;
+use crate::ln_dlc::reconciliation;
+use crate::ln_dlc::reconciliation::DlcChannelStatus;
+use crate::ln_dlc::reconciliation::SqliteStore;
+use crate::ln_dlc::validation::validate_dlc_channel_close_offer;
+use crate::ln_dlc::validation::validate_dlc_channel_offer;
+use crate::ln_dlc::validation::DlcChannelOffer;
 use crate::trade::order;
+use crate::trade::order::FailureReason;
 use crate::trade::position;
-use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
+use bdk::bitcoin::secp256k1::PublicKey;
 use bdk::TransactionDetails;
-use dlc_messages::Message;
+use coordinator_commons::TradeParams;
 use dlc_messages::SubChannelMessage;
 use ln_dlc_node::node::rust_dlc_manager::contract::Contract;
-use ln_dlc_node::node::rust_dlc_manager::Storage;
-use ln_dlc_node::node::sub_channel_message_as_str;
-use ln_dlc_node::node::DlcManager;
-use ln_dlc_node::node::NodeInfo;
+use ln_dlc_node::node::rust_dlc_manager::ChannelId;
+use ln_dlc_node::node::Balance;
+use ln_dlc_node::node::DlcChannelEvent;
+use ln_dlc_node::node::DlcChannelEventKind;
 use ln_dlc_node::node::PaymentDetails;
-use ln_dlc_node::Dlc;
+use rust_decimal::Decimal;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
 
 #[derive(Clone)]
 pub struct Node {
     pub inner: Arc<ln_dlc_node::node::Node>,
+    /// The trade we requested from the coordinator and for which we expect a DLC channel (close)
+    /// offer.
+    pub pending_trade: Arc<Mutex<Option<TradeParams>>>,
 }
 
-pub struct Balances {
-    pub on_chain: u64,
-    pub off_chain: u64,
-}
-
-impl From<Balances> for crate::api::Balances {
-    fn from(value: Balances) -> Self {
+impl From<Balance> for crate::api::Balances {
+    fn from(value: Balance) -> Self {
         Self {
-            on_chain: value.on_chain,
-            lightning: value.off_chain,
+            on_chain: value.on_chain_confirmed,
+            on_chain_unconfirmed: value.on_chain_unconfirmed,
+            lightning: value.lightning_spendable,
+            dlc_collateral: value.dlc_collateral,
+            pending_close: value.pending_close,
         }
     }
 }
@@ -41,14 +54,8 @@ pub struct WalletHistories {
 }
 
 impl Node {
-    pub fn get_wallet_balances(&self) -> Result<Balances> {
-        let on_chain = self.inner.get_on_chain_balance()?.confirmed;
-        let off_chain = self.inner.get_ldk_balance().available;
-
-        Ok(Balances {
-            on_chain,
-            off_chain,
-        })
+    pub fn get_wallet_balances(&self) -> Result<Balance> {
+        self.inner.get_balance()
     }
 
     pub fn get_wallet_histories(&self) -> Result<WalletHistories> {
@@ -61,192 +68,279 @@ impl Node {
         })
     }
 
-    pub fn process_incoming_messages(&self) -> Result<()> {
-        let messages = self
-            .inner
-            .dlc_message_handler
-            .get_and_clear_received_messages();
-
-        for (node_id, msg) in messages {
-            match msg {
-                Message::OnChain(_) | Message::Channel(_) => {
-                    tracing::debug!(from = %node_id, "Processing DLC-manager message");
-                    let resp = self
-                        .inner
-                        .dlc_manager
-                        .on_dlc_message(&msg, node_id)
-                        .map_err(|e| anyhow!(e.to_string()))?;
+    /// Decides whether to accept a DLC channel (close) offer from `node_id` which has already been
+    /// processed by the sub-channel manager.
+    pub fn on_sub_channel_message(&self, node_id: PublicKey, incoming_msg: &SubChannelMessage) {
+        if let SubChannelMessage::Offer(offer) = incoming_msg {
+            let channel_id = offer.channel_id;
 
-                    if let Some(msg) = resp {
-                        tracing::debug!(to = %node_id, "Sending DLC-manager message");
-                        self.inner.dlc_message_handler.send_message(node_id, msg);
+            match self.validate_dlc_channel_offer(node_id, &channel_id) {
+                Ok(()) => {
+                    if let Err(e) = self.inner.accept_dlc_channel_offer(&channel_id) {
+                        tracing::error!(channel_id = %hex::encode(channel_id), "Failed to accept DLC channel offer: {e:#}");
                     }
                 }
-                Message::SubChannel(incoming_msg) => {
-                    tracing::debug!(
-                        from = %node_id,
-                        msg = %sub_channel_message_as_str(&incoming_msg),
-                        "Processing DLC channel message"
-                    );
-                    let reply_msg = self
-                        .inner
-                        .sub_channel_manager
-                        .on_sub_channel_message(&incoming_msg, &node_id)
-                        .map_err(|e| anyhow!(e.to_string()))?;
+                Err(e) => {
+                    if let Err(e) = self.inner.reject_dlc_channel_offer(&channel_id) {
+                        tracing::error!(channel_id = %hex::encode(channel_id), "Failed to reject DLC channel offer: {e:#}");
+                    }
 
-                    if let SubChannelMessage::Offer(offer) = &incoming_msg {
-                        let channel_id = offer.channel_id;
+                    self.fail_pending_trade(node_id, e);
+                }
+            }
+        }
 
-                        // TODO: We should probably verify that: (1) the counterparty is the
-                        // coordinator and (2) the DLC channel offer is expected and correct.
-                        if let Err(e) = self.inner.accept_dlc_channel_offer(&channel_id) {
-                            tracing::error!(channel_id = %hex::encode(channel_id), "Failed to accept DLC channel offer: {e:#}");
-                        }
+        if let SubChannelMessage::CloseOffer(offer) = incoming_msg {
+            let channel_id = offer.channel_id;
+
+            match self.validate_dlc_channel_close_offer(node_id, &channel_id, offer.accept_balance)
+            {
+                Ok(()) => {
+                    // Stored before accepting, so that we know the amount even if we are stopped
+                    // before the DLC channel is closed
+                    if let Err(e) = db::insert_pending_settlement(channel_id, offer.accept_balance)
+                    {
+                        tracing::error!(channel_id = %hex::encode(channel_id), "Failed to store pending settlement amount: {e:#}");
                     }
 
-                    if let SubChannelMessage::CloseOffer(offer) = &incoming_msg {
-                        let channel_id = offer.channel_id;
+                    if let Err(e) = self
+                        .inner
+                        .accept_dlc_channel_collaborative_settlement(&channel_id)
+                    {
+                        tracing::error!(channel_id = %hex::encode(channel_id), "Failed to accept DLC channel close offer: {e:#}");
 
-                        // TODO: We should probably verify that: (1) the counterparty is the
-                        // coordinator and (2) the DLC channel close offer is expected and correct.
-                        if let Err(e) = self
-                            .inner
-                            .accept_dlc_channel_collaborative_settlement(&channel_id)
-                        {
-                            tracing::error!(channel_id = %hex::encode(channel_id), "Failed to accept DLC channel close offer: {e:#}");
+                        if let Err(e) = db::take_pending_settlement(channel_id) {
+                            tracing::error!(channel_id = %hex::encode(channel_id), "Failed to forget pending settlement amount: {e:#}");
                         }
                     }
-
-                    if let Some(reply_msg) = reply_msg {
-                        tracing::debug!(
-                            to = %node_id,
-                            msg = %sub_channel_message_as_str(&reply_msg),
-                            "Sending DLC channel message"
-                        );
-                        self.inner
-                            .dlc_message_handler
-                            .send_message(node_id, Message::SubChannel(reply_msg.clone()));
-
-                        match reply_msg {
-                            SubChannelMessage::Finalize(_) => {
-                                let accept_collateral =
-                                    get_first_confirmed_dlc(&self.inner.dlc_manager)?
-                                        .accept_collateral;
-
-                                let filled_order = match order::handler::order_filled() {
-                                    Ok(filled_order) => filled_order,
-                                    Err(e) => {
-                                        tracing::error!("Critical Error! We have a DLC but were unable to set the order to filled: {e:#}");
-                                        continue;
-                                    }
-                                };
-
-                                if let Err(e) =
-                                    position::handler::update_position_after_dlc_creation(
-                                        filled_order,
-                                        accept_collateral,
-                                    )
-                                {
-                                    tracing::error!(
-                                        "Failed to handle position after receiving DLC: {e:#}"
-                                    );
-                                    continue;
-                                }
-                            }
-                            SubChannelMessage::CloseFinalize(_) => {
-                                let filled_order = match order::handler::order_filled() {
-                                    Ok(filled_order) => filled_order,
-                                    Err(e) => {
-                                        tracing::error!("Critical Error! We have closed a DLC but were unable to set the order to filled: {e:#}");
-                                        continue;
-                                    }
-                                };
-
-                                if let Err(e) = position::handler::update_position_after_dlc_closure(
-                                    filled_order,
-                                ) {
-                                    tracing::error!(
-                                        "Failed to handle position after closing DLC: {e:#}"
-                                    );
-                                    continue;
-                                }
-                            }
-                            _ => (),
-                        }
+                }
+                Err(e) => {
+                    if let Err(e) = self
+                        .inner
+                        .reject_dlc_channel_collaborative_settlement(&channel_id)
+                    {
+                        tracing::error!(channel_id = %hex::encode(channel_id), "Failed to reject DLC channel close offer: {e:#}");
                     }
+
+                    self.fail_pending_trade(node_id, e);
                 }
             }
         }
+    }
 
-        // NOTE: According to the docs of `process_events` we shouldn't have to call this since we
-        // use `lightning-net-tokio`. But we copied this from `p2pderivatives/ldk-sample`
-        if self.inner.dlc_message_handler.has_pending_messages() {
-            self.inner.peer_manager.process_events();
-        }
+    /// Fixes the orders and positions which are inconsistent with the DLC channels with the
+    /// coordinator, e.g. because the app was stopped while executing a trade.
+    pub fn reconcile_orders_and_positions(&self) -> Result<()> {
+        let coordinator = config::get_coordinator_info().pubkey;
+
+        let sub_channels = self
+            .inner
+            .list_dlc_channels()?
+            .into_iter()
+            .filter(|sub_channel| sub_channel.counter_party == coordinator)
+            .collect::<Vec<_>>();
+        let dlc_channels = DlcChannelStatus::new(&sub_channels, |channel_id| {
+            self.inner.dlc_of_dlc_channel(channel_id)
+        });
 
-        Ok(())
+        reconciliation::reconcile(&mut SqliteStore, &dlc_channels)
     }
 
-    pub async fn keep_connected(&self, peer: NodeInfo) {
-        let reconnect_interval = Duration::from_secs(1);
+    /// Updates the order being filled and our position as the DLC channel with the coordinator
+    /// progresses.
+    pub async fn handle_dlc_channel_events(&self) {
+        let mut events = self.inner.subscribe_to_dlc_channel_events();
+
         loop {
-            let connection_closed_future = match self.inner.connect(peer).await {
-                Ok(fut) => fut,
-                Err(e) => {
-                    tracing::warn!(
-                        %peer,
-                        ?reconnect_interval,
-                        "Connection failed: {e:#}; reconnecting"
-                    );
-
-                    tokio::time::sleep(reconnect_interval).await;
-                    continue;
+            match events.recv().await {
+                Ok(event) => self.on_dlc_channel_event(event),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "Missed DLC channel events");
                 }
-            };
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
 
-            connection_closed_future.await;
-            tracing::debug!(
-                %peer,
-                ?reconnect_interval,
-                "Connection lost; reconnecting"
-            );
+    fn on_dlc_channel_event(&self, event: DlcChannelEvent) {
+        if event.counterparty != config::get_coordinator_info().pubkey {
+            return;
+        }
+
+        match event.kind {
+            DlcChannelEventKind::Signed => {
+                let order_id = self
+                    .pending_trade
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .map(|trade_params| trade_params.filled_with.order_id);
 
-            tokio::time::sleep(reconnect_interval).await;
+                let dlc = match event.dlc {
+                    Some(dlc) => dlc,
+                    None => {
+                        tracing::error!(
+                            channel_id = %hex::encode(event.channel_id),
+                            "Critical Error! We have a DLC but do not know its contract"
+                        );
+                        return;
+                    }
+                };
+
+                let filled_order = match order::handler::order_filled(order_id) {
+                    Ok(filled_order) => filled_order,
+                    Err(e) => {
+                        tracing::error!("Critical Error! We have a DLC but were unable to set the order to filled: {e:#}");
+                        return;
+                    }
+                };
+
+                if let Err(e) = position::handler::update_position_after_dlc_creation(
+                    filled_order,
+                    dlc.id,
+                    dlc.accept_collateral,
+                ) {
+                    tracing::error!("Failed to handle position after receiving DLC: {e:#}");
+                }
+            }
+            DlcChannelEventKind::Closed => {
+                let order_id = self
+                    .pending_trade
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .map(|trade_params| trade_params.filled_with.order_id);
+
+                let filled_order = match order::handler::order_filled(order_id) {
+                    Ok(filled_order) => filled_order,
+                    Err(e) => {
+                        tracing::error!("Critical Error! We have closed a DLC but were unable to set the order to filled: {e:#}");
+                        return;
+                    }
+                };
+
+                let contract_id = event.dlc.map(|dlc| dlc.id);
+                let settlement_amount = db::take_pending_settlement(event.channel_id)
+                    .unwrap_or_else(|e| {
+                        tracing::error!(channel_id = %hex::encode(event.channel_id), "Failed to load pending settlement amount: {e:#}");
+                        None
+                    });
+                if let Err(e) = position::handler::update_position_after_dlc_closure(
+                    filled_order,
+                    contract_id,
+                    settlement_amount,
+                ) {
+                    tracing::error!("Failed to handle position after closing DLC: {e:#}");
+                }
+            }
+            DlcChannelEventKind::ForceClosed => {
+                if let Err(e) = db::take_pending_settlement(event.channel_id) {
+                    tracing::error!(channel_id = %hex::encode(event.channel_id), "Failed to forget pending settlement amount: {e:#}");
+                }
+
+                tracing::error!(
+                    channel_id = %hex::encode(event.channel_id),
+                    dlc = ?event.dlc,
+                    "DLC channel with the coordinator was force-closed"
+                );
+            }
+            DlcChannelEventKind::OfferReceived
+            | DlcChannelEventKind::Accepted
+            | DlcChannelEventKind::CloseOffered
+            | DlcChannelEventKind::Rejected
+            | DlcChannelEventKind::CloseRejected => {}
         }
     }
-}
 
-fn get_first_confirmed_dlc(dlc_manager: &DlcManager) -> Result<Dlc> {
-    let contracts = dlc_manager
-        .get_store()
-        .get_contracts()
-        .map_err(|e| anyhow!("Unable to get contracts from manager: {e:#}"))?;
-
-    tracing::debug!(
-        ?contracts,
-        "Looking for latest confirmed DLC among all contracts"
-    );
-
-    let confirmed_dlcs = contracts
-        .iter()
-        .filter_map(|contract| match contract {
-            Contract::Confirmed(signed) => Some((contract.get_id(), signed)),
-            _ => None,
-        })
-        .map(|(id, signed)| Dlc {
-            id,
-            offer_collateral: signed
-                .accepted_contract
-                .offered_contract
-                .offer_params
-                .collateral,
-            accept_collateral: signed.accepted_contract.accept_params.collateral,
-            accept_pk: signed.accepted_contract.offered_contract.counter_party,
-        })
-        .collect::<Vec<_>>();
+    /// Checks the DLC channel offer we just received from `counterparty` for the LN channel
+    /// `channel_id` against our pending trade.
+    fn validate_dlc_channel_offer(
+        &self,
+        counterparty: PublicKey,
+        channel_id: &ChannelId,
+    ) -> Result<()> {
+        let trade_params = self
+            .pending_trade
+            .lock()
+            .unwrap()
+            .clone()
+            .context("Unexpected DLC channel offer without a pending trade")?;
 
-    confirmed_dlcs
-        .first()
-        .context("No confirmed DLC found")
-        .copied()
+        let contract_offer = match self.inner.get_dlc_channel_contract(channel_id)? {
+            Some(Contract::Offered(contract_offer)) => contract_offer,
+            contract => bail!(
+                "Expected a contract offer in DLC channel {}, got {contract:?}",
+                hex::encode(channel_id)
+            ),
+        };
+
+        let offer = DlcChannelOffer::try_from(&contract_offer)?;
+        ensure!(
+            offer.counterparty == counterparty,
+            "Contract offer from {} in DLC channel offer from {counterparty}",
+            offer.counterparty
+        );
+
+        tracing::info!(
+            fee_rate_per_vb = offer.fee_rate_per_vb,
+            "Validating DLC channel offer from coordinator"
+        );
+
+        validate_dlc_channel_offer(&offer, &trade_params, config::get_coordinator_info().pubkey)
+    }
+
+    /// Checks the DLC channel close offer we just received from `counterparty` for the LN channel
+    /// `channel_id` against our pending trade, i.e. that we get `accept_balance` out of the DLC.
+    fn validate_dlc_channel_close_offer(
+        &self,
+        counterparty: PublicKey,
+        channel_id: &ChannelId,
+        accept_balance: u64,
+    ) -> Result<()> {
+        let pending_trade = self.pending_trade.lock().unwrap().clone();
+
+        let contract_id = self
+            .inner
+            .get_dlc_channel_contract(channel_id)?
+            .with_context(|| format!("No DLC in DLC channel {}", hex::encode(channel_id)))?
+            .get_id();
+        let position = db::get_positions()?
+            .into_iter()
+            .find(|position| position.contract_id == contract_id)
+            .with_context(|| {
+                format!(
+                    "No position for DLC {} to be closed",
+                    hex::encode(contract_id)
+                )
+            })?;
+        let opening_price = Decimal::try_from(position.average_entry_price)?;
+
+        validate_dlc_channel_close_offer(
+            counterparty,
+            accept_balance,
+            pending_trade.as_ref(),
+            opening_price,
+            config::get_coordinator_info().pubkey,
+        )
+    }
+
+    /// Marks the order being filled as failed after we rejected an invalid DLC channel (close)
+    /// offer from the coordinator.
+    ///
+    /// Unsolicited offers from other nodes are rejected without affecting the pending trade.
+    fn fail_pending_trade(&self, counterparty: PublicKey, error: anyhow::Error) {
+        tracing::error!(%counterparty, "Rejected invalid DLC channel offer: {error:#}");
+
+        if counterparty != config::get_coordinator_info().pubkey {
+            return;
+        }
+
+        self.pending_trade.lock().unwrap().take();
+
+        if let Err(e) = order::handler::order_failed(None, FailureReason::InvalidDlcOffer, error) {
+            tracing::error!(
+                "Failed to set order to failed after rejecting DLC channel offer: {e:#}"
+            );
+        }
+    }
 }
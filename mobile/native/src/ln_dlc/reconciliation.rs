@@ -0,0 +1,866 @@
+use crate::db;
+use crate::trade::order::FailureReason;
+use crate::trade::order::Order;
+use crate::trade::order::OrderState;
+use crate::trade::position;
+use crate::trade::position::LegacyPosition;
+use crate::trade::position::Position;
+use crate::trade::position::PositionState;
+use crate::trade::position::Trade;
+use anyhow::Result;
+use ln_dlc_node::node::rust_dlc_manager::subchannel::SubChannel;
+use ln_dlc_node::node::rust_dlc_manager::subchannel::SubChannelState;
+use ln_dlc_node::node::rust_dlc_manager::ChannelId;
+use ln_dlc_node::node::rust_dlc_manager::ContractId;
+use ln_dlc_node::Dlc;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use trade::ContractSymbol;
+use uuid::Uuid;
+
+/// What the DLC channels with the coordinator tell us about our orders and positions.
+#[derive(Debug, Clone, Default)]
+pub struct DlcChannelStatus {
+    /// The collateral of our established DLCs, keyed by contract ID.
+    pub signed: HashMap<ContractId, u64>,
+    /// Whether a DLC is being set up or collaboratively closed, in which case the order being
+    /// filled waits for the DLC protocol to end.
+    pub in_progress: bool,
+    /// The DLCs which are being collaboratively closed.
+    pub closing: HashSet<ContractId>,
+    /// The DLCs whose LN channel was closed on-chain, but which are not settled on-chain yet.
+    pub force_closed: HashSet<ContractId>,
+    /// Whether there is an established DLC channel whose DLC we could not find, in which case we
+    /// cannot tell which positions are without DLC.
+    pub unidentified_dlc: bool,
+}
+
+impl DlcChannelStatus {
+    /// Derives the status from the DLC `sub_channels` with the coordinator, looking up the DLC of
+    /// each of them with `dlc_of_dlc_channel`.
+    pub fn new(
+        sub_channels: &[SubChannel],
+        dlc_of_dlc_channel: impl Fn(&ChannelId) -> Option<Dlc>,
+    ) -> Self {
+        let mut status = DlcChannelStatus::default();
+
+        for sub_channel in sub_channels {
+            let dlc = || dlc_of_dlc_channel(&sub_channel.channel_id);
+
+            match sub_channel.state {
+                SubChannelState::Offered(_)
+                | SubChannelState::Accepted(_)
+                | SubChannelState::Confirmed(_) => status.in_progress = true,
+                SubChannelState::Signed(_) => match dlc() {
+                    Some(dlc) => {
+                        status.signed.insert(dlc.id, dlc.accept_collateral);
+                    }
+                    None => {
+                        tracing::warn!(
+                            channel_id = %hex::encode(sub_channel.channel_id),
+                            "DLC channel is signed but we could not find its DLC"
+                        );
+                        status.unidentified_dlc = true;
+                    }
+                },
+                SubChannelState::CloseOffered(_)
+                | SubChannelState::CloseAccepted(_)
+                | SubChannelState::CloseConfirmed(_) => {
+                    status.in_progress = true;
+                    status.closing.extend(dlc().map(|dlc| dlc.id));
+                }
+                // Once the DLC is settled on-chain we cannot find it anymore
+                SubChannelState::Closing(_)
+                | SubChannelState::OnChainClosed
+                | SubChannelState::CounterOnChainClosed
+                | SubChannelState::ClosedPunished(_) => {
+                    status.force_closed.extend(dlc().map(|dlc| dlc.id));
+                }
+                SubChannelState::OffChainClosed | SubChannelState::Rejected => {}
+            }
+        }
+
+        status
+    }
+}
+
+/// The orders and positions which are reconciled with the DLC channel.
+pub trait Store {
+    fn get_order_in_filling(&self) -> Result<Option<Order>>;
+    fn get_last_order(&self) -> Result<Option<Order>>;
+    fn get_positions(&self) -> Result<Vec<Position>>;
+    fn update_order_state(&mut self, order_id: Uuid, state: OrderState) -> Result<()>;
+    fn insert_position(&mut self, position: Position) -> Result<()>;
+    fn update_position_state(
+        &mut self,
+        contract_id: ContractId,
+        state: PositionState,
+    ) -> Result<()>;
+    fn delete_position(&mut self, contract_id: ContractId) -> Result<()>;
+    fn get_legacy_positions(&self) -> Result<Vec<LegacyPosition>>;
+    fn delete_legacy_position(&mut self, contract_symbol: ContractSymbol) -> Result<()>;
+    fn insert_trade(&mut self, trade: Trade) -> Result<()>;
+}
+
+/// The orders and positions in the app's database.
+pub struct SqliteStore;
+
+impl Store for SqliteStore {
+    fn get_order_in_filling(&self) -> Result<Option<Order>> {
+        db::maybe_get_order_in_filling()
+    }
+
+    fn get_last_order(&self) -> Result<Option<Order>> {
+        db::get_last_order()
+    }
+
+    fn get_positions(&self) -> Result<Vec<Position>> {
+        db::get_positions()
+    }
+
+    fn update_order_state(&mut self, order_id: Uuid, state: OrderState) -> Result<()> {
+        db::update_order_state(order_id, state)
+    }
+
+    fn insert_position(&mut self, position: Position) -> Result<()> {
+        db::insert_position(position)?;
+        Ok(())
+    }
+
+    fn update_position_state(
+        &mut self,
+        contract_id: ContractId,
+        state: PositionState,
+    ) -> Result<()> {
+        db::update_position_state(contract_id, state)
+    }
+
+    fn delete_position(&mut self, contract_id: ContractId) -> Result<()> {
+        db::delete_position(contract_id)
+    }
+
+    fn get_legacy_positions(&self) -> Result<Vec<LegacyPosition>> {
+        db::get_legacy_positions()
+    }
+
+    fn delete_legacy_position(&mut self, contract_symbol: ContractSymbol) -> Result<()> {
+        db::delete_legacy_position(contract_symbol)
+    }
+
+    fn insert_trade(&mut self, trade: Trade) -> Result<()> {
+        db::insert_trade(trade)
+    }
+}
+
+/// Fixes the orders and positions in `store` which are inconsistent with the DLC channels with
+/// the coordinator.
+///
+/// This is meant to be run on startup, to recover from the app being stopped while a trade was
+/// being executed, in which case we may have missed the end of the DLC protocol.
+pub fn reconcile(store: &mut impl Store, dlc_channels: &DlcChannelStatus) -> Result<()> {
+    migrate_legacy_positions(store, dlc_channels)?;
+
+    let order_in_filling = store.get_order_in_filling()?;
+    let positions = store.get_positions()?;
+
+    tracing::debug!(
+        ?order_in_filling,
+        ?positions,
+        ?dlc_channels,
+        "Reconciling orders and positions with DLC channels"
+    );
+
+    // While the DLC protocol is still running, the order remains in `Filling` state until we know
+    // how it ends
+    if let Some(order) = order_in_filling.filter(|_| !dlc_channels.in_progress) {
+        let closing_position = positions
+            .iter()
+            .find(|position| matches!(position.position_state, PositionState::Closing));
+
+        match closing_position {
+            // Closing the position failed, so it is still open
+            Some(position) if dlc_channels.signed.contains_key(&position.contract_id) => {
+                fail_interrupted_order(store, &order)?;
+                store.update_position_state(position.contract_id, PositionState::Open)?;
+            }
+            // We closed our position but missed the end of the DLC protocol
+            Some(position) => {
+                tracing::info!(order_id = %order.id, "Position was closed while the app was stopped");
+
+                fill_order(store, &order)?;
+                store.insert_trade(position::handler::closing_trade(&order, position, None))?;
+                store.delete_position(position.contract_id)?;
+            }
+            None => {
+                let new_dlc = dlc_channels.signed.iter().find(|(contract_id, _)| {
+                    positions
+                        .iter()
+                        .all(|position| position.contract_id != **contract_id)
+                });
+
+                match new_dlc {
+                    // We opened a position but missed the end of the DLC protocol
+                    Some((contract_id, collateral)) => {
+                        tracing::info!(order_id = %order.id, "Order was filled while the app was stopped");
+
+                        fill_order(store, &order)?;
+                        store.insert_position(position::handler::open_position(
+                            &order,
+                            *contract_id,
+                            *collateral,
+                        ))?;
+                        store.insert_trade(position::handler::opening_trade(
+                            &order,
+                            *contract_id,
+                            *collateral,
+                        ))?;
+                    }
+                    None => fail_interrupted_order(store, &order)?,
+                }
+            }
+        }
+    }
+
+    let last_order = store.get_last_order()?;
+
+    for position in store.get_positions()? {
+        // The DLC protocol decides what happens to the position
+        if dlc_channels.closing.contains(&position.contract_id) {
+            continue;
+        }
+
+        if dlc_channels.signed.contains_key(&position.contract_id) {
+            // A filled closing order means that the coordinator is about to close the DLC
+            let is_closing_order_filled = last_order.map_or(false, |order| {
+                matches!(order.state, OrderState::Filled { .. })
+                    && order.contract_symbol == position.contract_symbol
+                    && order.direction != position.direction
+            });
+
+            // No order is closing the position, e.g. because the closing order failed before
+            // the DLC protocol started, so the position is still open
+            if matches!(position.position_state, PositionState::Closing) && !is_closing_order_filled
+            {
+                tracing::warn!(?position, "Reopening position which is not being closed");
+                store.update_position_state(position.contract_id, PositionState::Open)?;
+            }
+
+            continue;
+        }
+
+        if dlc_channels.force_closed.contains(&position.contract_id) {
+            tracing::warn!(?position, "Keeping position whose DLC was force-closed");
+            continue;
+        }
+
+        if dlc_channels.unidentified_dlc {
+            tracing::warn!(
+                ?position,
+                "Keeping position which might belong to an unknown DLC"
+            );
+            continue;
+        }
+
+        // E.g. because the coordinator closed the DLC channel
+        tracing::warn!(?position, "Removing position without DLC");
+        store.delete_position(position.contract_id)?;
+    }
+
+    let positions = store.get_positions()?;
+    for contract_id in dlc_channels.signed.keys() {
+        if positions
+            .iter()
+            .all(|position| position.contract_id != *contract_id)
+        {
+            tracing::error!(
+                contract_id = %hex::encode(contract_id),
+                "We have a DLC without a position, cannot restore the position"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns the positions created before positions were identified by their DLC into positions
+/// with the contract ID of a DLC which does not have a position yet.
+///
+/// Until then a trader had at most one DLC, so there is at most one such DLC per legacy position.
+/// Legacy positions for which there is no such DLC are removed like any other position without
+/// DLC, unless we cannot tell yet whether they have one.
+///
+/// Trades were not stored back then either, so we record the trade which opened the position
+/// together with its DLC.
+fn migrate_legacy_positions(store: &mut impl Store, dlc_channels: &DlcChannelStatus) -> Result<()> {
+    for legacy_position in store.get_legacy_positions()? {
+        let positions = store.get_positions()?;
+
+        let dlc_without_position = dlc_channels
+            .signed
+            .keys()
+            .chain(dlc_channels.closing.iter())
+            .chain(dlc_channels.force_closed.iter())
+            .find(|contract_id| {
+                positions
+                    .iter()
+                    .all(|position| position.contract_id != **contract_id)
+            });
+
+        match dlc_without_position {
+            Some(contract_id) => {
+                tracing::info!(
+                    contract_id = %hex::encode(contract_id),
+                    "Assigning DLC to legacy position"
+                );
+                store.insert_position(legacy_position.clone().with_contract_id(*contract_id))?;
+                if let Some(trade) = legacy_position.opening_trade(*contract_id) {
+                    store.insert_trade(trade)?;
+                }
+            }
+            None if dlc_channels.in_progress || dlc_channels.unidentified_dlc => {
+                tracing::warn!(
+                    ?legacy_position,
+                    "Keeping legacy position until its DLC can be identified"
+                );
+                continue;
+            }
+            None => {
+                tracing::warn!(?legacy_position, "Removing legacy position without DLC");
+            }
+        }
+
+        store.delete_legacy_position(legacy_position.contract_symbol)?;
+    }
+
+    Ok(())
+}
+
+fn fill_order(store: &mut impl Store, order: &Order) -> Result<()> {
+    let execution_price = order.execution_price().unwrap_or(0.0);
+    store.update_order_state(order.id, OrderState::Filled { execution_price })
+}
+
+/// The DLC protocol is not running, so the order cannot be filled anymore.
+fn fail_interrupted_order(store: &mut impl Store, order: &Order) -> Result<()> {
+    tracing::warn!(order_id = %order.id, "Order was not filled while the app was stopped");
+
+    store.update_order_state(
+        order.id,
+        OrderState::Failed {
+            reason: FailureReason::Interrupted,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trade::order::OrderType;
+    use crate::trade::position::TradeKind;
+    use anyhow::Context;
+    use time::OffsetDateTime;
+    use trade::Direction;
+
+    #[derive(Default)]
+    struct FakeStore {
+        orders: Vec<Order>,
+        positions: Vec<Position>,
+        trades: Vec<Trade>,
+        legacy_positions: Vec<LegacyPosition>,
+    }
+
+    impl Store for FakeStore {
+        fn get_order_in_filling(&self) -> Result<Option<Order>> {
+            let order = self
+                .orders
+                .iter()
+                .find(|order| matches!(order.state, OrderState::Filling { .. }));
+
+            Ok(order.copied())
+        }
+
+        fn get_last_order(&self) -> Result<Option<Order>> {
+            let order = self
+                .orders
+                .iter()
+                .max_by_key(|order| order.creation_timestamp);
+
+            Ok(order.copied())
+        }
+
+        fn get_positions(&self) -> Result<Vec<Position>> {
+            Ok(self.positions.clone())
+        }
+
+        fn update_order_state(&mut self, order_id: Uuid, state: OrderState) -> Result<()> {
+            let order = self
+                .orders
+                .iter_mut()
+                .find(|order| order.id == order_id)
+                .context("No such order")?;
+            order.state = state;
+
+            Ok(())
+        }
+
+        fn insert_position(&mut self, position: Position) -> Result<()> {
+            self.positions.push(position);
+            Ok(())
+        }
+
+        fn update_position_state(
+            &mut self,
+            contract_id: ContractId,
+            state: PositionState,
+        ) -> Result<()> {
+            let position = self
+                .positions
+                .iter_mut()
+                .find(|position| position.contract_id == contract_id)
+                .context("No such position")?;
+            position.position_state = state;
+
+            Ok(())
+        }
+
+        fn delete_position(&mut self, contract_id: ContractId) -> Result<()> {
+            self.positions
+                .retain(|position| position.contract_id != contract_id);
+            Ok(())
+        }
+
+        fn get_legacy_positions(&self) -> Result<Vec<LegacyPosition>> {
+            Ok(self.legacy_positions.clone())
+        }
+
+        fn delete_legacy_position(&mut self, contract_symbol: ContractSymbol) -> Result<()> {
+            self.legacy_positions
+                .retain(|position| position.contract_symbol != contract_symbol);
+            Ok(())
+        }
+
+        fn insert_trade(&mut self, trade: Trade) -> Result<()> {
+            self.trades.push(trade);
+            Ok(())
+        }
+    }
+
+    fn order(direction: Direction, state: OrderState) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            leverage: 2.0,
+            quantity: 100.0,
+            contract_symbol: ContractSymbol::BtcUsd,
+            direction,
+            order_type: OrderType::Market,
+            state,
+            creation_timestamp: OffsetDateTime::now_utc(),
+        }
+    }
+
+    fn filling() -> OrderState {
+        OrderState::Filling {
+            execution_price: 30_000.0,
+        }
+    }
+
+    fn position(contract_id: ContractId, position_state: PositionState) -> Position {
+        Position {
+            position_state,
+            ..position::handler::open_position(
+                &order(Direction::Long, filling()),
+                contract_id,
+                10_000,
+            )
+        }
+    }
+
+    fn store(orders: Vec<Order>, positions: Vec<Position>) -> FakeStore {
+        FakeStore {
+            orders,
+            positions,
+            ..Default::default()
+        }
+    }
+
+    fn signed(contract_ids: &[ContractId]) -> DlcChannelStatus {
+        DlcChannelStatus {
+            signed: contract_ids
+                .iter()
+                .map(|contract_id| (*contract_id, 10_000))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn is_interrupted(order: &Order) -> bool {
+        matches!(
+            order.state,
+            OrderState::Failed {
+                reason: FailureReason::Interrupted
+            }
+        )
+    }
+
+    #[test]
+    fn order_remains_filling_while_dlc_is_being_set_up() {
+        let mut store = store(vec![order(Direction::Long, filling())], vec![]);
+        let dlc_channels = DlcChannelStatus {
+            in_progress: true,
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filling { .. }));
+        assert!(store.positions.is_empty());
+    }
+
+    #[test]
+    fn order_is_filled_and_position_created_if_dlc_is_open() {
+        let mut store = store(vec![order(Direction::Long, filling())], vec![]);
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(matches!(
+            store.orders[0].state,
+            OrderState::Filled { execution_price } if execution_price == 30_000.0
+        ));
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [1; 32]);
+        assert_eq!(store.positions[0].collateral, 10_000);
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Open
+        ));
+        assert_eq!(store.trades.len(), 1);
+        assert_eq!(store.trades[0].order_id, store.orders[0].id);
+        assert_eq!(store.trades[0].kind, TradeKind::Open);
+        assert_eq!(store.trades[0].amount_sats, 10_000);
+    }
+
+    #[test]
+    fn order_is_filled_with_the_dlc_without_position() {
+        let mut store = store(
+            vec![order(Direction::Long, filling())],
+            vec![position([1; 32], PositionState::Open)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32], [2; 32]])).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filled { .. }));
+        assert_eq!(store.positions.len(), 2);
+        assert_eq!(store.positions[1].contract_id, [2; 32]);
+    }
+
+    #[test]
+    fn order_fails_if_no_dlc_is_being_set_up() {
+        let mut store = store(
+            vec![order(Direction::Long, filling())],
+            vec![position([1; 32], PositionState::Open)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(is_interrupted(&store.orders[0]));
+        assert_eq!(store.positions.len(), 1);
+    }
+
+    #[test]
+    fn closing_order_is_filled_and_position_removed_if_dlc_is_closed() {
+        let mut store = store(
+            vec![order(Direction::Short, filling())],
+            vec![
+                position([1; 32], PositionState::Open),
+                position([2; 32], PositionState::Closing),
+            ],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filled { .. }));
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [1; 32]);
+        assert_eq!(store.trades.len(), 1);
+        assert_eq!(store.trades[0].order_id, store.orders[0].id);
+        assert_eq!(store.trades[0].contract_id, [2; 32]);
+        assert_eq!(store.trades[0].kind, TradeKind::Close);
+        assert_eq!(store.trades[0].amount_sats, 10_000);
+    }
+
+    #[test]
+    fn closing_order_fails_and_position_reopened_if_dlc_is_still_open() {
+        let mut store = store(
+            vec![order(Direction::Short, filling())],
+            vec![position([1; 32], PositionState::Closing)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(is_interrupted(&store.orders[0]));
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Open
+        ));
+    }
+
+    #[test]
+    fn position_without_dlc_is_removed() {
+        let mut store = store(
+            vec![],
+            vec![
+                position([1; 32], PositionState::Open),
+                position([2; 32], PositionState::Open),
+            ],
+        );
+
+        reconcile(&mut store, &signed(&[[2; 32]])).unwrap();
+
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [2; 32]);
+    }
+
+    #[test]
+    fn position_is_kept_if_dlc_was_force_closed() {
+        let mut store = store(vec![], vec![position([1; 32], PositionState::Open)]);
+        let dlc_channels = DlcChannelStatus {
+            force_closed: HashSet::from([[1; 32]]),
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert_eq!(store.positions.len(), 1);
+    }
+
+    #[test]
+    fn force_closed_dlc_does_not_keep_other_positions_without_dlc() {
+        let mut store = store(
+            vec![],
+            vec![
+                position([1; 32], PositionState::Open),
+                position([2; 32], PositionState::Open),
+            ],
+        );
+        let dlc_channels = DlcChannelStatus {
+            force_closed: HashSet::from([[1; 32]]),
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [1; 32]);
+    }
+
+    #[test]
+    fn positions_are_kept_if_a_dlc_is_unidentified() {
+        let mut store = store(vec![], vec![position([1; 32], PositionState::Open)]);
+        let dlc_channels = DlcChannelStatus {
+            unidentified_dlc: true,
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert_eq!(store.positions.len(), 1);
+    }
+
+    #[test]
+    fn positions_are_reconciled_while_another_dlc_is_being_closed() {
+        let mut store = store(
+            vec![order(Direction::Short, filling())],
+            vec![
+                position([1; 32], PositionState::Open),
+                position([2; 32], PositionState::Closing),
+            ],
+        );
+        let dlc_channels = DlcChannelStatus {
+            in_progress: true,
+            closing: HashSet::from([[2; 32]]),
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filling { .. }));
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [2; 32]);
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Closing
+        ));
+    }
+
+    fn legacy_position() -> LegacyPosition {
+        let position = position([0; 32], PositionState::Open);
+
+        LegacyPosition {
+            leverage: position.leverage,
+            quantity: position.quantity,
+            contract_symbol: position.contract_symbol,
+            direction: position.direction,
+            average_entry_price: position.average_entry_price,
+            liquidation_price: position.liquidation_price,
+            position_state: position.position_state,
+            collateral: position.collateral,
+            creation_timestamp: OffsetDateTime::from_unix_timestamp(1_681_000_000).unwrap(),
+            opening_order_id: Some(Uuid::from_u128(1)),
+        }
+    }
+
+    #[test]
+    fn legacy_position_gets_the_contract_id_of_its_dlc() {
+        let mut store = FakeStore {
+            legacy_positions: vec![legacy_position()],
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(store.legacy_positions.is_empty());
+        assert_eq!(store.positions.len(), 1);
+        assert_eq!(store.positions[0].contract_id, [1; 32]);
+        assert_eq!(store.positions[0].quantity, 100.0);
+    }
+
+    #[test]
+    fn legacy_position_backfills_its_opening_trade() {
+        let legacy_position = legacy_position();
+        let mut store = FakeStore {
+            legacy_positions: vec![legacy_position.clone()],
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert_eq!(store.trades.len(), 1);
+        assert_eq!(store.trades[0].order_id, Uuid::from_u128(1));
+        assert_eq!(store.trades[0].contract_id, [1; 32]);
+        assert_eq!(store.trades[0].kind, TradeKind::Open);
+        assert_eq!(store.trades[0].amount_sats, legacy_position.collateral);
+        assert_eq!(
+            store.trades[0].timestamp,
+            legacy_position.creation_timestamp
+        );
+    }
+
+    #[test]
+    fn legacy_position_without_opening_order_is_not_backfilled() {
+        let mut store = FakeStore {
+            legacy_positions: vec![LegacyPosition {
+                opening_order_id: None,
+                ..legacy_position()
+            }],
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert_eq!(store.positions.len(), 1);
+        assert!(store.trades.is_empty());
+    }
+
+    #[test]
+    fn legacy_position_without_dlc_is_removed() {
+        let mut store = FakeStore {
+            legacy_positions: vec![legacy_position()],
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &DlcChannelStatus::default()).unwrap();
+
+        assert!(store.legacy_positions.is_empty());
+        assert!(store.positions.is_empty());
+        assert!(store.trades.is_empty());
+    }
+
+    #[test]
+    fn legacy_position_is_kept_if_a_dlc_is_unidentified() {
+        let mut store = FakeStore {
+            legacy_positions: vec![legacy_position()],
+            ..Default::default()
+        };
+        let dlc_channels = DlcChannelStatus {
+            unidentified_dlc: true,
+            ..Default::default()
+        };
+
+        reconcile(&mut store, &dlc_channels).unwrap();
+
+        assert_eq!(store.legacy_positions.len(), 1);
+        assert!(store.positions.is_empty());
+    }
+
+    #[test]
+    fn closing_position_without_order_in_filling_is_reopened() {
+        let closing_order = order(
+            Direction::Short,
+            OrderState::Failed {
+                reason: FailureReason::InvalidDlcOffer,
+            },
+        );
+        let mut store = store(
+            vec![closing_order],
+            vec![position([1; 32], PositionState::Closing)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(matches!(
+            store.orders[0].state,
+            OrderState::Failed {
+                reason: FailureReason::InvalidDlcOffer
+            }
+        ));
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Open
+        ));
+    }
+
+    #[test]
+    fn consistent_state_is_left_untouched() {
+        let open_order = order(
+            Direction::Short,
+            OrderState::Filled {
+                execution_price: 30_000.0,
+            },
+        );
+        let mut store = store(
+            vec![open_order],
+            vec![position([1; 32], PositionState::Closing)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filled { .. }));
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Closing
+        ));
+    }
+
+    #[test]
+    fn open_position_with_its_dlc_is_left_untouched() {
+        let open_order = order(
+            Direction::Long,
+            OrderState::Filled {
+                execution_price: 30_000.0,
+            },
+        );
+        let mut store = store(
+            vec![open_order],
+            vec![position([1; 32], PositionState::Open)],
+        );
+
+        reconcile(&mut store, &signed(&[[1; 32]])).unwrap();
+
+        assert!(matches!(store.orders[0].state, OrderState::Filled { .. }));
+        assert_eq!(store.positions.len(), 1);
+        assert!(matches!(
+            store.positions[0].position_state,
+            PositionState::Open
+        ));
+    }
+}
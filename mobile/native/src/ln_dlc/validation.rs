@@ -0,0 +1,373 @@
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use bdk::bitcoin::secp256k1::PublicKey;
+use bdk::bitcoin::XOnlyPublicKey;
+use coordinator_commons::TradeParams;
+use coordinator_commons::MAX_DLC_FEE_RATE_SATS_PER_VBYTE;
+use ln_dlc_node::node::rust_dlc_manager::contract::offered_contract::OfferedContract;
+use ln_dlc_node::node::rust_dlc_manager::contract::ContractDescriptor;
+use rust_decimal::Decimal;
+
+/// The terms of a DLC channel offered by the coordinator, which the app checks against the trade
+/// it requested before accepting the offer.
+#[derive(Debug, Clone)]
+pub struct DlcChannelOffer {
+    pub counterparty: PublicKey,
+    pub offer_collateral: u64,
+    pub accept_collateral: u64,
+    pub contract_descriptor: ContractDescriptor,
+    pub oracle_pk: XOnlyPublicKey,
+    pub event_id: String,
+    pub maturity_time: i64,
+    pub fee_rate_per_vb: u64,
+}
+
+impl TryFrom<&OfferedContract> for DlcChannelOffer {
+    type Error = anyhow::Error;
+
+    fn try_from(contract: &OfferedContract) -> Result<Self> {
+        let contract_info = match contract.contract_info.as_slice() {
+            [contract_info] => contract_info,
+            _ => bail!(
+                "Expected exactly one contract info, got {}",
+                contract.contract_info.len()
+            ),
+        };
+
+        let announcement = match contract_info.oracle_announcements.as_slice() {
+            [announcement] => announcement,
+            _ => bail!(
+                "Expected exactly one oracle announcement, got {}",
+                contract_info.oracle_announcements.len()
+            ),
+        };
+
+        let offer_collateral = contract.offer_params.collateral;
+        let accept_collateral = contract
+            .total_collateral
+            .checked_sub(offer_collateral)
+            .context("Offer collateral exceeds total collateral")?;
+
+        Ok(Self {
+            counterparty: contract.counter_party,
+            offer_collateral,
+            accept_collateral,
+            contract_descriptor: contract_info.contract_descriptor.clone(),
+            oracle_pk: announcement.oracle_public_key,
+            event_id: announcement.oracle_event.event_id.clone(),
+            maturity_time: announcement.oracle_event.event_maturity_epoch as i64,
+            fee_rate_per_vb: contract.fee_rate_per_vb,
+        })
+    }
+}
+
+/// Checks that `offer` was sent by the `coordinator` and sets up the DLC described by
+/// `trade_params`.
+pub fn validate_dlc_channel_offer(
+    offer: &DlcChannelOffer,
+    trade_params: &TradeParams,
+    coordinator: PublicKey,
+) -> Result<()> {
+    ensure!(
+        offer.counterparty == coordinator,
+        "DLC channel offer from {} instead of the coordinator",
+        offer.counterparty
+    );
+
+    let margin_coordinator = trade_params.margin_coordinator();
+    let margin_trader = trade_params.margin_trader();
+    ensure!(
+        offer.offer_collateral == margin_coordinator && offer.accept_collateral == margin_trader,
+        "Unexpected collateral split: expected {margin_coordinator}/{margin_trader}, got {}/{}",
+        offer.offer_collateral,
+        offer.accept_collateral
+    );
+
+    ensure!(
+        offer.oracle_pk == trade_params.filled_with.oracle_pk,
+        "Unexpected oracle: expected {}, got {}",
+        trade_params.filled_with.oracle_pk,
+        offer.oracle_pk
+    );
+
+    let event_id = trade_params.event_id();
+    ensure!(
+        offer.event_id == event_id,
+        "Unexpected oracle event: expected {event_id}, got {}",
+        offer.event_id
+    );
+
+    let maturity_time = trade_params.maturity_time();
+    ensure!(
+        offer.maturity_time == maturity_time,
+        "Unexpected expiry: expected {maturity_time}, got {}",
+        offer.maturity_time
+    );
+
+    let contract_descriptor = trade_params.contract_descriptor()?;
+    ensure!(
+        to_json(&offer.contract_descriptor)? == to_json(&contract_descriptor)?,
+        "Unexpected payout curve"
+    );
+
+    ensure!(
+        offer.fee_rate_per_vb <= MAX_DLC_FEE_RATE_SATS_PER_VBYTE,
+        "Fee rate of {} sats/vbyte exceeds the maximum of {MAX_DLC_FEE_RATE_SATS_PER_VBYTE} sats/vbyte",
+        offer.fee_rate_per_vb
+    );
+
+    Ok(())
+}
+
+/// Checks that a DLC channel close offer was sent by the `coordinator` while we are closing our
+/// position and that it pays us `accept_balance` according to the price the closing order was
+/// filled at.
+///
+/// `opening_price` is the average entry price of the position being closed.
+pub fn validate_dlc_channel_close_offer(
+    counterparty: PublicKey,
+    accept_balance: u64,
+    pending_trade: Option<&TradeParams>,
+    opening_price: Decimal,
+    coordinator: PublicKey,
+) -> Result<()> {
+    ensure!(
+        counterparty == coordinator,
+        "DLC channel close offer from {counterparty} instead of the coordinator"
+    );
+
+    let trade_params =
+        pending_trade.context("Unexpected DLC channel close offer without a pending trade")?;
+
+    let expected_accept_balance = trade_params.accept_settlement_amount(opening_price)?;
+    ensure!(
+        accept_balance >= expected_accept_balance,
+        "Expected to get {expected_accept_balance} sats out of the DLC, got {accept_balance} sats"
+    );
+
+    Ok(())
+}
+
+/// The contract descriptor does not implement `PartialEq`, so we compare its serialised form.
+fn to_json(contract_descriptor: &ContractDescriptor) -> Result<serde_json::Value> {
+    serde_json::to_value(contract_descriptor)
+        .map_err(|e| anyhow!("Failed to serialize contract descriptor: {e:#}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::secp256k1::Secp256k1;
+    use bdk::bitcoin::secp256k1::SecretKey;
+    use orderbook_commons::FilledWith;
+    use orderbook_commons::Match;
+    use rust_decimal_macros::dec;
+    use std::str::FromStr;
+    use time::OffsetDateTime;
+    use trade::ContractSymbol;
+    use trade::Direction;
+    use uuid::Uuid;
+
+    fn pubkey(byte: u8) -> PublicKey {
+        let secp = Secp256k1::new();
+        PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn oracle_pk() -> XOnlyPublicKey {
+        XOnlyPublicKey::from_str("16f88cf7d21e6c0f46bcbc983a4e3b19726c6c98858cc31c83551a88fde171c0")
+            .unwrap()
+    }
+
+    fn dummy_trade_params() -> TradeParams {
+        TradeParams {
+            pubkey: pubkey(1),
+            contract_symbol: ContractSymbol::BtcUsd,
+            leverage: 2.0,
+            quantity: 100.0,
+            direction: Direction::Long,
+            filled_with: FilledWith {
+                order_id: Uuid::new_v4(),
+                expiry_timestamp: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+                oracle_pk: oracle_pk(),
+                matches: vec![Match {
+                    order_id: Uuid::new_v4(),
+                    quantity: dec!(100),
+                    pubkey: pubkey(3),
+                    execution_price: dec!(30_000),
+                }],
+            },
+        }
+    }
+
+    fn expected_offer(trade_params: &TradeParams, coordinator: PublicKey) -> DlcChannelOffer {
+        DlcChannelOffer {
+            counterparty: coordinator,
+            offer_collateral: trade_params.margin_coordinator(),
+            accept_collateral: trade_params.margin_trader(),
+            contract_descriptor: trade_params.contract_descriptor().unwrap(),
+            oracle_pk: trade_params.filled_with.oracle_pk,
+            event_id: trade_params.event_id(),
+            maturity_time: trade_params.maturity_time(),
+            fee_rate_per_vb: 10,
+        }
+    }
+
+    #[test]
+    fn offer_matching_trade_is_valid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let offer = expected_offer(&trade_params, coordinator);
+
+        validate_dlc_channel_offer(&offer, &trade_params, coordinator).unwrap();
+    }
+
+    #[test]
+    fn offer_from_unknown_node_is_invalid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let offer = DlcChannelOffer {
+            counterparty: pubkey(4),
+            ..expected_offer(&trade_params, coordinator)
+        };
+
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+    }
+
+    #[test]
+    fn offer_with_different_collateral_split_is_invalid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let expected = expected_offer(&trade_params, coordinator);
+        let offer = DlcChannelOffer {
+            offer_collateral: expected.offer_collateral + 1_000,
+            accept_collateral: expected.accept_collateral - 1_000,
+            ..expected
+        };
+
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+    }
+
+    #[test]
+    fn offer_with_different_payout_curve_is_invalid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let other_trade_params = TradeParams {
+            direction: Direction::Short,
+            ..dummy_trade_params()
+        };
+        let offer = DlcChannelOffer {
+            contract_descriptor: other_trade_params.contract_descriptor().unwrap(),
+            ..expected_offer(&trade_params, coordinator)
+        };
+
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+    }
+
+    #[test]
+    fn offer_with_different_oracle_event_is_invalid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let expected = expected_offer(&trade_params, coordinator);
+
+        let offer = DlcChannelOffer {
+            oracle_pk: pubkey(5).x_only_public_key().0,
+            ..expected.clone()
+        };
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+
+        let offer = DlcChannelOffer {
+            event_id: "btcusd0".to_string(),
+            ..expected.clone()
+        };
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+
+        let offer = DlcChannelOffer {
+            maturity_time: expected.maturity_time + 1,
+            ..expected
+        };
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+    }
+
+    #[test]
+    fn offer_with_excessive_fee_rate_is_invalid() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+
+        let offer = DlcChannelOffer {
+            fee_rate_per_vb: MAX_DLC_FEE_RATE_SATS_PER_VBYTE,
+            ..expected_offer(&trade_params, coordinator)
+        };
+        validate_dlc_channel_offer(&offer, &trade_params, coordinator).unwrap();
+
+        let offer = DlcChannelOffer {
+            fee_rate_per_vb: MAX_DLC_FEE_RATE_SATS_PER_VBYTE + 1,
+            ..expected_offer(&trade_params, coordinator)
+        };
+        assert!(validate_dlc_channel_offer(&offer, &trade_params, coordinator).is_err());
+    }
+
+    #[test]
+    fn close_offer_requires_pending_trade_with_coordinator() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let opening_price = dec!(28_000);
+        let accept_balance = trade_params
+            .accept_settlement_amount(opening_price)
+            .unwrap();
+
+        validate_dlc_channel_close_offer(
+            coordinator,
+            accept_balance,
+            Some(&trade_params),
+            opening_price,
+            coordinator,
+        )
+        .unwrap();
+        assert!(validate_dlc_channel_close_offer(
+            coordinator,
+            accept_balance,
+            None,
+            opening_price,
+            coordinator
+        )
+        .is_err());
+        assert!(validate_dlc_channel_close_offer(
+            pubkey(4),
+            accept_balance,
+            Some(&trade_params),
+            opening_price,
+            coordinator
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn close_offer_has_to_pay_out_according_to_the_filled_price() {
+        let trade_params = dummy_trade_params();
+        let coordinator = pubkey(2);
+        let opening_price = dec!(28_000);
+        let accept_balance = trade_params
+            .accept_settlement_amount(opening_price)
+            .unwrap();
+
+        validate_dlc_channel_close_offer(
+            coordinator,
+            accept_balance + 1,
+            Some(&trade_params),
+            opening_price,
+            coordinator,
+        )
+        .unwrap();
+        assert!(validate_dlc_channel_close_offer(
+            coordinator,
+            accept_balance - 1,
+            Some(&trade_params),
+            opening_price,
+            coordinator
+        )
+        .is_err());
+    }
+}
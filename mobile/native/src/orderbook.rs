@@ -44,7 +44,11 @@ pub fn subscribe(secret_key: SecretKey) -> Result<()> {
 
         loop {
             let mut stream =
-                orderbook_client::subscribe_with_authentication(url.clone(), &authenticate);
+                orderbook_client::subscribe_with_authentication(
+                    url.clone(),
+                    &authenticate,
+                    config::get_socks5_proxy(),
+                );
 
             loop {
                 match stream.try_next().await {
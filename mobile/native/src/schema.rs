@@ -7,6 +7,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    legacy_positions (contract_symbol) {
+        contract_symbol -> Text,
+        leverage -> Double,
+        quantity -> Double,
+        direction -> Text,
+        average_entry_price -> Double,
+        liquidation_price -> Double,
+        state -> Text,
+        collateral -> BigInt,
+        creation_timestamp -> BigInt,
+        opening_order_id -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     orders (id) {
         id -> Text,
@@ -24,7 +39,15 @@ diesel::table! {
 }
 
 diesel::table! {
-    positions (contract_symbol) {
+    pending_settlements (channel_id) {
+        channel_id -> Text,
+        amount_sats -> BigInt,
+    }
+}
+
+diesel::table! {
+    positions (contract_id) {
+        contract_id -> Text,
         contract_symbol -> Text,
         leverage -> Double,
         quantity -> Double,
@@ -37,4 +60,21 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(last_login, orders, positions,);
+diesel::table! {
+    trades (order_id) {
+        order_id -> Text,
+        contract_id -> Text,
+        kind -> Text,
+        amount_sats -> BigInt,
+        timestamp -> BigInt,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    last_login,
+    legacy_positions,
+    orders,
+    pending_settlements,
+    positions,
+    trades,
+);
@@ -49,8 +49,14 @@ pub(crate) fn order_filling(order_id: Uuid, execution_price: f64) -> Result<()>
     Ok(())
 }
 
-pub(crate) fn order_filled() -> Result<Order> {
-    let order_being_filled = get_order_being_filled()?;
+/// Update order state to filled
+///
+/// If the order_id is not known we fall back to the order that is currently in `Filling` state.
+pub(crate) fn order_filled(order_id: Option<Uuid>) -> Result<Order> {
+    let order_being_filled = match order_id {
+        None => get_order_being_filled()?,
+        Some(order_id) => db::get_order(order_id)?,
+    };
 
     // Default the execution price in case we don't know
     let execution_price = order_being_filled.execution_price().unwrap_or(0.0);
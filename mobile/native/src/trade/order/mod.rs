@@ -30,6 +30,10 @@ pub enum FailureReason {
     ProposeDlcChannel,
     /// MVP scope: Can only close the order, not reduce or extend
     OrderNotAcceptable,
+    /// The DLC channel (close) offer we received does not match the trade we requested
+    InvalidDlcOffer,
+    /// The app was stopped while filling the order and the DLC channel was not set up
+    Interrupted,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -1,3 +1,4 @@
+use crate::config;
 use anyhow::bail;
 use anyhow::Result;
 use orderbook_commons::NewOrder;
@@ -15,7 +16,7 @@ impl OrderbookClient {
 
     pub(crate) async fn post_new_order(&self, order: NewOrder) -> Result<OrderResponse> {
         let url = self.url.join("/api/orderbook/orders")?;
-        let client = reqwest::Client::new();
+        let client = config::get_http_client()?;
 
         let response = client.post(url).json(&order).send().await?;
 
@@ -28,6 +28,8 @@ pub enum PositionState {
 #[frb]
 #[derive(Debug, Clone)]
 pub struct Position {
+    /// The hex encoded ID of the DLC of this position.
+    pub contract_id: String,
     pub leverage: f64,
     pub quantity: f64,
     pub contract_symbol: ContractSymbol,
@@ -50,6 +52,7 @@ impl From<position::PositionState> for PositionState {
 impl From<position::Position> for Position {
     fn from(value: position::Position) -> Self {
         Position {
+            contract_id: hex::encode(value.contract_id),
             leverage: value.leverage,
             quantity: value.quantity,
             contract_symbol: value.contract_symbol,
@@ -7,14 +7,17 @@ use crate::trade::order;
 use crate::trade::order::Order;
 use crate::trade::position::Position;
 use crate::trade::position::PositionState;
-use anyhow::bail;
+use crate::trade::position::Trade;
+use crate::trade::position::TradeKind;
 use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use coordinator_commons::TradeParams;
+use ln_dlc_node::node::rust_dlc_manager::ContractId;
 use orderbook_commons::FilledWith;
 use orderbook_commons::Prices;
 use rust_decimal::prelude::ToPrimitive;
+use time::OffsetDateTime;
 use trade::ContractSymbol;
 use trade::Direction;
 
@@ -59,35 +62,76 @@ pub async fn get_positions() -> Result<Vec<Position>> {
 
 /// Update the position once an order was submitted
 ///
-/// If the new order submitted is an order that closes the current position, then the position will
-/// be updated to `Closing` state.
+/// If the new order submitted is an order that closes one of the current positions, then that
+/// position will be updated to `Closing` state. Otherwise the order opens another position.
 pub fn update_position_after_order_submitted(submitted_order: Order) -> Result<()> {
-    if let Some(position) = db::get_positions()?.first() {
-        // closing the position
-        if position.direction == submitted_order.direction.opposite()
+    let positions = db::get_positions()?;
+
+    let position_to_close = positions.iter().find(|position| {
+        matches!(position.position_state, PositionState::Open)
+            && position.direction == submitted_order.direction.opposite()
             && position.quantity == submitted_order.quantity
-        {
-            db::update_position_state(position.contract_symbol, PositionState::Closing)?;
-            event::publish(&EventInternal::PositionUpdateNotification(position.clone()));
-        } else {
-            bail!("Currently not possible to extend or reduce a position, you can only close the position with a counter-order");
-        }
+    });
+
+    if let Some(position) = position_to_close {
+        db::update_position_state(position.contract_id, PositionState::Closing)?;
+        event::publish(&EventInternal::PositionUpdateNotification(position.clone()));
+
+        return Ok(());
     }
 
+    ensure!(
+        positions
+            .iter()
+            .all(|position| position.direction == submitted_order.direction),
+        "Currently not possible to reduce a position, you can only close the position with a counter-order"
+    );
+
     Ok(())
 }
 
 /// Create a position after the creation of a DLC channel.
-pub fn update_position_after_dlc_creation(filled_order: Order, collateral: u64) -> Result<()> {
+pub fn update_position_after_dlc_creation(
+    filled_order: Order,
+    contract_id: ContractId,
+    collateral: u64,
+) -> Result<()> {
     ensure!(
-        db::get_positions()?.is_empty(),
-        "Cannot create a position if one is already open"
+        db::get_positions()?
+            .iter()
+            .all(|position| position.contract_id != contract_id),
+        "Cannot create a position for a DLC which already has one"
+    );
+
+    tracing::debug!(
+        order = ?filled_order,
+        contract_id = %hex::encode(contract_id),
+        %collateral,
+        "Creating position after DLC channel creation"
     );
 
-    tracing::debug!(order = ?filled_order, %collateral, "Creating position after DLC channel creation");
+    let have_a_position = open_position(&filled_order, contract_id, collateral);
 
+    let position = db::insert_position_with_trade(
+        have_a_position,
+        opening_trade(&filled_order, contract_id, collateral),
+    )?;
+
+    event::publish(&EventInternal::PositionUpdateNotification(position));
+
+    Ok(())
+}
+
+/// The position resulting from `filled_order` once its DLC with `collateral` is established.
+pub(crate) fn open_position(
+    filled_order: &Order,
+    contract_id: ContractId,
+    collateral: u64,
+) -> Position {
     let average_entry_price = filled_order.execution_price().unwrap_or(0.0);
-    let have_a_position = Position {
+
+    Position {
+        contract_id,
         leverage: filled_order.leverage,
         quantity: filled_order.quantity,
         contract_symbol: filled_order.contract_symbol,
@@ -104,26 +148,87 @@ pub fn update_position_after_dlc_creation(filled_order: Order, collateral: u64)
         // TODO: Remove the PnL, that has to be calculated in the UI
         position_state: PositionState::Open,
         collateral,
-    };
+    }
+}
 
-    let position = db::insert_position(have_a_position)?;
-    event::publish(&EventInternal::PositionUpdateNotification(position));
+/// The trade which moved our `collateral` into the DLC opened by `filled_order`.
+pub(crate) fn opening_trade(
+    filled_order: &Order,
+    contract_id: ContractId,
+    collateral: u64,
+) -> Trade {
+    Trade {
+        order_id: filled_order.id,
+        contract_id,
+        kind: TradeKind::Open,
+        amount_sats: collateral,
+        timestamp: OffsetDateTime::now_utc(),
+    }
+}
 
-    Ok(())
+/// The trade which moved what we got out of the DLC of `position` back to us when
+/// `filled_order` closed it.
+///
+/// If the `settlement_amount` is unknown, the collateral of the position is recorded instead.
+pub(crate) fn closing_trade(
+    filled_order: &Order,
+    position: &Position,
+    settlement_amount: Option<u64>,
+) -> Trade {
+    let amount_sats = match settlement_amount {
+        Some(settlement_amount) => settlement_amount,
+        None => {
+            tracing::warn!(
+                ?position,
+                "Unknown settlement amount, recording the collateral of the position instead"
+            );
+            position.collateral
+        }
+    };
+
+    Trade {
+        order_id: filled_order.id,
+        contract_id: position.contract_id,
+        kind: TradeKind::Close,
+        amount_sats,
+        timestamp: OffsetDateTime::now_utc(),
+    }
 }
 
-/// Delete a position after closing a DLC channel.
-pub fn update_position_after_dlc_closure(filled_order: Order) -> Result<()> {
+/// Delete a position after closing its DLC channel.
+///
+/// If we do not know the `contract_id` of the closed DLC, we delete the position which is being
+/// closed. The `settlement_amount` is what we got out of the DLC, if known.
+pub fn update_position_after_dlc_closure(
+    filled_order: Order,
+    contract_id: Option<ContractId>,
+    settlement_amount: Option<u64>,
+) -> Result<()> {
     tracing::debug!(?filled_order, "Removing position after DLC channel closure");
 
-    if db::get_positions()?.is_empty() {
-        tracing::warn!("No position to remove");
-    }
+    let positions = db::get_positions()?;
+    let position = match contract_id {
+        Some(contract_id) => positions
+            .iter()
+            .find(|position| position.contract_id == contract_id),
+        None => positions
+            .iter()
+            .find(|position| matches!(position.position_state, PositionState::Closing)),
+    };
+
+    let position = match position {
+        Some(position) => position,
+        None => {
+            tracing::warn!("No position to remove");
+            return Ok(());
+        }
+    };
 
-    db::delete_positions()?;
+    db::insert_trade(closing_trade(&filled_order, position, settlement_amount))?;
+    db::delete_position(position.contract_id)?;
 
     event::publish(&EventInternal::PositionCloseNotification(
-        ContractSymbol::BtcUsd,
+        position.contract_id,
     ));
 
     Ok(())
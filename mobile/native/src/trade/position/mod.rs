@@ -1,5 +1,8 @@
+use ln_dlc_node::node::rust_dlc_manager::ContractId;
+use time::OffsetDateTime;
 use trade::ContractSymbol;
 use trade::Direction;
+use uuid::Uuid;
 
 pub mod api;
 pub mod handler;
@@ -28,6 +31,8 @@ pub enum PositionState {
 
 #[derive(Debug, Clone)]
 pub struct Position {
+    /// The ID of the DLC of this position.
+    pub contract_id: ContractId,
     pub leverage: f64,
     pub quantity: f64,
     pub contract_symbol: ContractSymbol,
@@ -37,3 +42,70 @@ pub struct Position {
     pub position_state: PositionState,
     pub collateral: u64,
 }
+
+/// A position created before positions were identified by the contract ID of their DLC.
+///
+/// Reconciliation turns it into a [`Position`] once it finds its DLC.
+#[derive(Debug, Clone)]
+pub struct LegacyPosition {
+    pub leverage: f64,
+    pub quantity: f64,
+    pub contract_symbol: ContractSymbol,
+    pub direction: Direction,
+    pub average_entry_price: f64,
+    pub liquidation_price: f64,
+    pub position_state: PositionState,
+    pub collateral: u64,
+    pub creation_timestamp: OffsetDateTime,
+    /// The filled order which opened the position, if it could be told from the orders.
+    pub opening_order_id: Option<Uuid>,
+}
+
+impl LegacyPosition {
+    /// The trade which moved our collateral into the DLC of the position, if we know the order
+    /// which opened it.
+    pub fn opening_trade(&self, contract_id: ContractId) -> Option<Trade> {
+        let order_id = self.opening_order_id?;
+
+        Some(Trade {
+            order_id,
+            contract_id,
+            kind: TradeKind::Open,
+            amount_sats: self.collateral,
+            timestamp: self.creation_timestamp,
+        })
+    }
+
+    pub fn with_contract_id(self, contract_id: ContractId) -> Position {
+        Position {
+            contract_id,
+            leverage: self.leverage,
+            quantity: self.quantity,
+            contract_symbol: self.contract_symbol,
+            direction: self.direction,
+            average_entry_price: self.average_entry_price,
+            liquidation_price: self.liquidation_price,
+            position_state: self.position_state,
+            collateral: self.collateral,
+        }
+    }
+}
+
+/// Funds which moved into or out of a DLC because a position was opened or closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    /// The ID of the order which opened or closed the position.
+    pub order_id: Uuid,
+    /// The ID of the DLC of the position.
+    pub contract_id: ContractId,
+    pub kind: TradeKind,
+    /// Our collateral when opening the position, what we got out of the DLC when closing it.
+    pub amount_sats: u64,
+    pub timestamp: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeKind {
+    Open,
+    Close,
+}